@@ -0,0 +1,160 @@
+// typed view of the raw admin command and response structs
+// the core decodes an AdminCommand into an AdminAction , applies it and encodes the result into an AdminResponse
+use thiserror::Error;
+use crate::admin::log_level::LogLevel;
+use crate::engine::symbol_registry::SymbolError;
+use crate::balance_manager::funds_ledger::{Asset, FundsError, FundsRequest};
+use crate::balance_manager::risk_limits::LimitKind;
+use crate::orderbook::types::BalanceManagerError;
+use crate::shm::admin_command_queue::*;
+use crate::shm::admin_response_queue::*;
+
+#[derive(Debug , Clone , Copy , PartialEq , Eq)]
+pub enum AdminAction{
+    HaltSymbol(u32),
+    ResumeSymbol(u32),
+    AddBook(u32),
+    RemoveBook(u32),
+    AddUser(u64),
+    // applied once per key , the request id of the command
+    Funds { key : u64 , request : FundsRequest },
+    SetLimit { user_id : u64 , kind : LimitKind , value : u64 },
+    FreezeUser(u64),
+    UnfreezeUser(u64),
+    StopTrading,
+    ResumeTrading,
+    SetMarkPrice { symbol : u32 , price : u64 },
+    Snapshot,
+    DumpStats,
+    SetLogLevel(LogLevel),
+    // taken by a standby only , never journalled
+    Promote,
+    Shutdown,
+}
+
+impl AdminAction{
+    pub fn decode(command : &AdminCommand)->Result<Self , AdminError>{
+        Ok(match command.command_type {
+            ADMIN_HALT_SYMBOL => Self::HaltSymbol(command.symbol),
+            ADMIN_RESUME_SYMBOL => Self::ResumeSymbol(command.symbol),
+            ADMIN_ADD_BOOK => Self::AddBook(command.symbol),
+            ADMIN_REMOVE_BOOK => Self::RemoveBook(command.symbol),
+            ADMIN_ADD_USER => Self::AddUser(command.user_id),
+            ADMIN_DEPOSIT | ADMIN_WITHDRAW | ADMIN_TRANSFER | ADMIN_ADJUST => {
+                let asset = Asset::from_code(command.asset, command.symbol).ok_or(AdminError::BadArgument("unknown asset"))?;
+                let (user_id , amount) = (command.user_id , command.available);
+                let request = match command.command_type {
+                    ADMIN_DEPOSIT => FundsRequest::Deposit { user_id , asset , amount },
+                    ADMIN_WITHDRAW => FundsRequest::Withdraw { user_id , asset , amount },
+                    ADMIN_ADJUST => FundsRequest::Adjust { user_id , asset , delta : amount as i64 },
+                    _ => FundsRequest::Transfer { from : user_id , to : command.reserved , asset , amount },
+                };
+                Self::Funds { key : command.request_id , request }
+            }
+            ADMIN_SET_LIMIT => {
+                let kind = LimitKind::from_u8(command.limit).ok_or(AdminError::BadArgument("unknown limit"))?;
+                if kind.is_narrow() && command.available > u32::MAX as u64 {
+                    return Err(AdminError::BadArgument("limit does not fit in u32"));
+                }
+                Self::SetLimit { user_id : command.user_id , kind , value : command.available }
+            }
+            ADMIN_FREEZE_USER => Self::FreezeUser(command.user_id),
+            ADMIN_UNFREEZE_USER => Self::UnfreezeUser(command.user_id),
+            ADMIN_STOP_TRADING => Self::StopTrading,
+            ADMIN_RESUME_TRADING => Self::ResumeTrading,
+            ADMIN_SET_MARK_PRICE => Self::SetMarkPrice { symbol : command.symbol , price : command.available },
+            ADMIN_SNAPSHOT => Self::Snapshot,
+            ADMIN_DUMP_STATS => Self::DumpStats,
+            ADMIN_PROMOTE => Self::Promote,
+            ADMIN_SHUTDOWN => Self::Shutdown,
+            ADMIN_SET_LOG_LEVEL => Self::SetLogLevel(LogLevel::from_u8(command.log_level).ok_or(AdminError::BadArgument("unknown log level"))?),
+            other => return Err(AdminError::UnknownCommand(other)),
+        })
+    }
+}
+
+// what dump stats reports
+#[derive(Debug , Clone , Copy , Default , PartialEq , Eq)]
+pub struct EngineStats{
+    pub processed_orders : u64,
+    pub book_count : u32,
+    pub user_count : u32,
+    pub halted_count : u32,
+}
+
+#[derive(Debug , Clone , Copy , PartialEq , Eq)]
+pub enum AdminReply{
+    Done,
+    Stats(EngineStats),
+}
+
+#[derive(Debug , Error , PartialEq , Eq)]
+pub enum AdminError{
+    #[error("operator {0} is not authorized")]
+    Unauthorized(u64),
+    #[error("unknown admin command {0}")]
+    UnknownCommand(u8),
+    #[error("bad argument: {0}")]
+    BadArgument(&'static str),
+    #[error(transparent)]
+    Symbol(#[from] SymbolError),
+    #[error("symbol {0} is already halted")]
+    AlreadyHalted(u32),
+    #[error("symbol {0} is not halted")]
+    NotHalted(u32),
+    #[error("balance manager rejected the change: {0:?}")]
+    User(BalanceManagerError),
+    #[error(transparent)]
+    Funds(#[from] FundsError),
+    #[error("only a standby can be promoted")]
+    NotStandby,
+    #[error("a standby only takes promotion , not admin command {0}")]
+    Standby(u8),
+}
+
+impl From<BalanceManagerError> for AdminError{
+    fn from(e : BalanceManagerError)->Self{
+        Self::User(e)
+    }
+}
+
+impl AdminError{
+    pub fn status(&self)->u8{
+        match self {
+            Self::Unauthorized(_) => ADMIN_STATUS_UNAUTHORIZED,
+            Self::UnknownCommand(_) => ADMIN_STATUS_UNKNOWN_COMMAND,
+            Self::BadArgument(_) => ADMIN_STATUS_BAD_ARGUMENT,
+            Self::Symbol(SymbolError::AlreadyListed(_)) => ADMIN_STATUS_SYMBOL_LISTED,
+            Self::Symbol(SymbolError::NotListed(_)) => ADMIN_STATUS_SYMBOL_NOT_LISTED,
+            Self::AlreadyHalted(_) | Self::NotHalted(_) => ADMIN_STATUS_HALT_STATE,
+            Self::User(_) | Self::Funds(FundsError::UnknownUser(_)) => ADMIN_STATUS_USER_REJECTED,
+            Self::Funds(FundsError::InsufficientAvailable { .. }) => ADMIN_STATUS_INSUFFICIENT_FUNDS,
+            Self::Funds(FundsError::KeyReused(_)) => ADMIN_STATUS_KEY_REUSED,
+            Self::Funds(FundsError::Frozen(_)) => ADMIN_STATUS_USER_FROZEN,
+            Self::NotStandby | Self::Standby(_) => ADMIN_STATUS_WRONG_ROLE,
+            Self::Funds(FundsError::ZeroAmount | FundsError::SelfTransfer(_) | FundsError::Overflow(_)) => ADMIN_STATUS_BAD_ARGUMENT,
+        }
+    }
+}
+
+/// the response for `command` applied at `sequence`
+pub fn admin_response(command : &AdminCommand , sequence : u64 , result : &Result<AdminReply , AdminError>)->AdminResponse{
+    let mut response = AdminResponse {
+        request_id : command.request_id,
+        sequence,
+        command_type : command.command_type,
+        ..AdminResponse::default()
+    };
+    match result {
+        Ok(AdminReply::Done) => response.status = ADMIN_STATUS_OK,
+        Ok(AdminReply::Stats(stats)) => {
+            response.status = ADMIN_STATUS_OK;
+            response.processed_orders = stats.processed_orders;
+            response.book_count = stats.book_count;
+            response.user_count = stats.user_count;
+            response.halted_count = stats.halted_count;
+        }
+        Err(e) => response.status = e.status(),
+    }
+    response
+}
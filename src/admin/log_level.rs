@@ -0,0 +1,38 @@
+// process wide log level for the diagnostic eprintln's , changed at runtime by the admin set log level command
+use std::sync::atomic::{AtomicU8, Ordering};
+
+#[derive(Debug , Clone , Copy , PartialEq , Eq , PartialOrd , Ord)]
+pub enum LogLevel{
+    Error = 0,
+    Warn = 1,
+    Info = 2,
+    Debug = 3,
+}
+
+impl LogLevel{
+    pub fn from_u8(level : u8)->Option<Self>{
+        match level {
+            0 => Some(Self::Error),
+            1 => Some(Self::Warn),
+            2 => Some(Self::Info),
+            3 => Some(Self::Debug),
+            _ => None,
+        }
+    }
+}
+
+static LOG_LEVEL : AtomicU8 = AtomicU8::new(LogLevel::Info as u8);
+
+pub fn set_log_level(level : LogLevel){
+    LOG_LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+pub fn log_level()->LogLevel{
+    LogLevel::from_u8(LOG_LEVEL.load(Ordering::Relaxed)).unwrap()
+}
+
+/// whether a message at `level` should be printed
+#[inline(always)]
+pub fn log_enabled(level : LogLevel)->bool{
+    level as u8 <= LOG_LEVEL.load(Ordering::Relaxed)
+}
@@ -0,0 +1,4 @@
+pub mod commands;
+pub mod log_level;
+pub mod plane;
+pub mod tests;
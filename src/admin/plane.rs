@@ -0,0 +1,74 @@
+// admin state shared by the single threaded core and the sharded risk thread
+// authorization is decided once when the command is read and journalled with it , so a replay reaches the same
+// outcome whatever operators the replaying process is configured with
+use rustc_hash::FxHashSet;
+use crate::admin::commands::AdminError;
+use crate::shm::admin_command_queue::AdminCommand;
+
+// an admin command as it is journalled
+#[derive(Debug , Clone , Copy)]
+pub struct AdminRequest{
+    pub command : AdminCommand,
+    pub authorized : bool,
+}
+
+#[derive(Debug , Default)]
+pub struct AdminPlane{
+    // operators allowed to run admin commands , empty rejects everything
+    pub operators : FxHashSet<u64>,
+    // symbols not accepting new orders , cancels still go through
+    pub halted : FxHashSet<u32>,
+}
+
+impl AdminPlane{
+    pub fn new(operators : impl IntoIterator<Item = u64>)->Self{
+        Self { operators : operators.into_iter().collect() , halted : FxHashSet::default() }
+    }
+
+    /// operators from the ADMIN_OPERATORS env var , none set leaves the admin plane locked
+    pub fn from_env()->Result<Self , String>{
+        match std::env::var("ADMIN_OPERATORS") {
+            Ok(list) => parse_operators(&list).map(Self::new),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    pub fn authorize(&self , command : AdminCommand)->AdminRequest{
+        AdminRequest { command , authorized : self.operators.contains(&command.operator_id) }
+    }
+
+    #[inline(always)]
+    pub fn is_halted(&self , symbol : u32)->bool{
+        self.halted.contains(&symbol)
+    }
+
+    pub fn halt(&mut self , symbol : u32)->Result<() , AdminError>{
+        if !self.halted.insert(symbol) {
+            return Err(AdminError::AlreadyHalted(symbol));
+        }
+        Ok(())
+    }
+
+    pub fn resume(&mut self , symbol : u32)->Result<() , AdminError>{
+        if !self.halted.remove(&symbol) {
+            return Err(AdminError::NotHalted(symbol));
+        }
+        Ok(())
+    }
+
+    /// halted symbols in ascending order , for checkpoints
+    pub fn halted_symbols(&self)->Vec<u32>{
+        let mut symbols : Vec<u32> = self.halted.iter().copied().collect();
+        symbols.sort_unstable();
+        symbols
+    }
+}
+
+/// operator ids from a comma separated list , the ADMIN_OPERATORS setting
+pub fn parse_operators(list : &str)->Result<Vec<u64> , String>{
+    list.split(',')
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(|id| id.parse::<u64>().map_err(|_| format!("bad operator id {:?}", id)))
+        .collect()
+}
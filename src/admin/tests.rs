@@ -0,0 +1,424 @@
+#[cfg(test)]
+mod tests {
+    use crate::admin::commands::{AdminAction, AdminError};
+    use crate::admin::log_level::{log_level, set_log_level, LogLevel};
+    use crate::admin::plane::{parse_operators, AdminPlane};
+    use crate::balance_manager::funds_ledger::{Asset, MovementKind};
+    use crate::balance_manager::my_balance_manager2::NANOS_PER_DAY;
+    use crate::engine::my_engine::Engine;
+    use crate::journal::checkpoint::restore_checkpoint;
+    use crate::journal::command_journal::{InboundCommand, JournalRecord, JournalWriter};
+    use crate::journal::replay::{detached_core, replay_journal, ReplaySink};
+    use crate::orderbook::order::{Order, OrderToBeCanceled, Side};
+    use crate::shm::admin_command_queue::*;
+    use crate::shm::admin_response_queue::*;
+    use crate::shm::event_queue::*;
+    use crate::trading_core::my_trading_core::TradingCore;
+
+    const OPERATOR: u64 = 7;
+
+    fn admin(request_id: u64, command_type: u8, symbol: u32) -> AdminCommand {
+        AdminCommand { request_id, operator_id: OPERATOR, symbol, command_type, ..AdminCommand::default() }
+    }
+
+    fn operator_core() -> (TradingCore, ReplaySink) {
+        let (mut core, sink) = detached_core();
+        core.admin = AdminPlane::new([OPERATOR]);
+        (core, sink)
+    }
+
+    fn submit_admin(core: &mut TradingCore, command: AdminCommand) {
+        let request = core.admin.authorize(command);
+        core.submit(InboundCommand::Admin(request));
+    }
+
+    #[test]
+    fn test_unauthorized_operator_is_refused() {
+        let (mut core, mut sink) = operator_core();
+        let mut command = admin(1, ADMIN_ADD_BOOK, 5);
+        command.operator_id = 99;
+        submit_admin(&mut core, command);
+        assert!(!core.engine.has_book(5));
+        let response = sink.admin_responses.try_pop().unwrap();
+        assert_eq!((response.request_id, response.status, response.sequence), (1, ADMIN_STATUS_UNAUTHORIZED, 1));
+    }
+
+    #[test]
+    fn test_halted_symbol_rejects_orders_but_takes_cancels() {
+        let (mut core, mut sink) = operator_core();
+        submit_admin(&mut core, admin(1, ADMIN_ADD_BOOK, 5));
+        core.submit(InboundCommand::NewOrder(Order::new(10, 1, Side::Bid, 1, 4, 9, 1, 5)));
+        submit_admin(&mut core, admin(2, ADMIN_HALT_SYMBOL, 5));
+        sink.drain();
+
+        core.submit(InboundCommand::NewOrder(Order::new(10, 2, Side::Bid, 1, 4, 9, 2, 5)));
+        let reject = sink.order_events_from_bm.try_pop().unwrap();
+        assert_eq!((reject.order_id, reject.event_kind, reject.error_code), (2, 3, REJECT_REASON_HALTED));
+
+        core.submit(InboundCommand::CancelOrder(OrderToBeCanceled { order_id: 1, user_id: 10, symbol: 5 }));
+        assert_eq!(core.engine.get_book(5).unwrap().manager.id_to_index.len(), 0);
+
+        submit_admin(&mut core, admin(3, ADMIN_HALT_SYMBOL, 5));
+        assert_eq!(sink.admin_responses.try_pop().unwrap().status, ADMIN_STATUS_HALT_STATE);
+        submit_admin(&mut core, admin(4, ADMIN_RESUME_SYMBOL, 5));
+        assert_eq!(sink.admin_responses.try_pop().unwrap().status, ADMIN_STATUS_OK);
+        core.submit(InboundCommand::NewOrder(Order::new(10, 3, Side::Bid, 1, 4, 9, 3, 5)));
+        assert_eq!(core.engine.get_book(5).unwrap().manager.id_to_index.len(), 1);
+    }
+
+    #[test]
+    fn test_typed_errors_and_stats() {
+        let (mut core, mut sink) = operator_core();
+        submit_admin(&mut core, admin(1, ADMIN_ADD_BOOK, 5));
+        submit_admin(&mut core, admin(2, ADMIN_ADD_BOOK, 5));
+        submit_admin(&mut core, admin(3, ADMIN_REMOVE_BOOK, 6));
+        submit_admin(&mut core, admin(4, 42, 0));
+        // promotion is for a standby , a running primary refuses it
+        submit_admin(&mut core, admin(7, ADMIN_PROMOTE, 0));
+        let statuses: Vec<u8> = std::iter::from_fn(|| sink.admin_responses.try_pop()).map(|response| response.status).collect();
+        assert_eq!(statuses, vec![ADMIN_STATUS_OK, ADMIN_STATUS_SYMBOL_LISTED, ADMIN_STATUS_SYMBOL_NOT_LISTED, ADMIN_STATUS_UNKNOWN_COMMAND, ADMIN_STATUS_WRONG_ROLE]);
+
+        submit_admin(&mut core, admin(5, ADMIN_HALT_SYMBOL, 5));
+        submit_admin(&mut core, admin(6, ADMIN_DUMP_STATS, 0));
+        let stats = std::iter::from_fn(|| sink.admin_responses.try_pop()).last().unwrap();
+        assert_eq!((stats.request_id, stats.status, stats.book_count, stats.halted_count), (6, ADMIN_STATUS_OK, 1, 1));
+        assert_eq!(stats.user_count, core.balance_manager.state.total_users);
+    }
+
+    #[test]
+    fn test_decode_rejects_bad_arguments() {
+        // the retired overwrites are gone
+        assert_eq!(AdminAction::decode(&admin(1, 4, 0)), Err(AdminError::UnknownCommand(4)));
+        let command = AdminCommand { asset: ASSET_COIN, ..admin(1, ADMIN_ADJUST, 0) };
+        assert!(matches!(AdminAction::decode(&command), Err(AdminError::BadArgument(_))));
+        let mut command = admin(2, ADMIN_SET_LOG_LEVEL, 0);
+        command.log_level = 9;
+        assert!(matches!(AdminAction::decode(&command), Err(AdminError::BadArgument(_))));
+        command.log_level = 3;
+        assert_eq!(AdminAction::decode(&command), Ok(AdminAction::SetLogLevel(LogLevel::Debug)));
+        assert_eq!(parse_operators(" 1, 2,,3 "), Ok(vec![1, 2, 3]));
+        assert!(parse_operators("1,x").is_err());
+    }
+
+    #[test]
+    fn test_set_log_level_applies_globally() {
+        let (mut core, mut sink) = operator_core();
+        let mut command = admin(1, ADMIN_SET_LOG_LEVEL, 0);
+        command.log_level = LogLevel::Warn as u8;
+        submit_admin(&mut core, command);
+        assert_eq!(sink.admin_responses.try_pop().unwrap().status, ADMIN_STATUS_OK);
+        assert_eq!(log_level(), LogLevel::Warn);
+        set_log_level(LogLevel::Info);
+    }
+
+    #[test]
+    fn test_admin_commands_replay_with_their_recorded_authorization() {
+        let path = std::env::temp_dir().join(format!("admin_test_replay_{}.journal", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let (mut live, mut live_sink) = operator_core();
+        live.journal = Some(JournalWriter::open(&path).unwrap());
+        submit_admin(&mut live, admin(1, ADMIN_ADD_BOOK, 5));
+        submit_admin(&mut live, admin(2, ADMIN_HALT_SYMBOL, 5));
+        let mut denied = admin(3, ADMIN_ADD_BOOK, 6);
+        denied.operator_id = 99;
+        submit_admin(&mut live, denied);
+        live.journal.as_mut().unwrap().sync().unwrap();
+        live_sink.drain();
+
+        // no operators configured at all , the journalled decisions still hold
+        let (mut replayed, mut replay_sink) = detached_core();
+        replay_journal(&mut replayed, &mut replay_sink, &path, 0, None).unwrap();
+        assert!(replayed.engine.has_book(5));
+        assert!(!replayed.engine.has_book(6));
+        assert!(replayed.admin.is_halted(5));
+
+        // and the halt survives a checkpoint
+        let dir = std::env::temp_dir().join(format!("admin_test_checkpoint_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        replayed.checkpoint_dir = Some(dir.clone());
+        let checkpoint = replayed.checkpoint().unwrap().unwrap();
+        let (mut restored, _sink) = detached_core();
+        restore_checkpoint(&mut restored, &checkpoint).unwrap();
+        assert_eq!(restored.admin.halted_symbols(), vec![5]);
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    fn funds(request_id: u64, command_type: u8, user_id: u64, amount: u64) -> AdminCommand {
+        AdminCommand { user_id, available: amount, ..admin(request_id, command_type, 0) }
+    }
+
+    fn status_of(sink: &mut ReplaySink) -> u8 {
+        let status = sink.admin_responses.try_pop().unwrap().status;
+        sink.drain();
+        status
+    }
+
+    #[test]
+    fn test_funds_movements_are_idempotent_and_leave_reserved_funds_alone() {
+        let (mut core, mut sink) = operator_core();
+        submit_admin(&mut core, admin(1, ADMIN_ADD_BOOK, 5));
+        submit_admin(&mut core, AdminCommand { user_id: 30, ..admin(2, ADMIN_ADD_USER, 0) });
+        sink.drain();
+        let user = core.balance_manager.get_user_index(30).unwrap() as usize;
+        assert_eq!(core.balance_manager.state.balances[user].available_balance, 0);
+
+        submit_admin(&mut core, funds(10, ADMIN_DEPOSIT, 30, 1000));
+        assert_eq!(status_of(&mut sink), ADMIN_STATUS_OK);
+        // a retry is answered but not applied , the same key for something else is refused
+        submit_admin(&mut core, funds(10, ADMIN_DEPOSIT, 30, 1000));
+        assert_eq!(status_of(&mut sink), ADMIN_STATUS_OK);
+        submit_admin(&mut core, funds(10, ADMIN_DEPOSIT, 30, 5000));
+        assert_eq!(status_of(&mut sink), ADMIN_STATUS_KEY_REUSED);
+        assert_eq!(core.balance_manager.state.balances[user].available_balance, 1000);
+
+        // 500 reserved by a resting bid cannot be withdrawn
+        core.submit(InboundCommand::NewOrder(Order::new(30, 1, Side::Bid, 1, 10, 50, 1, 5)));
+        submit_admin(&mut core, funds(11, ADMIN_WITHDRAW, 30, 600));
+        assert_eq!(status_of(&mut sink), ADMIN_STATUS_INSUFFICIENT_FUNDS);
+        submit_admin(&mut core, funds(12, ADMIN_WITHDRAW, 30, 300));
+        assert_eq!(status_of(&mut sink), ADMIN_STATUS_OK);
+        submit_admin(&mut core, AdminCommand { reserved: 10, ..funds(13, ADMIN_TRANSFER, 30, 200) });
+        assert_eq!(status_of(&mut sink), ADMIN_STATUS_OK);
+        submit_admin(&mut core, AdminCommand { symbol: 5, asset: ASSET_SHARES, ..funds(14, ADMIN_DEPOSIT, 30, 7) });
+        assert_eq!(status_of(&mut sink), ADMIN_STATUS_OK);
+        // corrections are movements too , a debit cannot take what is not available
+        submit_admin(&mut core, funds(15, ADMIN_ADJUST, 30, 50));
+        assert_eq!(status_of(&mut sink), ADMIN_STATUS_OK);
+        submit_admin(&mut core, funds(16, ADMIN_ADJUST, 30, -60i64 as u64));
+        assert_eq!(status_of(&mut sink), ADMIN_STATUS_INSUFFICIENT_FUNDS);
+        submit_admin(&mut core, funds(17, ADMIN_ADJUST, 30, -20i64 as u64));
+        assert_eq!(status_of(&mut sink), ADMIN_STATUS_OK);
+        let balance = core.balance_manager.state.balances[user];
+        assert_eq!((balance.available_balance, balance.reserved_balance), (30, 500));
+        assert_eq!(core.balance_manager.state.holdings[user].available(5), 7);
+        assert!(core.balance_manager.check_conservation().is_empty());
+
+        let history: Vec<(u64, MovementKind, Asset, u64, u64)> = core.balance_manager.state.funds.history(30)
+            .map(|movement| (movement.key, movement.kind, movement.asset, movement.amount, movement.available_after))
+            .collect();
+        assert_eq!(history, vec![
+            (10, MovementKind::Deposit, Asset::Cash, 1000, 1000),
+            (12, MovementKind::Withdrawal, Asset::Cash, 300, 200),
+            (13, MovementKind::TransferOut, Asset::Cash, 200, 0),
+            (14, MovementKind::Deposit, Asset::Shares(5), 7, 7),
+            (15, MovementKind::AdjustmentCredit, Asset::Cash, 50, 50),
+            (17, MovementKind::AdjustmentDebit, Asset::Cash, 20, 30),
+        ]);
+        assert_eq!(core.balance_manager.state.funds.history(10).next().unwrap().kind, MovementKind::TransferIn);
+
+        // the keys survive a checkpoint , a late retry is still applied once
+        let dir = std::env::temp_dir().join(format!("admin_test_funds_checkpoint_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        core.checkpoint_dir = Some(dir.clone());
+        let checkpoint = core.checkpoint().unwrap().unwrap();
+        let (mut restored, mut restored_sink) = operator_core();
+        restore_checkpoint(&mut restored, &checkpoint).unwrap();
+        assert_eq!(restored.balance_manager.state.funds.movements(), core.balance_manager.state.funds.movements());
+        submit_admin(&mut restored, AdminCommand { reserved: 10, ..funds(13, ADMIN_TRANSFER, 30, 200) });
+        assert_eq!(status_of(&mut restored_sink), ADMIN_STATUS_OK);
+        assert_eq!(restored.state_digest(), core.state_digest());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    fn set_limit(request_id: u64, user_id: u64, limit: u8, value: u64) -> AdminCommand {
+        AdminCommand { user_id, available: value, limit, ..admin(request_id, ADMIN_SET_LIMIT, 0) }
+    }
+
+    // the error code of the order if it was rejected
+    fn reject_of(core: &mut TradingCore, sink: &mut ReplaySink, order: Order) -> Option<u32> {
+        core.submit(InboundCommand::NewOrder(order));
+        let code = std::iter::from_fn(|| sink.order_events_from_bm.try_pop()).find(|event| event.event_kind == 3).map(|event| event.error_code);
+        sink.drain();
+        code
+    }
+
+    #[test]
+    fn test_pre_trade_limits_reject_with_their_own_code_and_change_live() {
+        let (mut core, mut sink) = operator_core();
+        submit_admin(&mut core, admin(1, ADMIN_ADD_BOOK, 5));
+        // a trade at 10 gives the deviation check a last price
+        core.submit(InboundCommand::NewOrder(Order::new(20, 1, Side::Ask, 1, 1, 10, 1, 5)));
+        core.submit(InboundCommand::NewOrder(Order::new(10, 2, Side::Bid, 1, 1, 10, 2, 5)));
+        sink.drain();
+        let buyer = core.balance_manager.get_user_index(10).unwrap() as usize;
+        let held = core.balance_manager.state.holdings[buyer].available(5) as u64;
+        let bid = |order_id: u64, qty: u32, price: u64| Order::new(10, order_id, Side::Bid, 1, qty, price, order_id, 5);
+
+        submit_admin(&mut core, set_limit(2, 10, LIMIT_MAX_ORDER_QTY, 10));
+        assert_eq!(status_of(&mut sink), ADMIN_STATUS_OK);
+        assert_eq!(reject_of(&mut core, &mut sink, bid(3, 11, 10)), Some(REJECT_REASON_ORDER_QTY));
+        submit_admin(&mut core, set_limit(3, 10, LIMIT_MAX_ORDER_NOTIONAL, 100));
+        assert_eq!(reject_of(&mut core, &mut sink, bid(4, 10, 11)), Some(REJECT_REASON_ORDER_NOTIONAL));
+        submit_admin(&mut core, set_limit(4, 10, LIMIT_MAX_OPEN_ORDERS, 1));
+        assert_eq!(reject_of(&mut core, &mut sink, bid(5, 1, 9)), None);
+        assert_eq!(reject_of(&mut core, &mut sink, bid(6, 1, 9)), Some(REJECT_REASON_OPEN_ORDERS));
+        // 0 turns a limit back off , the others stay
+        submit_admin(&mut core, set_limit(5, 10, LIMIT_MAX_OPEN_ORDERS, 0));
+        submit_admin(&mut core, set_limit(6, 10, LIMIT_MAX_POSITION, held + 2));
+        assert_eq!(reject_of(&mut core, &mut sink, bid(7, 3, 9)), Some(REJECT_REASON_POSITION));
+        submit_admin(&mut core, set_limit(7, 10, LIMIT_MAX_POSITION, 0));
+        submit_admin(&mut core, set_limit(8, 10, LIMIT_MAX_DAILY_NOTIONAL, 50));
+        assert_eq!(reject_of(&mut core, &mut sink, bid(8, 2, 9)), None);
+        assert_eq!(reject_of(&mut core, &mut sink, bid(9, 2, 9)), None);
+        assert_eq!(reject_of(&mut core, &mut sink, bid(10, 2, 9)), None);
+        core.submit(InboundCommand::NewOrder(Order::new(20, 11, Side::Ask, 1, 2, 9, 11, 5)));
+        sink.drain();
+        // 10 traded at first , 18 more now , and 27 is over 50
+        assert_eq!(reject_of(&mut core, &mut sink, bid(12, 1, 27)), Some(REJECT_REASON_DAILY_NOTIONAL));
+        submit_admin(&mut core, set_limit(9, 10, LIMIT_MAX_DAILY_NOTIONAL, 0));
+        // the last trade was at 9 , 8 is more than 10% away from it
+        submit_admin(&mut core, set_limit(10, 10, LIMIT_MAX_PRICE_DEVIATION_BPS, 1000));
+        sink.drain();
+        assert_eq!(reject_of(&mut core, &mut sink, bid(13, 1, 8)), Some(REJECT_REASON_PRICE_DEVIATION));
+        assert_eq!(reject_of(&mut core, &mut sink, bid(14, 1, 9)), None);
+        // other users keep the defaults
+        assert_eq!(reject_of(&mut core, &mut sink, Order::new(20, 15, Side::Ask, 1, 50, 40, 15, 5)), None);
+
+        submit_admin(&mut core, set_limit(11, 10, 9, 1));
+        assert_eq!(status_of(&mut sink), ADMIN_STATUS_BAD_ARGUMENT);
+        submit_admin(&mut core, set_limit(12, 10, LIMIT_MAX_ORDER_QTY, u64::from(u32::MAX) + 1));
+        assert_eq!(status_of(&mut sink), ADMIN_STATUS_BAD_ARGUMENT);
+        submit_admin(&mut core, set_limit(13, 999, LIMIT_MAX_ORDER_QTY, 1));
+        assert_eq!(status_of(&mut sink), ADMIN_STATUS_USER_REJECTED);
+
+        // the live limits and last prices survive a checkpoint
+        let dir = std::env::temp_dir().join(format!("admin_test_limits_checkpoint_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        core.checkpoint_dir = Some(dir.clone());
+        let checkpoint = core.checkpoint().unwrap().unwrap();
+        let (mut restored, mut restored_sink) = operator_core();
+        restore_checkpoint(&mut restored, &checkpoint).unwrap();
+        assert_eq!(restored.balance_manager.limits_of(10), core.balance_manager.limits_of(10));
+        assert_eq!(restored.state_digest(), core.state_digest());
+        assert_eq!(reject_of(&mut restored, &mut restored_sink, bid(16, 1, 12)), Some(REJECT_REASON_PRICE_DEVIATION));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_daily_counters_roll_over_at_the_day_boundary() {
+        // a second before midnight utc , then a second after
+        let before_midnight = 20_000 * NANOS_PER_DAY + NANOS_PER_DAY - 1_000_000_000;
+        let after_midnight = before_midnight + 2_000_000_000;
+        let (mut core, mut sink) = operator_core();
+        let mut sequence = 0;
+        let mut apply_at = |core: &mut TradingCore, timestamp: u64, command: InboundCommand| {
+            sequence += 1;
+            core.apply_record(&JournalRecord::at(sequence, timestamp, command));
+            sequence
+        };
+        let operators = AdminPlane::new([OPERATOR]);
+        let rejected = |sink: &mut ReplaySink| {
+            let code = std::iter::from_fn(|| sink.order_events_from_bm.try_pop()).find(|event| event.event_kind == 3).map(|event| event.error_code);
+            sink.drain();
+            code
+        };
+        let bid = |order_id: u64| InboundCommand::NewOrder(Order::new(10, order_id, Side::Bid, 1, 1, 10, order_id, 5));
+
+        apply_at(&mut core, before_midnight, InboundCommand::Admin(operators.authorize(admin(1, ADMIN_ADD_BOOK, 5))));
+        apply_at(&mut core, before_midnight, InboundCommand::Admin(operators.authorize(set_limit(2, 10, LIMIT_MAX_DAILY_NOTIONAL, 50))));
+        apply_at(&mut core, before_midnight, InboundCommand::NewOrder(Order::new(20, 1, Side::Ask, 1, 10, 10, 1, 5)));
+        apply_at(&mut core, before_midnight, InboundCommand::NewOrder(Order::new(10, 2, Side::Bid, 1, 5, 10, 2, 5)));
+        sink.drain();
+        let buyer = core.balance_manager.get_user_index(10).unwrap() as usize;
+        assert_eq!(core.balance_manager.state.trading_day, 20_000);
+        assert_eq!(core.balance_manager.state.balances[buyer].total_traded_today, 50);
+        apply_at(&mut core, before_midnight, bid(3));
+        assert_eq!(rejected(&mut sink), Some(REJECT_REASON_DAILY_NOTIONAL));
+
+        // the day is checkpointed with the counters
+        let dir = std::env::temp_dir().join(format!("admin_test_trading_day_checkpoint_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        core.checkpoint_dir = Some(dir.clone());
+        let checkpoint = core.checkpoint().unwrap().unwrap();
+        let (mut restored, mut restored_sink) = operator_core();
+        restore_checkpoint(&mut restored, &checkpoint).unwrap();
+        assert_eq!(restored.state_digest(), core.state_digest());
+
+        // the first command of the next day starts it over , live and restored alike
+        let next = apply_at(&mut core, after_midnight, bid(4));
+        assert_eq!(rejected(&mut sink), None);
+        restored.apply_record(&JournalRecord::at(next, after_midnight, bid(4)));
+        assert_eq!(rejected(&mut restored_sink), None);
+        assert_eq!(restored.state_digest(), core.state_digest());
+        let balance = core.balance_manager.state.balances[buyer];
+        assert_eq!((core.balance_manager.state.trading_day, balance.total_traded_today, balance.order_count_today), (20_001, 10, 1));
+
+        // a clock that went backwards stays in the new day
+        apply_at(&mut core, before_midnight, bid(5));
+        assert_eq!(core.balance_manager.state.trading_day, 20_001);
+        assert_eq!(core.balance_manager.state.balances[buyer].order_count_today, 2);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_kill_switches_cancel_reject_block_withdrawals_and_survive_a_restart() {
+        let (mut core, mut sink) = operator_core();
+        submit_admin(&mut core, admin(1, ADMIN_ADD_BOOK, 5));
+        core.submit(InboundCommand::NewOrder(Order::new(10, 1, Side::Bid, 1, 2, 9, 1, 5)));
+        core.submit(InboundCommand::NewOrder(Order::new(10, 2, Side::Bid, 1, 3, 8, 2, 5)));
+        core.submit(InboundCommand::NewOrder(Order::new(20, 3, Side::Ask, 1, 1, 20, 3, 5)));
+        sink.drain();
+        let user = core.balance_manager.get_user_index(10).unwrap() as usize;
+
+        // freezing cancels every resting order of the user and gives its funds back
+        submit_admin(&mut core, AdminCommand { user_id: 10, ..admin(2, ADMIN_FREEZE_USER, 0) });
+        let canceled: Vec<(u64, u32)> = std::iter::from_fn(|| sink.order_events_from_engine.try_pop())
+            .filter(|event| event.event_kind == 4)
+            .map(|event| (event.order_id, event.error_code))
+            .collect();
+        assert_eq!(canceled, vec![(1, CANCEL_REASON_FROZEN), (2, CANCEL_REASON_FROZEN)]);
+        assert_eq!(status_of(&mut sink), ADMIN_STATUS_OK);
+        assert_eq!(core.balance_manager.state.balances[user].reserved_balance, 0);
+        assert_eq!(core.engine.get_book(5).unwrap().manager.id_to_index.len(), 1);
+        assert_eq!(reject_of(&mut core, &mut sink, Order::new(10, 4, Side::Bid, 1, 1, 9, 4, 5)), Some(REJECT_REASON_USER_FROZEN));
+        // money can come in , nothing goes out
+        submit_admin(&mut core, funds(3, ADMIN_WITHDRAW, 10, 1));
+        assert_eq!(status_of(&mut sink), ADMIN_STATUS_USER_FROZEN);
+        submit_admin(&mut core, AdminCommand { reserved: 20, ..funds(4, ADMIN_TRANSFER, 10, 1) });
+        assert_eq!(status_of(&mut sink), ADMIN_STATUS_USER_FROZEN);
+        submit_admin(&mut core, funds(5, ADMIN_DEPOSIT, 10, 1));
+        assert_eq!(status_of(&mut sink), ADMIN_STATUS_OK);
+        submit_admin(&mut core, AdminCommand { user_id: 999, ..admin(6, ADMIN_FREEZE_USER, 0) });
+        assert_eq!(status_of(&mut sink), ADMIN_STATUS_USER_REJECTED);
+
+        // the venue wide stop rejects everyone's new orders , cancels still go through
+        submit_admin(&mut core, admin(7, ADMIN_STOP_TRADING, 0));
+        sink.drain();
+        assert_eq!(reject_of(&mut core, &mut sink, Order::new(20, 5, Side::Ask, 1, 1, 21, 5, 5)), Some(REJECT_REASON_TRADING_STOPPED));
+        assert_eq!(reject_of(&mut core, &mut sink, Order::new(0, 6, Side::Ask, 1, 1, 21, 6, 5)), Some(REJECT_REASON_TRADING_STOPPED));
+        core.submit(InboundCommand::CancelOrder(OrderToBeCanceled { order_id: 3, user_id: 20, symbol: 5 }));
+        assert_eq!(core.engine.get_book(5).unwrap().manager.id_to_index.len(), 0);
+
+        // both switches are in the checkpoint
+        let dir = std::env::temp_dir().join(format!("admin_test_kill_switch_checkpoint_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        core.checkpoint_dir = Some(dir.clone());
+        let checkpoint = core.checkpoint().unwrap().unwrap();
+        let (mut restored, mut restored_sink) = operator_core();
+        restore_checkpoint(&mut restored, &checkpoint).unwrap();
+        assert!(restored.engine.trading_stopped && restored.balance_manager.is_frozen(10));
+        assert_eq!(restored.state_digest(), core.state_digest());
+        submit_admin(&mut restored, admin(8, ADMIN_RESUME_TRADING, 0));
+        assert_eq!(reject_of(&mut restored, &mut restored_sink, Order::new(10, 7, Side::Bid, 1, 1, 9, 7, 5)), Some(REJECT_REASON_USER_FROZEN));
+        submit_admin(&mut restored, AdminCommand { user_id: 10, ..admin(9, ADMIN_UNFREEZE_USER, 0) });
+        assert_eq!(reject_of(&mut restored, &mut restored_sink, Order::new(10, 8, Side::Bid, 1, 1, 9, 8, 5)), None);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_books_that_do_not_add_up_after_an_admin_command_stop_trading() {
+        let (mut core, mut sink) = operator_core();
+        submit_admin(&mut core, admin(1, ADMIN_ADD_BOOK, 5));
+        sink.drain();
+        assert!(!core.engine.trading_stopped);
+
+        // a balance changed outside an entry is found by the next admin command whatever it was
+        let user = core.balance_manager.get_user_index(10).unwrap() as usize;
+        core.balance_manager.state.balances[user].available_balance += 7;
+        submit_admin(&mut core, admin(2, ADMIN_DUMP_STATS, 0));
+        assert_eq!(status_of(&mut sink), ADMIN_STATUS_OK);
+        assert!(core.engine.trading_stopped);
+        assert_eq!(reject_of(&mut core, &mut sink, Order::new(20, 1, Side::Ask, 1, 1, 20, 1, 5)), Some(REJECT_REASON_TRADING_STOPPED));
+    }
+}
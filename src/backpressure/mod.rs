@@ -0,0 +1,3 @@
+pub mod policy;
+pub mod sender;
+pub mod tests;
@@ -0,0 +1,116 @@
+// what a producer does when its queue is full , chosen per channel
+// defaults keep the old behaviour of each call site (try_push became drop newest , push became block)
+// but every drop is now counted and alarmed instead of vanishing
+use std::collections::HashMap;
+use std::str::FromStr;
+
+#[derive(Debug , Clone , Copy , PartialEq , Eq)]
+pub enum BackpressurePolicy{
+    // wait for room , sleeping between attempts so the consumer gets the core
+    Block,
+    // busy spin for `spins` attempts first , then wait like Block
+    SpinThenBlock { spins : u32 },
+    // park the message locally and drop the oldest parked one when the local buffer is full too
+    DropOldest,
+    // drop the message that did not fit
+    DropNewest,
+    // drop it and hand the error to the caller , the core and publisher stop on it
+    // the shm writer and logger only count it , their consumers are other processes
+    FailFast,
+}
+
+pub const DEFAULT_SPINS : u32 = 1000;
+
+impl FromStr for BackpressurePolicy{
+    type Err = String;
+    fn from_str(s : &str)->Result<Self , Self::Err>{
+        match s.trim() {
+            "block" => Ok(Self::Block),
+            "spin_then_block" => Ok(Self::SpinThenBlock { spins : DEFAULT_SPINS }),
+            "drop_oldest" => Ok(Self::DropOldest),
+            "drop_newest" => Ok(Self::DropNewest),
+            "fail_fast" => Ok(Self::FailFast),
+            other => match other.strip_prefix("spin_then_block:") {
+                Some(spins) => spins.parse().map(|spins| Self::SpinThenBlock { spins }).map_err(|_| format!("bad spin count in {:?}", other)),
+                None => Err(format!("unknown backpressure policy {:?}", other)),
+            },
+        }
+    }
+}
+
+// every channel that goes through a PolicySender , the names are what the config refers to
+pub mod channels{
+    pub const CORE_LOGS : &str = "core.logs";
+    pub const CORE_SNAPSHOTS : &str = "core.snapshots";
+    pub const CORE_MM_FEED : &str = "core.mm_feed";
+    // the single threaded core and the sharded risk thread both answer admin commands on this one
+    pub const CORE_ADMIN_RESPONSES : &str = "core.admin_responses";
+    pub const ENGINE_EVENTS : &str = "engine.events";
+    pub const ENGINE_ORDER_EVENTS : &str = "engine.order_events";
+    // only the sharded engines send these , the single threaded core uses the core.* ones
+    pub const ENGINE_MM_FEED : &str = "engine.mm_feed";
+    pub const ENGINE_SNAPSHOTS : &str = "engine.snapshots";
+    pub const PUBLISHER_TRADE_LOGS : &str = "publisher.trade_logs";
+    pub const PUBLISHER_ORDER_EVENTS : &str = "publisher.order_events";
+    pub const PUBLISHER_MM_FILLS : &str = "publisher.mm_fills";
+    pub const WRITER_ORDER_EVENTS : &str = "writer.order_events";
+    pub const WRITER_BALANCE_RESPONSES : &str = "writer.balance_responses";
+    pub const WRITER_HOLDING_RESPONSES : &str = "writer.holding_responses";
+    pub const WRITER_MM_FILLS : &str = "writer.mm_fills";
+    pub const WRITER_MM_FEED : &str = "writer.mm_feed";
+    pub const WRITER_ADMIN_RESPONSES : &str = "writer.admin_responses";
+    pub const LOGGER_ORDER_LOGS : &str = "logger.order_logs";
+    pub const LOGGER_BALANCE_LOGS : &str = "logger.balance_logs";
+    pub const LOGGER_HOLDING_LOGS : &str = "logger.holding_logs";
+    pub const LOGGER_TRADE_LOGS : &str = "logger.trade_logs";
+    pub const LOGGER_SNAPSHOTS : &str = "logger.snapshots";
+    pub const LOGGER_POSITIONS : &str = "logger.positions";
+}
+
+fn default_policy(channel : &str)->BackpressurePolicy{
+    use channels::*;
+    match channel {
+        // user facing results and the market maker's fills cant be lost
+        PUBLISHER_ORDER_EVENTS | PUBLISHER_MM_FILLS => BackpressurePolicy::Block,
+        // an operator waits on every response , and there are few of them
+        CORE_ADMIN_RESPONSES | WRITER_ADMIN_RESPONSES => BackpressurePolicy::Block,
+        WRITER_ORDER_EVENTS | WRITER_BALANCE_RESPONSES | WRITER_HOLDING_RESPONSES | WRITER_MM_FILLS => BackpressurePolicy::SpinThenBlock { spins : DEFAULT_SPINS },
+        // a stale feed is worse than a gap , keep the latest
+        CORE_MM_FEED | ENGINE_MM_FEED | WRITER_MM_FEED => BackpressurePolicy::DropOldest,
+        _ => BackpressurePolicy::DropNewest,
+    }
+}
+
+#[derive(Debug , Clone , Default)]
+pub struct BackpressureConfig{
+    pub overrides : HashMap<String , BackpressurePolicy>,
+}
+
+impl BackpressureConfig{
+    pub fn policy_for(&self , channel : &str)->BackpressurePolicy{
+        self.overrides.get(channel).copied().unwrap_or_else(|| default_policy(channel))
+    }
+
+    pub fn with_override(mut self , channel : &str , policy : BackpressurePolicy)->Self{
+        self.overrides.insert(channel.to_string(), policy);
+        self
+    }
+
+    /// reads `channel=policy` pairs separated by commas , e.g. BACKPRESSURE="core.logs=block,writer.mm_feed=drop_newest"
+    pub fn parse_overrides(&mut self , spec : &str)->Result<() , String>{
+        for pair in spec.split(',').map(str::trim).filter(|pair| !pair.is_empty()) {
+            let (channel , policy) = pair.split_once('=').ok_or_else(|| format!("expected channel=policy , got {:?}", pair))?;
+            self.overrides.insert(channel.trim().to_string(), policy.parse()?);
+        }
+        Ok(())
+    }
+
+    /// defaults plus whatever the BACKPRESSURE env var overrides
+    pub fn from_env()->Result<Self , String>{
+        let mut config = Self::default();
+        if let Ok(spec) = std::env::var("BACKPRESSURE") {
+            config.parse_overrides(&spec)?;
+        }
+        Ok(config)
+    }
+}
@@ -0,0 +1,303 @@
+// a producer side wrapper that applies a BackpressurePolicy when the queue underneath is full
+// works over the in process spsc queues and over the shared memory queues through TrySend
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use bounded_spsc_queue::Producer;
+use thiserror::Error;
+use crate::backpressure::policy::{BackpressureConfig, BackpressurePolicy};
+use crate::logger::types::{BalanceLogWrapper, HoldingLogWrapper, OrderBookSnapShot, OrderLogWrapper, PositionReport, TradeLogs};
+use crate::shm::admin_response_queue::{AdminResponse, AdminResponseQueue};
+use crate::shm::balance_log_queue::BalanceLogQueue;
+use crate::shm::balance_response_queue::{BalanceResQueue, BalanceResponse};
+use crate::shm::event_queue::{OrderEventQueue, OrderEvents};
+use crate::shm::fill_queue_mm::{MarketMakerFill, MarketMakerFillQueue};
+use crate::shm::holdings_log_queue::HoldingLogQueue;
+use crate::shm::holdings_response_queue::{HoldingResQueue, HoldingResponse};
+use crate::shm::market_maker_feed::{MarketMakerFeed, MarketMakerFeedQueue};
+use crate::shm::order_log_queue::OrderLogQueue;
+use crate::shm::position_queue::PositionQueue;
+use crate::shm::snapshot_queue::OrderBookSnapShotQueue;
+use crate::shm::trade_log_queue::TradeLogQueue;
+
+// pause between attempts once a blocking policy stops spinning
+const BLOCK_PAUSE : Duration = Duration::from_micros(20);
+// messages a DropOldest channel parks locally before it starts dropping
+pub const DROP_OLDEST_BUFFER : usize = 4096;
+// the queue depth is read every this many sends , reading it touches the consumer's cache line
+pub const DEPTH_SAMPLE_EVERY : u64 = 256;
+
+/// non blocking send that hands the message back when there is no room
+pub trait TrySend<T>{
+    fn try_send(&mut self , item : T)->Result<() , T>;
+    // messages waiting in the queue
+    fn depth(&self)->u64;
+}
+
+impl<T> TrySend<T> for Producer<T>{
+    #[inline(always)]
+    fn try_send(&mut self , item : T)->Result<() , T>{
+        match self.try_push(item) {
+            None => Ok(()),
+            Some(item) => Err(item),
+        }
+    }
+    fn depth(&self)->u64{
+        self.size() as u64
+    }
+}
+
+// the shm queues only fail an enqueue when they are full
+macro_rules! shm_try_send {
+    ($queue : ty , $item : ty) => {
+        impl TrySend<$item> for $queue{
+            #[inline(always)]
+            fn try_send(&mut self , item : $item)->Result<() , $item>{
+                self.enqueue(item).map_err(|_| item)
+            }
+            fn depth(&self)->u64{
+                <$queue>::depth(self)
+            }
+        }
+    };
+}
+
+shm_try_send!(OrderEventQueue , OrderEvents);
+shm_try_send!(BalanceResQueue , BalanceResponse);
+shm_try_send!(HoldingResQueue , HoldingResponse);
+shm_try_send!(MarketMakerFillQueue , MarketMakerFill);
+shm_try_send!(MarketMakerFeedQueue , MarketMakerFeed);
+shm_try_send!(OrderLogQueue , OrderLogWrapper);
+shm_try_send!(BalanceLogQueue , BalanceLogWrapper);
+shm_try_send!(HoldingLogQueue , HoldingLogWrapper);
+shm_try_send!(TradeLogQueue , TradeLogs);
+shm_try_send!(OrderBookSnapShotQueue , OrderBookSnapShot);
+shm_try_send!(PositionQueue , PositionReport);
+shm_try_send!(AdminResponseQueue , AdminResponse);
+
+#[derive(Debug , Error , PartialEq , Eq)]
+pub enum BackpressureError{
+    #[error("channel {channel} is full")]
+    Full { channel : &'static str },
+}
+
+// counters for one channel , shared with whoever reports on them
+#[derive(Debug)]
+pub struct ChannelStats{
+    pub name : &'static str,
+    pub policy : BackpressurePolicy,
+    sent : AtomicU64,
+    dropped : AtomicU64,
+    full : AtomicU64,
+    depth : AtomicU64,
+    alarm : AtomicBool,
+}
+
+impl ChannelStats{
+    fn new(name : &'static str , policy : BackpressurePolicy)->Self{
+        Self { name , policy , sent : AtomicU64::new(0) , dropped : AtomicU64::new(0) , full : AtomicU64::new(0) , depth : AtomicU64::new(0) , alarm : AtomicBool::new(false) }
+    }
+    pub fn sent(&self)->u64{
+        self.sent.load(Ordering::Relaxed)
+    }
+    pub fn dropped(&self)->u64{
+        self.dropped.load(Ordering::Relaxed)
+    }
+    // how many sends found the queue full , whatever the policy did about it
+    pub fn full(&self)->u64{
+        self.full.load(Ordering::Relaxed)
+    }
+    // queue depth as last sampled by the producer
+    pub fn depth(&self)->u64{
+        self.depth.load(Ordering::Relaxed)
+    }
+    // raised on the first drop and stays up
+    pub fn alarm(&self)->bool{
+        self.alarm.load(Ordering::Relaxed)
+    }
+}
+
+fn registry()->&'static Mutex<Vec<Arc<ChannelStats>>>{
+    static CHANNELS : OnceLock<Mutex<Vec<Arc<ChannelStats>>>> = OnceLock::new();
+    CHANNELS.get_or_init(|| Mutex::new(Vec::new()))
+}
+
+/// stats of every channel created in this process
+pub fn all_channel_stats()->Vec<Arc<ChannelStats>>{
+    registry().lock().unwrap().clone()
+}
+
+/// for callers that cannot go on without the message , a full fail fast channel stops the thread
+#[inline(always)]
+pub fn escalate(result : Result<() , BackpressureError>){
+    if let Err(e) = result {
+        panic!("[Backpressure] fail fast: {}", e);
+    }
+}
+
+pub type PolicyProducer<T> = PolicySender<T , Producer<T>>;
+
+pub struct PolicySender<T , S : TrySend<T>>{
+    inner : S,
+    policy : BackpressurePolicy,
+    stats : Arc<ChannelStats>,
+    // DropOldest only , messages waiting for room , oldest at the front
+    parked : VecDeque<T>,
+}
+
+impl<T , S : TrySend<T>> PolicySender<T , S>{
+    pub fn new(inner : S , name : &'static str , policy : BackpressurePolicy)->Self{
+        let stats = Arc::new(ChannelStats::new(name, policy));
+        registry().lock().unwrap().push(stats.clone());
+        Self { inner , policy , stats , parked : VecDeque::new() }
+    }
+
+    pub fn from_config(inner : S , name : &'static str , config : &BackpressureConfig)->Self{
+        Self::new(inner, name, config.policy_for(name))
+    }
+
+    pub fn stats(&self)->&Arc<ChannelStats>{
+        &self.stats
+    }
+
+    pub fn inner(&self)->&S{
+        &self.inner
+    }
+
+    /// swaps the destination for a freshly opened one , the stats and parked messages stay with the channel
+    pub fn replace_inner(&mut self , inner : S)->S{
+        std::mem::replace(&mut self.inner, inner)
+    }
+
+    pub fn parked(&self)->usize{
+        self.parked.len()
+    }
+
+    /// sends according to the channel's policy , only FailFast returns an error
+    #[inline]
+    pub fn send(&mut self , item : T)->Result<() , BackpressureError>{
+        if !self.parked.is_empty() {
+            self.flush();
+            if !self.parked.is_empty() {
+                // order is kept , the new message queues up behind the parked ones
+                self.full_hit();
+                self.park(item);
+                return Ok(());
+            }
+        }
+        let item = match self.inner.try_send(item) {
+            Ok(()) => {
+                if self.stats.sent.fetch_add(1, Ordering::Relaxed).is_multiple_of(DEPTH_SAMPLE_EVERY) {
+                    self.sample_depth();
+                }
+                return Ok(());
+            }
+            Err(item) => item,
+        };
+        self.full_hit();
+        match self.policy {
+            BackpressurePolicy::Block => {
+                self.wait_until_sent(item, 0);
+                Ok(())
+            }
+            BackpressurePolicy::SpinThenBlock { spins } => {
+                self.wait_until_sent(item, spins);
+                Ok(())
+            }
+            BackpressurePolicy::DropOldest => {
+                self.park(item);
+                Ok(())
+            }
+            BackpressurePolicy::DropNewest => {
+                self.record_drop();
+                Ok(())
+            }
+            BackpressurePolicy::FailFast => {
+                self.record_drop();
+                Err(BackpressureError::Full { channel : self.stats.name })
+            }
+        }
+    }
+
+    /// waits for room whatever the policy , for the messages that have to get through (final snapshot on shutdown)
+    pub fn send_blocking(&mut self , item : T){
+        self.flush_blocking();
+        match self.inner.try_send(item) {
+            Ok(()) => {
+                self.stats.sent.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(item) => {
+                self.full_hit();
+                self.wait_until_sent(item, 0);
+            }
+        }
+    }
+
+    /// moves parked messages into the queue while there is room
+    pub fn flush(&mut self){
+        while let Some(item) = self.parked.pop_front() {
+            if let Err(item) = self.inner.try_send(item) {
+                self.parked.push_front(item);
+                return;
+            }
+            self.stats.sent.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// delivers every parked message , used when draining on shutdown
+    pub fn flush_blocking(&mut self){
+        while let Some(item) = self.parked.pop_front() {
+            self.wait_until_sent(item, 0);
+        }
+    }
+
+    fn wait_until_sent(&mut self , mut item : T , spins : u32){
+        let started = Instant::now();
+        let mut attempts = 0u32;
+        loop {
+            match self.inner.try_send(item) {
+                Ok(()) => break,
+                Err(back) => item = back,
+            }
+            if attempts < spins {
+                attempts += 1;
+                std::hint::spin_loop();
+            } else {
+                std::thread::sleep(BLOCK_PAUSE);
+            }
+        }
+        self.stats.sent.fetch_add(1, Ordering::Relaxed);
+        let waited = started.elapsed();
+        if waited >= Duration::from_millis(10) {
+            eprintln!("[Backpressure] {} blocked for {:?} waiting for its consumer", self.stats.name, waited);
+        }
+    }
+
+    fn park(&mut self , item : T){
+        self.parked.push_back(item);
+        if self.parked.len() > DROP_OLDEST_BUFFER {
+            self.parked.pop_front();
+            self.record_drop();
+        }
+    }
+
+    #[inline(always)]
+    fn full_hit(&self){
+        self.stats.full.fetch_add(1, Ordering::Relaxed);
+        self.sample_depth();
+    }
+
+    fn sample_depth(&self){
+        self.stats.depth.store(self.inner.depth(), Ordering::Relaxed);
+    }
+
+    fn record_drop(&self){
+        let dropped = self.stats.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+        self.stats.alarm.store(true, Ordering::Relaxed);
+        // first drop and then every power of two , loud enough to notice without flooding stderr
+        if dropped.is_power_of_two() {
+            eprintln!("[Backpressure] ALARM {} is full , {} messages dropped so far ({:?})", self.stats.name, dropped, self.policy);
+        }
+    }
+}
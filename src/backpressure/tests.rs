@@ -0,0 +1,94 @@
+#[cfg(test)]
+mod tests {
+    use crate::backpressure::policy::{channels, BackpressureConfig, BackpressurePolicy};
+    use crate::backpressure::sender::{BackpressureError, PolicySender, DROP_OLDEST_BUFFER};
+
+    #[test]
+    fn test_drop_newest_counts_and_alarms() {
+        let (producer, consumer) = bounded_spsc_queue::make::<u32>(4);
+        let mut sender = PolicySender::new(producer, "test.drop_newest", BackpressurePolicy::DropNewest);
+        for i in 0..10 {
+            assert!(sender.send(i).is_ok());
+        }
+        let capacity = consumer.capacity() as u64;
+        assert_eq!(sender.stats().sent(), capacity);
+        assert_eq!(sender.stats().dropped(), 10 - capacity);
+        assert!(sender.stats().alarm());
+        // the first ones made it , the rest were dropped
+        assert_eq!(consumer.try_pop(), Some(0));
+    }
+
+    #[test]
+    fn test_fail_fast_returns_error() {
+        let (producer, consumer) = bounded_spsc_queue::make::<u32>(2);
+        let mut sender = PolicySender::new(producer, "test.fail_fast", BackpressurePolicy::FailFast);
+        for i in 0..consumer.capacity() as u32 {
+            assert!(sender.send(i).is_ok());
+        }
+        assert_eq!(sender.send(99), Err(BackpressureError::Full { channel: "test.fail_fast" }));
+        assert_eq!(sender.stats().dropped(), 1);
+    }
+
+    #[test]
+    fn test_drop_oldest_keeps_the_latest_messages() {
+        let (producer, consumer) = bounded_spsc_queue::make::<u32>(2);
+        let capacity = consumer.capacity() as u32;
+        let mut sender = PolicySender::new(producer, "test.drop_oldest", BackpressurePolicy::DropOldest);
+        let total = capacity + DROP_OLDEST_BUFFER as u32 + 5;
+        for i in 0..total {
+            sender.send(i).unwrap();
+        }
+        assert_eq!(sender.stats().dropped(), 5);
+        assert_eq!(sender.parked(), DROP_OLDEST_BUFFER);
+
+        let mut received = Vec::new();
+        loop {
+            while let Some(value) = consumer.try_pop() {
+                received.push(value);
+            }
+            if sender.parked() == 0 {
+                break;
+            }
+            sender.flush();
+        }
+        // what was already in the queue , then the newest parked ones in order
+        assert_eq!(&received[..capacity as usize], &(0..capacity).collect::<Vec<_>>()[..]);
+        assert_eq!(*received.last().unwrap(), total - 1);
+        assert_eq!(received.len(), capacity as usize + DROP_OLDEST_BUFFER);
+        assert!(received.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn test_block_waits_for_the_consumer() {
+        let (producer, consumer) = bounded_spsc_queue::make::<u64>(8);
+        let mut sender = PolicySender::new(producer, "test.block", BackpressurePolicy::SpinThenBlock { spins: 10 });
+        let consumer_thread = std::thread::spawn(move || {
+            let mut sum = 0u64;
+            for _ in 0..10_000 {
+                loop {
+                    if let Some(value) = consumer.try_pop() {
+                        sum += value;
+                        break;
+                    }
+                    std::hint::spin_loop();
+                }
+            }
+            sum
+        });
+        for i in 0..10_000u64 {
+            sender.send(i).unwrap();
+        }
+        assert_eq!(consumer_thread.join().unwrap(), (0..10_000u64).sum::<u64>());
+        assert_eq!(sender.stats().dropped(), 0);
+    }
+
+    #[test]
+    fn test_config_overrides() {
+        let mut config = BackpressureConfig::default();
+        assert_eq!(config.policy_for(channels::PUBLISHER_ORDER_EVENTS), BackpressurePolicy::Block);
+        config.parse_overrides("publisher.order_events=fail_fast , core.logs=spin_then_block:50").unwrap();
+        assert_eq!(config.policy_for(channels::PUBLISHER_ORDER_EVENTS), BackpressurePolicy::FailFast);
+        assert_eq!(config.policy_for(channels::CORE_LOGS), BackpressurePolicy::SpinThenBlock { spins: 50 });
+        assert!(config.parse_overrides("core.logs=sometimes").is_err());
+    }
+}
@@ -0,0 +1,45 @@
+// traded notional per user and quote asset over the last few trading days , what fee tiers are picked from
+// each asset counts in its own units , a fill quoted in one never moves a user's tier on a symbol quoted in another
+// days are the trading days of the command clock , so a replay lands every fill in the same day
+use std::collections::VecDeque;
+
+#[derive(Debug , Clone , Default , PartialEq , Eq)]
+pub struct VolumeWindow{
+    // (trading day , notional traded that day) , oldest first and one entry per day
+    days : VecDeque<(u64 , u64)>,
+}
+
+impl VolumeWindow{
+    /// adds `notional` to `day` , days that fell out of a `window` days long window are dropped
+    pub fn record(&mut self , day : u64 , notional : u64 , window : u64){
+        match self.days.back_mut() {
+            Some((last , total)) if *last == day => *total = total.saturating_add(notional),
+            _ => self.days.push_back((day , notional)),
+        }
+        self.expire(day, window);
+    }
+
+    /// drops the days before the window ending at `day` , true once nothing is left in it
+    pub fn expire(&mut self , day : u64 , window : u64)->bool{
+        while let Some((first , _)) = self.days.front() && first + window <= day {
+            self.days.pop_front();
+        }
+        self.days.is_empty()
+    }
+
+    /// notional traded in the `window` days ending at `day` , today included
+    pub fn volume(&self , day : u64 , window : u64)->u64{
+        self.days.iter().filter(|(traded , _)| traded + window > day).fold(0u64, |acc , (_ , notional)| acc.saturating_add(*notional))
+    }
+
+    pub fn days(&self)->impl Iterator<Item = (u64 , u64)> + '_{
+        self.days.iter().copied()
+    }
+}
+
+// rebuilt from checkpointed days , oldest first
+impl FromIterator<(u64 , u64)> for VolumeWindow{
+    fn from_iter<I : IntoIterator<Item = (u64 , u64)>>(days : I)->Self{
+        Self { days : days.into_iter().collect() }
+    }
+}
@@ -0,0 +1,202 @@
+// deposits , withdrawals , transfers and operator adjustments , the only way cash and shares enter , leave or move between users
+// every applied movement is kept in order with what the user had available after it , so a user's history reads straight off it
+// a request carries an idempotency key (the admin request id) , a retry with the same key is applied once
+// the ledger is part of the balance state : checkpointed , hashed and rebuilt by replay like everything else
+use rustc_hash::FxHashMap;
+use thiserror::Error;
+use crate::digest::state_hasher::StateHasher;
+use crate::shm::admin_command_queue::{ASSET_CASH, ASSET_COIN, ASSET_SHARES};
+
+#[derive(Debug , Clone , Copy , PartialEq , Eq , Hash)]
+pub enum Asset{
+    Cash,
+    Shares(u32),
+    // a wallet asset by id , the base or quote of a listed pair
+    Coin(u32),
+}
+
+impl Asset{
+    /// the asset an instrument pair names , id 0 is the cash balance
+    pub fn from_id(id : u32)->Self{
+        match id {
+            0 => Self::Cash,
+            id => Self::Coin(id),
+        }
+    }
+    /// the kind and id an asset travels as in admin commands and checkpoints
+    pub fn code(self)->(u8 , u32){
+        match self {
+            Self::Cash => (ASSET_CASH , 0),
+            Self::Shares(symbol) => (ASSET_SHARES , symbol),
+            Self::Coin(id) => (ASSET_COIN , id),
+        }
+    }
+    pub fn from_code(kind : u8 , id : u32)->Option<Self>{
+        match (kind , id) {
+            (ASSET_CASH , _) => Some(Self::Cash),
+            (ASSET_SHARES , symbol) => Some(Self::Shares(symbol)),
+            (ASSET_COIN , 0) => None,
+            (ASSET_COIN , id) => Some(Self::Coin(id)),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug , Clone , Copy , PartialEq , Eq)]
+pub enum MovementKind{
+    Deposit,
+    Withdrawal,
+    TransferIn,
+    TransferOut,
+    // an operator correction , what it added to or took from the user
+    AdjustmentCredit,
+    AdjustmentDebit,
+}
+
+impl MovementKind{
+    pub fn as_u8(self)->u8{
+        match self {
+            Self::Deposit => 0,
+            Self::Withdrawal => 1,
+            Self::TransferIn => 2,
+            Self::TransferOut => 3,
+            Self::AdjustmentCredit => 4,
+            Self::AdjustmentDebit => 5,
+        }
+    }
+    pub fn from_u8(kind : u8)->Option<Self>{
+        Some(match kind {
+            0 => Self::Deposit,
+            1 => Self::Withdrawal,
+            2 => Self::TransferIn,
+            3 => Self::TransferOut,
+            4 => Self::AdjustmentCredit,
+            5 => Self::AdjustmentDebit,
+            _ => return None,
+        })
+    }
+}
+
+// one side of a request as it hit one user
+#[derive(Debug , Clone , Copy , PartialEq , Eq)]
+pub struct FundsMovement{
+    pub key : u64,
+    pub user_id : u64,
+    pub kind : MovementKind,
+    pub asset : Asset,
+    pub amount : u64,
+    // available cash , or available shares of the asset , right after the movement
+    pub available_after : u64,
+}
+
+// what a request asked for , a retry with the same key has to ask for the same thing
+#[derive(Debug , Clone , Copy , PartialEq , Eq)]
+pub enum FundsRequest{
+    Deposit { user_id : u64 , asset : Asset , amount : u64 },
+    Withdraw { user_id : u64 , asset : Asset , amount : u64 },
+    Transfer { from : u64 , to : u64 , asset : Asset , amount : u64 },
+    // below zero takes from the user , against the system account either way
+    Adjust { user_id : u64 , asset : Asset , delta : i64 },
+}
+
+#[derive(Debug , Clone , Copy , PartialEq , Eq)]
+pub enum FundsOutcome{
+    Applied,
+    // the key was seen before with the same request , nothing changed
+    Duplicate,
+}
+
+#[derive(Debug , Clone , Copy , PartialEq , Eq , Error)]
+pub enum FundsError{
+    #[error("amount must be above zero")]
+    ZeroAmount,
+    #[error("user {0} not found")]
+    UnknownUser(u64),
+    #[error("user {user_id} has {available} available , {requested} requested")]
+    InsufficientAvailable { user_id : u64 , available : u64 , requested : u64 },
+    #[error("user {0} cannot transfer to itself")]
+    SelfTransfer(u64),
+    #[error("key {0} was already used for a different request")]
+    KeyReused(u64),
+    #[error("the holding of user {0} would overflow")]
+    Overflow(u64),
+    #[error("user {0} is frozen")]
+    Frozen(u64),
+}
+
+#[derive(Debug , Clone , Default)]
+pub struct FundsLedger{
+    movements : Vec<FundsMovement>,
+    requests : FxHashMap<u64 , FundsRequest>,
+    // every movement chained in the order applied , kept up to date so a state digest never walks the history
+    digest : u64,
+}
+
+impl FundsLedger{
+    /// the ledger the movements of a checkpoint describe , keys included
+    pub fn from_movements(movements : Vec<FundsMovement>)->Option<Self>{
+        let mut requests = FxHashMap::default();
+        let mut index = 0;
+        while index < movements.len() {
+            let movement = movements[index];
+            let (request , used) = match movement.kind {
+                MovementKind::Deposit => (FundsRequest::Deposit { user_id : movement.user_id , asset : movement.asset , amount : movement.amount } , 1),
+                MovementKind::Withdrawal => (FundsRequest::Withdraw { user_id : movement.user_id , asset : movement.asset , amount : movement.amount } , 1),
+                MovementKind::AdjustmentCredit => (FundsRequest::Adjust { user_id : movement.user_id , asset : movement.asset , delta : i64::try_from(movement.amount).ok()? } , 1),
+                MovementKind::AdjustmentDebit => (FundsRequest::Adjust { user_id : movement.user_id , asset : movement.asset , delta : i64::try_from(movement.amount).ok()?.checked_neg()? } , 1),
+                // a transfer is recorded as its out leg followed by its in leg
+                MovementKind::TransferOut => {
+                    let incoming = movements.get(index + 1).filter(|next| next.kind == MovementKind::TransferIn && next.key == movement.key)?;
+                    (FundsRequest::Transfer { from : movement.user_id , to : incoming.user_id , asset : movement.asset , amount : movement.amount } , 2)
+                }
+                MovementKind::TransferIn => return None,
+            };
+            requests.insert(movement.key, request);
+            index += used;
+        }
+        let digest = movements.iter().fold(0, chain);
+        Some(Self { movements , requests , digest })
+    }
+
+    /// whether `key` was already applied , an error when it was applied to a different request
+    pub fn seen(&self , key : u64 , request : &FundsRequest)->Result<bool , FundsError>{
+        match self.requests.get(&key) {
+            None => Ok(false),
+            Some(applied) if applied == request => Ok(true),
+            Some(_) => Err(FundsError::KeyReused(key)),
+        }
+    }
+
+    pub fn record(&mut self , key : u64 , request : FundsRequest , movements : &[FundsMovement]){
+        self.requests.insert(key, request);
+        self.movements.extend_from_slice(movements);
+        self.digest = movements.iter().fold(self.digest, chain);
+    }
+
+    /// hash of every movement so far and of their order
+    pub fn digest(&self)->u64{
+        self.digest
+    }
+
+    pub fn movements(&self)->&[FundsMovement]{
+        &self.movements
+    }
+
+    /// every movement of `user_id` , oldest first
+    pub fn history(&self , user_id : u64)->impl Iterator<Item = &FundsMovement>{
+        self.movements.iter().filter(move |movement| movement.user_id == user_id)
+    }
+}
+
+fn chain(digest : u64 , movement : &FundsMovement)->u64{
+    let mut hasher = StateHasher::new(digest);
+    hasher.write_u64(movement.key);
+    hasher.write_u64(movement.user_id);
+    hasher.write_u32(movement.kind.as_u8() as u32);
+    let (kind , id) = movement.asset.code();
+    hasher.write_u32(kind as u32);
+    hasher.write_u32(id);
+    hasher.write_u64(movement.amount);
+    hasher.write_u64(movement.available_after);
+    hasher.finish()
+}
@@ -1,3 +1,9 @@
 pub mod my_balance_manager;
 pub mod tests;
-pub mod my_balance_manager2;
\ No newline at end of file
+pub mod my_balance_manager2;
+pub mod funds_ledger;
+pub mod postings;
+pub mod risk_limits;
+pub mod rate_limits;
+pub mod positions;
+pub mod fee_volume;
@@ -11,20 +11,36 @@
 // avalable means free balance or holdings that can be reserved 
 use bounded_spsc_queue::{Consumer, Producer};
 use crossbeam_utils::Backoff;
-use crate::logger::types::{BalanceDelta, BaseLogs, HoldingDelta};
-use crate::shm::holdings_response_queue::{HoldingResQueue, HoldingResponse};
+use crate::logger::types::{BalanceDelta, BaseLogs, HoldingDelta, PositionReport, DELTA_REASON_ADJUSTMENT, DELTA_REASON_DEPOSIT, DELTA_REASON_FEE, DELTA_REASON_FILL, DELTA_REASON_LOCK, DELTA_REASON_RELEASE, DELTA_REASON_TRANSFER, DELTA_REASON_WITHDRAWAL};
+use crate::balance_manager::funds_ledger::{Asset, FundsError, FundsLedger, FundsMovement, FundsOutcome, FundsRequest, MovementKind};
+use crate::balance_manager::postings::{Account, ConservationError, Entry, HouseAccounts, PostingError};
+use smallvec::SmallVec;
+use thiserror::Error;
+use crate::shm::holdings_response_queue::HoldingResponse;
 use dashmap::DashMap;
+use rustc_hash::{FxHashMap, FxHashSet};
+use crate::digest::state_hasher::{combine_unordered, StateHasher};
+use crate::shutdown::shutdown_signal::ShutdownHandle;
 use crate::orderbook::types::{BalanceManagerError, Fills, };
 use crate::orderbook::order::{ Order, OrderToBeCanceled, Side};
 use crate::shm::event_queue::OrderEvents;
 use crate::shm::query_queue::{QueryQueue};
-use crate::shm::balance_response_queue::{ BalanceResQueue, BalanceResponse};
+use crate::shm::balance_response_queue::BalanceResponse;
+use crate::balance_manager::positions::Position;
+use crate::balance_manager::fee_volume::VolumeWindow;
+use crate::balance_manager::rate_limits::{RateAction, RateState};
+use crate::balance_manager::risk_limits::{Exposure, LimitKind, OrderTerms, RiskLimits, RiskState};
+use crate::config::settings::{BalanceDefaults, EngineConfig, FeeSettings, QueuePaths, RateLimitSettings, RiskSettings};
 const MAX_USERS: usize = 1000; 
-const MAX_SYMBOLS : usize = 100 ; 
-const DEFAULT_BALANCE : u64 = 10000;
-const DEFAULT_HOLDING_QTY: u32 = 100;
+// the built in defaults , a deployment sets its own in the [balances] config section
+// users start empty , cash and shares come in through deposits
+pub const DEFAULT_BALANCE : u64 = 0;
+pub const DEFAULT_HOLDING_QTY: u32 = 0;
+pub const MARKET_MAKER_HOLDING_QTY : u32 = 100;
 
-const MARKET_MAKER_BALANCE : u64 = 100000000;
+pub const MARKET_MAKER_BALANCE : u64 = 100000000;
+// a trading day is a utc calendar day of the command clock
+pub const NANOS_PER_DAY : u64 = 86_400 * 1_000_000_000;
 
 
 #[repr(C)]
@@ -62,9 +78,12 @@ impl UserBalance{
              }
     }
     pub fn market_maker(user_id : u64)->Self{
+        Self::with_balance(user_id, MARKET_MAKER_BALANCE)
+    }
+    pub fn with_balance(user_id : u64 , available_balance : u64)->Self{
         Self {
             user_id , 
-            available_balance: MARKET_MAKER_BALANCE, 
+            available_balance, 
             reserved_balance: 0, 
             total_traded_today: 0, 
             order_count_today: 0, 
@@ -73,52 +92,312 @@ impl UserBalance{
     }
 }
 
-#[repr(C)]
-// dont allign to 64 , huge data wont fit into cache line anyhow 
-#[derive(Debug , Clone, Copy)]
+// what a user holds of one wallet asset , entries that drop to nothing are removed
+#[derive(Debug , Clone , Copy , Default , PartialEq , Eq)]
+pub struct WalletBalance{
+    pub available : u64,
+    pub reserved : u64,
+}
+
+// one symbol's position for a user
+#[derive(Debug , Clone , Copy , PartialEq , Eq)]
+pub struct SymbolHolding{
+    pub available : u32,
+    pub reserved : u32,
+}
+
+// sparse , only symbols the user has touched have an entry
+// a symbol without one reads as `default_available` free and nothing reserved , so new listings need no backfill
+#[derive(Debug , Clone)]
 pub struct UserHoldings{
-    pub user_id: u64,     // 8 byte 
-    pub available_holdings : [u32 ; MAX_SYMBOLS], // symbol is a uique index in the array 
-    pub reserved_holdings : [u32 ; MAX_SYMBOLS]
+    pub user_id: u64,
+    pub default_available : u32,
+    pub positions : FxHashMap<u32 , SymbolHolding>,
 }
 impl Default for UserHoldings{
     fn default() -> Self {
-        UserHoldings {
-            user_id: 0,
-            available_holdings: [DEFAULT_HOLDING_QTY ; MAX_SYMBOLS],  // Faster than from_fn
-            reserved_holdings : [0u32 ; MAX_SYMBOLS]
-        }
+        Self::new(0)
     }
 
 }
 
 impl UserHoldings{
     pub fn new(user_id : u64)->Self{
+        Self::with_default(user_id, DEFAULT_HOLDING_QTY)
+    }
+    pub fn with_default(user_id : u64 , default_available : u32)->Self{
         Self { 
             user_id, 
-            available_holdings: [DEFAULT_HOLDING_QTY ; MAX_SYMBOLS],
-            reserved_holdings: [0u32 ; MAX_SYMBOLS]
+            default_available,
+            positions : FxHashMap::default()
         }
     }
+    #[inline(always)]
+    pub fn get(&self , symbol : u32)->SymbolHolding{
+        self.positions.get(&symbol).copied().unwrap_or(SymbolHolding { available : self.default_available , reserved : 0 })
+    }
+    #[inline(always)]
+    pub fn available(&self , symbol : u32)->u32{
+        self.get(symbol).available
+    }
+    #[inline(always)]
+    pub fn reserved(&self , symbol : u32)->u32{
+        self.get(symbol).reserved
+    }
+    // creates the entry from the default on first touch
+    #[inline(always)]
+    pub fn position_mut(&mut self , symbol : u32)->&mut SymbolHolding{
+        let default_available = self.default_available;
+        self.positions.entry(symbol).or_insert(SymbolHolding { available : default_available , reserved : 0 })
+    }
+    pub fn set(&mut self , symbol : u32 , available : u32 , reserved : u32){
+        self.positions.insert(symbol, SymbolHolding { available , reserved });
+    }
+    // entries that still read the same as having no entry
+    pub fn is_default(&self , holding : &SymbolHolding)->bool{
+        holding.available == self.default_available && holding.reserved == 0
+    }
+}
+// what one open order still has reserved , the aggregate reserved_balance and reserved holdings are the sums of these
+// locked when the order comes in , debited by its own fills and released when it is canceled or ends without resting
+// a bid holds funds locked at its limit price , a fill below the limit frees the difference right away
+// an ask holds shares , reserved is always its remaining quantity
+#[derive(Debug , Clone , Copy , PartialEq , Eq)]
+pub struct OrderReservation{
+    pub user_id : u64,
+    pub symbol : u32,
+    pub side : Side,
+    pub price : u64,
+    pub remaining_qty : u32,
+    pub reserved : u64,
+    // the fee rate a bid locked headroom for on top of its notional , 0 for an ask which pays out of its proceeds
+    pub fee_bps : u32,
 }
+
+impl OrderReservation{
+    pub fn for_order(order : &Order , reserved : u64 , fee_bps : u32)->Self{
+        Self { user_id : order.user_id , symbol : order.symbol , side : order.side , price : order.price , remaining_qty : order.shares_qty , reserved , fee_bps }
+    }
+}
+
+/// `bps` basis points of `notional` , rounded toward zero so no fill is charged more than its rate
+pub fn fee_on(notional : u64 , bps : i32)->i64{
+    (notional as i128 * bps as i128 / 10_000) as i64
+}
+
+// what a bid locks for `qty` at `price` : the notional and the most fee it can be charged on it
+// rounding each fill's share down never adds up to more than the whole order's
+fn bid_lock(price : u64 , qty : u32 , fee_bps : u32)->Option<u64>{
+    let notional = price.checked_mul(qty as u64)?;
+    notional.checked_add(fee_on(notional, fee_bps as i32) as u64)
+}
+
+// an aggregate that the open reservations do not add up to
+#[derive(Debug , Clone , PartialEq , Eq , Error)]
+pub enum ReservationMismatch{
+    #[error("user {user_id} has {aggregate} reserved , its open bids hold {open}")]
+    Balance { user_id : u64 , aggregate : u64 , open : u64 },
+    #[error("user {user_id} has {aggregate} shares of {symbol} reserved , its open asks hold {open}")]
+    Holding { user_id : u64 , symbol : u32 , aggregate : u64 , open : u64 },
+    #[error("user {user_id} has {aggregate} of asset {asset} reserved , its open orders hold {open}")]
+    Wallet { user_id : u64 , asset : u32 , aggregate : u64 , open : u64 },
+    #[error("order {order_id} holds a reservation for unknown user {user_id}")]
+    UnknownUser { order_id : u64 , user_id : u64 },
+}
+
+impl ReservationMismatch{
+    fn of(user_id : u64 , asset : Asset , aggregate : u64 , open : u64)->Self{
+        match asset {
+            Asset::Cash => Self::Balance { user_id , aggregate , open },
+            Asset::Shares(symbol) => Self::Holding { user_id , symbol , aggregate , open },
+            Asset::Coin(asset) => Self::Wallet { user_id , asset , aggregate , open },
+        }
+    }
+}
+
 pub struct BalanceState{
     pub balances : Box<[UserBalance ; MAX_USERS]>,
     pub holdings : Box<[UserHoldings ; MAX_USERS]>,
     pub user_id_to_index : DashMap<u64 , u32>, // user_id to balance index 
     pub next_free_slot: u32,
     pub total_users: u32,
-
+    // open orders by order id
+    pub reservations : FxHashMap<u64 , OrderReservation>,
+    // every deposit , withdrawal and transfer applied so far
+    pub funds : FundsLedger,
+    // the fee and system accounts of the double entry postings
+    pub house : HouseAccounts,
+    // wallet assets by (user id , asset id) , cash stays in balances and symbol shares in holdings
+    pub wallets : FxHashMap<(u64 , u32) , WalletBalance>,
+    // admin set limits , last trade prices and open order counts the pre-trade checks read
+    pub risk : RiskState,
+    // token buckets of the rate limits
+    pub rates : RateState,
+    // users the kill switch froze , no new orders and no withdrawals
+    pub frozen : FxHashSet<u64>,
+    // by (user id , symbol) , every pair that ever traded
+    pub positions : FxHashMap<(u64 , u32) , Position>,
+    // days since the epoch of the last command applied , total_traded_today and order_count_today count within it
+    pub trading_day : u64,
+    // admin set mark prices , a symbol without one is marked to its last trade
+    pub marks : FxHashMap<u32 , u64>,
+    // by (user id , quote asset) , the notional of the days the fee tiers look back over
+    pub fee_volume : FxHashMap<(u64 , Asset) , VolumeWindow>,
 }
 impl BalanceState {
     pub fn new() -> Self {
         Self {
-            balances: Box::new(std::array::from_fn(|_| UserBalance::default())),
-            holdings: Box::new(std::array::from_fn(|_| UserHoldings::default())),
+            // built straight on the heap , the holdings array alone is ~800KB and blows small thread stacks
+            balances: vec![UserBalance::default(); MAX_USERS].into_boxed_slice().try_into().unwrap(),
+            holdings: vec![UserHoldings::default(); MAX_USERS].into_boxed_slice().try_into().unwrap(),
             user_id_to_index: DashMap::with_capacity(MAX_USERS),
             next_free_slot: 0,
             total_users: 0,
+            reservations: FxHashMap::default(),
+            funds: FundsLedger::default(),
+            house: HouseAccounts::default(),
+            wallets: FxHashMap::default(),
+            risk: RiskState::default(),
+            rates: RateState::default(),
+            frozen: FxHashSet::default(),
+            positions: FxHashMap::default(),
+            trading_day: 0,
+            marks: FxHashMap::default(),
+            fee_volume: FxHashMap::default(),
         }
     }
+
+    /// records an order's reservation , the only way one enters the ledger
+    pub fn open_reservation(&mut self , order_id : u64 , reservation : OrderReservation){
+        if self.reservations.insert(order_id, reservation).is_none() {
+            *self.risk.open_orders.entry(reservation.user_id).or_default() += 1;
+        }
+    }
+
+    /// takes an order's reservation out of the ledger once it is settled or given back
+    pub fn close_reservation(&mut self , order_id : u64)->Option<OrderReservation>{
+        let reservation = self.reservations.remove(&order_id)?;
+        if let Some(open) = self.risk.open_orders.get_mut(&reservation.user_id) {
+            *open -= 1;
+            if *open == 0 {
+                self.risk.open_orders.remove(&reservation.user_id);
+            }
+        }
+        Some(reservation)
+    }
+
+    // digest of every user's balance and holdings , keyed by user id so slot assignment does not matter
+    // users are combined without order since the dashmap iterates in no particular order
+    pub fn state_hash(&self)->u64{
+        let mut acc = 0u64;
+        for entry in self.user_id_to_index.iter() {
+            let index = *entry.value() as usize;
+            let balance = &self.balances[index];
+            let holdings = &self.holdings[index];
+            let mut hasher = StateHasher::new(*entry.key());
+            hasher.write_u64(balance.available_balance);
+            hasher.write_u64(balance.reserved_balance);
+            hasher.write_u64(balance.total_traded_today);
+            hasher.write_u64(balance.order_count_today);
+            hasher.write_u32(holdings.default_available);
+            // positions that read as the default are skipped , a user hashes the same whether or not the entry exists
+            let mut positions = 0u64;
+            for (symbol , holding) in holdings.positions.iter() {
+                if holdings.is_default(holding) {
+                    continue;
+                }
+                let mut position_hasher = StateHasher::new(*symbol as u64);
+                position_hasher.write_u32(holding.available);
+                position_hasher.write_u32(holding.reserved);
+                positions = combine_unordered(positions, position_hasher.finish());
+            }
+            hasher.write_u64(positions);
+            acc = combine_unordered(acc, hasher.finish());
+        }
+        for (user_id , limits) in self.risk.overrides.iter() {
+            let mut hasher = StateHasher::new(*user_id);
+            hasher.write_u32(limits.max_order_qty);
+            hasher.write_u64(limits.max_order_notional);
+            hasher.write_u32(limits.max_open_orders);
+            hasher.write_u64(limits.max_position);
+            hasher.write_u64(limits.max_daily_notional);
+            hasher.write_u32(limits.max_price_deviation_bps);
+            acc = combine_unordered(acc, hasher.finish());
+        }
+        for (symbol , price) in self.risk.last_prices.iter() {
+            let mut hasher = StateHasher::new(*symbol as u64);
+            hasher.write_u64(*price);
+            acc = combine_unordered(acc, hasher.finish());
+        }
+        for ((user_id , symbol) , position) in self.positions.iter() {
+            let mut hasher = StateHasher::new(*user_id);
+            hasher.write_u32(*symbol);
+            hasher.write_u64(position.qty as u64);
+            hasher.write_u64(position.cost as u64);
+            hasher.write_u64((position.cost >> 64) as u64);
+            hasher.write_u64(position.realized_pnl as u64);
+            acc = combine_unordered(acc, hasher.finish());
+        }
+        for (symbol , mark) in self.marks.iter() {
+            let mut hasher = StateHasher::new(*symbol as u64);
+            hasher.write_u64(*mark);
+            acc = combine_unordered(acc, hasher.finish());
+        }
+        for user_id in self.frozen.iter() {
+            acc = combine_unordered(acc, StateHasher::new(*user_id).finish());
+        }
+        acc = combine_unordered(acc, StateHasher::new(self.trading_day).finish());
+        for ((user_id , asset) , window) in self.fee_volume.iter() {
+            let (kind , id) = asset.code();
+            for (day , notional) in window.days() {
+                let mut hasher = StateHasher::new(*user_id);
+                hasher.write_u32(kind as u32);
+                hasher.write_u32(id);
+                hasher.write_u64(day);
+                hasher.write_u64(notional);
+                acc = combine_unordered(acc, hasher.finish());
+            }
+        }
+        for ((user_id , action) , bucket) in self.rates.buckets.iter() {
+            let mut hasher = StateHasher::new(*user_id);
+            hasher.write_u32(action.as_u8() as u32);
+            hasher.write_u64(bucket.tokens);
+            hasher.write_u64(bucket.refilled_at);
+            acc = combine_unordered(acc, hasher.finish());
+        }
+        for ((user_id , asset) , wallet) in self.wallets.iter() {
+            let mut hasher = StateHasher::new(*user_id);
+            hasher.write_u32(*asset);
+            hasher.write_u64(wallet.available);
+            hasher.write_u64(wallet.reserved);
+            acc = combine_unordered(acc, hasher.finish());
+        }
+        for (order_id , reservation) in self.reservations.iter() {
+            let mut hasher = StateHasher::new(*order_id);
+            hasher.write_u64(reservation.user_id);
+            hasher.write_u32(reservation.symbol);
+            hasher.write_u32(reservation.side as u32);
+            hasher.write_u64(reservation.price);
+            hasher.write_u32(reservation.remaining_qty);
+            hasher.write_u64(reservation.reserved);
+            hasher.write_u32(reservation.fee_bps);
+            acc = combine_unordered(acc, hasher.finish());
+        }
+        // the movement history goes in as the ledger's running hash , the order of movements is part of the state
+        acc = combine_unordered(acc, self.funds.digest());
+        for (account , asset , balance) in self.house.iter() {
+            let mut hasher = StateHasher::new(match account {
+                Account::Fees => 1,
+                _ => 2,
+            });
+            let (kind , id) = asset.code();
+            hasher.write_u32(kind as u32);
+            hasher.write_u32(id);
+            hasher.write_u64(balance as u64);
+            acc = combine_unordered(acc, hasher.finish());
+        }
+        acc
+    }
 }
 
 impl Default for BalanceState {
@@ -139,19 +418,23 @@ pub struct MyBalanceManager2{
 
 
     pub balance_updates_sender : Producer<BalanceResponse>,
-    pub holding_update_sender : Producer<HoldingResponse>
+    pub holding_update_sender : Producer<HoldingResponse>,
+    pub defaults : BalanceDefaults
 
 }
 
 impl MyBalanceManager2{
+    #[allow(clippy::too_many_arguments)]
     pub fn new(fill_recv_from_engine_try : Consumer<Fills>,
         order_recv_from_shm_try : Consumer<Order>,
         order_send_to_engine_try : Producer<Order>,
         events_to_wrriter_try : Producer<OrderEvents>,
         balance_updates_sender : Producer<BalanceResponse>,
-        holding_update_sender : Producer<HoldingResponse>
+        holding_update_sender : Producer<HoldingResponse>,
+        queues : &QueuePaths,
+        defaults : BalanceDefaults
     )->Self{
-        let query_queue = QueryQueue::open("/tmp/Queries");
+        let query_queue = QueryQueue::open(&queues.queries);
         
         if query_queue.is_err(){
             eprintln!("query queue init error in balance manager");
@@ -165,7 +448,8 @@ impl MyBalanceManager2{
          order_send_to_engine_try,
          events_to_wrriter_try,
          balance_updates_sender,
-         holding_update_sender
+         holding_update_sender,
+         defaults
         }
     }
     pub fn get_user_index(&self , user_id : u64 )->Result<u32 , BalanceManagerError>{
@@ -184,7 +468,7 @@ impl MyBalanceManager2{
         self.state.balances[user_index as usize]
     }
     pub fn get_user_holdings_copy_for_query(&mut self , user_index : u32)->UserHoldings{
-        self.state.holdings[user_index as usize]
+        self.state.holdings[user_index as usize].clone()
     }
     //// returned the state so that it can be passed to the grpc server 
      #[cfg_attr(feature = "hotpath", hotpath::measure)]
@@ -196,15 +480,14 @@ impl MyBalanceManager2{
             Side::Ask =>{
                 let holdings = self.get_user_holdings(user_index);
                 // wants to sell 
-                let avalable_holdings_for_symbol = holdings.available_holdings[order.symbol as usize];
-                let reserved_holdings_for_symbol = holdings.reserved_holdings[order.symbol as usize];
+                let position = holdings.position_mut(order.symbol);
 
-                if order.shares_qty > avalable_holdings_for_symbol{
+                if order.shares_qty > position.available{
                     return Err(BalanceManagerError::InsufficientFunds);
                 }
                 
-                holdings.available_holdings[order.symbol as usize] = avalable_holdings_for_symbol - order.shares_qty;
-                holdings.reserved_holdings[order.symbol as usize] = reserved_holdings_for_symbol + order.shares_qty;
+                position.available -= order.shares_qty;
+                position.reserved += order.shares_qty;
             }
             Side::Bid =>{
                 // wants to buy  , if balacne > price * qty , we can rserve 
@@ -244,8 +527,8 @@ impl MyBalanceManager2{
                     
                     // remove holdings from resevred
                     {let  taker_holdings = self.get_user_holdings(taker_index);
-                    let taker_reserved_holdings = taker_holdings.reserved_holdings[fill.symbol as usize];
-                    taker_holdings.reserved_holdings[fill.symbol as usize] = taker_reserved_holdings - fill.quantity;}
+                    let taker_reserved_holdings = taker_holdings.reserved(fill.symbol);
+                    taker_holdings.position_mut(fill.symbol).reserved = taker_reserved_holdings - fill.quantity;}
                     
                     {let  maker_balance = self.get_user_balance(maker_index);
                     let maker_reserved_bal = maker_balance.reserved_balance;
@@ -253,8 +536,8 @@ impl MyBalanceManager2{
                     
                     // add shares , he bough 
                     {let  maker_holdings = self.get_user_holdings(maker_index);
-                    let maker_avail_holdings = maker_holdings.available_holdings[fill.symbol as usize];
-                    maker_holdings.available_holdings[fill.symbol as usize]= maker_avail_holdings + fill.quantity}
+                    let maker_avail_holdings = maker_holdings.available(fill.symbol);
+                    maker_holdings.position_mut(fill.symbol).available = maker_avail_holdings + fill.quantity}
                         
                 }
                 
@@ -267,8 +550,8 @@ impl MyBalanceManager2{
                         
                     
                     {let  taker_holdings = self.get_user_holdings(taker_index);
-                    let taker_avail_holdings = taker_holdings.available_holdings[fill.symbol as usize];
-                    taker_holdings.available_holdings[fill.symbol as usize]= taker_avail_holdings + fill.quantity;}
+                    let taker_avail_holdings = taker_holdings.available(fill.symbol);
+                    taker_holdings.position_mut(fill.symbol).available = taker_avail_holdings + fill.quantity;}
                     
                     {let  maker_balance = self.get_user_balance(maker_index);
                     let maker_avail_bal = maker_balance.available_balance;
@@ -276,8 +559,8 @@ impl MyBalanceManager2{
     
                     
                     {let  maker_holdings = self.get_user_holdings(maker_index);
-                    let maker_reserved_holdings = maker_holdings.reserved_holdings[fill.symbol as usize];
-                    maker_holdings.reserved_holdings[fill.symbol as usize]= maker_reserved_holdings - fill.quantity;}
+                    let maker_reserved_holdings = maker_holdings.reserved(fill.symbol);
+                    maker_holdings.position_mut(fill.symbol).reserved = maker_reserved_holdings - fill.quantity;}
                 }
             }
         }
@@ -292,11 +575,11 @@ impl MyBalanceManager2{
             Side::Ask => {
                 
                 // side was ask , he was selling so this was only chnaged , order wsent fullfilled , no use of price 
-                let old_reserved_holdings = self.state.holdings[user_index as usize].reserved_holdings[canceled_order.symbol as usize];
-                let old_available_holdings = self.state.holdings[user_index as usize].available_holdings[canceled_order.symbol as usize];
+                let old_reserved_holdings = self.state.holdings[user_index as usize].reserved(canceled_order.symbol);
+                let old_available_holdings = self.state.holdings[user_index as usize].available(canceled_order.symbol);
 
-                self.state.holdings[user_index as usize].reserved_holdings[canceled_order.symbol as usize] = old_reserved_holdings - qty;
-                self.state.holdings[user_index as usize].available_holdings[canceled_order.symbol as usize] = old_available_holdings + qty;
+                self.state.holdings[user_index as usize].position_mut(canceled_order.symbol).reserved = old_reserved_holdings - qty;
+                self.state.holdings[user_index as usize].position_mut(canceled_order.symbol).available = old_available_holdings + qty;
 
             }
             Side::Bid =>{
@@ -325,15 +608,15 @@ impl MyBalanceManager2{
         self.state.next_free_slot += 1;
         self.state.total_users += 1;
 
-        self.state.balances[idx as usize] = UserBalance::new(user_id);
-        self.state.holdings[idx as usize] = UserHoldings::new(user_id);
+        self.state.balances[idx as usize] = UserBalance::with_balance(user_id, self.defaults.balance);
+        self.state.holdings[idx as usize] = UserHoldings::with_default(user_id, self.defaults.holding_qty);
         
         self.state.user_id_to_index.insert(user_id, idx);
 
         Ok(idx)
     }
 
-pub fn run_balance_manager(&mut self) {
+pub fn run_balance_manager(&mut self , shutdown : &ShutdownHandle) {
     const BATCH_ORDERS: usize = 1000;
 
     eprintln!("[balance manager] Started on core (batched, prioritized fills)"); 
@@ -384,7 +667,8 @@ pub fn run_balance_manager(&mut self) {
                                     filled_qty: 0,
                                     remaining_qty: recieved_order.shares_qty,
                                     original_qty: recieved_order.shares_qty,
-                                    error_code: 1
+                                    error_code: 1,
+                                    fee: 0
                                  }
                             );
                         }
@@ -413,14 +697,21 @@ pub fn run_balance_manager(&mut self) {
 
             }
         }
+        if processed == 0
+        && shutdown.upstream_finished()
+        && self.fill_recv_from_engine_try.size() == 0
+        && self.order_recv_from_shm_try.size() == 0 {
+            break;
+        }
     }
+    shutdown.finish();
 }
 
     pub fn add_test_users(&mut self ){
         self.state.user_id_to_index.insert(10, 1);
         self.state.user_id_to_index.insert(20, 2);
         // user id 20  , index = 2 , symbol 0 
-        self.state.holdings[2].available_holdings[0] =10;
+        self.state.holdings[2].set(0, 10, 0);
     }
 
     pub fn add_throughput_test_users(&mut self) {
@@ -440,14 +731,12 @@ pub fn run_balance_manager(&mut self) {
         self.state.balances[2].available_balance = HIGH_BALANCE;
         
        
-        for symbol in 0..MAX_SYMBOLS {
-            self.state.holdings[2].available_holdings[symbol] = HIGH_HOLDINGS;
-        }
+        self.state.holdings[2].default_available = HIGH_HOLDINGS;
         
        
         let user10_bal = self.state.balances[1].available_balance;
         let user20_bal = self.state.balances[2].available_balance;
-        let user20_holdings = self.state.holdings[2].available_holdings[0];
+        let user20_holdings = self.state.holdings[2].available(0);
         
         eprintln!("[BM] User 10 balance: {}", user10_bal);
         eprintln!("[BM] User 20 balance: {}", user20_bal);
@@ -458,52 +747,101 @@ pub fn run_balance_manager(&mut self) {
     
     
 }
-pub enum BalanceManagerResForLocking{
-    BalanceManagerResUpdateDeltaBalance(BalanceManagerResUpdateDeltaBalance),
-    BalanceManagerResUpdateDeltaHolding(BalanceManagerResUpdateDeltaHolding)
-}
-pub struct BalanceManagerResUpdateDeltaBalance{
-    pub delta_available_balance : i64 ,
-    pub delta_reserved_balance  : i64 ,
+// what an entry did to one user's asset under one order , the unit the delta logs and cache responses go out in
+#[derive(Debug , Clone , Copy)]
+struct UserDelta{
+    user_id : u64,
+    asset : Asset,
+    order_id : u64,
+    available : i128,
+    reserved : i128,
 }
 
-pub struct BalanceManagerResUpdateDeltaHolding{
-
-    pub delta_available_holding  : i32 ,
-    pub delta_reserved_holding   : i32
+// the user postings of `entry` folded per user , asset and order , in the order they were posted
+// the logs carry 64 bit cash and wallet deltas and 32 bit share deltas , an entry that does not fit them is refused whole
+fn user_deltas(entry : &Entry)->Result<SmallVec<[UserDelta ; 4]> , PostingError>{
+    let mut deltas : SmallVec<[UserDelta ; 4]> = SmallVec::new();
+    for posting in entry.postings.iter() {
+        let Some(user_id) = posting.account.user_id() else {
+            continue;
+        };
+        let index = match deltas.iter().position(|delta| delta.user_id == user_id && delta.asset == posting.asset && delta.order_id == posting.order_id) {
+            Some(index) => index,
+            None => {
+                deltas.push(UserDelta { user_id , asset : posting.asset , order_id : posting.order_id , available : 0 , reserved : 0 });
+                deltas.len() - 1
+            }
+        };
+        match posting.account {
+            Account::Available(_) => deltas[index].available += posting.amount,
+            _ => deltas[index].reserved += posting.amount,
+        }
+    }
+    for delta in deltas.iter() {
+        let limit = match delta.asset {
+            Asset::Cash | Asset::Coin(_) => i64::MAX as i128,
+            Asset::Shares(_) => i32::MAX as i128,
+        };
+        for (account , amount) in [(Account::Available(delta.user_id) , delta.available) , (Account::Reserved(delta.user_id) , delta.reserved)] {
+            if amount.abs() > limit {
+                return Err(PostingError::Overflow { account , asset : delta.asset });
+            }
+        }
+    }
+    Ok(deltas)
 }
 
-
 pub struct STbalanceManager{
     pub state : BalanceState,
     pub events_to_wrriter_try : Producer<OrderEvents> , 
 
     pub balance_updates_sender : Producer<BalanceResponse>,
-    pub holding_update_sender : Producer<HoldingResponse>
+    pub holding_update_sender : Producer<HoldingResponse>,
+    // what add_user and add_market_maker hand out
+    pub defaults : BalanceDefaults,
+    // maker and taker rates charged on every fill
+    pub fees : FeeSettings,
+    // (base , quote) of every listed pair , an unlisted symbol trades its shares against cash
+    pub pairs : FxHashMap<u32 , (Asset , Asset)>,
+    // pre-trade limits from the config , the admin set ones are in the state
+    pub limits : RiskSettings,
+    // bucket sizes per user class , the buckets themselves are in the state
+    pub rate_limits : RateLimitSettings
 }
 
 impl STbalanceManager{
     pub fn new(
         events_to_wrriter_try : Producer<OrderEvents>,
         balance_updates_sender : Producer<BalanceResponse>,
-        holding_update_sender : Producer<HoldingResponse>
+        holding_update_sender : Producer<HoldingResponse>,
+        config : &EngineConfig
     )->Self{
-        let holding_response_queue = HoldingResQueue::open("/tmp/HoldingsResponse");
-        let balance_response_queue = BalanceResQueue::open("/tmp/BalanceResponse");
-        if balance_response_queue.is_err(){
-            eprintln!("response queue init error in balance manager");
-            eprintln!("{:?}" , balance_response_queue)
-        }
-        if holding_response_queue.is_err(){
-            eprintln!("response queue init error in balance manager");
-            eprintln!("{:?}" , holding_response_queue)
-        }
+        // the response queues are written by the shm writter , the balance manager only pushes deltas to it
         let balance_state = BalanceState::new();
         Self {  
             state: balance_state ,  
             events_to_wrriter_try,
             balance_updates_sender,
-            holding_update_sender
+            holding_update_sender,
+            defaults : config.balances,
+            fees : config.fees.clone(),
+            pairs : config.instruments.pairs.iter().map(|pair| (pair.symbol , (Asset::from_id(pair.base) , Asset::from_id(pair.quote)))).collect(),
+            limits : config.risk.clone(),
+            rate_limits : config.rate_limits.clone()
+        }
+    }
+
+    /// the (base , quote) assets `symbol` trades
+    pub fn pair(&self , symbol : u32)->(Asset , Asset){
+        self.pairs.get(&symbol).copied().unwrap_or((Asset::Shares(symbol) , Asset::Cash))
+    }
+
+    // a bid locks the quote it pays with , an ask the base it sells
+    fn locked_asset(&self , symbol : u32 , side : Side)->Asset{
+        let (base , quote) = self.pair(symbol);
+        match side {
+            Side::Bid => quote,
+            Side::Ask => base,
         }
     }
     #[inline(always)]
@@ -526,378 +864,670 @@ impl STbalanceManager{
         i32::try_from(ip)
     }
 
-    pub fn change_user_balance(&mut self , user_id : u64  , reserved_balance : u64 , available_balance : u64)->Result<() , BalanceManagerError>{
-        let user_index = self.get_user_index(user_id)?;
-        self.state.balances[user_index as usize].available_balance = available_balance;
-        self.state.balances[user_index as usize].reserved_balance = reserved_balance;
+    /// applies `entry` whole or not at all , then logs and sends to the cache what it did to every user it touched
+    pub fn post<F , G>(&mut self , entry : &Entry , mut emit : F , mut next_id : G)->Result<() , PostingError> where F : FnMut(BaseLogs) , G : FnMut()->u64 {
+        let deltas = user_deltas(entry)?;
+        self.apply_entry(entry)?;
+        for delta in deltas {
+            self.publish_delta(entry.reason, delta, &mut emit, &mut next_id);
+        }
         Ok(())
     }
 
-    pub fn change_user_holdings(&mut self , user_id : u64 , symbol : u32 , reserved_shares_qty : u32 , available_qty : u32)->Result<() , BalanceManagerError>{
-        let user_index = self.get_user_index(user_id)?;
-        self.state.holdings[user_index as usize].available_holdings[symbol as usize] = available_qty;
-        self.state.holdings[user_index as usize].reserved_holdings[symbol as usize] = reserved_shares_qty;
+    // every account the entry touches is checked before any of them changes
+    fn apply_entry(&mut self , entry : &Entry)->Result<() , PostingError>{
+        if let Some(asset) = entry.unbalanced() {
+            return Err(PostingError::Unbalanced(asset));
+        }
+        let mut nets : SmallVec<[(Account , Asset , i128) ; 6]> = SmallVec::new();
+        for posting in entry.postings.iter() {
+            match nets.iter_mut().find(|(account , asset , _)| *account == posting.account && *asset == posting.asset) {
+                Some((_ , _ , net)) => *net += posting.amount,
+                None => nets.push((posting.account, posting.asset, posting.amount)),
+            }
+        }
+        for (account , asset , net) in nets.iter_mut() {
+            let slot = self.account_slot(*account)?;
+            let after = self.account_balance(slot, *account, *asset) + *net;
+            // house accounts are signed , system is negative by design and fees pay out rebates
+            let (floor , ceiling) = match (slot , *asset) {
+                (None , _) => (i64::MIN as i128 , i64::MAX as i128),
+                (Some(_) , Asset::Cash | Asset::Coin(_)) => (0 , u64::MAX as i128),
+                (Some(_) , Asset::Shares(_)) => (0 , u32::MAX as i128),
+            };
+            if after < floor {
+                return Err(PostingError::Overdrawn { account : *account , asset : *asset });
+            }
+            if after > ceiling {
+                return Err(PostingError::Overflow { account : *account , asset : *asset });
+            }
+            *net = after;
+        }
+        for (account , asset , after) in nets {
+            let slot = self.account_slot(account)?;
+            self.set_account_balance(slot, account, asset, after);
+        }
         Ok(())
     }
 
+    // the balance slot of a user account , none for a house account
+    fn account_slot(&self , account : Account)->Result<Option<usize> , PostingError>{
+        match account.user_id() {
+            Some(user_id) => self.get_user_index(user_id).map(|index| Some(index as usize)).map_err(|_| PostingError::UnknownUser(user_id)),
+            None => Ok(None),
+        }
+    }
 
-    pub fn check_and_lock_funds(&mut self , order : Order)->Result<BalanceManagerResForLocking , BalanceManagerError>{
-        // currently for limit orders , we get an order 
-        // we have user id , symbol , side , holfings 
-        let user_index = self.get_user_index(order.user_id)?; 
-        // this functio would return the UserNotFound error 
-          // fatal error , return immidieately to the function who is calling
-        
-        match order.side {
-
-            Side::Ask =>{
-                //println!("sell order");
-                let holdings = self.get_user_holdings(user_index);
-                // wants to sell 
-                let avalable_holdings_for_symbol = holdings.available_holdings[order.symbol as usize];
-                let reserved_holdings_for_symbol = holdings.reserved_holdings[order.symbol as usize];
-
-                //println!("available holdings are {:?}" , avalable_holdings_for_symbol);
-                //println!("reserved holdings are {:?}" , reserved_holdings_for_symbol);
-                
+    fn account_balance(&self , slot : Option<usize> , account : Account , asset : Asset)->i128{
+        let Some(slot) = slot else {
+            return self.state.house.balance(account, asset) as i128;
+        };
+        let available = matches!(account , Account::Available(_));
+        match asset {
+            Asset::Cash if available => self.state.balances[slot].available_balance as i128,
+            Asset::Cash => self.state.balances[slot].reserved_balance as i128,
+            Asset::Shares(symbol) if available => self.state.holdings[slot].available(symbol) as i128,
+            Asset::Shares(symbol) => self.state.holdings[slot].reserved(symbol) as i128,
+            Asset::Coin(id) => {
+                let wallet = self.state.wallets.get(&(self.state.balances[slot].user_id , id)).copied().unwrap_or_default();
+                (if available { wallet.available } else { wallet.reserved }) as i128
+            }
+        }
+    }
 
-                if order.shares_qty > avalable_holdings_for_symbol{
-                    return Err(BalanceManagerError::InsufficientFunds);
+    // `balance` was range checked by apply_entry
+    fn set_account_balance(&mut self , slot : Option<usize> , account : Account , asset : Asset , balance : i128){
+        let Some(slot) = slot else {
+            self.state.house.set(account, asset, balance as i64);
+            return;
+        };
+        let available = matches!(account , Account::Available(_));
+        match asset {
+            Asset::Cash if available => self.state.balances[slot].available_balance = balance as u64,
+            Asset::Cash => self.state.balances[slot].reserved_balance = balance as u64,
+            Asset::Shares(symbol) if available => self.state.holdings[slot].position_mut(symbol).available = balance as u32,
+            Asset::Shares(symbol) => self.state.holdings[slot].position_mut(symbol).reserved = balance as u32,
+            Asset::Coin(id) => {
+                let key = (self.state.balances[slot].user_id , id);
+                let mut wallet = self.state.wallets.get(&key).copied().unwrap_or_default();
+                match available {
+                    true => wallet.available = balance as u64,
+                    false => wallet.reserved = balance as u64,
                 }
-                
-                holdings.available_holdings[order.symbol as usize] = avalable_holdings_for_symbol - order.shares_qty;
-                holdings.reserved_holdings[order.symbol as usize] = reserved_holdings_for_symbol + order.shares_qty;
-
-                self.holding_update_sender.push(HoldingResponse { 
-                    user_id: order.user_id, 
-                    symbol: order.symbol, 
-                    delta_available_holding: -self.get_i32(order.shares_qty).unwrap(), 
-                    delta_reserved_holding: self.get_i32(order.shares_qty).unwrap()
-                });
-                
+                match wallet == WalletBalance::default() {
+                    true => self.state.wallets.remove(&key),
+                    false => self.state.wallets.insert(key, wallet),
+                };
+            }
+        }
+    }
 
-                return Ok(BalanceManagerResForLocking::BalanceManagerResUpdateDeltaHolding(BalanceManagerResUpdateDeltaHolding{
-                    delta_available_holding : -self.get_i32(order.shares_qty).unwrap(),
-                    delta_reserved_holding : self.get_i32(order.shares_qty).unwrap()
+    fn publish_delta<F , G>(&mut self , reason : u8 , delta : UserDelta , emit : &mut F , next_id : &mut G) where F : FnMut(BaseLogs) , G : FnMut()->u64 {
+        if delta.available == 0 && delta.reserved == 0 {
+            return;
+        }
+        match delta.asset {
+            Asset::Cash | Asset::Coin(_) => {
+                let (available , reserved) = (delta.available as i64 , delta.reserved as i64);
+                let asset = delta.asset.code().1;
+                emit(BaseLogs::BalanceDelta(BalanceDelta {
+                    event_id: next_id(),
+                    user_id: delta.user_id,
+                    delta_available: available,
+                    delta_reserved: reserved,
+                    order_id: delta.order_id,
+                    reason,
+                    asset
+                }));
+                self.balance_updates_sender.push(BalanceResponse {
+                    user_id: delta.user_id,
+                    delta_available_balance: available,
+                    delta_reserved_balance: reserved,
+                    asset,
+                    _pad: 0
+                });
+            }
+            Asset::Shares(symbol) => {
+                let (available , reserved) = (delta.available as i32 , delta.reserved as i32);
+                emit(BaseLogs::HoldingDelta(HoldingDelta {
+                    order_id: delta.order_id,
+                    event_id: next_id(),
+                    user_id: delta.user_id,
+                    symbol,
+                    delta_available: available,
+                    delta_reserved: reserved,
+                    reason
                 }));
+                self.holding_update_sender.push(HoldingResponse {
+                    user_id: delta.user_id,
+                    symbol,
+                    delta_available_holding: available,
+                    delta_reserved_holding: reserved
+                });
             }
-            Side::Bid =>{
-                //println!("buy order");
-                // wants to buy  , if balacne > price * qty , we can rserve 
-                // avalable is the free balance right now and reserved is what is alr reserved 
-                let balance = self.get_user_balance(user_index);
-                let required_balance = order.price*order.shares_qty as u64;
-                let avalaible_balance = balance.available_balance;
-                let reserved_balance = balance.reserved_balance;
+        }
+    }
 
-                //println!("available balance is {:?}" ,avalaible_balance);
-                //println!("reserved balance is {:?}" , reserved_balance);
+    /// sums every account of every asset , each sum is zero unless something moved outside an entry
+    pub fn check_conservation(&self)->Vec<ConservationError>{
+        let mut totals : FxHashMap<Asset , i128> = FxHashMap::default();
+        for entry in self.state.user_id_to_index.iter() {
+            let index = *entry.value() as usize;
+            let balance = &self.state.balances[index];
+            *totals.entry(Asset::Cash).or_default() += balance.available_balance as i128 + balance.reserved_balance as i128;
+            // counted from the opening default , which never went through an entry
+            let holdings = &self.state.holdings[index];
+            for (symbol , holding) in holdings.positions.iter() {
+                *totals.entry(Asset::Shares(*symbol)).or_default() += holding.available as i128 + holding.reserved as i128 - holdings.default_available as i128;
+            }
+        }
+        for ((_ , asset) , wallet) in self.state.wallets.iter() {
+            *totals.entry(Asset::Coin(*asset)).or_default() += wallet.available as i128 + wallet.reserved as i128;
+        }
+        for (_ , asset , balance) in self.state.house.iter() {
+            *totals.entry(asset).or_default() += balance as i128;
+        }
+        totals.into_iter().filter(|(_ , total)| *total != 0).map(|(asset , total)| ConservationError { asset , total }).collect()
+    }
 
-                if required_balance > avalaible_balance {
-                    return Err(BalanceManagerError::InsufficientFunds);
-                }
-                // we can reserv and and pass on the order to the matching egnine 
-                balance.available_balance = avalaible_balance - required_balance;
-                balance.reserved_balance = reserved_balance + required_balance;  
+    // a new account's opening cash , out of the system account , logged and sent to the cache like any deposit
+    fn open_account<F , G>(&mut self , user_id : u64 , balance : u64 , emit : F , next_id : G) where F : FnMut(BaseLogs) , G : FnMut()->u64 {
+        let entry = Entry::new(DELTA_REASON_DEPOSIT).transfer(Account::System, Account::Available(user_id), Asset::Cash, balance, 0);
+        if let Err(e) = self.post(&entry, emit, next_id) {
+            eprintln!("[BM] opening balance of user {} refused: {}", user_id, e);
+        }
+    }
 
+    /// moves what the order needs from available to reserved , an ask locks the base asset it sells
+    /// a bid locks the quote asset at its limit price plus the highest fee its symbol's schedule can charge on it
+    /// a market bid locks at its price too , the book never fills it above that
+    /// refused with RiskLimit when the order breaks one of the user's pre-trade limits , nothing is reserved then
+    pub fn check_and_lock_funds<F , G>(&mut self , order : Order , emit : F , next_id : G)->Result<() , BalanceManagerError> where F : FnMut(BaseLogs) , G : FnMut()->u64 {
+        self.check_limits(&order)?;
+        let asset = self.locked_asset(order.symbol, order.side);
+        let (required , fee_bps) = match order.side {
+            Side::Ask => (order.shares_qty as u64 , 0),
+            Side::Bid => {
+                let fee_bps = self.fees.schedule(order.symbol).max_bps();
+                // a notional past u64 can never be covered
+                (bid_lock(order.price, order.shares_qty, fee_bps).ok_or(BalanceManagerError::InsufficientFunds)? , fee_bps)
+            }
+        };
+        let entry = Entry::new(DELTA_REASON_LOCK).transfer(Account::Available(order.user_id), Account::Reserved(order.user_id), asset, required, order.order_id);
+        self.post(&entry, emit, next_id)?;
+        self.state.open_reservation(order.order_id, OrderReservation::for_order(&order, required, fee_bps));
+        let index = self.get_user_index(order.user_id)? as usize;
+        self.state.balances[index].order_count_today += 1;
+        Ok(())
+    }
 
-                self.balance_updates_sender.push(BalanceResponse { 
-                    user_id: order.user_id, 
-                    delta_available_balance: -self.get_i64(required_balance).unwrap(), 
-                    delta_reserved_balance: self.get_i64(required_balance).unwrap()
-                });
+    /// the admin set mark of `symbol` , its last trade without one , 0 before either
+    pub fn mark_price(&self , symbol : u32)->u64{
+        self.state.marks.get(&symbol).or_else(|| self.state.risk.last_prices.get(&symbol)).copied().unwrap_or(0)
+    }
 
-                return Ok(BalanceManagerResForLocking::BalanceManagerResUpdateDeltaBalance(BalanceManagerResUpdateDeltaBalance { 
-                    delta_available_balance: -self.get_i64(required_balance).unwrap(), 
-                    delta_reserved_balance: self.get_i64(required_balance).unwrap()
-                }));
-            }
-            
+    /// 0 clears the mark , the symbol goes back to its last trade
+    pub fn set_mark_price(&mut self , symbol : u32 , price : u64){
+        if price == 0 {
+            self.state.marks.remove(&symbol);
+        } else {
+            self.state.marks.insert(symbol, price);
         }
-        
     }
-    
-    pub fn update_balances_after_trade<F , G>(&mut self, order_fills: Fills , mut emit : F , mut next_id : G)-> Result<(), BalanceManagerError>  where F : FnMut(BaseLogs) , G : FnMut()->u64 {
-        //println!("updating the post trade funds for each fill , sending updates for go cache");
-        for fill in order_fills.fills {
-            
-            let maker_index = self.get_user_index(fill.maker_user_id)?;
-            let taker_index = self.get_user_index(fill.taker_user_id)?;
-            let fill_value = fill.price * fill.quantity as u64;
 
-            let (taker_balance_update ,  
-                taker_holding_update  , 
-                maker_balance_update ,
-                maker_holding_update
-            )  = match fill.taker_side {
-                Side::Ask => {
-                    // Taker is selling , jo order aya was sell order , order book pe(maker) buy order 
-                    // add money , he sold 
-                    // sell order tha , execute hogya 
-                        
-                        let  taker_balance = self.get_user_balance(taker_index);
-                        let taker_avail_bal = taker_balance.available_balance;
-                        taker_balance.available_balance = taker_avail_bal + fill_value;
-
-                        // balacne updates for the go cache 
-                        let taker_balance_update = BalanceResponse{
-                            user_id : fill.taker_user_id,
-                            delta_available_balance : self.get_i64(fill_value).unwrap(),
-                            delta_reserved_balance : 0 
-                        };
-
-                        // logging 
-                        emit(BaseLogs::BalanceDelta(BalanceDelta { 
-                            event_id: next_id(), 
-                            user_id: fill.taker_user_id, 
-                            delta_available: self.get_i64(fill_value).unwrap(), 
-                            delta_reserved: 0, 
-                            order_id: fill.taker_order_id, 
-                            reason: 1 
-                        }));
-
-
-
-
-                        // remove holdings from resevred
-                       
-                        let  taker_holdings = self.get_user_holdings(taker_index);
-                        let taker_reserved_holdings = taker_holdings.reserved_holdings[fill.symbol as usize];
-                        taker_holdings.reserved_holdings[fill.symbol as usize] = taker_reserved_holdings - fill.quantity;
-
-                        // holding update for the go cache 
-                        let taker_holding_update = HoldingResponse{
-                            user_id : fill.taker_user_id ,
-                            symbol : fill.symbol ,
-                            delta_available_holding : 0 , 
-                            delta_reserved_holding : -self.get_i32(fill.quantity).unwrap()
-                        };
-
-                        emit(BaseLogs::HoldingDelta(HoldingDelta { 
-                            order_id: fill.taker_order_id, 
-                            event_id: next_id(), 
-                            user_id: fill.taker_user_id, 
-                            symbol: fill.symbol, 
-                            delta_available: 0, 
-                            delta_reserved: -self.get_i32(fill.quantity).unwrap(), 
-                            reason: 1 
-                        }));
-                        
-                        let  maker_balance = self.get_user_balance(maker_index);
-                        let maker_reserved_bal = maker_balance.reserved_balance;
-                        maker_balance.reserved_balance= maker_reserved_bal - fill_value;
-
-                        // balance update for the go cache 
-                        let maker_balance_update = BalanceResponse{
-                            user_id : fill.maker_user_id ,
-                            delta_available_balance : 0 , 
-                            delta_reserved_balance : -self.get_i64(fill_value).unwrap()
-                        };
-                        emit(BaseLogs::BalanceDelta(BalanceDelta { 
-                            event_id: next_id(), 
-                            user_id: fill.maker_user_id, 
-                            delta_available: 0, 
-                            delta_reserved: -self.get_i64(fill_value).unwrap(), 
-                            order_id: fill.maker_order_id, 
-                            reason: 1 
-                        }));
+    pub fn position(&self , user_id : u64 , symbol : u32)->Position{
+        self.state.positions.get(&(user_id , symbol)).copied().unwrap_or_default()
+    }
 
-                    
+    /// one report per position of `user_id` , or of every user when none , in user then symbol order
+    pub fn report_positions<F , G>(&self , user_id : Option<u64> , kind : u32 , mut emit : F , mut next_id : G) where F : FnMut(BaseLogs) , G : FnMut()->u64 {
+        let mut keys : Vec<(u64 , u32)> = self.state.positions.keys().filter(|(user , _)| user_id.is_none_or(|wanted| *user == wanted)).copied().collect();
+        keys.sort_unstable();
+        for (user_id , symbol) in keys {
+            let position = self.state.positions[&(user_id , symbol)];
+            let mark_price = self.mark_price(symbol);
+            emit(BaseLogs::Position(PositionReport {
+                timestamp : 0,
+                event_id : next_id(),
+                user_id,
+                qty : position.qty,
+                avg_entry_price : position.avg_entry_price(),
+                mark_price,
+                realized_pnl : position.realized_pnl,
+                // nothing to mark against before the symbol has a price
+                unrealized_pnl : if mark_price == 0 { 0 } else { position.unrealized_pnl(mark_price) },
+                symbol,
+                kind,
+            }));
+        }
+    }
 
-                        // add shares , he bough 
-                        let  maker_holdings = self.get_user_holdings(maker_index);
-                        let maker_avail_holdings = maker_holdings.available_holdings[fill.symbol as usize];
-                        maker_holdings.available_holdings[fill.symbol as usize]= maker_avail_holdings + fill.quantity;
-
-                        // holding update for the go cache 
-                        let maker_holding_update = HoldingResponse{
-                            user_id : fill.maker_user_id , 
-                            symbol : fill.symbol ,
-                            delta_available_holding : self.get_i32(fill.quantity).unwrap(),
-                            delta_reserved_holding : 0 
-                        };
-
-                        emit(BaseLogs::HoldingDelta(HoldingDelta { 
-                            order_id: fill.maker_order_id, 
-                            event_id: next_id(), 
-                            user_id: fill.maker_user_id, 
-                            symbol: fill.symbol, 
-                            delta_available: self.get_i32(fill.quantity).unwrap(), 
-                            delta_reserved: 0, 
-                            reason: 1 
-                        }));
-                    
-                    (taker_balance_update , taker_holding_update , maker_balance_update , maker_holding_update)
-                         
-                }   
-                
-                Side::Bid => {
-                    // Taker is buying , incoming is a buying order 
+    /// kill switch for one user , its new orders are rejected and its withdrawals refused until unfrozen
+    /// returns the orders it has open in order id order , the caller takes them off the books
+    pub fn freeze_user(&mut self , user_id : u64)->Result<Vec<OrderToBeCanceled> , BalanceManagerError>{
+        self.get_user_index(user_id)?;
+        self.state.frozen.insert(user_id);
+        let mut open : Vec<OrderToBeCanceled> = self.state.reservations.iter()
+            .filter(|(_ , reservation)| reservation.user_id == user_id)
+            .map(|(order_id , reservation)| OrderToBeCanceled { order_id : *order_id , user_id , symbol : reservation.symbol })
+            .collect();
+        open.sort_unstable_by_key(|order| order.order_id);
+        Ok(open)
+    }
 
-                       let  taker_balance = self.get_user_balance(taker_index);
-                       let taker_reserved_bal = taker_balance.reserved_balance;
-                       taker_balance.reserved_balance= taker_reserved_bal - fill_value;
-
-                       let taker_balance_update = BalanceResponse{
-                        user_id : fill.taker_user_id , 
-                        delta_available_balance : 0 ,
-                        delta_reserved_balance : -self.get_i64(fill_value).unwrap()
-                       };
-                       emit(BaseLogs::BalanceDelta(BalanceDelta { 
-                        event_id: next_id(), 
-                        user_id: fill.taker_user_id, 
-                        delta_available: 0, 
-                        delta_reserved: -self.get_i64(fill_value).unwrap(), 
-                        order_id: fill.taker_order_id, 
-                        reason: 1 
-                    }));
-
-
-
-
-                       
-                       let  taker_holdings = self.get_user_holdings(taker_index);
-                       let taker_avail_holdings = taker_holdings.available_holdings[fill.symbol as usize];
-                       taker_holdings.available_holdings[fill.symbol as usize]= taker_avail_holdings + fill.quantity;
-
-                       let taker_holding_update = HoldingResponse{
-                            user_id : fill.taker_user_id , 
-                            symbol : fill.symbol ,
-                            delta_available_holding : self.get_i32(fill.quantity).unwrap(),
-                            delta_reserved_holding : 0
-                       };
-                       emit(BaseLogs::HoldingDelta(HoldingDelta { 
-                        order_id: fill.taker_order_id, 
-                        event_id: next_id(), 
-                        user_id: fill.taker_user_id, 
-                        symbol: fill.symbol, 
-                        delta_available: self.get_i32(fill.quantity).unwrap(), 
-                        delta_reserved: 0, 
-                        reason: 1 
-                    }));
-
-
-
-                       let  maker_balance = self.get_user_balance(maker_index);
-                       let maker_avail_bal = maker_balance.available_balance;
-                       maker_balance.available_balance= maker_avail_bal + fill_value;
-
-                      let maker_balance_update = BalanceResponse{
-                        user_id : fill.maker_user_id , 
-                        delta_available_balance : self.get_i64(fill_value).unwrap(),
-                        delta_reserved_balance : 0 
-                      };
-
-                      emit(BaseLogs::BalanceDelta(BalanceDelta { 
-                        event_id: next_id(), 
-                        user_id: fill.maker_user_id, 
-                        delta_available: self.get_i64(fill_value).unwrap(), 
-                        delta_reserved: 0, 
-                        order_id: fill.maker_order_id, 
-                        reason: 1 
-                    }));
-                    
-            
-                    
-                       let  maker_holdings = self.get_user_holdings(maker_index);
-                       let maker_reserved_holdings = maker_holdings.reserved_holdings[fill.symbol as usize];
-                       maker_holdings.reserved_holdings[fill.symbol as usize]= maker_reserved_holdings - fill.quantity;
-
-
-                       let maker_holding_update = HoldingResponse{
-                        user_id : fill.maker_user_id , 
-                        symbol : fill.symbol , 
-                        delta_available_holding : 0 , 
-                        delta_reserved_holding : -self.get_i32(fill.quantity).unwrap()
-                       };
-                       emit(BaseLogs::HoldingDelta(HoldingDelta { 
-                        order_id: fill.maker_order_id, 
-                        event_id: next_id(), 
-                        user_id: fill.maker_user_id, 
-                        symbol: fill.symbol, 
-                        delta_available: 0, 
-                        delta_reserved: -self.get_i32(fill.quantity).unwrap(), 
-                        reason: 1 
-                    }));
-                       
-                       (taker_balance_update , taker_holding_update , maker_balance_update , maker_holding_update)
-                        
-                }
+    pub fn unfreeze_user(&mut self , user_id : u64)->Result<() , BalanceManagerError>{
+        self.get_user_index(user_id)?;
+        self.state.frozen.remove(&user_id);
+        Ok(())
+    }
+
+    pub fn is_frozen(&self , user_id : u64)->bool{
+        self.state.frozen.contains(&user_id)
+    }
+
+    /// starts a new trading day once `now` , the time of the command in nanos , is past the current one
+    /// every user's daily counters go back to zero and fee volume older than the window is dropped
+    /// a clock that went backwards changes nothing
+    pub fn roll_day(&mut self , now : u64)->bool{
+        let day = now / NANOS_PER_DAY;
+        if day <= self.state.trading_day {
+            return false;
+        }
+        self.state.trading_day = day;
+        for entry in self.state.user_id_to_index.iter() {
+            let balance = &mut self.state.balances[*entry.value() as usize];
+            balance.total_traded_today = 0;
+            balance.order_count_today = 0;
+        }
+        let window = self.fees.window_days as u64;
+        self.state.fee_volume.retain(|_ , volume| !volume.expire(day, window));
+        true
+    }
+
+    /// takes a token from the user's bucket for `action` , `now` is the time of the command in nanos
+    /// refused with Throttled when the bucket is empty , an unknown user has no buckets and is refused further on
+    pub fn throttle(&mut self , user_id : u64 , action : RateAction , now : u64)->Result<() , BalanceManagerError>{
+        if self.get_user_index(user_id).is_err() {
+            return Ok(());
+        }
+        let limit = self.rate_limits.class(user_id).bucket(action);
+        self.state.rates.take(user_id, action, limit, now).map_err(BalanceManagerError::Throttled)
+    }
+
+    /// the limits `user_id` trades under , the admin set ones over the config
+    pub fn limits_of(&self , user_id : u64)->RiskLimits{
+        self.state.risk.overrides.get(&user_id).copied().unwrap_or_else(|| self.limits.limits(user_id))
+    }
+
+    /// changes one of a user's limits live , the others stay what they were
+    pub fn set_limit(&mut self , user_id : u64 , kind : LimitKind , value : u64)->Result<() , BalanceManagerError>{
+        self.get_user_index(user_id)?;
+        let mut limits = self.limits_of(user_id);
+        limits.set(kind, value);
+        self.state.risk.overrides.insert(user_id, limits);
+        Ok(())
+    }
+
+    fn check_limits(&self , order : &Order)->Result<() , BalanceManagerError>{
+        let index = self.get_user_index(order.user_id)? as usize;
+        let base = self.pair(order.symbol).0;
+        let slot = Some(index);
+        let exposure = Exposure {
+            open_orders : self.state.risk.open_orders.get(&order.user_id).copied().unwrap_or(0),
+            position : (self.account_balance(slot, Account::Available(order.user_id), base) + self.account_balance(slot, Account::Reserved(order.user_id), base)) as u64,
+            traded_today : self.state.balances[index].total_traded_today,
+            last_price : self.state.risk.last_prices.get(&order.symbol).copied().unwrap_or(0),
+        };
+        let terms = OrderTerms { symbol : order.symbol , is_bid : order.side == Side::Bid , is_limit : order.order_type == 1 , price : order.price , qty : order.shares_qty };
+        self.limits_of(order.user_id).check(terms, exposure).map_err(BalanceManagerError::RiskLimit)
+    }
+
+    /// each fill is one entry : the buyer's reserved quote goes to the seller and the seller's reserved base to the buyer
+    /// each side's fee follows as an entry of its own in the quote asset and is stamped on the fill once posted
+    /// the book already matched , so a refused fill never stops the rest : both orders give back what they locked for it
+    /// and the first refusal is returned once every fill was settled
+    pub fn update_balances_after_trade<F , G>(&mut self, order_fills: &mut Fills , mut emit : F , mut next_id : G)-> Result<(), BalanceManagerError>  where F : FnMut(BaseLogs) , G : FnMut()->u64 {
+        let mut refused = None;
+        for fill in order_fills.fills.iter_mut() {
+            let (buyer , buyer_order , seller , seller_order) = match fill.taker_side {
+                Side::Bid => (fill.taker_user_id , fill.taker_order_id , fill.maker_user_id , fill.maker_order_id),
+                Side::Ask => (fill.maker_user_id , fill.maker_order_id , fill.taker_user_id , fill.taker_order_id),
             };
+            let fill_value = fill.price * fill.quantity as u64;
+            let (base , quote) = self.pair(fill.symbol);
+            let entry = Entry::new(DELTA_REASON_FILL)
+                .post(Account::Reserved(buyer), quote, -(fill_value as i128), buyer_order)
+                .post(Account::Available(seller), quote, fill_value as i128, seller_order)
+                .post(Account::Reserved(seller), base, -(fill.quantity as i128), seller_order)
+                .post(Account::Available(buyer), base, fill.quantity as i128, buyer_order);
+            if let Err(e) = self.post(&entry, &mut emit, &mut next_id) {
+                eprintln!("[BM] fill of {} between orders {} and {} refused , unwinding it: {}", fill.quantity, fill.taker_order_id, fill.maker_order_id, e);
+                self.unwind_fill(buyer_order, fill.quantity, &mut emit, &mut next_id);
+                self.unwind_fill(seller_order, fill.quantity, &mut emit, &mut next_id);
+                refused.get_or_insert(BalanceManagerError::from(e));
+                continue;
+            }
 
-            // send udates to the writter for the maker and the taker indexes 
-            let _ = self.balance_updates_sender.push(maker_balance_update);
-            let _ = self.balance_updates_sender.push(taker_balance_update);
-            let _ = self.holding_update_sender.push(maker_holding_update);
-            let _ = self.holding_update_sender.push(taker_holding_update);
-            
+            // rates follow the volume each user had before this fill
+            let maker_fee = fee_on(fill_value, self.fee_rates(fill.maker_user_id, fill.symbol).0);
+            let taker_fee = fee_on(fill_value, self.fee_rates(fill.taker_user_id, fill.symbol).1);
+            let fee_account = self.fees.fee_account.map(Account::Available).unwrap_or(Account::Fees);
+            // each side's fee is an entry of its own , a rebate the fee account cannot pay leaves the taker fee charged
+            // the taker goes first , it never gets a rebate and what it pays can fund the maker's
+            let taker_is_buyer = fill.taker_side == Side::Bid;
+            let mut buyer_paid = 0;
+            for (is_taker , user_id , order_id , fee) in [(true , fill.taker_user_id , fill.taker_order_id , taker_fee) , (false , fill.maker_user_id , fill.maker_order_id , maker_fee)] {
+                let is_buyer = is_taker == taker_is_buyer;
+                // the buyer pays out of the headroom its bid locked , the seller out of what it was just paid
+                let payer = if is_buyer { Account::Reserved(user_id) } else { Account::Available(user_id) };
+                let entry = match fee >= 0 {
+                    true => Entry::new(DELTA_REASON_FEE).transfer(payer, fee_account, quote, fee as u64, order_id),
+                    false => Entry::new(DELTA_REASON_FEE).transfer(fee_account, Account::Available(user_id), quote, fee.unsigned_abs(), order_id),
+                };
+                if let Err(e) = self.post(&entry, &mut emit, &mut next_id) {
+                    eprintln!("[BM] fee of {} for order {} refused: {}", fee, order_id, e);
+                    continue;
+                }
+                if is_taker {
+                    fill.taker_fee = fee;
+                } else {
+                    fill.maker_fee = fee;
+                }
+                if is_buyer {
+                    buyer_paid = fee.max(0) as u64;
+                }
+            }
+            for user_id in [buyer , seller] {
+                let Ok(index) = self.get_user_index(user_id) else {
+                    continue;
+                };
+                let balance = &mut self.state.balances[index as usize];
+                balance.total_traded_today = balance.total_traded_today.saturating_add(fill_value);
+                self.state.fee_volume.entry((user_id , quote)).or_default().record(self.state.trading_day, fill_value, self.fees.window_days as u64);
+            }
+            self.state.risk.last_prices.insert(fill.symbol, fill.price);
+            self.state.positions.entry((buyer , fill.symbol)).or_default().apply(Side::Bid, fill.price, fill.quantity);
+            self.state.positions.entry((seller , fill.symbol)).or_default().apply(Side::Ask, fill.price, fill.quantity);
+
+            // both orders give up exactly what the fill took from their reservations
+            self.settle_fill(buyer_order, fill.price, fill.quantity, buyer_paid, &mut emit, &mut next_id);
+            self.settle_fill(seller_order, fill.price, fill.quantity, 0, &mut emit, &mut next_id);
         }
+        refused.map_or(Ok(()), Err)
+    }
+
+    /// users 10 and 20 with enough cash , and 20 with shares of every symbol , for the producer's throughput runs
+    /// test and benchmark builds only , a deployment opens its users through the admin commands
+    #[cfg(any(test , feature = "throughput-users"))]
+    pub fn add_throughput_test_users<F , G>(&mut self , mut emit : F , mut next_id : G)->Result<() , BalanceManagerError> where F : FnMut(BaseLogs) , G : FnMut()->u64 {
+        const HIGH_BALANCE : u64 = 100_000_000_000;
+        const HIGH_HOLDINGS : u32 = 1_000_000_000;
+        for (user_id , holdings) in [(10 , 0) , (20 , HIGH_HOLDINGS)] {
+            if self.state.user_id_to_index.contains_key(&user_id){
+                return Err(BalanceManagerError::UserAlreadyExists);
+            }
+            if self.state.next_free_slot as usize >= MAX_USERS {
+                return Err(BalanceManagerError::MaxUsersReached);
+            }
+            let idx = self.state.next_free_slot;
+            self.state.next_free_slot += 1;
+            self.state.total_users += 1;
+            self.state.balances[idx as usize] = UserBalance::with_balance(user_id, 0);
+            // listed now or later
+            self.state.holdings[idx as usize] = UserHoldings::with_default(user_id, holdings);
+            self.state.user_id_to_index.insert(user_id, idx);
+            self.open_account(user_id, HIGH_BALANCE, &mut emit, &mut next_id);
+        }
+        eprintln!("[BM] throughput users 10 and 20 opened with {} each , 20 holds {} of every symbol", HIGH_BALANCE, HIGH_HOLDINGS);
+        Ok(())
+    }
+    /// releases what the canceled order still has reserved , `book_qty` is what the book had left of it and is only cross checked
+    /// refused with NotOrderOwner when the order belongs to another user , its reservation is left alone
+    pub fn update_balance_after_order_cancel<F , G>(&mut self , canceled_order : OrderToBeCanceled , book_qty : u32 , mut emit : F , mut next_id : G)->Result<() , BalanceManagerError> where F : FnMut(BaseLogs) , G : FnMut()->u64 {
+        self.get_user_index(canceled_order.user_id)?;
+        if self.state.reservations.get(&canceled_order.order_id).is_some_and(|reservation| reservation.user_id != canceled_order.user_id) {
+            return Err(BalanceManagerError::NotOrderOwner);
+        }
+        let reservation = self.state.close_reservation(canceled_order.order_id).ok_or(BalanceManagerError::ReservationNotFound)?;
+        if reservation.remaining_qty != book_qty {
+            eprintln!("[BM] order {} had {} left on the book , {} in its reservation", canceled_order.order_id, book_qty, reservation.remaining_qty);
+        }
+        self.release_reservation(canceled_order.order_id, reservation, &mut emit, &mut next_id);
         Ok(())
-        
     }
 
-    pub fn add_throughput_test_users(&mut self) {
-        const HIGH_BALANCE: u64 = 100_000_000_000;
-        const HIGH_HOLDINGS: u32 = 1_000_000_000;
-        
-        eprintln!("[BM]  INITIALIZING THROUGHPUT USERS...");
-        
-        // Add user 10 (buyer)
-        self.state.user_id_to_index.insert(10, 1);
-        self.state.balances[1].user_id =10;
-        self.state.balances[1].available_balance= HIGH_BALANCE;
-        
-        // Add user 20 (seller)
-        self.state.user_id_to_index.insert(20, 2);
-        self.state.balances[2].user_id=20;
-        self.state.balances[2].available_balance = HIGH_BALANCE;
-        
-        // Give seller holdings for symbol 0
-        for symbol in 0..MAX_SYMBOLS {
-            self.state.holdings[2].available_holdings[symbol] = HIGH_HOLDINGS;
+    /// what `user_id` traded in `quote` over the fee window ending today
+    pub fn fee_volume(&self , user_id : u64 , quote : Asset)->u64{
+        self.state.fee_volume.get(&(user_id , quote)).map(|window| window.volume(self.state.trading_day, self.fees.window_days as u64)).unwrap_or(0)
+    }
+
+    // (maker , taker) rates of `user_id` on `symbol` at the volume it traded in the symbol's quote asset within the window
+    fn fee_rates(&self , user_id : u64 , symbol : u32)->(i32 , i32){
+        self.fees.schedule(symbol).rates(self.fee_volume(user_id, self.pair(symbol).1))
+    }
+
+    // an order filled `qty` at `fill_price` , the fill and `fee_paid` out of its reservation already came out of the aggregates
+    // a bid also frees what was locked above the fill price and above the fee it was charged ,
+    // and either side gives back the whole rest once it is filled
+    fn settle_fill<F , G>(&mut self , order_id : u64 , fill_price : u64 , qty : u32 , fee_paid : u64 , emit : &mut F , next_id : &mut G) where F : FnMut(BaseLogs) , G : FnMut()->u64 {
+        let Some(reservation) = self.state.reservations.get_mut(&order_id) else {
+            eprintln!("[BM] fill for order {} without a reservation", order_id);
+            return;
+        };
+        let locked_for_fill = match reservation.side {
+            Side::Bid => bid_lock(reservation.price, qty, reservation.fee_bps).unwrap_or(u64::MAX),
+            Side::Ask => qty as u64,
+        };
+        reservation.reserved = reservation.reserved.saturating_sub(locked_for_fill);
+        reservation.remaining_qty = reservation.remaining_qty.saturating_sub(qty);
+        let (user_id , symbol , side , filled) = (reservation.user_id , reservation.symbol , reservation.side , reservation.remaining_qty == 0);
+        if side == Side::Bid {
+            let improvement = locked_for_fill.saturating_sub(fill_price * qty as u64 + fee_paid);
+            self.release(self.pair(symbol).1, user_id, order_id, improvement, emit, next_id);
+        }
+        if filled && let Some(reservation) = self.state.close_reservation(order_id) {
+            self.release_reservation(order_id, reservation, emit, next_id);
+        }
+    }
+
+    // the book took `qty` of the order in a fill whose entry was refused , what the order locked for it goes back
+    // so the reservation keeps matching the book , which no longer has that quantity
+    fn unwind_fill<F , G>(&mut self , order_id : u64 , qty : u32 , emit : &mut F , next_id : &mut G) where F : FnMut(BaseLogs) , G : FnMut()->u64 {
+        let Some(reservation) = self.state.reservations.get_mut(&order_id) else {
+            return;
+        };
+        let locked_for_fill = match reservation.side {
+            Side::Bid => bid_lock(reservation.price, qty, reservation.fee_bps).unwrap_or(u64::MAX),
+            Side::Ask => qty as u64,
+        }.min(reservation.reserved);
+        reservation.reserved -= locked_for_fill;
+        reservation.remaining_qty = reservation.remaining_qty.saturating_sub(qty);
+        let (user_id , symbol , side , filled) = (reservation.user_id , reservation.symbol , reservation.side , reservation.remaining_qty == 0);
+        self.release(self.locked_asset(symbol, side), user_id, order_id, locked_for_fill, emit, next_id);
+        if filled && let Some(reservation) = self.state.close_reservation(order_id) {
+            self.release_reservation(order_id, reservation, emit, next_id);
         }
-        
-        let user10_bal = self.state.balances[1].available_balance;
-        let user20_bal = self.state.balances[2].available_balance;
-        let user20_holdings = self.state.holdings[2].available_holdings[0];
-        
-        eprintln!("[BM] User 10 balance: {}", user10_bal);
-        eprintln!("[BM] User 20 balance: {}", user20_bal);
-        eprintln!("[BM] User 20 holdings[0]: {}", user20_holdings);
-        eprintln!("[BM] User map contains 10: {}", self.state.user_id_to_index.contains_key(&10));
-        eprintln!("[BM] User map contains 20: {}", self.state.user_id_to_index.contains_key(&20));
     }
-    pub fn update_balance_after_order_cancel(&mut self , canceled_order : OrderToBeCanceled , side : Side , qty : u32 , price : u64 )->Result<() , BalanceManagerError>{
-        let user_index = self.get_user_index(canceled_order.user_id)?;
-        // understand what happens with thw ? mark here 
-        match side {
-            Side::Ask => {
-                
-                // side was ask , he was selling so this was only chnaged , order wsent fullfilled , no use of price 
-                let old_reserved_holdings = self.state.holdings[user_index as usize].reserved_holdings[canceled_order.symbol as usize];
-                let old_available_holdings = self.state.holdings[user_index as usize].available_holdings[canceled_order.symbol as usize];
 
-                self.state.holdings[user_index as usize].reserved_holdings[canceled_order.symbol as usize] = old_reserved_holdings - qty;
-                self.state.holdings[user_index as usize].available_holdings[canceled_order.symbol as usize] = old_available_holdings + qty;
+    /// gives back whatever an order that ends without resting still has reserved , a market order's unfilled rest
+    pub fn release_order<F , G>(&mut self , order_id : u64 , mut emit : F , mut next_id : G) where F : FnMut(BaseLogs) , G : FnMut()->u64 {
+        let Some(reservation) = self.state.close_reservation(order_id) else {
+            return;
+        };
+        self.release_reservation(order_id, reservation, &mut emit, &mut next_id);
+    }
 
+    /// proves the aggregate reserved balances , holdings and wallets are exactly the sums of the open reservations
+    /// every aggregate that is off is returned , none means the ledger and the aggregates agree
+    pub fn reconcile_reservations(&self)->Vec<ReservationMismatch>{
+        let mut open : FxHashMap<(u64 , Asset) , u64> = FxHashMap::default();
+        let mut mismatches = Vec::new();
+        for (order_id , reservation) in self.state.reservations.iter() {
+            if !self.state.user_id_to_index.contains_key(&reservation.user_id) {
+                mismatches.push(ReservationMismatch::UnknownUser { order_id : *order_id , user_id : reservation.user_id });
+                continue;
             }
-            Side::Bid =>{
+            *open.entry((reservation.user_id , self.locked_asset(reservation.symbol, reservation.side))).or_default() += reservation.reserved;
+        }
+        let mut aggregates : Vec<(u64 , Asset , u64)> = Vec::new();
+        for entry in self.state.user_id_to_index.iter() {
+            let (user_id , index) = (*entry.key() , *entry.value() as usize);
+            aggregates.push((user_id , Asset::Cash , self.state.balances[index].reserved_balance));
+            for (symbol , holding) in self.state.holdings[index].positions.iter() {
+                aggregates.push((user_id , Asset::Shares(*symbol) , holding.reserved as u64));
+            }
+        }
+        for ((user_id , asset) , wallet) in self.state.wallets.iter() {
+            aggregates.push((*user_id , Asset::Coin(*asset) , wallet.reserved));
+        }
+        for (user_id , asset , aggregate) in aggregates {
+            let open = open.remove(&(user_id , asset)).unwrap_or(0);
+            if aggregate != open {
+                mismatches.push(ReservationMismatch::of(user_id, asset, aggregate, open));
+            }
+        }
+        // open orders holding an asset the user has no entry for
+        for ((user_id , asset) , open) in open {
+            if open != 0 {
+                mismatches.push(ReservationMismatch::of(user_id, asset, 0, open));
+            }
+        }
+        mismatches
+    }
 
-                // he was buying , his ballcne wud have been reserved 
-                let old_reserved_balance = self.get_user_balance(user_index).reserved_balance;
-                let old_available_balance = self.get_user_balance(user_index).available_balance;
+    // everything `reservation` still holds goes back to available
+    fn release_reservation<F , G>(&mut self , order_id : u64 , reservation : OrderReservation , emit : &mut F , next_id : &mut G) where F : FnMut(BaseLogs) , G : FnMut()->u64 {
+        let asset = self.locked_asset(reservation.symbol, reservation.side);
+        self.release(asset, reservation.user_id, order_id, reservation.reserved, emit, next_id);
+    }
 
-                self.state.balances[user_index as usize].available_balance = old_available_balance + price*qty as u64;
-                self.state.balances[user_index as usize].reserved_balance = old_reserved_balance - price*qty as u64;
-                
+    // reserved back to available , logged and sent to the cache like every other entry
+    fn release<F , G>(&mut self , asset : Asset , user_id : u64 , order_id : u64 , amount : u64 , emit : &mut F , next_id : &mut G) where F : FnMut(BaseLogs) , G : FnMut()->u64 {
+        let entry = Entry::new(DELTA_REASON_RELEASE).transfer(Account::Reserved(user_id), Account::Available(user_id), asset, amount, order_id);
+        if let Err(e) = self.post(&entry, &mut *emit, &mut *next_id) {
+            eprintln!("[BM] release for order {} refused: {}", order_id, e);
+        }
+    }
+
+    /// applies a deposit , withdrawal or transfer once per key , logged and sent to the cache like any other balance change
+    /// a withdrawal or the sending side of a transfer only ever takes from available , never from what open orders reserved
+    pub fn apply_funds<F , G>(&mut self , key : u64 , request : FundsRequest , mut emit : F , mut next_id : G)->Result<FundsOutcome , FundsError> where F : FnMut(BaseLogs) , G : FnMut()->u64 {
+        let amount = match request {
+            FundsRequest::Deposit { amount , .. } | FundsRequest::Withdraw { amount , .. } | FundsRequest::Transfer { amount , .. } => amount,
+            FundsRequest::Adjust { delta , .. } => delta.unsigned_abs(),
+        };
+        if amount == 0 {
+            return Err(FundsError::ZeroAmount);
+        }
+        if self.state.funds.seen(key, &request)? {
+            return Ok(FundsOutcome::Duplicate);
+        }
+        let movements = match request {
+            FundsRequest::Deposit { user_id , asset , amount } => {
+                let index = self.funds_index(user_id)?;
+                self.check_credit(index, user_id, asset, amount)?;
+                let entry = Entry::new(DELTA_REASON_DEPOSIT).transfer(Account::System, Account::Available(user_id), asset, amount, key);
+                self.post_funds(&entry, user_id, &mut emit, &mut next_id)?;
+                vec![self.movement(key, index, user_id, MovementKind::Deposit, asset, amount)]
+            }
+            FundsRequest::Withdraw { user_id , asset , amount } => {
+                let index = self.funds_index(user_id)?;
+                self.check_not_frozen(user_id)?;
+                self.check_debit(index, user_id, asset, amount)?;
+                let entry = Entry::new(DELTA_REASON_WITHDRAWAL).transfer(Account::Available(user_id), Account::System, asset, amount, key);
+                self.post_funds(&entry, user_id, &mut emit, &mut next_id)?;
+                vec![self.movement(key, index, user_id, MovementKind::Withdrawal, asset, amount)]
+            }
+            FundsRequest::Transfer { from , to , asset , amount } => {
+                if from == to {
+                    return Err(FundsError::SelfTransfer(from));
+                }
+                let from_index = self.funds_index(from)?;
+                let to_index = self.funds_index(to)?;
+                // a frozen user can be paid , nothing leaves it
+                self.check_not_frozen(from)?;
+                // both legs are checked before either is applied
+                self.check_debit(from_index, from, asset, amount)?;
+                self.check_credit(to_index, to, asset, amount)?;
+                let entry = Entry::new(DELTA_REASON_TRANSFER).transfer(Account::Available(from), Account::Available(to), asset, amount, key);
+                self.post_funds(&entry, from, &mut emit, &mut next_id)?;
+                vec![
+                    self.movement(key, from_index, from, MovementKind::TransferOut, asset, amount),
+                    self.movement(key, to_index, to, MovementKind::TransferIn, asset, amount),
+                ]
+            }
+            // a correction leaves reserved funds alone and is allowed on a frozen user , it is the operator's
+            FundsRequest::Adjust { user_id , asset , delta } => {
+                let index = self.funds_index(user_id)?;
+                let (entry , kind) = if delta > 0 {
+                    self.check_credit(index, user_id, asset, amount)?;
+                    (Entry::new(DELTA_REASON_ADJUSTMENT).transfer(Account::System, Account::Available(user_id), asset, amount, key) , MovementKind::AdjustmentCredit)
+                } else {
+                    self.check_debit(index, user_id, asset, amount)?;
+                    (Entry::new(DELTA_REASON_ADJUSTMENT).transfer(Account::Available(user_id), Account::System, asset, amount, key) , MovementKind::AdjustmentDebit)
+                };
+                self.post_funds(&entry, user_id, &mut emit, &mut next_id)?;
+                vec![self.movement(key, index, user_id, kind, asset, amount)]
             }
         };
+        self.state.funds.record(key, request, &movements);
+        Ok(FundsOutcome::Applied)
+    }
+
+    fn funds_index(&self , user_id : u64)->Result<u32 , FundsError>{
+        self.get_user_index(user_id).map_err(|_| FundsError::UnknownUser(user_id))
+    }
 
+    fn check_not_frozen(&self , user_id : u64)->Result<() , FundsError>{
+        if self.is_frozen(user_id) {
+            return Err(FundsError::Frozen(user_id));
+        }
         Ok(())
-        
     }
 
-    pub fn add_user(&mut self , user_id : u64)->Result<u32 , BalanceManagerError>{
+    fn available_of(&self , index : u32 , asset : Asset)->u64{
+        match asset {
+            Asset::Cash => self.state.balances[index as usize].available_balance,
+            Asset::Shares(symbol) => self.state.holdings[index as usize].available(symbol) as u64,
+            Asset::Coin(id) => self.state.wallets.get(&(self.state.balances[index as usize].user_id , id)).map(|wallet| wallet.available).unwrap_or(0),
+        }
+    }
+
+    fn check_debit(&self , index : u32 , user_id : u64 , asset : Asset , amount : u64)->Result<() , FundsError>{
+        let available = self.available_of(index, asset);
+        if amount > available {
+            return Err(FundsError::InsufficientAvailable { user_id , available , requested : amount });
+        }
+        Ok(())
+    }
+
+    // the deltas logged and sent to the cache are signed 64 bit for cash and wallet assets and 32 bit for shares
+    fn check_credit(&self , index : u32 , user_id : u64 , asset : Asset , amount : u64)->Result<() , FundsError>{
+        let (delta_limit , ceiling) = match asset {
+            Asset::Cash | Asset::Coin(_) => (i64::MAX as u64 , u64::MAX),
+            Asset::Shares(_) => (i32::MAX as u64 , u32::MAX as u64),
+        };
+        let fits = self.available_of(index, asset).checked_add(amount).is_some_and(|after| after <= ceiling);
+        if amount > delta_limit || !fits {
+            return Err(FundsError::Overflow(user_id));
+        }
+        Ok(())
+    }
+
+    // the checks above leave only the house side to fail , a system account past i64
+    fn post_funds<F , G>(&mut self , entry : &Entry , user_id : u64 , emit : &mut F , next_id : &mut G)->Result<() , FundsError> where F : FnMut(BaseLogs) , G : FnMut()->u64 {
+        self.post(entry, &mut *emit, &mut *next_id).map_err(|e| {
+            eprintln!("[BM] funds entry refused: {}", e);
+            FundsError::Overflow(user_id)
+        })
+    }
+
+    // the key stands in for the order id in the delta logs
+    fn movement(&self , key : u64 , index : u32 , user_id : u64 , kind : MovementKind , asset : Asset , amount : u64)->FundsMovement{
+        FundsMovement { key , user_id , kind , asset , amount , available_after : self.available_of(index, asset) }
+    }
+
+    pub fn add_user<F , G>(&mut self , user_id : u64 , emit : F , next_id : G)->Result<u32 , BalanceManagerError> where F : FnMut(BaseLogs) , G : FnMut()->u64 {
         if self.state.user_id_to_index.contains_key(&user_id){
             return Err(BalanceManagerError::UserAlreadyExists);
         }
@@ -908,14 +1538,15 @@ impl STbalanceManager{
         self.state.next_free_slot += 1;
         self.state.total_users += 1;
 
-        self.state.balances[idx as usize] = UserBalance::new(user_id);
-        self.state.holdings[idx as usize] = UserHoldings::new(user_id);
+        self.state.balances[idx as usize] = UserBalance::with_balance(user_id, 0);
+        self.state.holdings[idx as usize] = UserHoldings::with_default(user_id, self.defaults.holding_qty);
         
         self.state.user_id_to_index.insert(user_id, idx);
+        self.open_account(user_id, self.defaults.balance, emit, next_id);
         Ok(idx)
     }
 
-    pub fn add_market_maker(&mut self)->Result<u32 , BalanceManagerError>{
+    pub fn add_market_maker<F , G>(&mut self , emit : F , next_id : G)->Result<u32 , BalanceManagerError> where F : FnMut(BaseLogs) , G : FnMut()->u64 {
         if self.state.user_id_to_index.contains_key(&0){
             return Err(BalanceManagerError::UserAlreadyExists);
         }
@@ -926,10 +1557,11 @@ impl STbalanceManager{
         self.state.next_free_slot += 1;
         self.state.total_users += 1;
 
-        self.state.balances[idx as usize] = UserBalance::market_maker(0);
-        self.state.holdings[idx as usize] = UserHoldings::new(0);
+        self.state.balances[idx as usize] = UserBalance::with_balance(0, 0);
+        self.state.holdings[idx as usize] = UserHoldings::with_default(0, self.defaults.market_maker_holding_qty);
         
         self.state.user_id_to_index.insert(0, idx);
+        self.open_account(0, self.defaults.market_maker_balance, emit, next_id);
         Ok(idx)
     }
 
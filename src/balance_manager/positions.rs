@@ -0,0 +1,64 @@
+// per user and symbol positions with their cost basis , moved by fills only
+// deposits , withdrawals and default holdings are not trades , a user selling what it was given goes short here
+// realized pnl is booked when a fill closes part of a position , against the average entry price
+// unrealized pnl is what is left open marked to the symbol's mark price , fees are on the fills and not in either
+use crate::orderbook::order::Side;
+
+#[derive(Debug , Clone , Copy , Default , PartialEq , Eq)]
+pub struct Position{
+    // signed , short below zero
+    pub qty : i64,
+    // what the open quantity was entered at , signed like qty
+    pub cost : i128,
+    pub realized_pnl : i64,
+}
+
+impl Position{
+    /// books a fill of `qty` at `price` , returns the pnl it realized
+    /// a fill larger than the position closes it and opens the rest the other way at the fill price
+    pub fn apply(&mut self , side : Side , price : u64 , qty : u32)->i64{
+        let price = price as i128;
+        let mut delta = match side {
+            Side::Bid => qty as i64,
+            Side::Ask => -(qty as i64),
+        };
+        let mut realized = 0i128;
+        if self.qty != 0 && self.qty.signum() != delta.signum() {
+            let closed = delta.unsigned_abs().min(self.qty.unsigned_abs()) as i128;
+            // the closed part's share of the entry cost , signed like the position
+            let basis = self.cost * closed / self.qty.unsigned_abs() as i128;
+            realized = self.qty.signum() as i128 * price * closed - basis;
+            self.cost -= basis;
+            self.qty += delta.signum() * closed as i64;
+            delta -= delta.signum() * closed as i64;
+        }
+        if delta != 0 {
+            self.qty += delta;
+            self.cost += delta as i128 * price;
+        }
+        if self.qty == 0 {
+            self.cost = 0;
+        }
+        let realized = realized as i64;
+        self.realized_pnl += realized;
+        realized
+    }
+
+    /// entry cost over the open quantity , 0 when flat
+    pub fn avg_entry_price(&self)->u64{
+        if self.qty == 0 {
+            return 0;
+        }
+        (self.cost / self.qty as i128) as u64
+    }
+
+    /// what closing the open quantity at `mark` would realize
+    pub fn unrealized_pnl(&self , mark : u64)->i64{
+        (mark as i128 * self.qty as i128 - self.cost) as i64
+    }
+
+    /// nothing open and nothing booked , not worth keeping
+    pub fn is_empty(&self)->bool{
+        self.qty == 0 && self.realized_pnl == 0
+    }
+}
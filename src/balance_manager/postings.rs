@@ -0,0 +1,129 @@
+// double entry postings , every change to a balance or a holding is one entry
+// an entry is a set of postings that sums to zero per asset : whatever leaves one account lands in another
+// user accounts are the available and reserved columns of UserBalance / UserHoldings , the house accounts live here
+// fees collects trading fees , system is the outside world : deposits come out of it and withdrawals go back in
+// so system runs negative by everything users and fees hold , and the accounts of an asset always sum to zero
+// a user's default holding (UserHoldings::default_available) is an opening balance outside the ledger , only what moved from it counts
+use rustc_hash::FxHashMap;
+use smallvec::SmallVec;
+use thiserror::Error;
+use crate::balance_manager::funds_ledger::Asset;
+use crate::orderbook::types::BalanceManagerError;
+
+#[derive(Debug , Clone , Copy , PartialEq , Eq , Hash)]
+pub enum Account{
+    Available(u64),
+    Reserved(u64),
+    Fees,
+    System,
+}
+
+impl Account{
+    pub fn user_id(self)->Option<u64>{
+        match self {
+            Self::Available(user_id) | Self::Reserved(user_id) => Some(user_id),
+            Self::Fees | Self::System => None,
+        }
+    }
+}
+
+#[derive(Debug , Clone , Copy , PartialEq , Eq)]
+pub struct Posting{
+    pub account : Account,
+    pub asset : Asset,
+    // signed , what the account gains
+    pub amount : i128,
+    // what the delta logs of a user posting are attributed to , an order id or a funds key
+    pub order_id : u64,
+}
+
+#[derive(Debug , Clone , PartialEq , Eq)]
+pub struct Entry{
+    // the reason the delta logs carry
+    pub reason : u8,
+    pub postings : SmallVec<[Posting ; 6]>,
+}
+
+impl Entry{
+    pub fn new(reason : u8)->Self{
+        Self { reason , postings : SmallVec::new() }
+    }
+
+    pub fn post(mut self , account : Account , asset : Asset , amount : i128 , order_id : u64)->Self{
+        if amount != 0 {
+            self.postings.push(Posting { account , asset , amount , order_id });
+        }
+        self
+    }
+
+    /// moves `amount` of `asset` from one account to another
+    pub fn transfer(self , from : Account , to : Account , asset : Asset , amount : u64 , order_id : u64)->Self{
+        self.post(from, asset, -(amount as i128), order_id).post(to, asset, amount as i128, order_id)
+    }
+
+    /// the first asset whose postings do not sum to zero
+    pub fn unbalanced(&self)->Option<Asset>{
+        let mut sums : SmallVec<[(Asset , i128) ; 4]> = SmallVec::new();
+        for posting in self.postings.iter() {
+            match sums.iter_mut().find(|(asset , _)| *asset == posting.asset) {
+                Some((_ , sum)) => *sum += posting.amount,
+                None => sums.push((posting.asset, posting.amount)),
+            }
+        }
+        sums.into_iter().find(|(_ , sum)| *sum != 0).map(|(asset , _)| asset)
+    }
+}
+
+#[derive(Debug , Clone , Copy , PartialEq , Eq , Error)]
+pub enum PostingError{
+    #[error("entry does not balance for {0:?}")]
+    Unbalanced(Asset),
+    #[error("{account:?} would go below zero on {asset:?}")]
+    Overdrawn { account : Account , asset : Asset },
+    #[error("{account:?} would overflow on {asset:?}")]
+    Overflow { account : Account , asset : Asset },
+    #[error("user {0} not found")]
+    UnknownUser(u64),
+}
+
+impl From<PostingError> for BalanceManagerError{
+    fn from(e : PostingError)->Self{
+        match e {
+            PostingError::Overdrawn { .. } => Self::InsufficientFunds,
+            PostingError::UnknownUser(_) => Self::UserNotFound,
+            PostingError::Unbalanced(_) | PostingError::Overflow { .. } => Self::PostingRejected,
+        }
+    }
+}
+
+// an asset whose accounts do not sum to zero , something moved outside an entry
+#[derive(Debug , Clone , Copy , PartialEq , Eq , Error)]
+#[error("{asset:?} is not conserved , its accounts sum to {total}")]
+pub struct ConservationError{
+    pub asset : Asset,
+    pub total : i128,
+}
+
+// balances of the accounts that belong to no user , signed since system runs negative
+#[derive(Debug , Clone , Default , PartialEq , Eq)]
+pub struct HouseAccounts{
+    balances : FxHashMap<(Account , Asset) , i64>,
+}
+
+impl HouseAccounts{
+    pub fn balance(&self , account : Account , asset : Asset)->i64{
+        self.balances.get(&(account , asset)).copied().unwrap_or(0)
+    }
+
+    pub fn set(&mut self , account : Account , asset : Asset , balance : i64){
+        if balance == 0 {
+            self.balances.remove(&(account , asset));
+        } else {
+            self.balances.insert((account , asset), balance);
+        }
+    }
+
+    pub fn iter(&self)->impl Iterator<Item = (Account , Asset , i64)> + '_{
+        self.balances.iter().map(|((account , asset) , balance)| (*account, *asset, *balance))
+    }
+}
@@ -0,0 +1,120 @@
+// token bucket rate limits per user , one bucket for new orders , one for cancels and one for amends
+// the user's class (retail or market maker) sets how many tokens a bucket holds and how fast it fills back up
+// buckets refill by the time of the command being applied , the journal timestamp in the single threaded core
+// so a replay throttles exactly what the live run did , the buckets are part of the balance state like the limits
+// a capacity of 0 is off
+use rustc_hash::FxHashMap;
+use serde::Deserialize;
+use thiserror::Error;
+
+const NANOS_PER_SEC : u64 = 1_000_000_000;
+// a bucket counts in billionths of a token so a refill of n a second adds n per elapsed nanosecond
+const TOKEN : u64 = NANOS_PER_SEC;
+
+#[derive(Debug , Clone , Copy , Default , PartialEq , Eq , Deserialize)]
+#[serde(default , deny_unknown_fields)]
+pub struct BucketLimit{
+    // the burst a user can send at once
+    pub capacity : u32,
+    pub refill_per_sec : u32,
+}
+
+#[derive(Debug , Clone , Copy , Default , PartialEq , Eq , Deserialize)]
+#[serde(default , deny_unknown_fields)]
+pub struct ClassLimits{
+    pub new_order : BucketLimit,
+    pub cancel : BucketLimit,
+    pub amend : BucketLimit,
+}
+
+impl ClassLimits{
+    pub fn bucket(&self , action : RateAction)->BucketLimit{
+        match action {
+            RateAction::NewOrder => self.new_order,
+            RateAction::Cancel => self.cancel,
+            RateAction::Amend => self.amend,
+        }
+    }
+}
+
+#[derive(Debug , Clone , Copy , PartialEq , Eq , Hash , PartialOrd , Ord)]
+pub enum RateAction{
+    NewOrder,
+    Cancel,
+    Amend,
+}
+
+impl RateAction{
+    pub const ALL : [Self ; 3] = [Self::NewOrder , Self::Cancel , Self::Amend];
+
+    pub fn from_u8(action : u8)->Option<Self>{
+        Self::ALL.get(action as usize).copied()
+    }
+    pub fn as_u8(self)->u8{
+        self as u8
+    }
+    pub fn label(self)->&'static str{
+        match self {
+            Self::NewOrder => "new_order",
+            Self::Cancel => "cancel",
+            Self::Amend => "amend",
+        }
+    }
+}
+
+#[derive(Debug , Clone , Copy , PartialEq , Eq , Error)]
+#[error("{action:?} rate is over the limit of {capacity} with {refill_per_sec} a second")]
+pub struct Throttled{
+    pub action : RateAction,
+    pub capacity : u32,
+    pub refill_per_sec : u32,
+}
+
+#[derive(Debug , Clone , Copy , PartialEq , Eq)]
+pub struct TokenBucket{
+    // billionths of a token
+    pub tokens : u64,
+    // the command time the tokens were last topped up to
+    pub refilled_at : u64,
+}
+
+impl TokenBucket{
+    pub fn full(limit : BucketLimit , now : u64)->Self{
+        Self { tokens : limit.capacity as u64 * TOKEN , refilled_at : now }
+    }
+
+    /// tops the bucket up to `now` and takes a token , false when there was not a whole one
+    /// a clock that went backwards refills nothing
+    pub fn take(&mut self , limit : BucketLimit , now : u64)->bool{
+        let elapsed = now.saturating_sub(self.refilled_at);
+        let refill = elapsed.saturating_mul(limit.refill_per_sec as u64);
+        self.tokens = self.tokens.saturating_add(refill).min(limit.capacity as u64 * TOKEN);
+        self.refilled_at = self.refilled_at.max(now);
+        if self.tokens < TOKEN {
+            return false;
+        }
+        self.tokens -= TOKEN;
+        true
+    }
+}
+
+// buckets of the users that sent something under a limit , a bucket starts full
+#[derive(Debug , Clone , Default , PartialEq , Eq)]
+pub struct RateState{
+    pub buckets : FxHashMap<(u64 , RateAction) , TokenBucket>,
+}
+
+impl RateState{
+    /// takes a token for `action` from `user_id`'s bucket
+    pub fn take(&mut self , user_id : u64 , action : RateAction , limit : BucketLimit , now : u64)->Result<() , Throttled>{
+        if limit.capacity == 0 {
+            return Ok(());
+        }
+        let bucket = self.buckets.entry((user_id , action)).or_insert_with(|| TokenBucket::full(limit, now));
+        if bucket.take(limit, now) {
+            Ok(())
+        } else {
+            Err(Throttled { action , capacity : limit.capacity , refill_per_sec : limit.refill_per_sec })
+        }
+    }
+}
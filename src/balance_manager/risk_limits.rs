@@ -0,0 +1,168 @@
+// pre-trade limits per user , checked when a new order comes in before anything is reserved for it
+// every user runs under the [risk] defaults unless the config or an admin command gave it limits of its own
+// the admin ones are part of the balance state : checkpointed , hashed and rebuilt by replay of the admin commands
+// a limit of 0 is off
+use rustc_hash::FxHashMap;
+use serde::Deserialize;
+use thiserror::Error;
+use crate::shm::admin_command_queue::{LIMIT_MAX_DAILY_NOTIONAL, LIMIT_MAX_OPEN_ORDERS, LIMIT_MAX_ORDER_NOTIONAL, LIMIT_MAX_ORDER_QTY, LIMIT_MAX_POSITION, LIMIT_MAX_PRICE_DEVIATION_BPS};
+use crate::shm::event_queue::{REJECT_REASON_DAILY_NOTIONAL, REJECT_REASON_OPEN_ORDERS, REJECT_REASON_ORDER_NOTIONAL, REJECT_REASON_ORDER_QTY, REJECT_REASON_POSITION, REJECT_REASON_PRICE_DEVIATION};
+
+#[derive(Debug , Clone , Copy , Default , PartialEq , Eq , Deserialize)]
+#[serde(default , deny_unknown_fields)]
+pub struct RiskLimits{
+    pub max_order_qty : u32,
+    // price times quantity
+    pub max_order_notional : u64,
+    pub max_open_orders : u32,
+    // the base asset a user may hold of one symbol once a bid fills
+    pub max_position : u64,
+    // total_traded_today plus the order's notional
+    pub max_daily_notional : u64,
+    // how far a limit price may be from the symbol's last trade , in basis points of it
+    pub max_price_deviation_bps : u32,
+}
+
+// which limit an ADMIN_SET_LIMIT command changes , its `limit` byte
+#[derive(Debug , Clone , Copy , PartialEq , Eq)]
+pub enum LimitKind{
+    OrderQty,
+    OrderNotional,
+    OpenOrders,
+    Position,
+    DailyNotional,
+    PriceDeviation,
+}
+
+impl LimitKind{
+    pub fn from_u8(kind : u8)->Option<Self>{
+        Some(match kind {
+            LIMIT_MAX_ORDER_QTY => Self::OrderQty,
+            LIMIT_MAX_ORDER_NOTIONAL => Self::OrderNotional,
+            LIMIT_MAX_OPEN_ORDERS => Self::OpenOrders,
+            LIMIT_MAX_POSITION => Self::Position,
+            LIMIT_MAX_DAILY_NOTIONAL => Self::DailyNotional,
+            LIMIT_MAX_PRICE_DEVIATION_BPS => Self::PriceDeviation,
+            _ => return None,
+        })
+    }
+    pub fn as_u8(self)->u8{
+        match self {
+            Self::OrderQty => LIMIT_MAX_ORDER_QTY,
+            Self::OrderNotional => LIMIT_MAX_ORDER_NOTIONAL,
+            Self::OpenOrders => LIMIT_MAX_OPEN_ORDERS,
+            Self::Position => LIMIT_MAX_POSITION,
+            Self::DailyNotional => LIMIT_MAX_DAILY_NOTIONAL,
+            Self::PriceDeviation => LIMIT_MAX_PRICE_DEVIATION_BPS,
+        }
+    }
+    // the 32 bit ones
+    pub fn is_narrow(self)->bool{
+        matches!(self , Self::OrderQty | Self::OpenOrders | Self::PriceDeviation)
+    }
+}
+
+#[derive(Debug , Clone , Copy , PartialEq , Eq , Error)]
+pub enum RiskError{
+    #[error("quantity {qty} is over the limit of {limit}")]
+    OrderQty { qty : u32 , limit : u32 },
+    #[error("notional {notional} is over the limit of {limit}")]
+    OrderNotional { notional : u128 , limit : u64 },
+    #[error("{open} orders already open , the limit is {limit}")]
+    OpenOrders { open : u32 , limit : u32 },
+    #[error("a position of {position} in symbol {symbol} would be over the limit of {limit}")]
+    Position { symbol : u32 , position : u128 , limit : u64 },
+    #[error("{traded} traded today would be over the limit of {limit}")]
+    DailyNotional { traded : u128 , limit : u64 },
+    #[error("price {price} is more than {limit_bps} bps away from the last trade at {last}")]
+    PriceDeviation { price : u64 , last : u64 , limit_bps : u32 },
+}
+
+impl RiskError{
+    /// the error_code of the rejected event
+    pub fn reject_reason(self)->u32{
+        match self {
+            Self::OrderQty { .. } => REJECT_REASON_ORDER_QTY,
+            Self::OrderNotional { .. } => REJECT_REASON_ORDER_NOTIONAL,
+            Self::OpenOrders { .. } => REJECT_REASON_OPEN_ORDERS,
+            Self::Position { .. } => REJECT_REASON_POSITION,
+            Self::DailyNotional { .. } => REJECT_REASON_DAILY_NOTIONAL,
+            Self::PriceDeviation { .. } => REJECT_REASON_PRICE_DEVIATION,
+        }
+    }
+}
+
+// what the limits are checked against besides the order itself
+#[derive(Debug , Clone , Copy , PartialEq , Eq)]
+pub struct Exposure{
+    pub open_orders : u32,
+    // the base asset held of the order's symbol , available and reserved
+    pub position : u64,
+    pub traded_today : u64,
+    // 0 before the symbol's first trade
+    pub last_price : u64,
+}
+
+#[derive(Debug , Clone , Copy , PartialEq , Eq)]
+pub struct OrderTerms{
+    pub symbol : u32,
+    pub is_bid : bool,
+    // a market order has no price to hold against the last trade
+    pub is_limit : bool,
+    pub price : u64,
+    pub qty : u32,
+}
+
+impl RiskLimits{
+    /// `value` must fit the limit , see LimitKind::is_narrow
+    pub fn set(&mut self , kind : LimitKind , value : u64){
+        match kind {
+            LimitKind::OrderQty => self.max_order_qty = value as u32,
+            LimitKind::OrderNotional => self.max_order_notional = value,
+            LimitKind::OpenOrders => self.max_open_orders = value as u32,
+            LimitKind::Position => self.max_position = value,
+            LimitKind::DailyNotional => self.max_daily_notional = value,
+            LimitKind::PriceDeviation => self.max_price_deviation_bps = value as u32,
+        }
+    }
+
+    /// the first limit `order` breaks
+    pub fn check(&self , order : OrderTerms , exposure : Exposure)->Result<() , RiskError>{
+        let notional = order.price as u128 * order.qty as u128;
+        if self.max_order_qty != 0 && order.qty > self.max_order_qty {
+            return Err(RiskError::OrderQty { qty : order.qty , limit : self.max_order_qty });
+        }
+        if self.max_order_notional != 0 && notional > self.max_order_notional as u128 {
+            return Err(RiskError::OrderNotional { notional , limit : self.max_order_notional });
+        }
+        if self.max_open_orders != 0 && exposure.open_orders >= self.max_open_orders {
+            return Err(RiskError::OpenOrders { open : exposure.open_orders , limit : self.max_open_orders });
+        }
+        let position = exposure.position as u128 + order.qty as u128;
+        if self.max_position != 0 && order.is_bid && position > self.max_position as u128 {
+            return Err(RiskError::Position { symbol : order.symbol , position , limit : self.max_position });
+        }
+        let traded = exposure.traded_today as u128 + notional;
+        if self.max_daily_notional != 0 && traded > self.max_daily_notional as u128 {
+            return Err(RiskError::DailyNotional { traded , limit : self.max_daily_notional });
+        }
+        if self.max_price_deviation_bps != 0 && order.is_limit && exposure.last_price != 0 {
+            let distance = order.price.abs_diff(exposure.last_price) as u128;
+            if distance * 10_000 > exposure.last_price as u128 * self.max_price_deviation_bps as u128 {
+                return Err(RiskError::PriceDeviation { price : order.price , last : exposure.last_price , limit_bps : self.max_price_deviation_bps });
+            }
+        }
+        Ok(())
+    }
+}
+
+// the part of the pre-trade state that moves with trading
+#[derive(Debug , Clone , Default , PartialEq , Eq)]
+pub struct RiskState{
+    // limits set through the admin queue , they win over the config
+    pub overrides : FxHashMap<u64 , RiskLimits>,
+    // price of the last fill per symbol
+    pub last_prices : FxHashMap<u32 , u64>,
+    // open orders per user , follows the reservation ledger
+    pub open_orders : FxHashMap<u64 , u32>,
+}
@@ -4,9 +4,25 @@ mod tests {
     use crate::balance_manager::my_balance_manager::{MyBalanceManager};
     use crate::orderbook::types::BalanceManagerError;
     use crossbeam::channel::{Sender, Receiver};
-    use crate::orderbook::order::{Order, Side};
+    use crate::orderbook::order::{Order, Side, ORDER_TYPE_AMEND};
     use crate::orderbook::types::{Fills, Fill,OrderId};
     use smallvec::smallvec;
+    use crate::journal::command_journal::{InboundCommand, JournalRecord};
+    use crate::journal::checkpoint::{restore_checkpoint, CheckpointError};
+    use crate::engine::my_engine::Engine;
+    use crate::trading_core::my_trading_core::TradingCore;
+    use crate::shm::event_queue::{CANCEL_REASON_AMENDED, REJECT_REASON_NOT_OWNER, REJECT_REASON_THROTTLED, REJECT_REASON_UNKNOWN_ORDER};
+    use crate::journal::replay::{detached_core, detached_core_with, ReplaySink};
+    use crate::config::settings::{EngineConfig, FeeSchedule, FeeTier, InstrumentPair, SymbolFees};
+    use crate::logger::types::{BaseLogs, DELTA_REASON_RELEASE};
+    use crate::orderbook::order::OrderToBeCanceled;
+    use crate::shm::query_queue::Query;
+    use crate::balance_manager::my_balance_manager2::{ReservationMismatch, WalletBalance, NANOS_PER_DAY};
+    use crate::balance_manager::funds_ledger::{Asset, FundsLedger, FundsMovement, FundsRequest, MovementKind};
+    use crate::balance_manager::postings::{Account, ConservationError, Entry, PostingError};
+    use crate::logger::types::{DELTA_REASON_DEPOSIT, DELTA_REASON_FEE, DELTA_REASON_TRANSFER, POSITION_REPORT_QUERY, POSITION_REPORT_SNAPSHOT, PositionReport};
+    use crate::admin::plane::{AdminPlane, AdminRequest};
+    use crate::shm::admin_command_queue::{AdminCommand, ADMIN_ADD_BOOK, ADMIN_ADD_USER, ADMIN_SET_MARK_PRICE, ADMIN_SNAPSHOT};
 
     // Helper function to create a test balance manager
     fn setup_balance_manager() -> (MyBalanceManager, Receiver<Order>, Sender<Fills>, Sender<Order>) {
@@ -64,6 +80,8 @@ mod tests {
             maker_user_id,
             taker_user_id,
             symbol,
+            taker_fee: 0,
+            maker_fee: 0,
         }
     }
 
@@ -639,4 +657,617 @@ mod tests {
         // Order should NOT reach engine
         assert!(engine_rx.try_recv().is_err());
     }
+
+    // bids on the single threaded manager , driven through a detached core so the engine produces the fills
+    fn released_by(sink: &mut ReplaySink, order_id: OrderId) -> u64 {
+        let mut released = 0;
+        while let Some(log) = sink.logs.try_pop() {
+            if let BaseLogs::BalanceDelta(delta) = log && delta.reason == DELTA_REASON_RELEASE && delta.order_id == order_id {
+                assert_eq!(delta.delta_available, -delta.delta_reserved);
+                released += delta.delta_available as u64;
+            }
+        }
+        sink.drain();
+        released
+    }
+
+    fn new_order(user_id: u64, order_id: OrderId, side: Side, order_type: u8, qty: u32, price: u64, symbol: u32) -> InboundCommand {
+        InboundCommand::NewOrder(Order::new(user_id, order_id, side, order_type, qty, price, order_id, symbol))
+    }
+
+    fn add_book(symbol: u32) -> InboundCommand {
+        InboundCommand::Admin(AdminRequest { command: AdminCommand { symbol, command_type: ADMIN_ADD_BOOK, ..AdminCommand::default() }, authorized: true })
+    }
+
+    #[test]
+    fn test_bid_price_improvement_and_final_fill_are_released() {
+        let (mut core, mut sink) = detached_core();
+        core.apply_command(add_book(500));
+        sink.drain();
+        let buyer = core.balance_manager.get_user_index(10).unwrap() as usize;
+        let available_before = core.balance_manager.state.balances[buyer].available_balance;
+
+        core.apply_command(new_order(20, 1, Side::Ask, 1, 5, 10, 500));
+        core.apply_command(new_order(20, 2, Side::Ask, 1, 5, 11, 500));
+        sink.drain();
+        // locked at 12 , fills 5 at 10 and 5 at 11 , the rest 5 rests at 12
+        core.apply_command(new_order(10, 3, Side::Bid, 1, 15, 12, 500));
+        assert_eq!(released_by(&mut sink, 3), 5 * 2 + 5);
+        let reservation = core.balance_manager.state.reservations[&3];
+        assert_eq!((reservation.remaining_qty, reservation.reserved), (5, 5 * 12));
+        assert_eq!(core.balance_manager.state.balances[buyer].reserved_balance, 5 * 12);
+
+        // the resting rest fills at its own price , nothing to improve but the order is done
+        core.apply_command(new_order(20, 4, Side::Ask, 1, 5, 12, 500));
+        assert_eq!(released_by(&mut sink, 3), 0);
+        assert!(!core.balance_manager.state.reservations.contains_key(&3));
+        let balance = core.balance_manager.state.balances[buyer];
+        assert_eq!(balance.reserved_balance, 0);
+        assert_eq!(balance.available_balance, available_before - (5 * 10 + 5 * 11 + 5 * 12));
+    }
+
+    #[test]
+    fn test_cancel_and_market_remainder_release_exactly_what_is_left() {
+        let (mut core, mut sink) = detached_core();
+        core.apply_command(add_book(501));
+        sink.drain();
+        let buyer = core.balance_manager.get_user_index(10).unwrap() as usize;
+        let available_before = core.balance_manager.state.balances[buyer].available_balance;
+
+        core.apply_command(new_order(20, 1, Side::Ask, 1, 4, 9, 501));
+        sink.drain();
+        core.apply_command(new_order(10, 2, Side::Bid, 1, 10, 10, 501));
+        assert_eq!(released_by(&mut sink, 2), 4);
+        // only its owner cancels it , another user's cancel is rejected and the order keeps resting
+        core.apply_command(InboundCommand::CancelOrder(OrderToBeCanceled { order_id: 2, user_id: 20, symbol: 501 }));
+        let rejected = std::iter::from_fn(|| sink.order_events_from_bm.try_pop()).find(|event| event.order_id == 2).unwrap();
+        assert_eq!((rejected.user_id, rejected.event_kind, rejected.error_code), (20, 3, REJECT_REASON_NOT_OWNER));
+        assert_eq!(released_by(&mut sink, 2), 0);
+        assert_eq!(core.balance_manager.state.reservations[&2].reserved, 6 * 10);
+        assert!(core.engine.get_book(501).unwrap().manager.id_to_index.contains_key(&2));
+        core.apply_command(InboundCommand::CancelOrder(OrderToBeCanceled { order_id: 2, user_id: 10, symbol: 501 }));
+        assert_eq!(released_by(&mut sink, 2), 6 * 10);
+
+        // a market bid takes what is there and gives back the unfilled part
+        core.apply_command(new_order(20, 3, Side::Ask, 1, 3, 9, 501));
+        sink.drain();
+        core.apply_command(new_order(10, 4, Side::Bid, 0, 5, 10, 501));
+        assert_eq!(released_by(&mut sink, 4), 3 + 2 * 10);
+
+        let state = &core.balance_manager.state;
+        assert!(state.reservations.is_empty());
+        assert_eq!(state.balances[buyer].reserved_balance, 0);
+        assert_eq!(state.balances[buyer].available_balance, available_before - 7 * 9);
+
+        // an ask above the market bid's price is not taken , the resting bid's reservation is left alone
+        core.apply_command(new_order(10, 5, Side::Bid, 1, 5, 10, 501));
+        core.apply_command(new_order(20, 6, Side::Ask, 1, 5, 12, 501));
+        sink.drain();
+        core.apply_command(new_order(10, 7, Side::Bid, 0, 5, 10, 501));
+        assert_eq!(released_by(&mut sink, 7), 5 * 10);
+        let state = &core.balance_manager.state;
+        assert_eq!(core.engine.get_book(501).unwrap().manager.id_to_index.len(), 2);
+        assert_eq!((state.reservations[&5].reserved, state.balances[buyer].reserved_balance), (5 * 10, 5 * 10));
+        assert!(core.balance_manager.reconcile_reservations().is_empty());
+    }
+
+    #[test]
+    fn test_a_refused_fill_is_unwound_and_the_rest_still_settle() {
+        let (mut core, mut sink) = detached_core();
+        core.apply_command(add_book(508));
+        // the buyer is 3 shares short of the holding ceiling , its second fill cannot be credited
+        for (key, amount) in [(1, i32::MAX as u64), (2, u32::MAX as u64 - 3 - i32::MAX as u64)] {
+            let request = FundsRequest::Deposit { user_id: 10, asset: Asset::Shares(508), amount };
+            core.balance_manager.apply_funds(key, request, |_| {}, || 0).unwrap();
+        }
+        sink.drain();
+        let (buyer, seller) = (core.balance_manager.get_user_index(10).unwrap() as usize, core.balance_manager.get_user_index(20).unwrap() as usize);
+        let (cash, shares) = (core.balance_manager.state.balances[buyer].available_balance, core.balance_manager.state.holdings[seller].available(508));
+
+        core.apply_command(new_order(20, 1, Side::Ask, 1, 2, 10, 508));
+        core.apply_command(new_order(20, 2, Side::Ask, 1, 5, 10, 508));
+        core.apply_command(new_order(10, 3, Side::Bid, 1, 7, 10, 508));
+        sink.drain();
+
+        // the book took both asks , the first fill settled and the second gave back what each side locked for it
+        assert_eq!(core.engine.get_book(508).unwrap().manager.id_to_index.len(), 0);
+        let state = &core.balance_manager.state;
+        assert!(state.reservations.is_empty());
+        assert_eq!((state.balances[buyer].available_balance, state.balances[buyer].reserved_balance), (cash - 2 * 10, 0));
+        assert_eq!(state.holdings[buyer].available(508), u32::MAX - 1);
+        assert_eq!((state.holdings[seller].available(508), state.holdings[seller].reserved(508)), (shares - 2, 0));
+        assert!(core.balance_manager.reconcile_reservations().is_empty());
+        assert!(core.balance_manager.check_conservation().is_empty());
+    }
+
+    #[test]
+    fn test_every_open_order_is_in_the_ledger_and_reconciles() {
+        let (mut core, mut sink) = detached_core();
+        core.apply_command(add_book(502));
+        sink.drain();
+        let seller = core.balance_manager.get_user_index(20).unwrap() as usize;
+        let shares_before = core.balance_manager.state.holdings[seller].available(502);
+
+        core.apply_command(new_order(20, 1, Side::Ask, 1, 10, 9, 502));
+        core.apply_command(new_order(20, 2, Side::Ask, 1, 5, 11, 502));
+        core.apply_command(new_order(10, 3, Side::Bid, 1, 4, 9, 502));
+        core.apply_command(new_order(10, 4, Side::Bid, 1, 6, 8, 502));
+        sink.drain();
+        let ledger = &core.balance_manager.state.reservations;
+        assert_eq!((ledger[&1].side, ledger[&1].remaining_qty, ledger[&1].reserved), (Side::Ask, 6, 6));
+        assert_eq!((ledger[&2].remaining_qty, ledger[&2].reserved), (5, 5));
+        assert!(!ledger.contains_key(&3));
+        assert_eq!((ledger[&4].side, ledger[&4].reserved), (Side::Bid, 6 * 8));
+        assert!(core.balance_manager.reconcile_reservations().is_empty());
+
+        // the partly filled ask gives back exactly its 6 , whatever the book reports
+        core.apply_command(InboundCommand::CancelOrder(OrderToBeCanceled { order_id: 1, user_id: 20, symbol: 502 }));
+        let mut released = 0;
+        while let Some(log) = sink.logs.try_pop() {
+            if let BaseLogs::HoldingDelta(delta) = log && delta.reason == DELTA_REASON_RELEASE {
+                assert_eq!((delta.order_id, delta.symbol), (1, 502));
+                released += delta.delta_available;
+            }
+        }
+        sink.drain();
+        assert_eq!(released, 6);
+        let holdings = &core.balance_manager.state.holdings[seller];
+        assert_eq!((holdings.available(502), holdings.reserved(502)), (shares_before - 4 - 5, 5));
+        assert!(core.balance_manager.reconcile_reservations().is_empty());
+
+        // an aggregate that drifted from the ledger is caught
+        core.balance_manager.state.holdings[seller].position_mut(502).reserved += 1;
+        core.balance_manager.state.balances[seller].reserved_balance += 3;
+        let mismatches = core.balance_manager.reconcile_reservations();
+        assert_eq!(mismatches.len(), 2);
+        assert!(mismatches.contains(&ReservationMismatch::Holding { user_id: 20, symbol: 502, aggregate: 6, open: 5 }));
+        assert!(mismatches.contains(&ReservationMismatch::Balance { user_id: 20, aggregate: 3, open: 0 }));
+    }
+
+    #[test]
+    fn test_entries_balance_and_every_asset_is_conserved() {
+        let (mut core, mut sink) = detached_core();
+        core.apply_command(add_book(503));
+        sink.drain();
+        let buyer = core.balance_manager.get_user_index(10).unwrap() as usize;
+        let system_cash = core.balance_manager.state.house.balance(Account::System, Asset::Cash);
+        assert!(system_cash < 0);
+        assert!(core.balance_manager.check_conservation().is_empty());
+
+        core.apply_command(new_order(20, 1, Side::Ask, 1, 10, 9, 503));
+        core.apply_command(new_order(10, 2, Side::Bid, 1, 4, 11, 503));
+        core.apply_command(InboundCommand::CancelOrder(OrderToBeCanceled { order_id: 1, user_id: 20, symbol: 503 }));
+        // the delta logs come out of the entries , and between users cash only changes hands
+        let (mut cash, mut shares) = (0i64, 0i32);
+        while let Some(log) = sink.logs.try_pop() {
+            match log {
+                BaseLogs::BalanceDelta(delta) => cash += delta.delta_available + delta.delta_reserved,
+                BaseLogs::HoldingDelta(delta) => shares += delta.delta_available + delta.delta_reserved,
+                _ => {}
+            }
+        }
+        sink.drain();
+        assert_eq!((cash, shares), (0, 0));
+        assert!(core.balance_manager.check_conservation().is_empty());
+        assert_eq!(core.balance_manager.state.house.balance(Account::System, Asset::Cash), system_cash);
+
+        // an entry that does not balance , or would overdraw any of its accounts , changes nothing
+        let before = core.balance_manager.state.balances[buyer];
+        let unbalanced = Entry::new(DELTA_REASON_TRANSFER).post(Account::Available(10), Asset::Cash, 5, 0);
+        assert_eq!(core.balance_manager.post(&unbalanced, |_| {}, || 0), Err(PostingError::Unbalanced(Asset::Cash)));
+        let overdrawn = Entry::new(DELTA_REASON_TRANSFER)
+            .transfer(Account::System, Account::Available(10), Asset::Cash, 5, 0)
+            .transfer(Account::Reserved(10), Account::Available(20), Asset::Cash, 1, 0);
+        assert_eq!(core.balance_manager.post(&overdrawn, |_| {}, || 0), Err(PostingError::Overdrawn { account: Account::Reserved(10), asset: Asset::Cash }));
+        assert_eq!(core.balance_manager.state.balances[buyer].available_balance, before.available_balance);
+        assert_eq!(core.balance_manager.state.house.balance(Account::System, Asset::Cash), system_cash);
+
+        // an opening balance is an entry like any other , logged and sent to the cache
+        core.balance_manager.defaults.balance = 500;
+        core.apply_command(InboundCommand::Admin(AdminRequest { command: AdminCommand { user_id: 31, command_type: ADMIN_ADD_USER, ..AdminCommand::default() }, authorized: true }));
+        let logged = std::iter::from_fn(|| sink.logs.try_pop()).any(|log| matches!(log, BaseLogs::BalanceDelta(delta) if delta.user_id == 31 && delta.delta_available == 500 && delta.reason == DELTA_REASON_DEPOSIT));
+        assert!(logged);
+        let cached = sink.balance_updates.try_pop().unwrap();
+        assert_eq!((cached.user_id, cached.delta_available_balance), (31, 500));
+        sink.drain();
+
+        // a balance changed outside an entry is caught , and the books that do not add up are not checkpointed
+        core.balance_manager.state.balances[buyer].available_balance += 7;
+        assert_eq!(core.balance_manager.check_conservation(), vec![ConservationError { asset: Asset::Cash, total: 7 }]);
+        let dir = std::env::temp_dir().join(format!("bm_test_conservation_checkpoint_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        core.checkpoint_dir = Some(dir.clone());
+        assert!(matches!(core.checkpoint(), Err(CheckpointError::NotConserved(ConservationError { asset: Asset::Cash, total: 7 }))));
+        assert!(!dir.exists() || std::fs::read_dir(&dir).unwrap().next().is_none());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_fills_pay_tiered_fees_and_makers_earn_rebates() {
+        let mut config = EngineConfig::default();
+        // 20 bps to take and a 10 bps rebate to make , past 1000 traded 10 and 20
+        let schedule = FeeSchedule { maker_bps: -10, taker_bps: 20, tiers: vec![FeeTier { min_volume: 1000, maker_bps: -20, taker_bps: 10 }] };
+        config.fees.symbols.push(SymbolFees { symbol: 504, schedule });
+        let (mut core, mut sink) = detached_core_with(&config);
+        core.apply_command(add_book(504));
+        sink.drain();
+        let (buyer, seller) = (core.balance_manager.get_user_index(10).unwrap() as usize, core.balance_manager.get_user_index(20).unwrap() as usize);
+        let (buyer_cash, seller_cash) = (core.balance_manager.state.balances[buyer].available_balance, core.balance_manager.state.balances[seller].available_balance);
+
+        // the bid locks room for the highest rate on top of its notional
+        core.apply_command(new_order(20, 1, Side::Ask, 1, 10, 100, 504));
+        core.apply_command(new_order(10, 2, Side::Bid, 1, 20, 100, 504));
+        assert_eq!(core.balance_manager.state.reservations[&2].reserved, 10 * 100 + 2);
+        let fills: Vec<(i64, i64)> = std::iter::from_fn(|| sink.events_to_publisher.try_pop())
+            .flat_map(|event| event.market_update.match_result.fills.fills.into_iter().map(|fill| (fill.taker_fee, fill.maker_fee)))
+            .collect();
+        assert_eq!(fills, vec![(2, -1)]);
+        let mut fees = 0;
+        while let Some(log) = sink.logs.try_pop() {
+            if let BaseLogs::BalanceDelta(delta) = log && delta.reason == DELTA_REASON_FEE {
+                fees += delta.delta_available + delta.delta_reserved;
+            }
+        }
+        sink.drain();
+        assert_eq!(fees, -2 + 1);
+        assert_eq!(core.balance_manager.state.house.balance(Account::Fees, Asset::Cash), 1);
+
+        // both reached the tier , the resting rest of the bid now makes at -20
+        core.apply_command(new_order(20, 3, Side::Ask, 1, 10, 100, 504));
+        sink.drain();
+        assert!(!core.balance_manager.state.reservations.contains_key(&2));
+        let state = &core.balance_manager.state;
+        assert_eq!((state.balances[buyer].total_traded_today, state.balances[seller].total_traded_today), (2000, 2000));
+        // the buyer's unused headroom comes back with its rebate , the seller takes at 10
+        assert_eq!(state.balances[buyer].available_balance, buyer_cash - 2000 - 2 + 2);
+        assert_eq!(state.balances[seller].available_balance, seller_cash + 2000 + 1 - 1);
+        assert_eq!(state.house.balance(Account::Fees, Asset::Cash), 1 + 1 - 2);
+        assert!(core.balance_manager.check_conservation().is_empty());
+        assert!(core.balance_manager.reconcile_reservations().is_empty());
+    }
+
+    #[test]
+    fn test_an_unfunded_rebate_leaves_the_taker_fee_charged() {
+        let mut config = EngineConfig::default();
+        config.fees.fee_account = Some(31);
+        // past 1000 traded the rebate to make is larger than the fee to take
+        let schedule = FeeSchedule { maker_bps: -10, taker_bps: 20, tiers: vec![FeeTier { min_volume: 1000, maker_bps: -20, taker_bps: 10 }] };
+        config.fees.symbols.push(SymbolFees { symbol: 504, schedule });
+        let (mut core, mut sink) = detached_core_with(&config);
+        core.balance_manager.defaults.balance = 0;
+        core.apply_command(add_book(504));
+        core.apply_command(InboundCommand::Admin(AdminRequest { command: AdminCommand { user_id: 31, command_type: ADMIN_ADD_USER, ..AdminCommand::default() }, authorized: true }));
+        let (buyer, seller, fee_account) = (core.balance_manager.get_user_index(10).unwrap() as usize, core.balance_manager.get_user_index(20).unwrap() as usize, core.balance_manager.get_user_index(31).unwrap() as usize);
+        core.apply_command(new_order(20, 1, Side::Ask, 1, 10, 100, 504));
+        core.apply_command(new_order(10, 2, Side::Bid, 1, 10, 100, 504));
+        assert_eq!(core.balance_manager.state.balances[fee_account].available_balance, 2 - 1);
+        core.balance_manager.apply_funds(1, FundsRequest::Withdraw { user_id: 31, asset: Asset::Cash, amount: 1 }, |_| {}, || 0).unwrap();
+        sink.drain();
+        let (buyer_cash, seller_cash) = (core.balance_manager.state.balances[buyer].available_balance, core.balance_manager.state.balances[seller].available_balance);
+
+        // 10 makes for a rebate of 2 , 20 takes for a fee of 1 that is all the fee account then has
+        core.apply_command(new_order(10, 3, Side::Ask, 1, 10, 100, 504));
+        core.apply_command(new_order(20, 4, Side::Bid, 1, 10, 100, 504));
+        let fills: Vec<(i64, i64)> = std::iter::from_fn(|| sink.events_to_publisher.try_pop())
+            .flat_map(|event| event.market_update.match_result.fills.fills.into_iter().map(|fill| (fill.taker_fee, fill.maker_fee)))
+            .collect();
+        sink.drain();
+        assert_eq!(fills, vec![(1, 0)]);
+        let state = &core.balance_manager.state;
+        assert_eq!(state.balances[fee_account].available_balance, 1);
+        assert_eq!(state.balances[buyer].available_balance, buyer_cash + 1000);
+        assert_eq!(state.balances[seller].available_balance, seller_cash - 1000 - 1);
+        assert!(core.balance_manager.check_conservation().is_empty());
+        assert!(core.balance_manager.reconcile_reservations().is_empty());
+    }
+
+    #[test]
+    fn test_fee_tiers_follow_a_rolling_window_per_quote_asset() {
+        let mut config = EngineConfig::default();
+        config.fees.window_days = 2;
+        // past 1000 of volume a taker pays 10 instead of 20 , on a cash symbol and on one quoted in asset 1
+        let schedule = FeeSchedule { maker_bps: 0, taker_bps: 20, tiers: vec![FeeTier { min_volume: 1000, maker_bps: 0, taker_bps: 10 }] };
+        config.fees.symbols.push(SymbolFees { symbol: 504, schedule: schedule.clone() });
+        config.fees.symbols.push(SymbolFees { symbol: 505, schedule });
+        config.instruments.pairs.push(InstrumentPair { symbol: 505, base: 2, quote: 1 });
+        let (mut core, mut sink) = detached_core_with(&config);
+        let day = 20_000;
+        let mut sequence = 0;
+        let mut apply_on = |core: &mut TradingCore, day: u64, command: InboundCommand| {
+            sequence += 1;
+            core.apply_record(&JournalRecord::at(sequence, day * NANOS_PER_DAY, command));
+        };
+        apply_on(&mut core, day, add_book(504));
+        apply_on(&mut core, day, add_book(505));
+        let mut taker_fee = |core: &mut TradingCore, day: u64, id: u64, symbol: u32, price: u64| {
+            apply_on(core, day, new_order(20, id, Side::Ask, 1, 10, price, symbol));
+            apply_on(core, day, new_order(10, id + 1, Side::Bid, 1, 10, price, symbol));
+            let fees: Vec<i64> = std::iter::from_fn(|| sink.events_to_publisher.try_pop())
+                .flat_map(|event| event.market_update.match_result.fills.fills.into_iter().map(|fill| fill.taker_fee))
+                .collect();
+            sink.drain();
+            fees
+        };
+        let deposits = [(1, 10, Asset::Coin(1), 100_000), (2, 20, Asset::Coin(2), 100)];
+        for (key, user_id, asset, amount) in deposits {
+            core.balance_manager.apply_funds(key, FundsRequest::Deposit { user_id, asset, amount }, |_| {}, || 0).unwrap();
+        }
+
+        // the first fill pays the base rate and puts the buyer in the tier of cash quoted symbols only
+        assert_eq!(taker_fee(&mut core, day, 1, 504, 100), vec![2]);
+        assert_eq!((core.balance_manager.fee_volume(10, Asset::Cash), core.balance_manager.fee_volume(10, Asset::Coin(1))), (1000, 0));
+        assert_eq!(taker_fee(&mut core, day, 3, 505, 1000), vec![20]);
+        assert_eq!(core.balance_manager.fee_volume(10, Asset::Coin(1)), 10_000);
+
+        // the next day still counts it , the volume survives a checkpoint
+        assert_eq!(taker_fee(&mut core, day + 1, 5, 504, 100), vec![1]);
+        assert_eq!(core.balance_manager.fee_volume(10, Asset::Cash), 2000);
+        let dir = std::env::temp_dir().join(format!("bm_test_fee_volume_checkpoint_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        core.checkpoint_dir = Some(dir.clone());
+        let checkpoint = core.checkpoint().unwrap().unwrap();
+        let (mut restored, _restored_sink) = detached_core_with(&config);
+        restore_checkpoint(&mut restored, &checkpoint).unwrap();
+        assert_eq!(restored.state_digest(), core.state_digest());
+        assert_eq!(restored.balance_manager.fee_volume(10, Asset::Cash), 2000);
+        let _ = std::fs::remove_dir_all(&dir);
+
+        // once both days fall out of the window the buyer is back on the base rate
+        assert_eq!(taker_fee(&mut core, day + 3, 7, 504, 100), vec![2]);
+        assert_eq!(core.balance_manager.fee_volume(10, Asset::Cash), 1000);
+        assert!(!core.balance_manager.state.fee_volume.contains_key(&(10, Asset::Coin(1))));
+        assert!(core.balance_manager.check_conservation().is_empty());
+    }
+
+    #[test]
+    fn test_pairs_lock_and_settle_their_own_base_and_quote() {
+        let mut config = EngineConfig::default();
+        // symbol 505 trades asset 2 priced in asset 1 , neither is cash or shares
+        config.instruments.pairs.push(InstrumentPair { symbol: 505, base: 2, quote: 1 });
+        let (mut core, mut sink) = detached_core_with(&config);
+        core.apply_command(add_book(505));
+        let deposits = [(1, 10, Asset::Coin(1), 1_000), (2, 20, Asset::Coin(2), 8)];
+        for (key, user_id, asset, amount) in deposits {
+            core.balance_manager.apply_funds(key, FundsRequest::Deposit { user_id, asset, amount }, |_| {}, || 0).unwrap();
+        }
+        sink.drain();
+        let buyer = core.balance_manager.get_user_index(10).unwrap() as usize;
+        let (cash, shares) = (core.balance_manager.state.balances[buyer].available_balance, core.balance_manager.state.holdings[buyer].available(505));
+
+        core.apply_command(new_order(20, 1, Side::Ask, 1, 5, 40, 505));
+        assert_eq!(core.balance_manager.state.wallets[&(20, 2)], WalletBalance { available: 3, reserved: 5 });
+        // a bid for more quote than the buyer holds is refused whatever its cash
+        core.apply_command(new_order(10, 2, Side::Bid, 1, 30, 40, 505));
+        assert!(!core.balance_manager.state.reservations.contains_key(&2));
+        core.apply_command(new_order(10, 3, Side::Bid, 1, 3, 50, 505));
+        let mut assets = Vec::new();
+        while let Some(log) = sink.logs.try_pop() {
+            if let BaseLogs::BalanceDelta(delta) = log {
+                assets.push(delta.asset);
+            }
+            assert!(!matches!(log, BaseLogs::HoldingDelta(_)));
+        }
+        sink.drain();
+        assert!(!assets.is_empty() && assets.iter().all(|asset| [1, 2].contains(asset)));
+
+        // 3 filled at 40 , the buyer gets its price improvement back in the quote asset
+        let wallets = &core.balance_manager.state.wallets;
+        assert_eq!(wallets[&(10, 1)], WalletBalance { available: 1_000 - 3 * 40, reserved: 0 });
+        assert_eq!(wallets[&(10, 2)], WalletBalance { available: 3, reserved: 0 });
+        assert_eq!(wallets[&(20, 1)], WalletBalance { available: 3 * 40, reserved: 0 });
+        assert_eq!(wallets[&(20, 2)], WalletBalance { available: 3, reserved: 2 });
+        assert_eq!(core.balance_manager.state.balances[buyer].available_balance, cash);
+        assert_eq!(core.balance_manager.state.holdings[buyer].available(505), shares);
+        assert!(core.balance_manager.check_conservation().is_empty());
+        assert!(core.balance_manager.reconcile_reservations().is_empty());
+
+        core.balance_manager.state.wallets.get_mut(&(20, 2)).unwrap().reserved += 1;
+        assert_eq!(core.balance_manager.reconcile_reservations(), vec![ReservationMismatch::Wallet { user_id: 20, asset: 2, aggregate: 3, open: 2 }]);
+    }
+
+    #[test]
+    fn test_the_movement_digest_runs_with_the_ledger_and_is_rebuilt_from_a_checkpoint() {
+        let deposit = |key: u64, user_id: u64, amount: u64| FundsMovement { key, user_id, kind: MovementKind::Deposit, asset: Asset::Cash, amount, available_after: amount };
+        let request = |movement: &FundsMovement| FundsRequest::Deposit { user_id: movement.user_id, asset: movement.asset, amount: movement.amount };
+        let (first, second) = (deposit(1, 10, 5), deposit(2, 20, 7));
+        let mut ledger = FundsLedger::default();
+        for movement in [first, second] {
+            ledger.record(movement.key, request(&movement), &[movement]);
+        }
+        let mut swapped = FundsLedger::default();
+        for movement in [second, first] {
+            swapped.record(movement.key, request(&movement), &[movement]);
+        }
+        // the same movements in another order are another history
+        assert_ne!(ledger.digest(), swapped.digest());
+        assert_ne!(ledger.digest(), FundsLedger::default().digest());
+        assert_eq!(FundsLedger::from_movements(ledger.movements().to_vec()).unwrap().digest(), ledger.digest());
+    }
+
+    #[test]
+    fn test_rate_limits_throttle_orders_and_cancels_by_command_time() {
+        const SEC: u64 = 1_000_000_000;
+        let config = EngineConfig::from_toml("[rate_limits.retail]\nnew_order = { capacity = 2 , refill_per_sec = 1 }\ncancel = { capacity = 1 , refill_per_sec = 1 }\n", Vec::new()).unwrap();
+        let (mut core, mut sink) = detached_core_with(&config);
+        let mut sequence = 0;
+        let mut apply_at = |core: &mut TradingCore, timestamp: u64, command: InboundCommand| {
+            sequence += 1;
+            core.apply_record(&JournalRecord::at(sequence, timestamp, command));
+        };
+        apply_at(&mut core, 0, add_book(506));
+        sink.drain();
+        let throttled = |sink: &mut ReplaySink, order_id: OrderId| {
+            let rejected = std::iter::from_fn(|| sink.order_events_from_bm.try_pop()).any(|event| event.order_id == order_id && event.event_kind == 3 && event.error_code == REJECT_REASON_THROTTLED);
+            sink.drain();
+            rejected
+        };
+
+        // a full bucket takes a burst of 2 , the third order in the same second is refused
+        apply_at(&mut core, SEC, new_order(20, 1, Side::Ask, 1, 1, 50, 506));
+        apply_at(&mut core, SEC, new_order(20, 2, Side::Ask, 1, 1, 51, 506));
+        assert!(!throttled(&mut sink, 2));
+        apply_at(&mut core, SEC, new_order(20, 3, Side::Ask, 1, 1, 52, 506));
+        assert!(throttled(&mut sink, 3));
+        // half a second refills half a token , a whole second one
+        apply_at(&mut core, SEC + SEC / 2, new_order(20, 4, Side::Ask, 1, 1, 53, 506));
+        assert!(throttled(&mut sink, 4));
+        apply_at(&mut core, 2 * SEC, new_order(20, 5, Side::Ask, 1, 1, 54, 506));
+        assert!(!throttled(&mut sink, 5));
+        // the market maker is in its own class , unlimited by default
+        for order_id in 6..10 {
+            apply_at(&mut core, 2 * SEC, new_order(0, order_id, Side::Ask, 1, 1, 60, 506));
+            assert!(!throttled(&mut sink, order_id));
+        }
+
+        // cancels have their own bucket , a throttled cancel leaves the order on the book
+        apply_at(&mut core, 2 * SEC, InboundCommand::CancelOrder(OrderToBeCanceled { order_id: 1, user_id: 20, symbol: 506 }));
+        assert!(!throttled(&mut sink, 1));
+        apply_at(&mut core, 2 * SEC, InboundCommand::CancelOrder(OrderToBeCanceled { order_id: 2, user_id: 20, symbol: 506 }));
+        assert!(throttled(&mut sink, 2));
+        let book = core.engine.get_book(506).unwrap();
+        assert!(!book.manager.id_to_index.contains_key(&1) && book.manager.id_to_index.contains_key(&2));
+        let seller = core.balance_manager.get_user_index(20).unwrap() as usize;
+        assert_eq!(core.balance_manager.state.balances[seller].order_count_today, 3);
+
+        // the buckets are checkpointed , a restored core throttles the same
+        let dir = std::env::temp_dir().join(format!("bm_test_rate_limits_checkpoint_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        core.checkpoint_dir = Some(dir.clone());
+        let checkpoint = core.checkpoint().unwrap().unwrap();
+        let (mut restored, mut restored_sink) = detached_core_with(&config);
+        restore_checkpoint(&mut restored, &checkpoint).unwrap();
+        assert_eq!(restored.state_digest(), core.state_digest());
+        restored.apply_record(&JournalRecord::at(sequence + 1, 2 * SEC, new_order(20, 10, Side::Ask, 1, 1, 55, 506)));
+        assert!(throttled(&mut restored_sink, 10));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_amends_replace_the_resting_order_from_their_own_bucket() {
+        const SEC: u64 = 1_000_000_000;
+        let config = EngineConfig::from_toml("[rate_limits.retail]\nnew_order = { capacity = 1 , refill_per_sec = 1 }\namend = { capacity = 1 , refill_per_sec = 1 }\n", Vec::new()).unwrap();
+        let (mut core, mut sink) = detached_core_with(&config);
+        let mut sequence = 0;
+        let mut apply_at = |core: &mut TradingCore, timestamp: u64, command: InboundCommand| {
+            sequence += 1;
+            core.apply_record(&JournalRecord::at(sequence, timestamp, command));
+        };
+        apply_at(&mut core, 0, add_book(509));
+        sink.drain();
+        let rejected = |sink: &mut ReplaySink, user_id: u64| {
+            let reason = std::iter::from_fn(|| sink.order_events_from_bm.try_pop()).find(|event| event.user_id == user_id && event.event_kind == 3).map(|event| event.error_code);
+            sink.drain();
+            reason
+        };
+        let resting = |core: &TradingCore| {
+            let book = core.engine.get_book(509).unwrap();
+            book.manager.id_to_index.get(&1).map(|&index| {
+                let order = book.manager.get(index).unwrap();
+                (order.side, order.shares_qty, order.price)
+            })
+        };
+        let seller = core.balance_manager.get_user_index(20).unwrap() as usize;
+
+        // the new order takes the only new order token , the amend is charged to its own bucket
+        apply_at(&mut core, SEC, new_order(20, 1, Side::Ask, 1, 5, 50, 509));
+        apply_at(&mut core, SEC, new_order(20, 1, Side::Bid, ORDER_TYPE_AMEND, 3, 60, 509));
+        let amended = std::iter::from_fn(|| sink.order_events_from_engine.try_pop()).any(|event| event.order_id == 1 && event.event_kind == 4 && event.error_code == CANCEL_REASON_AMENDED);
+        assert!(amended);
+        assert_eq!(rejected(&mut sink, 20), None);
+        // the side is the resting order's , only qty and price change
+        assert_eq!(resting(&core), Some((Side::Ask, 3, 60)));
+        assert_eq!(core.balance_manager.state.holdings[seller].reserved(509), 3);
+
+        // a second amend in the same second finds the bucket empty and leaves the order as it was
+        apply_at(&mut core, SEC, new_order(20, 1, Side::Ask, ORDER_TYPE_AMEND, 4, 55, 509));
+        assert_eq!(rejected(&mut sink, 20), Some(REJECT_REASON_THROTTLED));
+        assert_eq!(resting(&core), Some((Side::Ask, 3, 60)));
+
+        // only the owner amends , and only an order that is on the book
+        apply_at(&mut core, 2 * SEC, new_order(10, 1, Side::Ask, ORDER_TYPE_AMEND, 4, 55, 509));
+        assert_eq!(rejected(&mut sink, 10), Some(REJECT_REASON_NOT_OWNER));
+        apply_at(&mut core, 2 * SEC, new_order(20, 2, Side::Ask, ORDER_TYPE_AMEND, 4, 55, 509));
+        assert_eq!(rejected(&mut sink, 20), Some(REJECT_REASON_UNKNOWN_ORDER));
+        assert_eq!(resting(&core), Some((Side::Ask, 3, 60)));
+        assert_eq!(core.balance_manager.state.holdings[seller].reserved(509), 3);
+        assert!(core.balance_manager.reconcile_reservations().is_empty());
+        assert!(core.balance_manager.check_conservation().is_empty());
+    }
+
+    #[test]
+    fn test_positions_track_entry_price_and_pnl_through_fills_and_marks() {
+        let (mut core, mut sink) = detached_core();
+        core.admin = AdminPlane::new([7]);
+        core.submit(add_book(507));
+        // 20 sells 10 at 100 to 10 and buys 4 back at 120 , then buys 10 at 90 from the market maker and ends up long 4
+        for (order_id, user_id, side, qty, price) in [
+            (1, 20, Side::Ask, 10, 100), (2, 10, Side::Bid, 10, 100),
+            (3, 10, Side::Ask, 4, 120), (4, 20, Side::Bid, 4, 120),
+            (5, 0, Side::Ask, 10, 90), (6, 20, Side::Bid, 10, 90),
+        ] {
+            core.submit(new_order(user_id, order_id, side, 1, qty, price, 507));
+            if order_id == 4 {
+                let seller = core.balance_manager.position(20, 507);
+                assert_eq!((seller.qty, seller.avg_entry_price(), seller.realized_pnl), (-6, 100, -80));
+            }
+        }
+        let (buyer, seller) = (core.balance_manager.position(10, 507), core.balance_manager.position(20, 507));
+        assert_eq!((buyer.qty, buyer.avg_entry_price(), buyer.realized_pnl), (6, 100, 80));
+        // the flip closes the short 6 at a gain of 10 each and opens the other 4 at the fill price
+        assert_eq!((seller.qty, seller.avg_entry_price(), seller.realized_pnl), (4, 90, -20));
+        assert_eq!(core.balance_manager.position(0, 507).qty, -10);
+        assert_eq!(core.balance_manager.mark_price(507), 90);
+        assert_eq!(seller.unrealized_pnl(90), 0);
+
+        // an admin mark wins over the last trade , 0 goes back to it
+        let admin = |request_id: u64, command_type: u8, available: u64| {
+            InboundCommand::Admin(AdminPlane::new([7]).authorize(AdminCommand { request_id, operator_id: 7, symbol: 507, command_type, available, ..AdminCommand::default() }))
+        };
+        core.submit(admin(1, ADMIN_SET_MARK_PRICE, 80));
+        assert_eq!(core.balance_manager.mark_price(507), 80);
+        sink.drain();
+        let reports = |sink: &mut ReplaySink| -> Vec<PositionReport> {
+            std::iter::from_fn(|| sink.logs.try_pop()).filter_map(|log| match log {
+                BaseLogs::Position(report) => Some(report),
+                _ => None,
+            }).collect()
+        };
+        core.submit(InboundCommand::Query(Query {
+            available_balance: 0,
+            reserved_balance: 0,
+            user_id: 20,
+            symbol: 0,
+            reserved_shares_qty: 0,
+            available_shares_qty: 0,
+            query_type: 6,
+        }));
+        let queried = reports(&mut sink);
+        assert_eq!(queried.len(), 1);
+        let report = queried[0];
+        assert_eq!((report.user_id, report.symbol, report.kind), (20, 507, POSITION_REPORT_QUERY));
+        assert_eq!((report.qty, report.avg_entry_price, report.mark_price), (4, 90, 80));
+        assert_eq!((report.realized_pnl, report.unrealized_pnl), (-20, -40));
+
+        // snapshots report every position , the market maker's included
+        core.submit(admin(2, ADMIN_SNAPSHOT, 0));
+        let snapshot = reports(&mut sink);
+        assert!(snapshot.iter().all(|report| report.kind == POSITION_REPORT_SNAPSHOT));
+        assert!(snapshot.iter().any(|report| (report.user_id, report.unrealized_pnl) == (10, -120)));
+        assert!(snapshot.is_sorted_by_key(|report| (report.user_id, report.symbol)));
+
+        // positions and the mark are checkpointed
+        let dir = std::env::temp_dir().join(format!("bm_test_positions_checkpoint_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        core.checkpoint_dir = Some(dir.clone());
+        let checkpoint = core.checkpoint().unwrap().unwrap();
+        let (mut restored, _restored_sink) = detached_core();
+        restore_checkpoint(&mut restored, &checkpoint).unwrap();
+        assert_eq!(restored.state_digest(), core.state_digest());
+        assert_eq!(restored.balance_manager.position(20, 507), seller);
+        assert_eq!(restored.balance_manager.mark_price(507), 80);
+        core.submit(admin(3, ADMIN_SET_MARK_PRICE, 0));
+        assert_eq!(core.balance_manager.mark_price(507), 90);
+        assert_ne!(restored.state_digest(), core.state_digest());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
 }
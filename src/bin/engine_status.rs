@@ -0,0 +1,41 @@
+// prints the status page of a running engine , one line per pipeline thread
+// usage : engine_status [status_page_path]
+// the path defaults to watchdog.status_page of the config (ENGINE_CONFIG) the engine was started with
+// exits 1 when a thread is stalled or panicked , 2 when the page cannot be opened , so it doubles as a health check
+use std::env;
+use rust_orderbook_2::config::settings::EngineConfig;
+use rust_orderbook_2::metrics::latency::unix_nanos;
+use rust_orderbook_2::shm::status_page::{StatusPage, ThreadState};
+
+fn main() {
+    let config = EngineConfig::load().unwrap_or_else(|e| panic!("[Status] {}", e));
+    let path = env::args().nth(1).unwrap_or(config.watchdog.status_page);
+    let page = match StatusPage::open(&path) {
+        Ok(page) => page,
+        Err(e) => {
+            eprintln!("[Status] cannot read {}: {}", path, e);
+            std::process::exit(2);
+        }
+    };
+
+    let now = unix_nanos();
+    let since = |at_ns: u64| if at_ns == 0 { "never".to_string() } else { format!("{}ms ago", now.saturating_sub(at_ns) / 1_000_000) };
+    println!("[Status] watchdog last pass {}", since(page.watchdog_ns()));
+    let mut unhealthy = 0;
+    for thread in page.snapshot() {
+        if matches!(thread.state, ThreadState::Stalled | ThreadState::Panicked) {
+            unhealthy += 1;
+        }
+        println!(
+            "{:<16} {:<9} heartbeat {:<12} progress {:<12} input depths {:?}",
+            thread.name,
+            thread.state.as_str(),
+            thread.heartbeat,
+            since(thread.last_progress_ns),
+            thread.input_depths
+        );
+    }
+    if unhealthy != 0 {
+        std::process::exit(1);
+    }
+}
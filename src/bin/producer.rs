@@ -5,6 +5,7 @@ use std::{
     time::{Duration, Instant},
 };
 
+use rust_orderbook_2::config::settings::EngineConfig;
 use rust_orderbook_2::orderbook::order::ShmOrder;
 use rust_orderbook_2::shm::queue::IncomingOrderQueue;
 
@@ -26,8 +27,10 @@ fn main() {
     println!("[OMS] Using concentrated price levels with alternating users");
 
     // ==== Open queue ====
+    // same config file as the engine so both sides agree on the queue path
+    let config = EngineConfig::load().unwrap_or_else(|e| panic!("[OMS] {}", e));
     let mut q =
-        IncomingOrderQueue::open("/tmp/IncomingOrders").expect("Failed to open queue");
+        IncomingOrderQueue::open(&config.queues.incoming_orders).expect("Failed to open queue");
 
     static ATOMIC_COUNT: AtomicI64 = AtomicI64::new(0);
 
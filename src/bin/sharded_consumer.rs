@@ -0,0 +1,232 @@
+// the trading core split over several threads : one risk thread owning the balances and
+// engine.shards engine shards (default 2) each owning the books the router gave it
+// publisher , writer and logger read every shard's queue through a fan in
+// not journalled yet : settlement order across shards depends on thread timing , so a replay of the inbound
+// commands would not rebuild the same balances , run single_threaded_consumer where recovery matters
+// not a production target : no journal , no checkpoint , a crash loses every balance , book and kill switch
+// so it refuses to start unless engine.allow_unjournaled_shards is set
+use rust_orderbook_2::{
+    admin::plane::AdminPlane,
+    backpressure::policy::BackpressureConfig,
+    config::settings::EngineConfig,
+    metrics::exporter::spawn_exporter,
+    metrics::latency::spawn_latency_dumper,
+    watchdog::{heartbeat::Heartbeat, monitor::spawn_watchdog},
+    logger::{log_reciever::LogReciever, types::{BaseLogs, OrderBookSnapShot, TradeLogs}},
+    orderbook::types::Event,
+    publisher::event_publisher::EventPublisher,
+    pubsub::pubsub_manager::RedisPubSubManager,
+    sharding::{engine_shard::EngineShard, fan_in::FanIn, messages::{ShardCommand, ShardReport}, risk::RiskManager},
+    shm::{admin_command_queue::AdminCommandQueue, admin_response_queue::{AdminResponse, AdminResponseQueue}, balance_log_queue::BalanceLogQueue, balance_response_queue::{BalanceResQueue, BalanceResponse}, cancel_orders_queue::CancelOrderQueue, event_queue::{OrderEventQueue, OrderEvents}, fill_queue_mm::{MarketMakerFill, MarketMakerFillQueue}, holdings_log_queue::HoldingLogQueue, holdings_response_queue::{HoldingResQueue, HoldingResponse}, market_maker_feed::{MarketMakerFeed, MarketMakerFeedQueue}, order_log_queue::OrderLogQueue, query_queue::QueryQueue, queue::IncomingOrderQueue, snapshot_queue::OrderBookSnapShotQueue, trade_log_queue::TradeLogQueue, position_queue::PositionQueue, status_page::StatusPage, writer::ShmWriter},
+    shutdown::shutdown_signal::{Shutdown, Stage},
+    trading_core::my_trading_core::CoreInbound,
+};
+use std::sync::Arc;
+
+// shard i runs on cores.shards[i % len] , the risk thread takes cores.trading_core like the single threaded core
+fn main() {
+    let config = EngineConfig::load().unwrap_or_else(|e| panic!("[Main] {}", e));
+    config.engine.check_sharded_mode().unwrap_or_else(|e| panic!("[Main] {}", e));
+    eprintln!("[Main] sharded mode , nothing is journalled , a crash loses all state");
+    let available_cores: Vec<usize> = core_affinity::get_core_ids().unwrap_or_default().into_iter().map(|core| core.id).collect();
+    let missing_cores = config.cores.missing(&available_cores);
+    if !missing_cores.is_empty() {
+        panic!("[Main] configured cores {:?} do not exist on this host", missing_cores);
+    }
+    // prometheus text on http://<metrics.listen>/metrics , a busy port only costs the metrics , not the engine
+    if let Err(e) = spawn_exporter(&config.metrics, &config.queues) {
+        eprintln!("[Main] metrics endpoint on {} not started: {}", config.metrics.listen, e);
+    }
+    spawn_latency_dumper(config.metrics.latency_window(), config.metrics.latency_log).expect("failed to start the latency dumper");
+    let shards = config.engine.shards;
+    let queues = &config.queues;
+    let capacity = config.capacities.channel;
+
+    // risk stops the shards and waits for them , main marks the engine stage once every shard thread is joined
+    let shutdown = Shutdown::new();
+    shutdown.register_signals().expect("failed to register shutdown signals");
+    let risk_shutdown = shutdown.handle(Stage::BalanceManager, &[]);
+    let publisher_shutdown = shutdown.handle(Stage::Publisher, &[Stage::Engine]);
+    let writter_shutdown = shutdown.handle(Stage::Writer, &[Stage::BalanceManager, Stage::Publisher, Stage::Engine]);
+    let logger_shutdown = shutdown.handle(Stage::Logger, &[Stage::BalanceManager, Stage::Engine, Stage::Publisher]);
+
+    let backpressure = BackpressureConfig::from_env().unwrap_or_else(|e| panic!("[Main] bad BACKPRESSURE setting: {}", e));
+    let risk_backpressure = backpressure.clone();
+    let publisher_backpressure = backpressure.clone();
+    let writter_backpressure = backpressure.clone();
+    let logger_backpressure = backpressure.clone();
+    let admin = AdminPlane::from_env().unwrap_or_else(|e| panic!("[Main] bad ADMIN_OPERATORS setting: {}", e));
+    if admin.operators.is_empty() {
+        eprintln!("[Main] no admin operators configured , every admin command will be refused");
+    }
+
+    let _ = IncomingOrderQueue::create(&queues.incoming_orders).expect("failed to create queue");
+    let _ = CancelOrderQueue::create(&queues.cancel_orders).expect("failed to create queue");
+    let _ = OrderEventQueue::create(&queues.order_events).expect("failed to create queue");
+    let _ = QueryQueue::create(&queues.queries).expect("failed to create queue");
+    let _ = HoldingResQueue::create(&queues.holdings_response).expect("failed to create queue");
+    let _ = BalanceResQueue::create(&queues.balance_response).expect("failed to open queue");
+    let _ = OrderLogQueue::create(&queues.order_logs).expect("failed to create the Log queue");
+    let _ = BalanceLogQueue::create(&queues.balance_logs).expect("failed to open balance log queue");
+    let _ = HoldingLogQueue::create(&queues.holding_logs).expect("failed to open holding queues");
+    let _ = TradeLogQueue::create(&queues.trade_logs).expect("failed to open trade logs queue");
+    let _ = PositionQueue::create(&queues.positions).expect("failed to open positions queue");
+    let _ = OrderBookSnapShotQueue::create(&queues.snapshot).expect("failed to open snap shot queue");
+    let _ = MarketMakerFillQueue::create(&queues.market_maker_fills).expect("failed to open market maker fill queue");
+    let _ = MarketMakerFeedQueue::create(&queues.market_maker_feed).expect("failed to open the feed queue");
+    let _ = AdminCommandQueue::create(&queues.admin_commands).expect("failed to create the admin command queue");
+    let _ = AdminResponseQueue::create(&queues.admin_responses).expect("failed to create the admin response queue");
+
+    // every pipeline thread beats into the status page , engine_status reads it from outside the process
+    let status_page = Arc::new(StatusPage::create(&config.watchdog.status_page).expect("failed to create the status page"));
+    spawn_watchdog(status_page.clone(), config.watchdog.period(), config.watchdog.stall_after()).expect("failed to start the watchdog");
+
+    let (order_event_producer_risk , order_event_consumer_writter_from_risk) = bounded_spsc_queue::make::<OrderEvents>(capacity);
+    let (order_event_producer_publisher , order_event_consumer_writter_from_publisher) = bounded_spsc_queue::make::<OrderEvents>(capacity);
+    let (balance_event_producer_risk , balance_event_consumer_writter) = bounded_spsc_queue::make::<BalanceResponse>(capacity);
+    let (holding_event_producer_risk , holding_event_consumer_writter) = bounded_spsc_queue::make::<HoldingResponse>(capacity);
+    let (log_producer_risk , log_consumer_logger) = bounded_spsc_queue::make::<BaseLogs>(capacity);
+    let (trade_log_producer_publisher , trade_log_consumer_logger) = bounded_spsc_queue::make::<TradeLogs>(capacity);
+    let (mm_fill_sender , mm_fill_reciever) = bounded_spsc_queue::make::<MarketMakerFill>(capacity);
+    let (admin_response_sender , admin_response_reciever) = bounded_spsc_queue::make::<AdminResponse>(config.capacities.admin_responses);
+
+    // per shard queues , the fan ins collect the consuming ends
+    let mut shard_command_senders = Vec::with_capacity(shards);
+    let mut shard_report_recievers = Vec::with_capacity(shards);
+    let mut events_to_publisher = FanIn::<Event>::new(Vec::new());
+    let mut order_events_to_writter = FanIn::<OrderEvents>::new(Vec::new());
+    let mut mm_feed_to_writter = FanIn::<MarketMakerFeed>::new(Vec::new());
+    let mut snapshots_to_logger = FanIn::<OrderBookSnapShot>::new(Vec::new());
+    let mut shard_handles = Vec::with_capacity(shards);
+    for shard_id in 0..shards {
+        let (command_sender , command_reciever) = bounded_spsc_queue::make::<ShardCommand>(capacity);
+        let (report_sender , report_reciever) = bounded_spsc_queue::make::<ShardReport>(capacity);
+        let (event_sender , event_reciever) = bounded_spsc_queue::make::<Event>(capacity);
+        let (order_event_sender , order_event_reciever) = bounded_spsc_queue::make::<OrderEvents>(capacity);
+        let (mm_feed_sender , mm_feed_reciever) = bounded_spsc_queue::make::<MarketMakerFeed>(capacity);
+        let (snapshot_sender , snapshot_reciever) = bounded_spsc_queue::make::<OrderBookSnapShot>(capacity);
+        shard_command_senders.push(command_sender);
+        shard_report_recievers.push(report_reciever);
+        events_to_publisher.add_input(event_reciever);
+        order_events_to_writter.add_input(order_event_reciever);
+        mm_feed_to_writter.add_input(mm_feed_reciever);
+        snapshots_to_logger.add_input(snapshot_reciever);
+
+        let shard_backpressure = backpressure.clone();
+        let shard_config = config.clone();
+        let shard_page = status_page.clone();
+        shard_handles.push(std::thread::spawn(move || {
+            core_affinity::set_for_current(core_affinity::CoreId { id: shard_config.cores.shard(shard_id) });
+            let mut shard = EngineShard::new(
+                shard_id,
+                command_reciever,
+                report_sender,
+                event_sender,
+                order_event_sender,
+                mm_feed_sender,
+                snapshot_sender,
+                &shard_backpressure,
+                &shard_config
+            );
+            shard.heartbeat = Some(Heartbeat::register(&shard_page, &format!("shard_{}", shard_id)).expect("failed to register the shard heartbeat"));
+            shard.run();
+        }));
+    }
+
+    let risk_config = config.clone();
+    let risk_page = status_page.clone();
+    let risk_handle = std::thread::spawn(move || {
+        core_affinity::set_for_current(core_affinity::CoreId { id: risk_config.cores.trading_core });
+        let mut risk = RiskManager::new(
+            order_event_producer_risk,
+            balance_event_producer_risk,
+            holding_event_producer_risk,
+            log_producer_risk,
+            shard_command_senders,
+            shard_report_recievers,
+            admin_response_sender,
+            &risk_backpressure,
+            &risk_config
+        );
+        risk.admin = admin;
+        risk.bootstrap_state();
+        #[cfg(feature = "throughput-users")]
+        risk.add_throughput_users();
+        let mut inbound = CoreInbound::open(&risk_config.queues).expect("failed to open the risk thread input queues");
+        risk.heartbeat = Some(Heartbeat::register(&risk_page, "risk").expect("failed to register the risk heartbeat"));
+        risk.run(&mut inbound, &risk_shutdown);
+    });
+
+    let pubsub_connection = RedisPubSubManager::new(&config.redis.url);
+    if pubsub_connection.is_err(){
+        panic!("pubsub error , not initialising publisher");
+    }
+    let publisher_core = config.cores.publisher;
+    let publisher_page = status_page.clone();
+    let publisher_handle = std::thread::spawn(move || {
+        core_affinity::set_for_current(core_affinity::CoreId { id: publisher_core });
+        let mut my_publisher = EventPublisher::new(
+            pubsub_connection.unwrap() ,
+            events_to_publisher,
+            order_event_producer_publisher,
+            trade_log_producer_publisher ,
+            mm_fill_sender ,
+            &publisher_backpressure
+        );
+        my_publisher.heartbeat = Some(Heartbeat::register(&publisher_page, "publisher").expect("failed to register the publisher heartbeat"));
+        my_publisher.start_publisher(&publisher_shutdown);
+    });
+
+    let writter_config = config.clone();
+    let writter_page = status_page.clone();
+    let writter_handle = std::thread::spawn(move || {
+        core_affinity::set_for_current(core_affinity::CoreId { id: writter_config.cores.writer });
+        let shm_writter = ShmWriter::new(
+            order_event_consumer_writter_from_risk,
+            order_event_consumer_writter_from_publisher,
+            order_events_to_writter,
+            balance_event_consumer_writter,
+            holding_event_consumer_writter,
+            mm_fill_reciever,
+            mm_feed_to_writter,
+            admin_response_reciever,
+            &writter_backpressure,
+            &writter_config.queues
+        );
+        match shm_writter {
+            Some(mut shm_writter) => {
+                shm_writter.heartbeat = Some(Heartbeat::register(&writter_page, "writer").expect("failed to register the writer heartbeat"));
+                shm_writter.start_shm_writter(&writter_shutdown);
+            }
+            None => eprintln!("error initialising shm writter"),
+        }
+    });
+
+    let logger_config = config.clone();
+    let logger_page = status_page;
+    let log_reciver_handle = std::thread::spawn(move || {
+        core_affinity::set_for_current(core_affinity::CoreId { id: logger_config.cores.logger });
+        let mut log_reciver = LogReciever::new(
+            log_consumer_logger ,
+            trade_log_consumer_logger ,
+            snapshots_to_logger ,
+            &logger_backpressure ,
+            &logger_config.queues
+        );
+        log_reciver.heartbeat = Some(Heartbeat::register(&logger_page, "logger").expect("failed to register the logger heartbeat"));
+        log_reciver.run(&logger_shutdown);
+    });
+
+    eprintln!("[Main] Initialization complete , {} engine shards", shards);
+
+    for handle in shard_handles {
+        handle.join().expect("engine shard panicked");
+    }
+    // every shard has flushed its queues before exiting
+    shutdown.finish(Stage::Engine);
+    risk_handle.join().expect("risk thread panicked");
+    publisher_handle.join().expect("publisher panicked");
+    writter_handle.join().expect("writter panicked");
+    log_reciver_handle.join().expect("log reciever panicked");
+    println!("System shutdown");
+}
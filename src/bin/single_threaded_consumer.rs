@@ -1,344 +1,168 @@
 
 use rust_orderbook_2::{
-    balance_manager::my_balance_manager2::{BalanceManagerResForLocking, STbalanceManager}, engine::my_engine::{Engine, STEngine}, logger::{log_reciever::LogReciever, types::{BalanceDelta, BaseLogs, HoldingDelta, OrderBookSnapShot, OrderDelta, TradeLogs}}, orderbook::{order::Order, types::Event}, shm::{balance_log_queue::BalanceLogQueue, balance_response_queue::BalanceResponse, fill_queue_mm::{MarketMakerFill, MarketMakerFillQueue}, holdings_log_queue::HoldingLogQueue, holdings_response_queue::HoldingResponse, market_maker_feed::{MarketMakerFeed, MarketMakerFeedQueue}, reader::StShmReader, snapshot_queue::OrderBookSnapShotQueue, trade_log_queue::TradeLogQueue}
+    backpressure::policy::BackpressureConfig,
+    config::settings::EngineConfig,
+    metrics::exporter::spawn_exporter,
+    metrics::latency::spawn_latency_dumper,
+    watchdog::{heartbeat::Heartbeat, monitor::spawn_watchdog},
+    supervisor::thread_supervisor::{RestartPolicy, Supervisor},
+    journal::{command_journal::JournalWriter, replay::{detached_core_with, recover}}, replication::{primary::ReplicationServer, standby::{Standby, StandbyAdmin}}, digest::digest_log::DigestLog, shutdown::shutdown_signal::{Shutdown, Stage}, logger::{log_reciever::LogReciever, types::{BaseLogs, OrderBookSnapShot, TradeLogs}}, orderbook::types::Event, shm::{balance_log_queue::BalanceLogQueue, balance_response_queue::BalanceResponse, fill_queue_mm::{MarketMakerFill, MarketMakerFillQueue}, holdings_log_queue::HoldingLogQueue, holdings_response_queue::HoldingResponse, market_maker_feed::{MarketMakerFeed, MarketMakerFeedQueue}, snapshot_queue::OrderBookSnapShotQueue, trade_log_queue::TradeLogQueue, position_queue::PositionQueue}, trading_core::my_trading_core::{CoreInbound, TradingCore}
 };
-use std::time::{Instant , Duration};
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
 use rust_orderbook_2::shm::queue::{IncomingOrderQueue};
 use rust_orderbook_2::shm::balance_response_queue::BalanceResQueue;
 use rust_orderbook_2::shm::cancel_orders_queue::CancelOrderQueue;
 use rust_orderbook_2::shm::event_queue::OrderEventQueue;
 use rust_orderbook_2::shm::holdings_response_queue::HoldingResQueue;
 use rust_orderbook_2::shm::query_queue::QueryQueue;
-use bounded_spsc_queue::Producer;
 use rust_orderbook_2::shm::event_queue::OrderEvents;
 use rust_orderbook_2::pubsub::pubsub_manager::RedisPubSubManager;
 use rust_orderbook_2::shm::writer::ShmWriter;
 use rust_orderbook_2::shm::order_log_queue::OrderLogQueue;
 use rust_orderbook_2::publisher::event_publisher::EventPublisher;
-use rust_orderbook_2::orderbook::order::Side;
-use std::sync::atomic::{AtomicU64, Ordering};
-
-static EVENT_ID: AtomicU64 = AtomicU64::new(1);
-#[inline(always)]
-fn next_event_id() -> u64 {
-    EVENT_ID.fetch_add(1, Ordering::Relaxed)
-}
-
-
-const SNAPSHOT_INTERVAL: Duration = Duration::from_secs(30);
-pub struct TradingCore {
-    pub balance_manager: STbalanceManager,
-    pub shm_reader: StShmReader,
-    pub engine: STEngine,
-    pub query_queue : QueryQueue,
-    processed_count: u64,
-    order_batch : Vec<Order> , 
-    pub log_sender_to_logger : Producer<BaseLogs>,
-    pub snapshot_sender_to_logger : Producer<OrderBookSnapShot> ,
-    pub last_snap_shot : Instant,
-    pub market_maker_feed_sender : Producer<MarketMakerFeed>
-}
-impl TradingCore {
-    pub fn new(
-        event_sender_to_writter : Producer<OrderEvents> ,
-        event_sender_to_publisher_by_engine : Producer<Event> , 
-        order_event_producer_engine : Producer<OrderEvents>,
-        balance_event_producer_bm : Producer<BalanceResponse>,
-        holding_event_producer_bm : Producer<HoldingResponse>,
-        log_sender_to_logger : Producer<BaseLogs>,
-        snapshot_sender_to_logger : Producer<OrderBookSnapShot>,
-        market_maker_feed_sender : Producer<MarketMakerFeed>
-    ) -> Self {
-        let query_queue = QueryQueue::open("/tmp/Queries");
-        if query_queue.is_err(){
-            eprintln!("query queue init error in balance manager");
-            eprintln!("{:?}" , query_queue)
-        }
-        Self {
-            market_maker_feed_sender,
-            query_queue : query_queue.unwrap(),
-            balance_manager: STbalanceManager::new(event_sender_to_writter , balance_event_producer_bm , holding_event_producer_bm),
-            shm_reader: StShmReader::new().unwrap(),
-            engine: STEngine::new(0 , event_sender_to_publisher_by_engine , order_event_producer_engine  ),
-            processed_count: 0,
-            order_batch : Vec::with_capacity(1000),
-            log_sender_to_logger , 
-            snapshot_sender_to_logger,
-            last_snap_shot : Instant::now()
-        }
+use rust_orderbook_2::admin::plane::AdminPlane;
+use rust_orderbook_2::shm::admin_command_queue::AdminCommandQueue;
+use rust_orderbook_2::shm::admin_response_queue::{AdminResponse, AdminResponseQueue};
+use rust_orderbook_2::shm::status_page::StatusPage;
+
+// paths , capacities and cores come from the config file named by ENGINE_CONFIG , see config/engine.toml
+// unlike the shm queues the journal is never recreated , it outlives the process
+
+// rebuilds local state from `journal_path` and `checkpoint_dir` into `core` and attaches the journal for appends
+fn recover_core(core: &mut TradingCore, config: &EngineConfig, journal_path: &Path, checkpoint_dir: &Path) {
+    // opening first cuts off a torn tail so recovery and appends agree on the last sequence
+    let journal = JournalWriter::open(journal_path).expect("failed to open the command journal");
+    match recover(core, config, journal_path, Some(checkpoint_dir)) {
+        Ok(stats) => eprintln!("[Trading Core] loaded checkpoint {:?} and replayed {} journalled commands", stats.checkpoint_sequence, stats.replay.records_applied),
+        Err(e) => panic!("[Trading Core] recovery failed: {}", e),
     }
-    pub fn run(&mut self) {
-        eprintln!("[Trading Core] Starting single-threaded mode");
-        // initiliased the market maker 
-        let _ = self.balance_manager.add_market_maker();
-
-        loop {
-
-            self.order_batch.clear();
-            for _ in 0 ..1000{
-                if let Some(order) = self.shm_reader.receive_order() {
-                    // some order recved 
-                    //println!("order recived {:?}" , order);
-                    self.log_sender_to_logger.try_push(BaseLogs::OrderDelta(OrderDelta{
-                        event_id : next_event_id(),
-                        order_id : order.order_id ,
-                        user_id : order.user_id ,
-                        price : order.price ,
-                        symbol : order.symbol ,
-                        shares_qty : order.shares_qty ,
-                        side : match order.side {
-                            Side::Ask => 1,
-                            Side::Bid => 0 
-                        }, 
-                        order_event_type : 0 
-                    }));
-                    self.order_batch.push(order);       
-                    
-                }
-                else {
-                    break;
-                }
-            }
-            for order in self.order_batch.drain(..){
-                match self.balance_manager.check_and_lock_funds(order) {
-                    Ok(balance_response_for_logger) => {
-                        //println!("balances have been locked and holdings reserved it ws valid order");
-                        // balances have been locked or holding shave been reserved 
-                        match balance_response_for_logger {
-                            BalanceManagerResForLocking::BalanceManagerResUpdateDeltaBalance(balance_delta)=>{
-                                self.log_sender_to_logger.try_push(BaseLogs::BalanceDelta(BalanceDelta{
-                                    event_id : next_event_id() ,
-                                    user_id : order.user_id ,
-                                    delta_available : balance_delta.delta_available_balance , 
-                                    delta_reserved : balance_delta.delta_reserved_balance ,
-                                    reason : 0 ,
-                                    order_id : order.order_id
-                                }));
-                            }
-
-                            BalanceManagerResForLocking::BalanceManagerResUpdateDeltaHolding(holding_delta)=>{
-                                self.log_sender_to_logger.try_push(BaseLogs::HoldingDelta(HoldingDelta{
-                                    event_id : next_event_id() , 
-                                    user_id : order.user_id ,
-                                    symbol : order.symbol ,
-                                    delta_available : holding_delta.delta_available_holding ,
-                                    delta_reserved : holding_delta.delta_reserved_holding,
-                                    reason : 0 ,
-                                    order_id : order.order_id
-                                }));
-                            }
-                            
-                        }
-                        // Process order in engine
-                        let engine_res = self.engine.process_order(order, |feed|{
-                            let _ = self.market_maker_feed_sender.try_push(feed);
-                        }) ; 
-                        match engine_res.0 {
-                            Some(match_result) => {
-                                // log that order has been matched 
-                                //println!("{:?}" , match_result);
-                                //println!("logging that order has been matched ");
-                                self.log_sender_to_logger.try_push(BaseLogs::OrderDelta(OrderDelta { 
-                                    event_id: next_event_id(), 
-                                    order_id: order.order_id, 
-                                    user_id: order.user_id, 
-                                    price: order.price, 
-                                    symbol: order.symbol, 
-                                    shares_qty: order.shares_qty, 
-                                    side : match order.side {
-                                        Side::Ask => 1,
-                                        Side::Bid => 0 
-                                    }, 
-                                    order_event_type: 1
-                                }));
-                                // Update balances from fills
-                                if let Err(e) = self.balance_manager
-                                    .update_balances_after_trade(match_result.fills ,
-                                         |log|{
-                                            let _ = self.log_sender_to_logger.try_push(log);
-                                         } , 
-                                            ||->u64{
-                                                next_event_id()
-                                            }
-                                        )
-                                // to do add logging here also when balance changes for consistanc 
-                                {
-                                    
-                                    eprintln!("[Trading Core] Balance update error: {:?}", e);
-                                }
-                                
-                            }
-                            None => {
-                                eprintln!("[Trading Core] Failed to process order");
-                            }
-                        }
-
-                        match engine_res.1 {
-                            Some(market_update)=>{
-                               // println!("sending data to publisher");
-                               let _ = self.engine.sending_event_to_publisher_try.try_push(Event::new(market_update));
-                            }
-                            None=>{
-                                // no amrket update , dosent matter 
-                            }
-                        }
-                        
-                        self.processed_count += 1;
-                    }
-                    Err(_) => {
-                        println!("insufficient funds");
-                        // log that order has been rejected 
-                        self.log_sender_to_logger.try_push(BaseLogs::OrderDelta(OrderDelta { 
-                            event_id: next_event_id(), 
-                            order_id: order.order_id, 
-                            user_id: order.user_id, 
-                            price: order.price, 
-                            symbol: order.symbol, 
-                            shares_qty: order.shares_qty, 
-                            side : match order.side {
-                                Side::Ask => 1,
-                                Side::Bid => 0 
-                            }, 
-                            order_event_type: 2, 
-                        }));
-                        // Order rejected (insufficient funds, etc)
-                        let _ = self.balance_manager.events_to_wrriter_try.push(
-                            OrderEvents { 
-                                user_id: order.user_id,
-                                order_id: order.order_id,
-                                symbol: order.symbol,
-                                event_kind: 3,
-                                filled_qty: 0,
-                                remaining_qty: order.shares_qty,
-                                original_qty: order.shares_qty,
-                                error_code: 1
-                            }
-                        );
-                    }
-                }
-            }
-
-            match self.engine.cancel_order_queue.dequeue(){
-                Ok(Some(order_to_be_canceled))=>{
-                    if let Some(order_book) = self.engine.get_book_mut(order_to_be_canceled.symbol){
-                        // we canceled the order update the balance , pass the orderEvent to the Writter too
-                        // this queue would be only for canceling the limit orderrs 
-                        // we need to get the detials of this order from the order manager 
-                        // side , qty nd price 
-                        let order_index = order_book.manager.id_to_index[&order_to_be_canceled.order_id];
-                        let order_detials = order_book.manager.get(order_index).unwrap();
-                        match self.balance_manager.update_balance_after_order_cancel(order_to_be_canceled, order_detials.side, order_detials.shares_qty, order_detials.price) {
-                            Ok(_)=>{
-                                order_book.cancel_order(order_to_be_canceled.order_id , |feed|{
-                                    let _ = self.market_maker_feed_sender.try_push(feed);
-                                });
-                                // order can be aprtialyl filled also when cancl order comes 
-                                // need to chnage the order struct to include '
-                                self.engine.sending_order_events_to_writter_try.try_push(OrderEvents { 
-                                    user_id: order_to_be_canceled.user_id, 
-                                    order_id: order_to_be_canceled.order_id, 
-                                    symbol: order_to_be_canceled.symbol, 
-                                    event_kind: 4, // cancel order 
-                                    filled_qty: 0, 
-                                    remaining_qty: 0, 
-                                    original_qty: 0, 
-                                    error_code: 0
-                                 });
-                            }
-                            Err(_)=>{
-
-                            }
-                        }
-                    }else{
-                        eprint!("invalid order")
-                    }
-                }
-                Ok(None)=>{
-                    
-                }
-                Err(_) => {
-
-                }
-            }
-            match self.query_queue.dequeue(){
-                Ok(Some(query))=>{
-                    // we wud only get the add user query from here , qid , user_id , query_Type 2 
-                    match query.query_type{
-                        0 =>{
-                            let _ = self.balance_manager.change_user_balance(query.user_id, query.reserved_balance , query.available_balance);
-                        }
-                        1 =>{
-                            // channgel usr hldigs 
-                            let _ = self.balance_manager.change_user_holdings(query.user_id, query.symbol, query.reserved_shares_qty , query.available_shares_qty);
-                        }
-
-                        2 => {
-                            let _= self.balance_manager.add_user(query.user_id);
-
-                        }
-                        3=>{
-                            // add order book 
-                            let _ = self.engine.add_book(query.symbol);
-                        }
-                        _=>{
-
-                        }
-                    }
-                }
-                Ok(None)=>{}
-                Err(_)=>{}
-            }     
-
-            if self.last_snap_shot.elapsed() >= SNAPSHOT_INTERVAL{
-                //println!("need to send snapshot now");
-                self.engine.snapshot_for_all_book(|snapshot|{
-                    //println!("sending snapshot to logg recv");
-                    //println!("{:?}" , snapshot);
-                    let _ = self.snapshot_sender_to_logger.try_push(snapshot);
-                }, next_event_id);
-                self.last_snap_shot = Instant::now();
-            }   
-
+    // an empty journal is fine , a checkpoint alone is a complete state
+    if journal.last_sequence() != 0 {
+        assert_eq!(core.sequence, journal.last_sequence(), "journal and recovered state disagree");
+    }
+    core.journal = Some(journal);
+    core.checkpoint_dir = Some(checkpoint_dir.to_path_buf());
+}
 
-        }
+// `single_threaded_consumer standby` follows a running primary and only starts serving once promoted
+// promotion is ADMIN_PROMOTE from an ADMIN_OPERATORS operator on the standby admin queues , stop the old primary first
+// `kill -USR1 <pid>` of the standby still promotes it , the break glass way when the admin tooling is down
+fn follow_primary(config: &EngineConfig, admin: AdminPlane) -> TradingCore {
+    let (mut core, sink) = detached_core_with(config);
+    recover_core(&mut core, config, &config.storage.standby_journal, &config.storage.standby_checkpoints);
+    let promote = Arc::new(AtomicBool::new(false));
+    signal_hook::flag::register(signal_hook::consts::SIGUSR1, promote.clone()).expect("failed to register the promotion signal");
+    let standby_admin = StandbyAdmin {
+        plane: admin,
+        commands: AdminCommandQueue::create(&config.queues.standby_admin_commands).expect("failed to create the standby admin command queue"),
+        responses: AdminResponseQueue::create(&config.queues.standby_admin_responses).expect("failed to create the standby admin response queue"),
+    };
+    let (core, stats) = Standby::new(core, sink, &config.storage.replication_socket, promote).with_admin(standby_admin).run_until_promoted();
+    if stats.digests_mismatched != 0 {
+        eprintln!("[Standby] promoting with {} digest mismatches on record , check the books before trading", stats.digests_mismatched);
     }
+    core
 }
 
 
 #[hotpath::main]
 fn main() {
+    let config = EngineConfig::load().unwrap_or_else(|e| panic!("[Main] {}", e));
+    let available_cores: Vec<usize> = core_affinity::get_core_ids().unwrap_or_default().into_iter().map(|core| core.id).collect();
+    let missing_cores = config.cores.missing(&available_cores);
+    if !missing_cores.is_empty() {
+        panic!("[Main] configured cores {:?} do not exist on this host", missing_cores);
+    }
 
-    let _ = IncomingOrderQueue::create("/tmp/IncomingOrders").expect("failed to create queue");
-    let _ = CancelOrderQueue::create("/tmp/CancelOrders").expect("failed to create queue");
-    let _ = OrderEventQueue::create("/tmp/OrderEvents").expect("failed to create queue");
-    let _ = QueryQueue::create("/tmp/Queries").expect("failed to create queue");
-    let _ = HoldingResQueue::create("/tmp/HoldingsResponse").expect("failed to create queue");
-    let _ = BalanceResQueue::create("/tmp/BalanceResponse").expect("failed to open queue");
-    let _ = OrderLogQueue::create("/tmp/OrderLogs").expect("failed to create the Log queue");
-    let _ = BalanceLogQueue::create("/tmp/BalanceLogs").expect("failed to open balance log queue");
-    let _ = HoldingLogQueue::create("/tmp/HoldingLogs").expect("failed to open holding queues");
-    let _ = TradeLogQueue::create("/tmp/TradeLogs").expect("failed to open trade logs queue");
-    let _ = OrderBookSnapShotQueue::create("/tmp/SnapShot").expect("failed to open snap shot queue");
-    let _ = MarketMakerFillQueue::create("/tmp/MarketMakerFills").expect("failed to open market maker fill queue");
-    let _ = MarketMakerFeedQueue::create("/tmp/MarketMakerFeed").expect("failed to open the feed queue");
+    // in standby mode nothing below runs until promotion , the shm queues belong to the primary until then
+    let promoted = match std::env::args().nth(1).as_deref() {
+        Some("standby") => {
+            let admin = AdminPlane::from_env().unwrap_or_else(|e| panic!("[Main] bad ADMIN_OPERATORS setting: {}", e));
+            Some(follow_primary(&config, admin))
+        }
+        _ => None,
+    };
+    let journal_path = if promoted.is_some() { config.storage.standby_journal.clone() } else { config.storage.journal.clone() };
+    let digest_log_path = if promoted.is_some() { config.storage.standby_digest_log.clone() } else { config.storage.digest_log.clone() };
+    // prometheus text on http://<metrics.listen>/metrics , a busy port only costs the metrics , not the engine
+    if let Err(e) = spawn_exporter(&config.metrics, &config.queues) {
+        eprintln!("[Main] metrics endpoint on {} not started: {}", config.metrics.listen, e);
+    }
+    spawn_latency_dumper(config.metrics.latency_window(), config.metrics.latency_log).expect("failed to start the latency dumper");
+    let core_config = config.clone();
+    let publisher_core = config.cores.publisher;
+    let writter_config = config.clone();
+    let logger_config = config.clone();
+    let queues = &config.queues;
+    let capacity = config.capacities.channel;
+
+    // SIGTERM , SIGINT or ADMIN_SHUTDOWN stop the core , the rest drain in order : core -> publisher -> writer and logger
+    let shutdown = Shutdown::new();
+    shutdown.register_signals().expect("failed to register shutdown signals");
+    let core_shutdown = shutdown.handle(Stage::Core, &[]);
+    let publisher_shutdown = shutdown.handle(Stage::Publisher, &[Stage::Core]);
+    let writter_shutdown = shutdown.handle(Stage::Writer, &[Stage::Core, Stage::Publisher]);
+    let logger_shutdown = shutdown.handle(Stage::Logger, &[Stage::Core, Stage::Publisher]);
+
+    // per channel overrides , BACKPRESSURE=core.logs=block,writer.mm_feed=drop_newest
+    let backpressure = BackpressureConfig::from_env().unwrap_or_else(|e| panic!("[Main] bad BACKPRESSURE setting: {}", e));
+    let core_backpressure = backpressure.clone();
+    let publisher_backpressure = backpressure.clone();
+    let writter_backpressure = backpressure.clone();
+    let logger_backpressure = backpressure;
+    // ADMIN_OPERATORS=1,2 , operator ids allowed to send admin commands
+    let admin = AdminPlane::from_env().unwrap_or_else(|e| panic!("[Main] bad ADMIN_OPERATORS setting: {}", e));
+    if admin.operators.is_empty() {
+        eprintln!("[Main] no admin operators configured , every admin command will be refused");
+    }
 
-    // the market maker order queue will be initiliased in the market maker binary itself 
+    let _ = IncomingOrderQueue::create(&queues.incoming_orders).expect("failed to create queue");
+    let _ = CancelOrderQueue::create(&queues.cancel_orders).expect("failed to create queue");
+    let _ = OrderEventQueue::create(&queues.order_events).expect("failed to create queue");
+    let _ = QueryQueue::create(&queues.queries).expect("failed to create queue");
+    let _ = HoldingResQueue::create(&queues.holdings_response).expect("failed to create queue");
+    let _ = BalanceResQueue::create(&queues.balance_response).expect("failed to open queue");
+    let _ = OrderLogQueue::create(&queues.order_logs).expect("failed to create the Log queue");
+    let _ = BalanceLogQueue::create(&queues.balance_logs).expect("failed to open balance log queue");
+    let _ = HoldingLogQueue::create(&queues.holding_logs).expect("failed to open holding queues");
+    let _ = TradeLogQueue::create(&queues.trade_logs).expect("failed to open trade logs queue");
+    let _ = PositionQueue::create(&queues.positions).expect("failed to open positions queue");
+    let _ = OrderBookSnapShotQueue::create(&queues.snapshot).expect("failed to open snap shot queue");
+    let _ = MarketMakerFillQueue::create(&queues.market_maker_fills).expect("failed to open market maker fill queue");
+    let _ = MarketMakerFeedQueue::create(&queues.market_maker_feed).expect("failed to open the feed queue");
+    let _ = AdminCommandQueue::create(&queues.admin_commands).expect("failed to create the admin command queue");
+    let _ = AdminResponseQueue::create(&queues.admin_responses).expect("failed to create the admin response queue");
+
+    // every pipeline thread beats into the status page , engine_status reads it from outside the process
+    let status_page = Arc::new(StatusPage::create(&config.watchdog.status_page).expect("failed to create the status page"));
+    spawn_watchdog(status_page.clone(), config.watchdog.period(), config.watchdog.stall_after()).expect("failed to start the watchdog");
+    let core_page = status_page.clone();
+    let publisher_page = status_page.clone();
+    let writter_page = status_page.clone();
+    let logger_page = status_page;
 
+    // the market maker order queue will be initiliased in the market maker binary itself 
 
-    let (order_event_producer_bm , order_event_consumer_writter_from_bm) = bounded_spsc_queue::make::<OrderEvents>(32678);
-    let (event_producer_engine , event_consumer_publisher) = bounded_spsc_queue::make::<Event>(32678);
-    let (order_event_producer_publisher , order_event_consumer_writter_from_publisher) = bounded_spsc_queue::make::<OrderEvents>(32768);
-    let (order_event_producer_engine , order_event_consumer_writter_from_engine) = bounded_spsc_queue::make::<OrderEvents>(32768);
-    let (balance_event_producer_bm , balance_event_consumer_writter) = bounded_spsc_queue::make::<BalanceResponse>(32768);
-    let (holding_event_producer_bm , holding_event_consumer_writter) = bounded_spsc_queue::make::<HoldingResponse>(32768);
-    let (log_producer_core , log_consumer_logger)=bounded_spsc_queue::make::<BaseLogs>(32786);
-    let (trade_log_producer_publisher , trade_log_consumer_logger)= bounded_spsc_queue::make::<TradeLogs>(32768);
-    let (orderbook_snapshot_sender , order_book_snapshot_reciver) = bounded_spsc_queue::make::<OrderBookSnapShot>(32768);
-    let (mm_fill_sender , mm_fill_reciever)= bounded_spsc_queue::make::<MarketMakerFill>(32768);
-    let (mm_feed_sender , mm_feed_receiver) = bounded_spsc_queue::make::<MarketMakerFeed>(32768);
 
-    let trading_core_handle = std::thread::spawn(move ||{
-        core_affinity::set_for_current(core_affinity::CoreId { id: 2 });
+    let (order_event_producer_bm , order_event_consumer_writter_from_bm) = bounded_spsc_queue::make::<OrderEvents>(capacity);
+    let (event_producer_engine , event_consumer_publisher) = bounded_spsc_queue::make::<Event>(capacity);
+    let (order_event_producer_publisher , order_event_consumer_writter_from_publisher) = bounded_spsc_queue::make::<OrderEvents>(capacity);
+    let (order_event_producer_engine , order_event_consumer_writter_from_engine) = bounded_spsc_queue::make::<OrderEvents>(capacity);
+    let (balance_event_producer_bm , balance_event_consumer_writter) = bounded_spsc_queue::make::<BalanceResponse>(capacity);
+    let (holding_event_producer_bm , holding_event_consumer_writter) = bounded_spsc_queue::make::<HoldingResponse>(capacity);
+    let (log_producer_core , log_consumer_logger)=bounded_spsc_queue::make::<BaseLogs>(capacity);
+    let (trade_log_producer_publisher , trade_log_consumer_logger)= bounded_spsc_queue::make::<TradeLogs>(capacity);
+    let (orderbook_snapshot_sender , order_book_snapshot_reciver) = bounded_spsc_queue::make::<OrderBookSnapShot>(capacity);
+    let (mm_fill_sender , mm_fill_reciever)= bounded_spsc_queue::make::<MarketMakerFill>(capacity);
+    let (mm_feed_sender , mm_feed_receiver) = bounded_spsc_queue::make::<MarketMakerFeed>(capacity);
+    let (admin_response_sender , admin_response_receiver) = bounded_spsc_queue::make::<AdminResponse>(config.capacities.admin_responses);
+
+    // a publisher , writer or logger panic restarts that thread in place , a trading core panic shuts everything down
+    let mut supervisor = Supervisor::new(shutdown.clone(), RestartPolicy::from(&config.supervisor));
+    supervisor.spawn_critical("trading_core", core_shutdown.clone(), move ||{
+        core_affinity::set_for_current(core_affinity::CoreId { id: core_config.cores.trading_core });
         let mut trading_system = TradingCore::new(
             order_event_producer_bm , 
             event_producer_engine , 
@@ -347,36 +171,67 @@ fn main() {
             holding_event_producer_bm,
             log_producer_core ,
             orderbook_snapshot_sender,
-            mm_feed_sender
+            mm_feed_sender,
+            admin_response_sender,
+            &core_backpressure,
+            &core_config
         );
-        trading_system.balance_manager.add_throughput_test_users();
+        trading_system.bootstrap_state();
+        #[cfg(feature = "throughput-users")]
+        trading_system.add_throughput_users();
         //trading_system.engine.add_book(0);
-        trading_system.run();
-    });
 
+        match promoted {
+            Some(mut standby) => {
+                trading_system.journal = standby.journal.take();
+                trading_system.checkpoint_dir = standby.checkpoint_dir.take();
+                trading_system.adopt_state(standby);
+            }
+            None => recover_core(&mut trading_system, &core_config, &core_config.storage.journal, &core_config.storage.checkpoints),
+        }
+        // written only from here on , replaying the journal with the replay tool fills in the history
+        trading_system.digest_log = Some(DigestLog::open(digest_log_path, core_config.engine.digest_interval).expect("failed to open the digest log"));
+        match ReplicationServer::bind(&core_config.storage.replication_socket, journal_path, trading_system.sequence) {
+            Ok(server) => trading_system.replication = Some(server),
+            // running without a standby is allowed , it just has no failover
+            Err(e) => eprintln!("[Trading Core] replication disabled: {}", e),
+        }
+
+        trading_system.shutdown = Some(core_shutdown);
+        // the halted symbols came with the recovered state , only the operators are configuration
+        trading_system.admin.operators = admin.operators;
+
+        let mut inbound = CoreInbound::open(&core_config.queues).expect("failed to open the trading core input queues");
+        // registered after recovery , a long replay is not a stall
+        trading_system.heartbeat = Some(Heartbeat::register(&core_page, "trading_core").expect("failed to register the trading core heartbeat"));
+        trading_system.run(&mut inbound);
+    }).expect("failed to start the trading core");
 
-    let pubsub_connection = RedisPubSubManager::new("redis://localhost:6379");
+
+    let pubsub_connection = RedisPubSubManager::new(&config.redis.url);
     if pubsub_connection.is_err(){
         panic!("pubsub error , not initialising publisher");
     }
     //PUBLISHER REQUIRES AN EVENT RECV ONLY 
-    let publisher_handle = std::thread::spawn(move || {
-        core_affinity::set_for_current(core_affinity::CoreId { id: 5 });
+    supervisor.spawn_worker("publisher", publisher_shutdown, move || {
+        core_affinity::set_for_current(core_affinity::CoreId { id: publisher_core });
         let mut my_publisher = EventPublisher::new(
             pubsub_connection.unwrap() , 
             event_consumer_publisher,
             order_event_producer_publisher,
             trade_log_producer_publisher ,
-            mm_fill_sender
+            mm_fill_sender ,
+            &publisher_backpressure
         );
-        my_publisher.start_publisher();
-    });
+        my_publisher.heartbeat = Some(Heartbeat::register(&publisher_page, "publisher").expect("failed to register the publisher heartbeat"));
+        Some(my_publisher)
+    }).expect("failed to start the publisher");
 
 
 
     // SHM WRITTER TO WRITE TO QUEUES 
-    let writter_handle = std::thread::spawn(move|| {
-        core_affinity::set_for_current(core_affinity::CoreId { id: 7 });
+    supervisor.spawn_worker("writer", writter_shutdown, move|| {
+        core_affinity::set_for_current(core_affinity::CoreId { id: writter_config.cores.writer });
         let  shm_writter = ShmWriter::new(
             order_event_consumer_writter_from_bm,
             order_event_consumer_writter_from_publisher,
@@ -384,32 +239,37 @@ fn main() {
             balance_event_consumer_writter,
             holding_event_consumer_writter,
             mm_fill_reciever,
-            mm_feed_receiver
+            mm_feed_receiver,
+            admin_response_receiver,
+            &writter_backpressure,
+            &writter_config.queues
         );
-        if shm_writter.is_some(){
-            shm_writter.unwrap().start_shm_writter();
-        }
-        else{
-            eprintln!("error initialising shm writter")
-        }
-    });
-
-    let log_reciver_handle = std::thread::spawn(move||{
-        core_affinity::set_for_current(core_affinity::CoreId { id: 3 });
+        shm_writter.map(|mut shm_writter| {
+            shm_writter.heartbeat = Some(Heartbeat::register(&writter_page, "writer").expect("failed to register the writer heartbeat"));
+            shm_writter
+        })
+    }).expect("failed to start the writer");
+
+    supervisor.spawn_worker("logger", logger_shutdown, move||{
+        core_affinity::set_for_current(core_affinity::CoreId { id: logger_config.cores.logger });
         let mut log_reciver = LogReciever::new(
 log_consumer_logger , 
 trade_log_consumer_logger , 
-order_book_snapshot_reciver) ;
-        log_reciver.run();
-    });
+order_book_snapshot_reciver ,
+&logger_backpressure ,
+&logger_config.queues) ;
+        log_reciver.heartbeat = Some(Heartbeat::register(&logger_page, "logger").expect("failed to register the logger heartbeat"));
+        Some(log_reciver)
+    }).expect("failed to start the logger");
     
 
     eprintln!("[Main] Initialization complete, starting trading loop");
     
-    publisher_handle.join().expect("publisher pankicked");
-    writter_handle.join().expect("writter panicked");
-    trading_core_handle.join().expect("trading core oanicked ");
-    log_reciver_handle.join().expect("log eciver panicked");
+    let report = supervisor.run();
+    if report.escalated {
+        println!("System shutdown after a thread failure");
+        std::process::exit(1);
+    }
     println!("System shutdown");
 }
 
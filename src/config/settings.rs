@@ -0,0 +1,640 @@
+// everything a deployment may differ in : shm queue paths , storage paths , channel capacities , core pinning ,
+// engine intervals , the redis url , the metrics endpoint , the watchdog , the supervisor , the balances new users start with , the fees , the assets each symbol trades , the pre-trade limits and the rate limits
+// read once at startup from the toml file named by ENGINE_CONFIG (defaults when unset) , then env overrides ,
+// then validated , a bad setting stops the process before any thread is started
+// overrides are ENGINE_CONFIG__<SECTION>__<KEY>=<toml value> , ENGINE_CONFIG__CORES__SHARDS=[4,6] or ENGINE_CONFIG__REDIS__URL=redis://cache:6379
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use serde::Deserialize;
+use thiserror::Error;
+use crate::balance_manager::my_balance_manager2::{DEFAULT_BALANCE, DEFAULT_HOLDING_QTY, MARKET_MAKER_BALANCE, MARKET_MAKER_HOLDING_QTY};
+use crate::balance_manager::rate_limits::{ClassLimits, RateAction};
+use crate::balance_manager::risk_limits::RiskLimits;
+use crate::digest::digest_log::DEFAULT_DIGEST_INTERVAL;
+use crate::engine::my_engine::DEPTH_N;
+
+pub const CONFIG_PATH_VAR : &str = "ENGINE_CONFIG";
+pub const OVERRIDE_PREFIX : &str = "ENGINE_CONFIG__";
+// 100% , no side of a fill pays more than its notional
+pub const MAX_FEE_BPS : i32 = 10_000;
+// the longest volume window fee tiers can look back over , in trading days
+pub const MAX_FEE_WINDOW_DAYS : u32 = 366;
+
+#[derive(Debug , Error)]
+pub enum ConfigError{
+    #[error("cannot read config file {path:?}: {reason}")]
+    Read { path : PathBuf , reason : String },
+    #[error("bad config: {0}")]
+    Parse(#[from] toml::de::Error),
+    #[error("bad override {var}: {reason}")]
+    Override { var : String , reason : String },
+    #[error("invalid config: {0}")]
+    Invalid(String),
+}
+
+// the shared memory queues , creator and every opener must agree on these
+#[derive(Debug , Clone , PartialEq , Eq , Deserialize)]
+#[serde(default , deny_unknown_fields)]
+pub struct QueuePaths{
+    pub incoming_orders : String,
+    pub cancel_orders : String,
+    pub order_events : String,
+    pub queries : String,
+    pub holdings_response : String,
+    pub balance_response : String,
+    pub order_logs : String,
+    pub balance_logs : String,
+    pub holding_logs : String,
+    pub trade_logs : String,
+    pub snapshot : String,
+    pub market_maker_fills : String,
+    pub market_maker_feed : String,
+    pub admin_commands : String,
+    pub admin_responses : String,
+    pub positions : String,
+    // a standby's own admin queues , it only takes ADMIN_PROMOTE on them
+    pub standby_admin_commands : String,
+    pub standby_admin_responses : String,
+}
+
+impl Default for QueuePaths{
+    fn default()->Self{
+        Self {
+            incoming_orders : "/tmp/IncomingOrders".into(),
+            cancel_orders : "/tmp/CancelOrders".into(),
+            order_events : "/tmp/OrderEvents".into(),
+            queries : "/tmp/Queries".into(),
+            holdings_response : "/tmp/HoldingsResponse".into(),
+            balance_response : "/tmp/BalanceResponse".into(),
+            order_logs : "/tmp/OrderLogs".into(),
+            balance_logs : "/tmp/BalanceLogs".into(),
+            holding_logs : "/tmp/HoldingLogs".into(),
+            trade_logs : "/tmp/TradeLogs".into(),
+            snapshot : "/tmp/SnapShot".into(),
+            market_maker_fills : "/tmp/MarketMakerFills".into(),
+            market_maker_feed : "/tmp/MarketMakerFeed".into(),
+            admin_commands : "/tmp/AdminCommands".into(),
+            admin_responses : "/tmp/AdminResponses".into(),
+            positions : "/tmp/Positions".into(),
+            standby_admin_commands : "/tmp/StandbyAdminCommands".into(),
+            standby_admin_responses : "/tmp/StandbyAdminResponses".into(),
+        }
+    }
+}
+
+impl QueuePaths{
+    pub fn all(&self)->[(&'static str , &str) ; 18]{
+        [
+            ("incoming_orders" , &self.incoming_orders),
+            ("cancel_orders" , &self.cancel_orders),
+            ("order_events" , &self.order_events),
+            ("queries" , &self.queries),
+            ("holdings_response" , &self.holdings_response),
+            ("balance_response" , &self.balance_response),
+            ("order_logs" , &self.order_logs),
+            ("balance_logs" , &self.balance_logs),
+            ("holding_logs" , &self.holding_logs),
+            ("trade_logs" , &self.trade_logs),
+            ("snapshot" , &self.snapshot),
+            ("market_maker_fills" , &self.market_maker_fills),
+            ("market_maker_feed" , &self.market_maker_feed),
+            ("admin_commands" , &self.admin_commands),
+            ("admin_responses" , &self.admin_responses),
+            ("positions" , &self.positions),
+            ("standby_admin_commands" , &self.standby_admin_commands),
+            ("standby_admin_responses" , &self.standby_admin_responses),
+        ]
+    }
+}
+
+// unlike the shm queues these outlive the process
+#[derive(Debug , Clone , PartialEq , Eq , Deserialize)]
+#[serde(default , deny_unknown_fields)]
+pub struct StoragePaths{
+    pub journal : PathBuf,
+    pub checkpoints : PathBuf,
+    // a standby on the same host keeps its own copy of both
+    pub standby_journal : PathBuf,
+    pub standby_checkpoints : PathBuf,
+    pub digest_log : PathBuf,
+    pub standby_digest_log : PathBuf,
+    pub replication_socket : PathBuf,
+}
+
+impl Default for StoragePaths{
+    fn default()->Self{
+        Self {
+            journal : "./data/commands.journal".into(),
+            checkpoints : "./data/checkpoints".into(),
+            standby_journal : "./data/standby/commands.journal".into(),
+            standby_checkpoints : "./data/standby/checkpoints".into(),
+            digest_log : "./data/digests.log".into(),
+            standby_digest_log : "./data/standby/digests.log".into(),
+            replication_socket : "/tmp/TradingCoreReplication.sock".into(),
+        }
+    }
+}
+
+// in process channels between threads , the shm queue capacity is part of the mapped layout and stays fixed
+#[derive(Debug , Clone , Copy , PartialEq , Eq , Deserialize)]
+#[serde(default , deny_unknown_fields)]
+pub struct Capacities{
+    pub channel : usize,
+    pub admin_responses : usize,
+}
+
+impl Default for Capacities{
+    fn default()->Self{
+        Self { channel : 32768 , admin_responses : 4096 }
+    }
+}
+
+// core ids each thread is pinned to , checked against the host's cores at startup
+#[derive(Debug , Clone , PartialEq , Eq , Deserialize)]
+#[serde(default , deny_unknown_fields)]
+pub struct CorePinning{
+    // the single threaded core , or the risk thread in sharded mode , or the shm reader in the legacy binary
+    pub trading_core : usize,
+    // the legacy binary's balance manager thread
+    pub balance_manager : usize,
+    pub publisher : usize,
+    pub writer : usize,
+    pub logger : usize,
+    // shard i runs on shards[i % len]
+    pub shards : Vec<usize>,
+}
+
+impl Default for CorePinning{
+    fn default()->Self{
+        Self { trading_core : 2 , balance_manager : 6 , publisher : 5 , writer : 7 , logger : 3 , shards : vec![4 , 6 , 8 , 9] }
+    }
+}
+
+impl CorePinning{
+    pub fn shard(&self , shard_id : usize)->usize{
+        self.shards[shard_id % self.shards.len()]
+    }
+
+    /// configured core ids that are not in `available` , in ascending order
+    pub fn missing(&self , available : &[usize])->Vec<usize>{
+        let mut missing : Vec<usize> = [self.trading_core , self.balance_manager , self.publisher , self.writer , self.logger]
+            .iter()
+            .chain(self.shards.iter())
+            .copied()
+            .filter(|id| !available.contains(id))
+            .collect();
+        missing.sort_unstable();
+        missing.dedup();
+        missing
+    }
+}
+
+#[derive(Debug , Clone , Copy , PartialEq , Eq , Deserialize)]
+#[serde(default , deny_unknown_fields)]
+pub struct EngineSettings{
+    // top of book snapshots to the logger
+    pub snapshot_interval_secs : u64,
+    // full state checkpoints , restart cost is loading the latest one plus replaying the journal written since
+    pub checkpoint_interval_secs : u64,
+    // price levels per side in a snapshot , at most DEPTH_N
+    pub depth : usize,
+    // a state digest every n sequence numbers
+    pub digest_interval : u64,
+    // engine shards in sharded mode
+    pub shards : usize,
+    // sharded mode keeps no journal and a crash loses everything , it only starts when this says so
+    pub allow_unjournaled_shards : bool,
+}
+
+impl Default for EngineSettings{
+    fn default()->Self{
+        Self { snapshot_interval_secs : 30 , checkpoint_interval_secs : 60 , depth : DEPTH_N , digest_interval : DEFAULT_DIGEST_INTERVAL , shards : 2 , allow_unjournaled_shards : false }
+    }
+}
+
+impl EngineSettings{
+    pub fn snapshot_interval(&self)->Duration{
+        Duration::from_secs(self.snapshot_interval_secs)
+    }
+    pub fn checkpoint_interval(&self)->Duration{
+        Duration::from_secs(self.checkpoint_interval_secs)
+    }
+    /// sharded mode has no journal and no checkpoint , it is not a production target and only runs when opted into
+    pub fn check_sharded_mode(&self)->Result<() , ConfigError>{
+        if !self.allow_unjournaled_shards{
+            return Err(ConfigError::Invalid("sharded mode keeps no journal or checkpoint and loses all state on a crash , not for production , set engine.allow_unjournaled_shards = true to run it anyway".into()));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug , Clone , PartialEq , Eq , Deserialize)]
+#[serde(default , deny_unknown_fields)]
+pub struct RedisSettings{
+    pub url : String,
+}
+
+impl Default for RedisSettings{
+    fn default()->Self{
+        Self { url : "redis://localhost:6379".into() }
+    }
+}
+
+// the prometheus endpoint , localhost only by default , scraped over plain http
+#[derive(Debug , Clone , PartialEq , Eq , Deserialize)]
+#[serde(default , deny_unknown_fields)]
+pub struct MetricsSettings{
+    pub enabled : bool,
+    pub listen : String,
+    // how often the engine threads refresh the per symbol book gauges and the queue depths
+    pub interval_ms : u64,
+    // per stage latency windows , each is logged (when latency_log is set) and then reset
+    pub latency_window_secs : u64,
+    pub latency_log : bool,
+}
+
+impl Default for MetricsSettings{
+    fn default()->Self{
+        Self { enabled : true , listen : "127.0.0.1:9187".into() , interval_ms : 1000 , latency_window_secs : 60 , latency_log : true }
+    }
+}
+
+impl MetricsSettings{
+    pub fn interval(&self)->Duration{
+        Duration::from_millis(self.interval_ms)
+    }
+    pub fn latency_window(&self)->Duration{
+        Duration::from_secs(self.latency_window_secs)
+    }
+}
+
+// the heartbeat page every pipeline thread reports into and the watchdog reading it
+#[derive(Debug , Clone , PartialEq , Eq , Deserialize)]
+#[serde(default , deny_unknown_fields)]
+pub struct WatchdogSettings{
+    pub status_page : String,
+    pub period_ms : u64,
+    // a thread whose heartbeat has not moved for this long is reported stalled
+    pub stall_after_ms : u64,
+}
+
+impl Default for WatchdogSettings{
+    fn default()->Self{
+        Self { status_page : "/tmp/EngineStatus".into() , period_ms : 100 , stall_after_ms : 2000 }
+    }
+}
+
+impl WatchdogSettings{
+    pub fn period(&self)->Duration{
+        Duration::from_millis(self.period_ms)
+    }
+    pub fn stall_after(&self)->Duration{
+        Duration::from_millis(self.stall_after_ms)
+    }
+}
+
+// restarts of the stateless pipeline threads (publisher , writer , logger) after a panic
+// more than max_restarts within restart_window_ms and the thread is given up on , which shuts the engine down
+#[derive(Debug , Clone , PartialEq , Eq , Deserialize)]
+#[serde(default , deny_unknown_fields)]
+pub struct SupervisorSettings{
+    pub max_restarts : u32,
+    pub restart_window_ms : u64,
+    // pause before reopening the thread's handles , a panic caused by a missing resource gets a moment to clear
+    pub backoff_ms : u64,
+}
+
+impl Default for SupervisorSettings{
+    fn default()->Self{
+        Self { max_restarts : 5 , restart_window_ms : 60_000 , backoff_ms : 100 }
+    }
+}
+
+impl SupervisorSettings{
+    pub fn restart_window(&self)->Duration{
+        Duration::from_millis(self.restart_window_ms)
+    }
+    pub fn backoff(&self)->Duration{
+        Duration::from_millis(self.backoff_ms)
+    }
+}
+
+// what a user starts with , part of the replayed state : a journal must be replayed with the balances it was written with
+// real users start at zero and are funded by deposits , a test deployment can still hand out a starting balance
+#[derive(Debug , Clone , Copy , PartialEq , Eq , Deserialize)]
+#[serde(default , deny_unknown_fields)]
+pub struct BalanceDefaults{
+    pub balance : u64,
+    pub holding_qty : u32,
+    pub market_maker_balance : u64,
+    pub market_maker_holding_qty : u32,
+}
+
+impl Default for BalanceDefaults{
+    fn default()->Self{
+        Self {
+            balance : DEFAULT_BALANCE,
+            holding_qty : DEFAULT_HOLDING_QTY,
+            market_maker_balance : MARKET_MAKER_BALANCE,
+            market_maker_holding_qty : MARKET_MAKER_HOLDING_QTY,
+        }
+    }
+}
+
+// trading fees in basis points of a fill's notional , each side pays its own out of its cash
+// a negative maker rate is a rebate paid out of the fee account
+#[derive(Debug , Clone , PartialEq , Eq , Deserialize)]
+#[serde(default , deny_unknown_fields)]
+pub struct FeeSettings{
+    // the user fees are credited to , unset keeps them in the house fee account
+    pub fee_account : Option<u64>,
+    // trading days of volume a tier is picked from , today included
+    pub window_days : u32,
+    // every symbol without a schedule of its own
+    pub default : FeeSchedule,
+    pub symbols : Vec<SymbolFees>,
+}
+
+impl Default for FeeSettings{
+    fn default()->Self{
+        Self { fee_account : None , window_days : 30 , default : FeeSchedule::default() , symbols : Vec::new() }
+    }
+}
+
+#[derive(Debug , Clone , Default , PartialEq , Eq , Deserialize)]
+#[serde(default , deny_unknown_fields)]
+pub struct FeeSchedule{
+    pub maker_bps : i32,
+    pub taker_bps : i32,
+    // ascending by min_volume , a user whose volume in the symbol's quote asset over the window reached a tier's min_volume pays its rates instead
+    pub tiers : Vec<FeeTier>,
+}
+
+#[derive(Debug , Clone , Copy , PartialEq , Eq , Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FeeTier{
+    pub min_volume : u64,
+    pub maker_bps : i32,
+    pub taker_bps : i32,
+}
+
+#[derive(Debug , Clone , PartialEq , Eq , Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SymbolFees{
+    pub symbol : u32,
+    pub schedule : FeeSchedule,
+}
+
+impl FeeSchedule{
+    /// (maker , taker) rates for a user who traded `volume` of the quote asset within the window
+    pub fn rates(&self , volume : u64)->(i32 , i32){
+        match self.tiers.iter().rev().find(|tier| volume >= tier.min_volume) {
+            Some(tier) => (tier.maker_bps , tier.taker_bps),
+            None => (self.maker_bps , self.taker_bps),
+        }
+    }
+
+    fn all_rates(&self)->impl Iterator<Item = (i32 , i32)> + '_{
+        std::iter::once((self.maker_bps , self.taker_bps)).chain(self.tiers.iter().map(|tier| (tier.maker_bps , tier.taker_bps)))
+    }
+
+    /// the highest rate any order on the schedule can be charged , what a bid locks on top of its notional
+    pub fn max_bps(&self)->u32{
+        self.all_rates().map(|(maker , taker)| maker.max(taker).max(0) as u32).max().unwrap_or(0)
+    }
+
+    fn validate(&self , name : &str)->Result<() , ConfigError>{
+        for (maker , taker) in self.all_rates() {
+            if !(0..=MAX_FEE_BPS).contains(&taker) || !(-MAX_FEE_BPS..=MAX_FEE_BPS).contains(&maker) {
+                return Err(ConfigError::Invalid(format!("{} rates must be within {} bps , only makers may be negative", name, MAX_FEE_BPS)));
+            }
+            // a rebate is paid out of the taker fee of the same fill
+            if maker + taker < 0 {
+                return Err(ConfigError::Invalid(format!("{} pays a maker rebate larger than its taker fee", name)));
+            }
+        }
+        if self.tiers.windows(2).any(|pair| pair[0].min_volume >= pair[1].min_volume) {
+            return Err(ConfigError::Invalid(format!("{} tiers must be in ascending min_volume", name)));
+        }
+        Ok(())
+    }
+}
+
+impl FeeSettings{
+    pub fn schedule(&self , symbol : u32)->&FeeSchedule{
+        self.symbols.iter().find(|fees| fees.symbol == symbol).map(|fees| &fees.schedule).unwrap_or(&self.default)
+    }
+}
+
+// pre-trade limits , every user runs under the default unless listed here or given limits through the admin queue
+#[derive(Debug , Clone , Default , PartialEq , Eq , Deserialize)]
+#[serde(default , deny_unknown_fields)]
+pub struct RiskSettings{
+    pub default : RiskLimits,
+    pub users : Vec<UserLimits>,
+}
+
+#[derive(Debug , Clone , Copy , PartialEq , Eq , Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UserLimits{
+    pub user_id : u64,
+    pub limits : RiskLimits,
+}
+
+impl RiskSettings{
+    pub fn limits(&self , user_id : u64)->RiskLimits{
+        self.users.iter().find(|user| user.user_id == user_id).map(|user| user.limits).unwrap_or(self.default)
+    }
+}
+
+// token buckets per user and action , market makers get their own class , everyone else is retail
+#[derive(Debug , Clone , PartialEq , Eq , Deserialize)]
+#[serde(default , deny_unknown_fields)]
+pub struct RateLimitSettings{
+    pub market_makers : Vec<u64>,
+    pub retail : ClassLimits,
+    pub market_maker : ClassLimits,
+}
+
+impl Default for RateLimitSettings{
+    fn default()->Self{
+        // user 0 is the market maker the engine seeds
+        Self { market_makers : vec![0] , retail : ClassLimits::default() , market_maker : ClassLimits::default() }
+    }
+}
+
+impl RateLimitSettings{
+    pub fn class(&self , user_id : u64)->&ClassLimits{
+        if self.market_makers.contains(&user_id) { &self.market_maker } else { &self.retail }
+    }
+}
+
+// the assets each symbol trades , an unlisted symbol trades its own shares against cash
+// asset 0 is the cash balance , any other id is a wallet asset users are funded with through deposits
+#[derive(Debug , Clone , Default , PartialEq , Eq , Deserialize)]
+#[serde(default , deny_unknown_fields)]
+pub struct InstrumentSettings{
+    pub pairs : Vec<InstrumentPair>,
+}
+
+#[derive(Debug , Clone , Copy , PartialEq , Eq , Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct InstrumentPair{
+    pub symbol : u32,
+    // what an ask sells , the quantity of an order is in it
+    pub base : u32,
+    // what a bid pays with , prices and fees are in it
+    pub quote : u32,
+}
+
+#[derive(Debug , Clone , Default , PartialEq , Eq , Deserialize)]
+#[serde(default , deny_unknown_fields)]
+pub struct EngineConfig{
+    pub queues : QueuePaths,
+    pub storage : StoragePaths,
+    pub capacities : Capacities,
+    pub cores : CorePinning,
+    pub engine : EngineSettings,
+    pub redis : RedisSettings,
+    pub metrics : MetricsSettings,
+    pub watchdog : WatchdogSettings,
+    pub supervisor : SupervisorSettings,
+    pub balances : BalanceDefaults,
+    pub fees : FeeSettings,
+    pub instruments : InstrumentSettings,
+    pub risk : RiskSettings,
+    pub rate_limits : RateLimitSettings,
+}
+
+impl EngineConfig{
+    /// the file named by ENGINE_CONFIG (or the defaults) with the process's ENGINE_CONFIG__* overrides applied
+    pub fn load()->Result<Self , ConfigError>{
+        let text = match std::env::var(CONFIG_PATH_VAR) {
+            Ok(path) => read_file(Path::new(&path))?,
+            Err(_) => String::new(),
+        };
+        Self::from_toml(&text, std::env::vars())
+    }
+
+    /// `text` with every `overrides` entry that carries the ENGINE_CONFIG__ prefix applied on top , validated
+    pub fn from_toml(text : &str , overrides : impl IntoIterator<Item = (String , String)>)->Result<Self , ConfigError>{
+        let mut table : toml::Table = text.parse()?;
+        for (var , value) in overrides {
+            if let Some(key) = var.strip_prefix(OVERRIDE_PREFIX) {
+                apply_override(&mut table, key, &value).map_err(|reason| ConfigError::Override { var : var.clone() , reason })?;
+            }
+        }
+        let config : Self = table.try_into()?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn validate(&self)->Result<() , ConfigError>{
+        let invalid = |reason : String| Err(ConfigError::Invalid(reason));
+        let paths = self.queues.all();
+        for (i , (name , path)) in paths.iter().enumerate() {
+            if path.is_empty() {
+                return invalid(format!("queues.{} is empty", name));
+            }
+            if let Some((other , _)) = paths[..i].iter().find(|(_ , earlier)| earlier == path) {
+                return invalid(format!("queues.{} and queues.{} are both {:?}", other, name, path));
+            }
+        }
+        if self.capacities.channel == 0 || self.capacities.admin_responses == 0 {
+            return invalid("channel capacities must be positive".into());
+        }
+        if self.cores.shards.is_empty() {
+            return invalid("cores.shards needs at least one core".into());
+        }
+        if self.engine.shards == 0 {
+            return invalid("engine.shards must be positive".into());
+        }
+        if self.engine.depth == 0 || self.engine.depth > DEPTH_N {
+            return invalid(format!("engine.depth must be between 1 and {}", DEPTH_N));
+        }
+        if self.engine.snapshot_interval_secs == 0 || self.engine.checkpoint_interval_secs == 0 || self.engine.digest_interval == 0 {
+            return invalid("engine intervals must be positive".into());
+        }
+        if !(self.redis.url.starts_with("redis://") || self.redis.url.starts_with("rediss://")) {
+            return invalid(format!("redis.url {:?} is not a redis:// url", self.redis.url));
+        }
+        if self.metrics.listen.parse::<std::net::SocketAddr>().is_err() {
+            return invalid(format!("metrics.listen {:?} is not an ip:port address", self.metrics.listen));
+        }
+        if self.metrics.interval_ms == 0 || self.metrics.latency_window_secs == 0 {
+            return invalid("metrics intervals must be positive".into());
+        }
+        if self.watchdog.status_page.is_empty() || paths.iter().any(|(_ , path)| *path == self.watchdog.status_page) {
+            return invalid(format!("watchdog.status_page {:?} must be a path of its own", self.watchdog.status_page));
+        }
+        if self.watchdog.period_ms == 0 || self.watchdog.stall_after_ms <= self.watchdog.period_ms {
+            return invalid("watchdog.stall_after_ms must be longer than a positive watchdog.period_ms".into());
+        }
+        if self.supervisor.restart_window_ms == 0 || self.supervisor.backoff_ms >= self.supervisor.restart_window_ms {
+            return invalid("supervisor.backoff_ms must be shorter than a positive supervisor.restart_window_ms".into());
+        }
+        if !(1..=MAX_FEE_WINDOW_DAYS).contains(&self.fees.window_days) {
+            return invalid(format!("fees.window_days must be between 1 and {}", MAX_FEE_WINDOW_DAYS));
+        }
+        self.fees.default.validate("fees.default")?;
+        for (i , fees) in self.fees.symbols.iter().enumerate() {
+            if self.fees.symbols[..i].iter().any(|earlier| earlier.symbol == fees.symbol) {
+                return invalid(format!("fees.symbols lists symbol {} twice", fees.symbol));
+            }
+            fees.schedule.validate(&format!("fees of symbol {}", fees.symbol))?;
+        }
+        for (i , pair) in self.instruments.pairs.iter().enumerate() {
+            if self.instruments.pairs[..i].iter().any(|earlier| earlier.symbol == pair.symbol) {
+                return invalid(format!("instruments.pairs lists symbol {} twice", pair.symbol));
+            }
+            if pair.base == pair.quote {
+                return invalid(format!("symbol {} has the same base and quote asset", pair.symbol));
+            }
+        }
+        for (i , user) in self.risk.users.iter().enumerate() {
+            if self.risk.users[..i].iter().any(|earlier| earlier.user_id == user.user_id) {
+                return invalid(format!("risk.users lists user {} twice", user.user_id));
+            }
+        }
+        for (i , user_id) in self.rate_limits.market_makers.iter().enumerate() {
+            if self.rate_limits.market_makers[..i].contains(user_id) {
+                return invalid(format!("rate_limits.market_makers lists user {} twice", user_id));
+            }
+        }
+        for (class , limits) in [("retail" , &self.rate_limits.retail) , ("market_maker" , &self.rate_limits.market_maker)] {
+            for action in RateAction::ALL {
+                let bucket = limits.bucket(action);
+                // a bucket that never refills throttles the user for good once it is empty
+                if bucket.capacity != 0 && bucket.refill_per_sec == 0 {
+                    return invalid(format!("rate_limits.{}.{} needs a refill_per_sec", class, action.label()));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn read_file(path : &Path)->Result<String , ConfigError>{
+    std::fs::read_to_string(path).map_err(|e| ConfigError::Read { path : path.to_path_buf() , reason : e.to_string() })
+}
+
+// `key` is SECTION__FIELD , the value is parsed as toml and taken as a plain string when that fails
+fn apply_override(table : &mut toml::Table , key : &str , raw : &str)->Result<() , String>{
+    let Some((section , field)) = key.split_once("__") else {
+        return Err("expected ENGINE_CONFIG__<SECTION>__<KEY>".into());
+    };
+    let value = match format!("value = {}", raw).parse::<toml::Table>() {
+        Ok(mut parsed) => parsed.remove("value").unwrap_or_else(|| toml::Value::String(raw.into())),
+        Err(_) => toml::Value::String(raw.into()),
+    };
+    let section = table
+        .entry(section.to_ascii_lowercase())
+        .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+    match section {
+        toml::Value::Table(section) => {
+            section.insert(field.to_ascii_lowercase(), value);
+            Ok(())
+        }
+        _ => Err("not a config section".into()),
+    }
+}
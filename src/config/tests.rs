@@ -0,0 +1,105 @@
+#[cfg(test)]
+mod tests {
+    use crate::config::settings::{ConfigError, EngineConfig};
+    use crate::engine::my_engine::{Engine, STEngine, DEPTH_N};
+    use crate::journal::replay::detached_core_with;
+    use crate::orderbook::order::{Order, Side};
+
+    fn overrides(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs.iter().map(|(var, value)| (var.to_string(), value.to_string())).collect()
+    }
+
+    #[test]
+    fn test_shipped_file_matches_the_defaults() {
+        let shipped = EngineConfig::from_toml(include_str!("../../config/engine.toml"), Vec::new()).unwrap();
+        assert_eq!(shipped, EngineConfig::default());
+        assert_eq!(EngineConfig::from_toml("", Vec::new()).unwrap(), EngineConfig::default());
+    }
+
+    #[test]
+    fn test_env_overrides_win_over_the_file() {
+        let text = "[cores]\nwriter = 1\n[engine]\nshards = 4\n";
+        let config = EngineConfig::from_toml(text, overrides(&[
+            ("ENGINE_CONFIG__CORES__WRITER", "11"),
+            ("ENGINE_CONFIG__CORES__SHARDS", "[0, 1]"),
+            // not valid toml , taken as a string
+            ("ENGINE_CONFIG__REDIS__URL", "redis://cache:6380"),
+            ("ENGINE_CONFIG__QUEUES__INCOMING_ORDERS", "/dev/shm/staging/IncomingOrders"),
+            ("UNRELATED", "1"),
+        ])).unwrap();
+        assert_eq!(config.cores.writer, 11);
+        assert_eq!(config.cores.shards, vec![0, 1]);
+        assert_eq!(config.cores.shard(3), 1);
+        assert_eq!(config.engine.shards, 4);
+        assert_eq!(config.redis.url, "redis://cache:6380");
+        assert_eq!(config.queues.incoming_orders, "/dev/shm/staging/IncomingOrders");
+        assert_eq!(config.queues.cancel_orders, "/tmp/CancelOrders");
+    }
+
+    #[test]
+    fn test_unknown_keys_and_bad_values_are_rejected() {
+        assert!(matches!(EngineConfig::from_toml("[cores]\nwritter = 7\n", Vec::new()), Err(ConfigError::Parse(_))));
+        assert!(matches!(EngineConfig::from_toml("", overrides(&[("ENGINE_CONFIG__CORES__WRITER", "seven")])), Err(ConfigError::Parse(_))));
+        assert!(matches!(EngineConfig::from_toml("", overrides(&[("ENGINE_CONFIG__SHARDS", "4")])), Err(ConfigError::Override { .. })));
+        assert!(matches!(EngineConfig::from_toml("redis = 1\n", overrides(&[("ENGINE_CONFIG__REDIS__URL", "x")])), Err(ConfigError::Override { .. })));
+    }
+
+    #[test]
+    fn test_validation() {
+        let invalid = |text: &str| matches!(EngineConfig::from_toml(text, Vec::new()), Err(ConfigError::Invalid(_)));
+        assert!(invalid("[engine]\ndepth = 0\n"));
+        assert!(invalid(&format!("[engine]\ndepth = {}\n", DEPTH_N + 1)));
+        assert!(invalid("[engine]\nshards = 0\n"));
+        assert!(invalid("[engine]\ncheckpoint_interval_secs = 0\n"));
+        assert!(invalid("[cores]\nshards = []\n"));
+        assert!(invalid("[capacities]\nchannel = 0\n"));
+        assert!(invalid("[queues]\nqueries = \"/tmp/CancelOrders\"\n"));
+        assert!(invalid("[redis]\nurl = \"localhost:6379\"\n"));
+        assert!(invalid("[metrics]\nlisten = \"localhost\"\n"));
+        assert!(invalid("[metrics]\nlatency_window_secs = 0\n"));
+        assert!(invalid("[watchdog]\nstatus_page = \"/tmp/Queries\"\n"));
+        assert!(invalid("[watchdog]\nperiod_ms = 500\nstall_after_ms = 500\n"));
+        assert!(invalid("[supervisor]\nrestart_window_ms = 0\n"));
+        assert!(invalid("[fees.default]\ntaker_bps = -1\n"));
+        assert!(invalid("[fees.default]\nmaker_bps = -5\ntaker_bps = 4\n"));
+        assert!(invalid("[fees.default]\ntiers = [{ min_volume = 10, maker_bps = 0, taker_bps = 0 }, { min_volume = 10, maker_bps = 0, taker_bps = 0 }]\n"));
+        assert!(invalid("[[fees.symbols]]\nsymbol = 1\nschedule = {}\n[[fees.symbols]]\nsymbol = 1\nschedule = {}\n"));
+        assert!(invalid("[fees]\nwindow_days = 0\n"));
+        assert!(invalid("[[instruments.pairs]]\nsymbol = 1\nbase = 2\nquote = 2\n"));
+        assert!(invalid("[[risk.users]]\nuser_id = 4\nlimits = {}\n[[risk.users]]\nuser_id = 4\nlimits = { max_order_qty = 5 }\n"));
+        assert!(invalid("[rate_limits]\nmarket_makers = [0, 7, 0]\n"));
+        assert!(invalid("[rate_limits.retail]\ncancel = { capacity = 10 }\n"));
+        assert!(invalid("[[instruments.pairs]]\nsymbol = 1\nbase = 2\nquote = 0\n[[instruments.pairs]]\nsymbol = 1\nbase = 3\nquote = 0\n"));
+
+        let config = EngineConfig::default();
+        assert_eq!(config.cores.missing(&[0, 1, 2, 3, 4, 5, 6, 7]), vec![8, 9]);
+        assert!(config.cores.missing(&(0..16).collect::<Vec<_>>()).is_empty());
+    }
+
+    #[test]
+    fn test_sharded_mode_is_refused_unless_unjournaled_shards_are_allowed() {
+        let config = EngineConfig::default();
+        assert!(matches!(config.engine.check_sharded_mode(), Err(ConfigError::Invalid(_))));
+        let shipped = EngineConfig::from_toml(include_str!("../../config/engine.toml"), Vec::new()).unwrap();
+        assert!(shipped.engine.check_sharded_mode().is_err());
+        let allowed = EngineConfig::from_toml("[engine]\nallow_unjournaled_shards = true\n", Vec::new()).unwrap();
+        assert!(allowed.engine.check_sharded_mode().is_ok());
+    }
+
+    #[test]
+    fn test_balances_and_depth_reach_the_core() {
+        let config = EngineConfig::from_toml("[balances]\nbalance = 500\nholding_qty = 7\n[engine]\ndepth = 2\n", Vec::new()).unwrap();
+        let (mut core, _sink) = detached_core_with(&config);
+        core.balance_manager.add_user(4242, |_| {}, || 0).unwrap();
+        let index = core.balance_manager.get_user_index(4242).unwrap();
+        assert_eq!(core.balance_manager.get_user_balance(index).available_balance, 500);
+        assert_eq!(core.balance_manager.get_user_holdings(index).available(9), 7);
+
+        core.engine.add_book(9);
+        for (order_id, price) in [(1, 10), (2, 11), (3, 12)] {
+            let _ = core.engine.process_order(Order::new(4242, order_id, Side::Bid, 1, 1, price, order_id, 9), |_| {});
+        }
+        let snapshot = STEngine::book_snapshot(core.engine.get_book(9).unwrap(), core.engine.snapshot_depth, 1);
+        assert_eq!(snapshot.bids.iter().filter(|(price, _)| *price != 0).count(), 2);
+    }
+}
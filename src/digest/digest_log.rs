@@ -0,0 +1,44 @@
+// publishes the combined state digest every `interval` sequence numbers
+// one line per digest : sequence digest_hex , two runs over the same journal must write identical files
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+// the live core and the replay tool both default to this so their files line up
+pub const DEFAULT_DIGEST_INTERVAL : u64 = 10_000;
+
+pub struct DigestLog{
+    writer : BufWriter<File>,
+    interval : u64,
+}
+
+impl DigestLog{
+    /// appends to `path` , `interval` of 0 is treated as 1
+    pub fn open<P : AsRef<Path>>(path : P , interval : u64)->std::io::Result<Self>{
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() && !parent.as_os_str().is_empty() {
+            fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self { writer : BufWriter::new(file) , interval : interval.max(1) })
+    }
+
+    #[inline(always)]
+    pub fn due(&self , sequence : u64)->bool{
+        sequence.is_multiple_of(self.interval)
+    }
+
+    pub fn record(&mut self , sequence : u64 , digest : u64)->std::io::Result<()>{
+        writeln!(self.writer, "{} {:016x}", sequence, digest)
+    }
+
+    pub fn flush(&mut self)->std::io::Result<()>{
+        self.writer.flush()
+    }
+}
+
+impl Drop for DigestLog{
+    fn drop(&mut self){
+        let _ = self.writer.flush();
+    }
+}
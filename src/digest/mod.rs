@@ -0,0 +1,3 @@
+pub mod digest_log;
+pub mod state_hasher;
+pub mod tests;
@@ -0,0 +1,43 @@
+// stable 64 bit hashing for engine state digests
+// std's DefaultHasher is free to change between rust releases , digests written by one build have to match another
+// so this is a fixed splitmix64 style mix over u64 words
+
+#[inline(always)]
+pub fn mix64(mut x : u64)->u64{
+    x = (x ^ (x >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    x ^ (x >> 31)
+}
+
+// order dependent , feed words in a fixed order (levels in price order , orders in queue order)
+// for collections without a fixed order hash each entry on its own and combine with `combine_unordered`
+#[derive(Debug , Clone , Copy)]
+pub struct StateHasher{
+    state : u64,
+}
+
+impl StateHasher{
+    pub fn new(seed : u64)->Self{
+        Self { state : mix64(seed ^ 0x9E37_79B9_7F4A_7C15) }
+    }
+
+    #[inline(always)]
+    pub fn write_u64(&mut self , word : u64){
+        self.state = mix64(self.state.rotate_left(5) ^ word);
+    }
+
+    #[inline(always)]
+    pub fn write_u32(&mut self , word : u32){
+        self.write_u64(word as u64);
+    }
+
+    pub fn finish(&self)->u64{
+        mix64(self.state)
+    }
+}
+
+// wrapping add is commutative so the result does not depend on iteration order
+#[inline(always)]
+pub fn combine_unordered(acc : u64 , entry_hash : u64)->u64{
+    acc.wrapping_add(mix64(entry_hash))
+}
@@ -0,0 +1,118 @@
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use crate::balance_manager::my_balance_manager2::BalanceState;
+    use crate::digest::digest_log::DigestLog;
+    use crate::journal::command_journal::{InboundCommand, JournalWriter};
+    use crate::journal::replay::{detached_core, replay_journal};
+    use crate::orderbook::order::{Order, OrderToBeCanceled, Side};
+    use crate::orderbook::order_book::OrderBook;
+
+    fn temp_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("digest_test_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    fn order(order_id: u64, side: Side, qty: u32, price: u64) -> Order {
+        Order::new(10, order_id, side, 1, qty, price, order_id, 0)
+    }
+
+    fn state_with(users: &[(u64, u32)]) -> BalanceState {
+        let state = BalanceState::new();
+        for (user_id, slot) in users {
+            state.user_id_to_index.insert(*user_id, *slot);
+        }
+        state
+    }
+
+    fn fill_user(state: &mut BalanceState, user_id: u64, available: u64, shares: u32) {
+        let slot = *state.user_id_to_index.get(&user_id).unwrap() as usize;
+        state.balances[slot].available_balance = available;
+        state.holdings[slot].position_mut(3).available = shares;
+    }
+
+    #[test]
+    fn test_book_hash_ignores_slots_but_not_queue_order() {
+        let mut a = OrderBook::new(0);
+        a.insert_order(order(1, Side::Bid, 10, 100));
+        a.insert_order(order(2, Side::Bid, 20, 100));
+        a.insert_order(order(3, Side::Ask, 30, 110));
+
+        // same queues , different order manager slots
+        let mut b = OrderBook::new(0);
+        b.insert_order(order(3, Side::Ask, 30, 110));
+        b.insert_order(order(1, Side::Bid, 10, 100));
+        b.insert_order(order(2, Side::Bid, 20, 100));
+        assert_eq!(a.state_hash(), b.state_hash());
+
+        let mut c = OrderBook::new(0);
+        c.insert_order(order(2, Side::Bid, 20, 100));
+        c.insert_order(order(1, Side::Bid, 10, 100));
+        c.insert_order(order(3, Side::Ask, 30, 110));
+        assert_ne!(a.state_hash(), c.state_hash());
+
+        let mut d = OrderBook::new(0);
+        d.insert_order(order(1, Side::Bid, 10, 100));
+        d.insert_order(order(2, Side::Bid, 21, 100));
+        d.insert_order(order(3, Side::Ask, 30, 110));
+        assert_ne!(a.state_hash(), d.state_hash());
+    }
+
+    #[test]
+    fn test_balance_hash_ignores_slot_assignment() {
+        let mut a = state_with(&[(1, 0), (2, 1)]);
+        fill_user(&mut a, 1, 500, 7);
+        fill_user(&mut a, 2, 900, 0);
+
+        let mut b = state_with(&[(2, 0), (1, 1)]);
+        fill_user(&mut b, 1, 500, 7);
+        fill_user(&mut b, 2, 900, 0);
+        assert_eq!(a.state_hash(), b.state_hash());
+
+        fill_user(&mut b, 2, 900, 1);
+        assert_ne!(a.state_hash(), b.state_hash());
+    }
+
+    #[test]
+    fn test_two_replays_publish_identical_digests() {
+        let journal = temp_path("journal");
+        let live_digests = temp_path("live.digests");
+        let commands = vec![
+            InboundCommand::NewOrder(Order::new(20, 1, Side::Ask, 1, 50, 10, 1, 0)),
+            InboundCommand::NewOrder(Order::new(20, 2, Side::Ask, 1, 30, 11, 2, 0)),
+            InboundCommand::NewOrder(Order::new(10, 3, Side::Bid, 1, 60, 11, 3, 0)),
+            InboundCommand::NewOrder(Order::new(10, 4, Side::Bid, 1, 40, 9, 4, 0)),
+            InboundCommand::CancelOrder(OrderToBeCanceled { order_id: 4, user_id: 10, symbol: 0 }),
+            InboundCommand::NewOrder(Order::new(10, 5, Side::Bid, 0, 10, 0, 5, 0)),
+        ];
+        {
+            let (mut live, mut sink) = detached_core();
+            live.journal = Some(JournalWriter::open(&journal).unwrap());
+            live.digest_log = Some(DigestLog::open(&live_digests, 2).unwrap());
+            for command in commands {
+                live.submit(command);
+                sink.drain();
+            }
+        }
+
+        let mut published = Vec::new();
+        for run in 0..2 {
+            let digests = temp_path(&format!("replay{}.digests", run));
+            {
+                let (mut core, mut sink) = detached_core();
+                core.digest_log = Some(DigestLog::open(&digests, 2).unwrap());
+                replay_journal(&mut core, &mut sink, &journal, 0, None).unwrap();
+            }
+            published.push(std::fs::read_to_string(&digests).unwrap());
+            let _ = std::fs::remove_file(&digests);
+        }
+        let live = std::fs::read_to_string(&live_digests).unwrap();
+        assert_eq!(live.lines().count(), 3);
+        assert_eq!(published[0], published[1]);
+        assert_eq!(published[0], live);
+
+        let _ = std::fs::remove_file(&journal);
+        let _ = std::fs::remove_file(&live_digests);
+    }
+}
@@ -1 +1,3 @@
-pub mod my_engine;
\ No newline at end of file
+pub mod my_engine;
+pub mod symbol_registry;
+pub mod tests;
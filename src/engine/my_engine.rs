@@ -1,17 +1,17 @@
-use bounded_spsc_queue::{Consumer, Producer};
+use bounded_spsc_queue::Consumer;
+use crate::backpressure::sender::PolicyProducer;
 use chrono::prelude::*;
 use crate::logger::types::OrderBookSnapShot;
 use crate::orderbook::order::{Order, Side};
 use crate::orderbook::types::{Event, Fills, MarketUpdateAfterTrade, MatchResult, OrderBookError} ;
 use crate::orderbook::order_book::{ OrderBook};
-use crate::shm::cancel_orders_queue::{ CancelOrderQueue};
+use crate::engine::symbol_registry::{SymbolError, SymbolRegistry};
 use crate::shm::event_queue::OrderEvents;
 use crate::shm::market_maker_feed::MarketMakerFeed;
 use std::time::{SystemTime, UNIX_EPOCH};
 
 // 100 max symbols for now 
 
-const MAX_SYMBOLS : usize = 100 ; 
 pub trait Engine{
     fn add_book(&mut self , symbol : u32);
     fn get_book(&self , symbol : u32)->Option<&OrderBook>; // can only get a refrence , orderbooks are owned by the engine
@@ -178,30 +178,35 @@ pub trait Engine{
 
 
 
+// levels per side an OrderBookSnapShot has room for , the configured depth can only be lower
 pub const DEPTH_N: usize = 20;
 pub struct STEngine{
     pub engine_id :usize ,
     pub book_count : usize, 
+    // levels per side filled in snapshots , the rest stay zeroed
+    pub snapshot_depth : usize,
+    // indexed by the slot the registry gave the symbol , not by the symbol id
     pub books : Vec<Option<OrderBook>>,
-    pub cancel_order_queue : CancelOrderQueue,
-    pub sending_event_to_publisher_try : Producer<Event>,
-    pub sending_order_events_to_writter_try : Producer<OrderEvents>,
+    pub symbols : SymbolRegistry,
+    // the venue wide kill switch , new orders are rejected while it is set , checkpointed with the books
+    pub trading_stopped : bool,
+    pub sending_event_to_publisher_try : PolicyProducer<Event>,
+    pub sending_order_events_to_writter_try : PolicyProducer<OrderEvents>,
 }
 impl STEngine{
     pub fn new( engine_id : usize , 
-        event_sender_to_publisher : Producer<Event> , 
-        sending_order_events_to_writter_try : Producer<OrderEvents>, 
+        event_sender_to_publisher : PolicyProducer<Event> , 
+        sending_order_events_to_writter_try : PolicyProducer<OrderEvents>, 
+        snapshot_depth : usize,
     )->Self {
-        // initialise the publisher channel here 
-            let cancel_order_queue = CancelOrderQueue::open("/tmp/CancelOrders");
-            if cancel_order_queue.is_err(){
-                eprintln!("Error initialising the cancel order queue in the Stengine ");   
-            }
+        // the cancel order queue is read by the trading core , the engine only owns books and outbound channels 
             Self{
                 engine_id,
                 book_count : 0 ,
-                books : (0..MAX_SYMBOLS).map(|_| None).collect(),
-                cancel_order_queue : cancel_order_queue.unwrap(),
+                snapshot_depth : snapshot_depth.min(DEPTH_N),
+                books : Vec::new(),
+                symbols : SymbolRegistry::new(),
+                trading_stopped : false,
                 sending_event_to_publisher_try : event_sender_to_publisher,
                 sending_order_events_to_writter_try
             } 
@@ -244,57 +249,96 @@ impl STEngine{
         (None , None)
     }
 
+    /// lists the book's symbol and places it in the symbol's slot
+    pub fn insert_book(&mut self , book : OrderBook)->Result<u32 , SymbolError>{
+        let slot = self.symbols.register(book.symbol)? as usize;
+        if slot == self.books.len() {
+            self.books.push(None);
+        }
+        self.books[slot] = Some(book);
+        self.book_count = self.book_count.saturating_add(1);
+        Ok(slot as u32)
+    }
+
+    /// delists the symbol and hands back its book , the slot is reused by the next listing
+    pub fn take_book(&mut self , symbol : u32)->Result<OrderBook , SymbolError>{
+        let slot = self.symbols.delist(symbol)?;
+        self.book_count = self.book_count.saturating_sub(1);
+        Ok(self.books[slot as usize].take().expect("listed symbol without a book"))
+    }
+
+    pub fn clear_books(&mut self){
+        self.books.clear();
+        self.symbols.clear();
+        self.book_count = 0;
+    }
+
+    /// books in ascending symbol order , independent of slot assignment (checkpoints and digests)
+    pub fn books_by_symbol(&self)->impl Iterator<Item = &OrderBook>{
+        self.symbols.listed_symbols().into_iter().filter_map(|symbol| self.get_book(symbol))
+    }
+
     pub fn snapshot_for_all_book<F , G>(&mut self , mut emit : F  , mut next_event_id : G )where F : FnMut(OrderBookSnapShot) , G : FnMut()->u64{
        // println!("inside the snapthost function");
         
         for orderbook in self.books.iter(){
             match orderbook {
                 Some(book)=>{
-                    let (bids , asks) = book.get_depth_upto_n::<DEPTH_N>();
-                    emit(OrderBookSnapShot { 
-                        timestamp : SystemTime::now()
-                        .duration_since(UNIX_EPOCH)
-                        .unwrap()
-                        .as_nanos() as i64 , 
-                        event_id: next_event_id(), 
-                        symbol: book.symbol, 
-                        bids ,
-                        asks
-                    });
+                    emit(Self::book_snapshot(book, self.snapshot_depth, next_event_id()));
                 }
                 None => {}
             }
           
         }
     }
+
+    pub fn book_snapshot(book : &OrderBook , depth : usize , event_id : u64)->OrderBookSnapShot{
+        let (mut bids , mut asks) = book.get_depth_upto_n::<DEPTH_N>();
+        let depth = depth.min(DEPTH_N);
+        bids[depth..].fill((0 , 0));
+        asks[depth..].fill((0 , 0));
+        OrderBookSnapShot { 
+            timestamp : SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos() as i64 , 
+            event_id, 
+            symbol: book.symbol, 
+            bids ,
+            asks
+        }
+    }
 }
 
 
 
 impl Engine for STEngine{
     fn add_book(&mut self , symbol : u32) {
-        let new_book = OrderBook::new(symbol);
-        self.books[symbol as usize] = Some(new_book);
-        self.book_count = self.book_count.saturating_add(1);
+        // relisting a live symbol would throw its resting orders away , the existing book stays
+        if let Err(e) = self.insert_book(OrderBook::new(symbol)) {
+            eprintln!("[Engine] add book: {}", e);
+        }
     }
+    #[inline(always)]
     fn get_book(&self , symbol : u32)->Option<&OrderBook> {
-        self.books[symbol as usize].as_ref()
+        let slot = self.symbols.slot(symbol)?;
+        self.books[slot as usize].as_ref()
     }
+    #[inline(always)]
     fn get_book_mut(&mut self, symbol: u32) -> Option<&mut OrderBook> {
-        self.books[symbol as usize].as_mut()
+        let slot = self.symbols.slot(symbol)?;
+        self.books[slot as usize].as_mut()
     }
     fn get_book_count(&self)->usize {
         self.book_count
     }
     fn has_book(&self, symbol: u32) -> bool {
-       self.books[symbol as usize].is_some()
+       self.symbols.is_listed(symbol)
     }
 
-    // cleaning up logic reqd 
+    // drops the book as it is , resting orders keep their reservations
+    // delisting a live symbol goes through TradingCore::delist_book which cancels them first
     fn remove_book(&mut self , symbol : u32) {
-        if self.has_book(symbol){
-            self.books[symbol as usize] = None;
-            self.book_count = self.book_count.saturating_sub(1);
-        }
+        let _ = self.take_book(symbol);
     }
 }
\ No newline at end of file
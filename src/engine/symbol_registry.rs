@@ -0,0 +1,94 @@
+// maps external symbol ids to dense internal slots
+// symbol ids come from outside and can be anything , the books live in a vec indexed by slot
+// a delisted symbol's slot goes on the free list and is handed to the next listing
+use rustc_hash::FxHashMap;
+use thiserror::Error;
+
+#[derive(Debug , Error , PartialEq , Eq)]
+pub enum SymbolError{
+    #[error("symbol {0} is already listed")]
+    AlreadyListed(u32),
+    #[error("symbol {0} is not listed")]
+    NotListed(u32),
+}
+
+#[derive(Debug , Default)]
+pub struct SymbolRegistry{
+    symbol_to_slot : FxHashMap<u32 , u32>,
+    // slot to symbol , none for a free slot
+    slots : Vec<Option<u32>>,
+    free_slots : Vec<u32>,
+}
+
+impl SymbolRegistry{
+    pub fn new()->Self{
+        Self::default()
+    }
+
+    /// lists `symbol` and returns its slot , a freed slot is reused before the table grows
+    pub fn register(&mut self , symbol : u32)->Result<u32 , SymbolError>{
+        if self.symbol_to_slot.contains_key(&symbol) {
+            return Err(SymbolError::AlreadyListed(symbol));
+        }
+        let slot = match self.free_slots.pop() {
+            Some(slot) => {
+                self.slots[slot as usize] = Some(symbol);
+                slot
+            }
+            None => {
+                self.slots.push(Some(symbol));
+                (self.slots.len() - 1) as u32
+            }
+        };
+        self.symbol_to_slot.insert(symbol, slot);
+        Ok(slot)
+    }
+
+    /// removes `symbol` and returns the slot it had , the caller clears whatever it kept there
+    pub fn delist(&mut self , symbol : u32)->Result<u32 , SymbolError>{
+        let slot = self.symbol_to_slot.remove(&symbol).ok_or(SymbolError::NotListed(symbol))?;
+        self.slots[slot as usize] = None;
+        self.free_slots.push(slot);
+        Ok(slot)
+    }
+
+    // hot path , one hash lookup
+    #[inline(always)]
+    pub fn slot(&self , symbol : u32)->Option<u32>{
+        self.symbol_to_slot.get(&symbol).copied()
+    }
+
+    pub fn symbol_at(&self , slot : u32)->Option<u32>{
+        self.slots.get(slot as usize).copied().flatten()
+    }
+
+    pub fn is_listed(&self , symbol : u32)->bool{
+        self.symbol_to_slot.contains_key(&symbol)
+    }
+
+    pub fn len(&self)->usize{
+        self.symbol_to_slot.len()
+    }
+
+    pub fn is_empty(&self)->bool{
+        self.symbol_to_slot.is_empty()
+    }
+
+    // slots ever handed out , the size the slot indexed tables need
+    pub fn slot_capacity(&self)->usize{
+        self.slots.len()
+    }
+
+    /// listed symbols in ascending order , for anything that has to come out the same however the slots were assigned
+    pub fn listed_symbols(&self)->Vec<u32>{
+        let mut symbols : Vec<u32> = self.symbol_to_slot.keys().copied().collect();
+        symbols.sort_unstable();
+        symbols
+    }
+
+    pub fn clear(&mut self){
+        self.symbol_to_slot.clear();
+        self.slots.clear();
+        self.free_slots.clear();
+    }
+}
@@ -0,0 +1,135 @@
+#[cfg(test)]
+mod tests {
+    use crate::engine::my_engine::Engine;
+    use crate::engine::symbol_registry::{SymbolError, SymbolRegistry};
+    use crate::journal::command_journal::InboundCommand;
+    use crate::journal::replay::detached_core;
+    use crate::orderbook::order::{Order, Side};
+    use crate::shm::event_queue::CANCEL_REASON_DELISTED;
+    use crate::shm::query_queue::Query;
+    use crate::admin::plane::AdminRequest;
+    use crate::shm::admin_command_queue::{AdminCommand, ADMIN_ADD_BOOK, ADMIN_REMOVE_BOOK};
+
+    fn book_query(query_type: u8, symbol: u32) -> InboundCommand {
+        InboundCommand::Query(Query {
+            available_balance: 0,
+            reserved_balance: 0,
+            user_id: 0,
+            symbol,
+            reserved_shares_qty: 0,
+            available_shares_qty: 0,
+            query_type,
+        })
+    }
+
+    fn book_admin(command_type: u8, symbol: u32) -> InboundCommand {
+        InboundCommand::Admin(AdminRequest { command: AdminCommand { symbol, command_type, ..AdminCommand::default() }, authorized: true })
+    }
+
+    fn add_book(symbol: u32) -> InboundCommand {
+        book_admin(ADMIN_ADD_BOOK, symbol)
+    }
+
+    #[test]
+    fn test_registry_reuses_delisted_slots() {
+        let mut registry = SymbolRegistry::new();
+        assert_eq!(registry.register(7), Ok(0));
+        assert_eq!(registry.register(1_000_000), Ok(1));
+        assert_eq!(registry.register(7), Err(SymbolError::AlreadyListed(7)));
+
+        assert_eq!(registry.delist(7), Ok(0));
+        assert_eq!(registry.delist(7), Err(SymbolError::NotListed(7)));
+        assert_eq!(registry.slot(7), None);
+
+        // the freed slot goes to the next listing , the table does not grow
+        assert_eq!(registry.register(42), Ok(0));
+        assert_eq!(registry.slot_capacity(), 2);
+        assert_eq!(registry.symbol_at(0), Some(42));
+        assert_eq!(registry.listed_symbols(), vec![42, 1_000_000]);
+    }
+
+    #[test]
+    fn test_trading_on_symbols_past_the_old_limit() {
+        let (mut core, mut sink) = detached_core();
+        for symbol in [100, 250, 4_000_000] {
+            core.apply_command(add_book(symbol));
+        }
+        core.apply_command(InboundCommand::NewOrder(Order::new(20, 1, Side::Ask, 1, 30, 10, 1, 4_000_000)));
+        core.apply_command(InboundCommand::NewOrder(Order::new(10, 2, Side::Bid, 1, 20, 10, 2, 4_000_000)));
+        sink.drain();
+
+        let book = core.engine.get_book(4_000_000).unwrap();
+        assert_eq!(book.last_trade_price, 10);
+        assert_eq!(book.manager.id_to_index.len(), 1);
+
+        let state = &core.balance_manager.state;
+        let buyer = &state.holdings[*state.user_id_to_index.get(&10).unwrap() as usize];
+        let seller = &state.holdings[*state.user_id_to_index.get(&20).unwrap() as usize];
+        assert_eq!(buyer.available(4_000_000), buyer.default_available + 20);
+        assert_eq!(seller.reserved(4_000_000), 10);
+        // untouched symbols stay sparse
+        assert!(!buyer.positions.contains_key(&250));
+    }
+
+    #[test]
+    fn test_delisted_symbol_is_gone_and_slot_reused() {
+        let (mut core, _sink) = detached_core();
+        core.engine.add_book(500);
+        core.engine.add_book(600);
+        core.engine.remove_book(500);
+        assert!(!core.engine.has_book(500));
+        assert!(core.engine.get_book(500).is_none());
+        assert_eq!(core.engine.get_book_count(), 1);
+
+        core.engine.add_book(700);
+        assert_eq!(core.engine.books.len(), 2);
+        assert_eq!(core.engine.get_book(700).unwrap().symbol, 700);
+        assert_eq!(core.engine.get_book(600).unwrap().symbol, 600);
+    }
+
+    #[test]
+    fn test_delisting_cancels_resting_orders_and_releases_funds() {
+        let (mut core, mut sink) = detached_core();
+        core.apply_command(add_book(300));
+        sink.drain();
+        let state = &core.balance_manager.state;
+        let buyer = *state.user_id_to_index.get(&10).unwrap() as usize;
+        let seller = *state.user_id_to_index.get(&20).unwrap() as usize;
+        let buyer_before = state.balances[buyer].available_balance;
+        let seller_shares_before = state.holdings[seller].available(300);
+
+        core.apply_command(InboundCommand::NewOrder(Order::new(10, 1, Side::Bid, 1, 5, 9, 1, 300)));
+        core.apply_command(InboundCommand::NewOrder(Order::new(10, 2, Side::Bid, 1, 7, 8, 2, 300)));
+        core.apply_command(InboundCommand::NewOrder(Order::new(20, 3, Side::Ask, 1, 4, 12, 3, 300)));
+        sink.drain();
+        assert_eq!(core.balance_manager.state.balances[buyer].reserved_balance, 5 * 9 + 7 * 8);
+
+        // the query queue is not authenticated , a delist query does nothing
+        core.apply_command(book_query(5, 300));
+        assert!(core.engine.has_book(300));
+        assert_eq!(core.balance_manager.state.balances[buyer].reserved_balance, 5 * 9 + 7 * 8);
+
+        core.apply_command(book_admin(ADMIN_REMOVE_BOOK, 300));
+        assert!(!core.engine.has_book(300));
+        assert_eq!(core.engine.get_book_count(), 0);
+
+        let state = &core.balance_manager.state;
+        assert_eq!(state.balances[buyer].available_balance, buyer_before);
+        assert_eq!(state.balances[buyer].reserved_balance, 0);
+        assert_eq!(state.holdings[seller].available(300), seller_shares_before);
+        assert_eq!(state.holdings[seller].reserved(300), 0);
+
+        let mut canceled = Vec::new();
+        while let Some(event) = sink.order_events_from_engine.try_pop() {
+            assert_eq!(event.event_kind, 4);
+            assert_eq!(event.error_code, CANCEL_REASON_DELISTED);
+            canceled.push(event.order_id);
+        }
+        assert_eq!(canceled, vec![2, 1, 3]);
+
+        // the final state goes to the logger , an empty book
+        let snapshot = sink.snapshots.try_pop().unwrap();
+        assert_eq!(snapshot.symbol, 300);
+        assert!(snapshot.bids.iter().chain(snapshot.asks.iter()).all(|level| level.1 == 0));
+    }
+}
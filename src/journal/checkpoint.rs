@@ -0,0 +1,524 @@
+// full state checkpoint of the trading core at a journal sequence number
+// unlike the top 20 snapshot for the logger this holds every resting order (L3) and every balance , enough to restart from
+// layout , all little endian
+// magic u32 | version u32 | sequence u64 | timestamp u64
+// book_count u32 , per book in symbol order : symbol u32 | last_trade_price u64 | order_count u32 | orders in time priority per level
+// halted_count u32 , halted symbols ascending u32 | trading_stopped u8
+// slot_count u32 , per slot : index u32 | balance fields | default_available u32 | position_count u32 , per position : symbol u32 | available u32 | reserved u32
+// mapping_count u32 , per mapping : user_id u64 | index u32
+// next_free_slot u32 | total_users u32
+// reservation_count u32 , per open order in order id order : order_id u64 | user_id u64 | symbol u32 | side u8 | price u64 | remaining_qty u32 | reserved u64 | fee_bps u32
+// movement_count u32 , per funds movement in the order applied : key u64 | user_id u64 | kind u8 | asset u8 | symbol u32 | amount u64 | available_after u64
+// house_count u32 , per nonzero fee or system account , sorted : account u8 | asset u8 | symbol u32 | balance i64
+// wallet_count u32 , per wallet asset a user holds , sorted : user_id u64 | asset u32 | available u64 | reserved u64
+// limit_count u32 , per user with admin set limits , sorted : user_id u64 | max_order_qty u32 | max_order_notional u64 | max_open_orders u32
+//   | max_position u64 | max_daily_notional u64 | max_price_deviation_bps u32
+// price_count u32 , per traded symbol , sorted : symbol u32 | last fill price u64
+// bucket_count u32 , per rate limit bucket , sorted : user_id u64 | action u8 | tokens u64 | refilled_at u64
+// frozen_count u32 , frozen user ids ascending u64
+// position_count u32 , per position , sorted : user_id u64 | symbol u32 | qty i64 | cost high i64 | cost low u64 | realized_pnl i64
+// mark_count u32 , per admin set mark , sorted : symbol u32 | price u64
+// trading_day u64 , the day the daily counters of the balances belong to
+// volume_count u32 , per user , quote asset and day in the fee window , sorted : user_id u64 | asset u8 | symbol u32 | day u64 | notional u64
+// crc32 of everything before it
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use rustc_hash::FxHashMap;
+use thiserror::Error;
+use crate::balance_manager::funds_ledger::{Asset, FundsLedger, FundsMovement, MovementKind};
+use crate::balance_manager::positions::Position;
+use crate::balance_manager::postings::{Account, ConservationError};
+use crate::balance_manager::rate_limits::{RateAction, TokenBucket};
+use crate::balance_manager::risk_limits::RiskLimits;
+use crate::balance_manager::my_balance_manager2::{BalanceState, OrderReservation, SymbolHolding, UserBalance, UserHoldings, WalletBalance};
+use crate::journal::codec::{ByteReader, ByteWriter};
+use crate::journal::command_journal::{now_nanos, JournalError};
+use crate::orderbook::book::BookSide;
+use crate::orderbook::order::{Order, Side};
+use crate::orderbook::order_book::OrderBook;
+use crate::trading_core::my_trading_core::TradingCore;
+
+const CHECKPOINT_MAGIC : u32 = 0x54504B43; // "CKPT"
+// the layout described at the top , a checkpoint written in any other is refused
+const CHECKPOINT_VERSION : u32 = 1;
+const HOUSE_FEES : u8 = 0;
+const HOUSE_SYSTEM : u8 = 1;
+const CHECKPOINT_EXTENSION : &str = "ckpt";
+// older checkpoints are pruned once a new one is safely on disk
+const CHECKPOINTS_TO_KEEP : usize = 3;
+
+#[derive(Debug , Error)]
+pub enum CheckpointError{
+    #[error("checkpoint io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("checkpoint {0:?} is corrupt")]
+    Corrupt(PathBuf),
+    #[error("checkpoint {path:?} has version {got} , expected {expected}")]
+    VersionMismatch { path : PathBuf , got : u32 , expected : u32 },
+    #[error("journal ends at sequence {journal} but the checkpoint is at {checkpoint}")]
+    JournalBehindCheckpoint { journal : u64 , checkpoint : u64 },
+    #[error(transparent)]
+    Journal(#[from] JournalError),
+    #[error("not checkpointing , {0}")]
+    NotConserved(ConservationError),
+}
+
+pub fn checkpoint_file_name(sequence : u64)->String{
+    format!("checkpoint-{:020}.{}", sequence, CHECKPOINT_EXTENSION)
+}
+
+fn encode_side(w : &mut ByteWriter , book : &OrderBook , side : &BookSide){
+    for level in side.levels.values() {
+        let mut cursor = level.head;
+        while let Some(index) = cursor {
+            let order = book.manager.get(index).unwrap();
+            w.put_u8(match order.side {
+                Side::Bid => 0,
+                Side::Ask => 1
+            });
+            w.put_u64(order.user_id);
+            w.put_u64(order.order_id);
+            w.put_u8(order.order_type);
+            w.put_u32(order.shares_qty);
+            w.put_u64(order.price);
+            w.put_u64(order.timestamp);
+            cursor = order.next;
+        }
+    }
+}
+
+fn encode_checkpoint(core : &TradingCore , buf : &mut Vec<u8>){
+    let mut w = ByteWriter::new(buf);
+    w.put_u32(CHECKPOINT_MAGIC);
+    w.put_u32(CHECKPOINT_VERSION);
+    w.put_u64(core.sequence);
+    w.put_u64(now_nanos());
+
+    w.put_u32(core.engine.symbols.len() as u32);
+    for book in core.engine.books_by_symbol() {
+        w.put_u32(book.symbol);
+        w.put_u64(book.last_trade_price);
+        w.put_u32(book.manager.id_to_index.len() as u32);
+        encode_side(&mut w, book, &book.bidside);
+        encode_side(&mut w, book, &book.askside);
+    }
+    let halted = core.admin.halted_symbols();
+    w.put_u32(halted.len() as u32);
+    for symbol in halted {
+        w.put_u32(symbol);
+    }
+    w.put_u8(core.engine.trading_stopped as u8);
+
+    let state = &core.balance_manager.state;
+    let mut mappings : Vec<(u64 , u32)> = state.user_id_to_index.iter().map(|entry| (*entry.key(), *entry.value())).collect();
+    mappings.sort_unstable();
+    let mut slots : Vec<u32> = mappings.iter().map(|(_, index)| *index).collect();
+    slots.sort_unstable();
+    slots.dedup();
+
+    w.put_u32(slots.len() as u32);
+    for index in slots {
+        let balance = &state.balances[index as usize];
+        let holdings = &state.holdings[index as usize];
+        w.put_u32(index);
+        w.put_u64(balance.user_id);
+        w.put_u64(balance.available_balance);
+        w.put_u64(balance.reserved_balance);
+        w.put_u64(balance.total_traded_today);
+        w.put_u64(balance.order_count_today);
+        w.put_u64(holdings.user_id);
+        w.put_u32(holdings.default_available);
+        let mut positions : Vec<(u32 , SymbolHolding)> = holdings.positions.iter()
+            .filter(|(_, holding)| !holdings.is_default(holding))
+            .map(|(symbol , holding)| (*symbol, *holding))
+            .collect();
+        positions.sort_unstable_by_key(|(symbol, _)| *symbol);
+        w.put_u32(positions.len() as u32);
+        for (symbol , holding) in positions {
+            w.put_u32(symbol);
+            w.put_u32(holding.available);
+            w.put_u32(holding.reserved);
+        }
+    }
+    w.put_u32(mappings.len() as u32);
+    for (user_id , index) in mappings {
+        w.put_u64(user_id);
+        w.put_u32(index);
+    }
+    w.put_u32(state.next_free_slot);
+    w.put_u32(state.total_users);
+    let mut reservations : Vec<(u64 , OrderReservation)> = state.reservations.iter().map(|(order_id , reservation)| (*order_id, *reservation)).collect();
+    reservations.sort_unstable_by_key(|(order_id, _)| *order_id);
+    w.put_u32(reservations.len() as u32);
+    for (order_id , reservation) in reservations {
+        w.put_u64(order_id);
+        w.put_u64(reservation.user_id);
+        w.put_u32(reservation.symbol);
+        w.put_u8(match reservation.side {
+            Side::Bid => 0,
+            Side::Ask => 1,
+        });
+        w.put_u64(reservation.price);
+        w.put_u32(reservation.remaining_qty);
+        w.put_u64(reservation.reserved);
+        w.put_u32(reservation.fee_bps);
+    }
+    let movements = state.funds.movements();
+    w.put_u32(movements.len() as u32);
+    for movement in movements {
+        w.put_u64(movement.key);
+        w.put_u64(movement.user_id);
+        w.put_u8(movement.kind.as_u8());
+        let (asset , symbol) = movement.asset.code();
+        w.put_u8(asset);
+        w.put_u32(symbol);
+        w.put_u64(movement.amount);
+        w.put_u64(movement.available_after);
+    }
+    let mut house : Vec<(u8 , u8 , u32 , i64)> = state.house.iter().map(|(account , asset , balance)| {
+        let account = if account == Account::Fees { HOUSE_FEES } else { HOUSE_SYSTEM };
+        let (asset , symbol) = asset.code();
+        (account , asset , symbol , balance)
+    }).collect();
+    house.sort_unstable();
+    w.put_u32(house.len() as u32);
+    for (account , asset , symbol , balance) in house {
+        w.put_u8(account);
+        w.put_u8(asset);
+        w.put_u32(symbol);
+        w.put_i64(balance);
+    }
+    let mut wallets : Vec<((u64 , u32) , WalletBalance)> = state.wallets.iter().map(|(key , wallet)| (*key, *wallet)).collect();
+    wallets.sort_unstable_by_key(|(key , _)| *key);
+    w.put_u32(wallets.len() as u32);
+    for ((user_id , asset) , wallet) in wallets {
+        w.put_u64(user_id);
+        w.put_u32(asset);
+        w.put_u64(wallet.available);
+        w.put_u64(wallet.reserved);
+    }
+    let mut limits : Vec<(u64 , RiskLimits)> = state.risk.overrides.iter().map(|(user_id , limits)| (*user_id, *limits)).collect();
+    limits.sort_unstable_by_key(|(user_id , _)| *user_id);
+    w.put_u32(limits.len() as u32);
+    for (user_id , limits) in limits {
+        w.put_u64(user_id);
+        w.put_u32(limits.max_order_qty);
+        w.put_u64(limits.max_order_notional);
+        w.put_u32(limits.max_open_orders);
+        w.put_u64(limits.max_position);
+        w.put_u64(limits.max_daily_notional);
+        w.put_u32(limits.max_price_deviation_bps);
+    }
+    let mut prices : Vec<(u32 , u64)> = state.risk.last_prices.iter().map(|(symbol , price)| (*symbol, *price)).collect();
+    prices.sort_unstable();
+    w.put_u32(prices.len() as u32);
+    for (symbol , price) in prices {
+        w.put_u32(symbol);
+        w.put_u64(price);
+    }
+    let mut buckets : Vec<((u64 , RateAction) , TokenBucket)> = state.rates.buckets.iter().map(|(key , bucket)| (*key, *bucket)).collect();
+    buckets.sort_unstable_by_key(|(key , _)| *key);
+    w.put_u32(buckets.len() as u32);
+    for ((user_id , action) , bucket) in buckets {
+        w.put_u64(user_id);
+        w.put_u8(action.as_u8());
+        w.put_u64(bucket.tokens);
+        w.put_u64(bucket.refilled_at);
+    }
+    let mut frozen : Vec<u64> = state.frozen.iter().copied().collect();
+    frozen.sort_unstable();
+    w.put_u32(frozen.len() as u32);
+    for user_id in frozen {
+        w.put_u64(user_id);
+    }
+    let mut positions : Vec<((u64 , u32) , Position)> = state.positions.iter().map(|(key , position)| (*key, *position)).collect();
+    positions.sort_unstable_by_key(|(key , _)| *key);
+    w.put_u32(positions.len() as u32);
+    for ((user_id , symbol) , position) in positions {
+        w.put_u64(user_id);
+        w.put_u32(symbol);
+        w.put_i64(position.qty);
+        w.put_i64((position.cost >> 64) as i64);
+        w.put_u64(position.cost as u64);
+        w.put_i64(position.realized_pnl);
+    }
+    let mut marks : Vec<(u32 , u64)> = state.marks.iter().map(|(symbol , price)| (*symbol, *price)).collect();
+    marks.sort_unstable();
+    w.put_u32(marks.len() as u32);
+    for (symbol , price) in marks {
+        w.put_u32(symbol);
+        w.put_u64(price);
+    }
+    w.put_u64(state.trading_day);
+    let mut volume : Vec<(u64 , u8 , u32 , u64 , u64)> = state.fee_volume.iter().flat_map(|((user_id , asset) , window)| {
+        let (kind , id) = asset.code();
+        window.days().map(move |(day , notional)| (*user_id , kind , id , day , notional))
+    }).collect();
+    volume.sort_unstable();
+    w.put_u32(volume.len() as u32);
+    for (user_id , kind , id , day , notional) in volume {
+        w.put_u64(user_id);
+        w.put_u8(kind);
+        w.put_u32(id);
+        w.put_u64(day);
+        w.put_u64(notional);
+    }
+}
+
+/// writes a checkpoint of `core` into `dir` , returns the path of the new file
+/// the file is written under a temporary name , synced and renamed so a crash never leaves a half checkpoint behind
+pub fn write_checkpoint<P : AsRef<Path>>(core : &TradingCore , dir : P)->Result<PathBuf , CheckpointError>{
+    let dir = dir.as_ref();
+    fs::create_dir_all(dir)?;
+
+    let mut buf = Vec::with_capacity(1 << 20);
+    encode_checkpoint(core, &mut buf);
+    let crc = crc32fast::hash(&buf);
+    buf.extend_from_slice(&crc.to_le_bytes());
+
+    let final_path = dir.join(checkpoint_file_name(core.sequence));
+    let tmp_path = final_path.with_extension("tmp");
+    {
+        let mut file = File::create(&tmp_path)?;
+        file.write_all(&buf)?;
+        file.sync_all()?;
+    }
+    fs::rename(&tmp_path, &final_path)?;
+    File::open(dir)?.sync_all()?;
+
+    prune_checkpoints(dir)?;
+    Ok(final_path)
+}
+
+/// checkpoint files in `dir` , newest first
+pub fn list_checkpoints<P : AsRef<Path>>(dir : P)->Result<Vec<PathBuf> , CheckpointError>{
+    let dir = dir.as_ref();
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == CHECKPOINT_EXTENSION) {
+            paths.push(path);
+        }
+    }
+    // the zero padded sequence makes the name order the sequence order
+    paths.sort_unstable_by(|a, b| b.cmp(a));
+    Ok(paths)
+}
+
+fn prune_checkpoints(dir : &Path)->Result<() , CheckpointError>{
+    for stale in list_checkpoints(dir)?.into_iter().skip(CHECKPOINTS_TO_KEEP) {
+        fs::remove_file(stale)?;
+    }
+    Ok(())
+}
+
+fn decode_book(r : &mut ByteReader)->Option<OrderBook>{
+    let symbol = r.get_u32()?;
+    let mut book = OrderBook::new(symbol);
+    book.last_trade_price = r.get_u64()?;
+    let order_count = r.get_u32()?;
+    for _ in 0..order_count {
+        let side = match r.get_u8()? {
+            0 => Side::Bid,
+            1 => Side::Ask,
+            _ => return None
+        };
+        let user_id = r.get_u64()?;
+        let order_id = r.get_u64()?;
+        let order_type = r.get_u8()?;
+        let shares_qty = r.get_u32()?;
+        let price = r.get_u64()?;
+        let timestamp = r.get_u64()?;
+        // orders were written head to tail so inserting at the tail rebuilds the same queue
+        book.insert_order(Order::new(user_id, order_id, side, order_type, shares_qty, price, timestamp, symbol));
+    }
+    Some(book)
+}
+
+fn decode_balances(r : &mut ByteReader)->Option<BalanceState>{
+    let mut state = BalanceState::new();
+    let slot_count = r.get_u32()?;
+    for _ in 0..slot_count {
+        let index = r.get_u32()? as usize;
+        let mut balance = UserBalance::new(r.get_u64()?);
+        balance.available_balance = r.get_u64()?;
+        balance.reserved_balance = r.get_u64()?;
+        balance.total_traded_today = r.get_u64()?;
+        balance.order_count_today = r.get_u64()?;
+        let mut holdings = UserHoldings::new(r.get_u64()?);
+        holdings.default_available = r.get_u32()?;
+        let position_count = r.get_u32()?;
+        if index >= state.balances.len() {
+            return None;
+        }
+        for _ in 0..position_count {
+            let symbol = r.get_u32()?;
+            let available = r.get_u32()?;
+            let reserved = r.get_u32()?;
+            holdings.set(symbol, available, reserved);
+        }
+        state.balances[index] = balance;
+        state.holdings[index] = holdings;
+    }
+    let mapping_count = r.get_u32()?;
+    for _ in 0..mapping_count {
+        let user_id = r.get_u64()?;
+        let index = r.get_u32()?;
+        state.user_id_to_index.insert(user_id, index);
+    }
+    state.next_free_slot = r.get_u32()?;
+    state.total_users = r.get_u32()?;
+    let reservation_count = r.get_u32()?;
+    for _ in 0..reservation_count {
+        let order_id = r.get_u64()?;
+        let reservation = OrderReservation {
+            user_id : r.get_u64()?,
+            symbol : r.get_u32()?,
+            side : match r.get_u8()? {
+                0 => Side::Bid,
+                1 => Side::Ask,
+                _ => return None,
+            },
+            price : r.get_u64()?,
+            remaining_qty : r.get_u32()?,
+            reserved : r.get_u64()?,
+            fee_bps : r.get_u32()?,
+        };
+        state.open_reservation(order_id, reservation);
+    }
+    let movement_count = r.get_u32()?;
+    let mut movements = Vec::with_capacity(movement_count as usize);
+    for _ in 0..movement_count {
+        let key = r.get_u64()?;
+        let user_id = r.get_u64()?;
+        let kind = MovementKind::from_u8(r.get_u8()?)?;
+        let asset = Asset::from_code(r.get_u8()?, r.get_u32()?)?;
+        movements.push(FundsMovement { key , user_id , kind , asset , amount : r.get_u64()? , available_after : r.get_u64()? });
+    }
+    state.funds = FundsLedger::from_movements(movements)?;
+    let house_count = r.get_u32()?;
+    for _ in 0..house_count {
+        let account = match r.get_u8()? {
+            HOUSE_FEES => Account::Fees,
+            HOUSE_SYSTEM => Account::System,
+            _ => return None,
+        };
+        let asset = Asset::from_code(r.get_u8()?, r.get_u32()?)?;
+        state.house.set(account, asset, r.get_i64()?);
+    }
+    let wallet_count = r.get_u32()?;
+    for _ in 0..wallet_count {
+        let key = (r.get_u64()? , r.get_u32()?);
+        state.wallets.insert(key, WalletBalance { available : r.get_u64()? , reserved : r.get_u64()? });
+    }
+    let limit_count = r.get_u32()?;
+    for _ in 0..limit_count {
+        let user_id = r.get_u64()?;
+        let limits = RiskLimits {
+            max_order_qty : r.get_u32()?,
+            max_order_notional : r.get_u64()?,
+            max_open_orders : r.get_u32()?,
+            max_position : r.get_u64()?,
+            max_daily_notional : r.get_u64()?,
+            max_price_deviation_bps : r.get_u32()?,
+        };
+        state.risk.overrides.insert(user_id, limits);
+    }
+    let price_count = r.get_u32()?;
+    for _ in 0..price_count {
+        let symbol = r.get_u32()?;
+        state.risk.last_prices.insert(symbol, r.get_u64()?);
+    }
+    let bucket_count = r.get_u32()?;
+    for _ in 0..bucket_count {
+        let key = (r.get_u64()? , RateAction::from_u8(r.get_u8()?)?);
+        state.rates.buckets.insert(key, TokenBucket { tokens : r.get_u64()? , refilled_at : r.get_u64()? });
+    }
+    let frozen_count = r.get_u32()?;
+    for _ in 0..frozen_count {
+        state.frozen.insert(r.get_u64()?);
+    }
+    let position_count = r.get_u32()?;
+    for _ in 0..position_count {
+        let key = (r.get_u64()? , r.get_u32()?);
+        let qty = r.get_i64()?;
+        let cost = ((r.get_i64()? as i128) << 64) | r.get_u64()? as i128;
+        state.positions.insert(key, Position { qty , cost , realized_pnl : r.get_i64()? });
+    }
+    let mark_count = r.get_u32()?;
+    for _ in 0..mark_count {
+        let symbol = r.get_u32()?;
+        state.marks.insert(symbol, r.get_u64()?);
+    }
+    state.trading_day = r.get_u64()?;
+    let volume_count = r.get_u32()?;
+    let mut volume : FxHashMap<(u64 , Asset) , Vec<(u64 , u64)>> = FxHashMap::default();
+    for _ in 0..volume_count {
+        let user_id = r.get_u64()?;
+        let asset = Asset::from_code(r.get_u8()?, r.get_u32()?)?;
+        volume.entry((user_id , asset)).or_default().push((r.get_u64()? , r.get_u64()?));
+    }
+    state.fee_volume = volume.into_iter().map(|(key , days)| (key, days.into_iter().collect())).collect();
+    Some(state)
+}
+
+/// replaces the books , balances and sequence of `core` with the checkpoint at `path`
+/// returns the sequence the checkpoint was taken at
+pub fn restore_checkpoint<P : AsRef<Path>>(core : &mut TradingCore , path : P)->Result<u64 , CheckpointError>{
+    let path = path.as_ref();
+    let bytes = fs::read(path)?;
+    if bytes.len() < 4 {
+        return Err(CheckpointError::Corrupt(path.to_path_buf()));
+    }
+    let (body , crc) = bytes.split_at(bytes.len() - 4);
+    if crc32fast::hash(body) != u32::from_le_bytes(crc.try_into().unwrap()) {
+        return Err(CheckpointError::Corrupt(path.to_path_buf()));
+    }
+
+    let mut r = ByteReader::new(body);
+    let corrupt = || CheckpointError::Corrupt(path.to_path_buf());
+    if r.get_u32().ok_or_else(corrupt)? != CHECKPOINT_MAGIC {
+        return Err(corrupt());
+    }
+    let version = r.get_u32().ok_or_else(corrupt)?;
+    if version != CHECKPOINT_VERSION {
+        return Err(CheckpointError::VersionMismatch { path : path.to_path_buf() , got : version , expected : CHECKPOINT_VERSION });
+    }
+    let sequence = r.get_u64().ok_or_else(corrupt)?;
+    let _taken_at = r.get_u64().ok_or_else(corrupt)?;
+
+    let book_count = r.get_u32().ok_or_else(corrupt)?;
+    let mut books = Vec::with_capacity(book_count as usize);
+    for _ in 0..book_count {
+        books.push(decode_book(&mut r).ok_or_else(corrupt)?);
+    }
+    let halted_count = r.get_u32().ok_or_else(corrupt)?;
+    let mut halted = Vec::with_capacity(halted_count as usize);
+    for _ in 0..halted_count {
+        halted.push(r.get_u32().ok_or_else(corrupt)?);
+    }
+    let trading_stopped = match r.get_u8().ok_or_else(corrupt)? {
+        0 => false,
+        1 => true,
+        _ => return Err(corrupt()),
+    };
+    let state = decode_balances(&mut r).ok_or_else(corrupt)?;
+    if r.remaining() != 0 {
+        return Err(corrupt());
+    }
+
+    core.engine.clear_books();
+    for book in books {
+        // a symbol twice in one file cannot come from write_checkpoint
+        core.engine.insert_book(book).map_err(|_| corrupt())?;
+    }
+    core.admin.halted = halted.into_iter().collect();
+    core.engine.trading_stopped = trading_stopped;
+    core.balance_manager.state = state;
+    core.sequence = sequence;
+    Ok(sequence)
+}
@@ -0,0 +1,65 @@
+// little endian byte helpers shared by the journal and anything else we persist to disk
+// we never transmute the repr(C) structs , field by field keeps the files portable across builds
+
+pub struct ByteWriter<'a>{
+    buf : &'a mut Vec<u8>,
+}
+
+impl<'a> ByteWriter<'a>{
+    pub fn new(buf : &'a mut Vec<u8>)->Self{
+        Self { buf }
+    }
+    #[inline(always)]
+    pub fn put_u8(&mut self , v : u8){
+        self.buf.push(v);
+    }
+    #[inline(always)]
+    pub fn put_u32(&mut self , v : u32){
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+    #[inline(always)]
+    pub fn put_u64(&mut self , v : u64){
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+    #[inline(always)]
+    pub fn put_i64(&mut self , v : i64){
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+}
+
+pub struct ByteReader<'a>{
+    buf : &'a [u8],
+    pos : usize,
+}
+
+impl<'a> ByteReader<'a>{
+    pub fn new(buf : &'a [u8])->Self{
+        Self { buf , pos : 0 }
+    }
+
+    pub fn remaining(&self)->usize{
+        self.buf.len() - self.pos
+    }
+
+    fn take<const N : usize>(&mut self)->Option<[u8 ; N]>{
+        let bytes = self.buf.get(self.pos..self.pos + N)?;
+        self.pos += N;
+        bytes.try_into().ok()
+    }
+    #[inline(always)]
+    pub fn get_u8(&mut self)->Option<u8>{
+        self.take::<1>().map(|b| b[0])
+    }
+    #[inline(always)]
+    pub fn get_u32(&mut self)->Option<u32>{
+        self.take::<4>().map(u32::from_le_bytes)
+    }
+    #[inline(always)]
+    pub fn get_u64(&mut self)->Option<u64>{
+        self.take::<8>().map(u64::from_le_bytes)
+    }
+    #[inline(always)]
+    pub fn get_i64(&mut self)->Option<i64>{
+        self.take::<8>().map(i64::from_le_bytes)
+    }
+}
@@ -0,0 +1,411 @@
+// append only journal of every inbound command the trading core accepted
+// each record is framed as
+// magic u32 | kind u8 | reserved [u8 ; 3] | payload_len u32 | sequence u64 | timestamp u64 | payload | crc32 u32
+// the crc covers everything from kind to the end of the payload , all integers little endian
+// records are written before the command is applied so the journal is always a superset of the in memory state
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+use crate::journal::codec::{ByteReader, ByteWriter};
+use crate::orderbook::order::{Order, OrderToBeCanceled, Side};
+use crate::shm::query_queue::Query;
+use crate::shm::admin_command_queue::AdminCommand;
+use crate::admin::plane::AdminRequest;
+
+const RECORD_MAGIC : u32 = 0x4C4E524A; // "JRNL"
+const HEADER_SIZE : usize = 28;
+const CRC_SIZE : usize = 4;
+// largest payload we ever write is the admin command (49 bytes) , anything bigger is garbage
+const MAX_PAYLOAD_SIZE : usize = 256;
+
+const KIND_NEW_ORDER : u8 = 1;
+const KIND_CANCEL_ORDER : u8 = 2;
+const KIND_QUERY : u8 = 3;
+const KIND_ADMIN : u8 = 4;
+
+#[derive(Debug , Error)]
+pub enum JournalError{
+    #[error("journal io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("bad record magic 0x{got:X} at offset {offset}")]
+    BadMagic { offset : u64 , got : u32 },
+    #[error("checksum mismatch at offset {offset}")]
+    ChecksumMismatch { offset : u64 },
+    #[error("malformed record of kind {kind} at offset {offset}")]
+    Malformed { offset : u64 , kind : u8 },
+    #[error("truncated record at offset {offset}")]
+    TornTail { offset : u64 },
+    #[error("sequence gap , expected {expected} got {got}")]
+    SequenceGap { expected : u64 , got : u64 },
+}
+
+#[derive(Debug , Clone , Copy)]
+pub enum InboundCommand{
+    NewOrder(Order),
+    CancelOrder(OrderToBeCanceled),
+    Query(Query),
+    Admin(AdminRequest),
+}
+
+impl InboundCommand{
+    fn kind(&self)->u8{
+        match self {
+            InboundCommand::NewOrder(_) => KIND_NEW_ORDER,
+            InboundCommand::CancelOrder(_) => KIND_CANCEL_ORDER,
+            InboundCommand::Query(_) => KIND_QUERY,
+            InboundCommand::Admin(_) => KIND_ADMIN,
+        }
+    }
+
+    fn encode_payload(&self , w : &mut ByteWriter){
+        match self {
+            InboundCommand::NewOrder(order)=>{
+                w.put_u64(order.user_id);
+                w.put_u64(order.order_id);
+                w.put_u8(match order.side {
+                    Side::Bid => 0,
+                    Side::Ask => 1
+                });
+                w.put_u8(order.order_type);
+                w.put_u32(order.shares_qty);
+                w.put_u64(order.price);
+                w.put_u64(order.timestamp);
+                w.put_u32(order.symbol);
+            }
+            InboundCommand::CancelOrder(cancel)=>{
+                w.put_u64(cancel.order_id);
+                w.put_u64(cancel.user_id);
+                w.put_u32(cancel.symbol);
+            }
+            InboundCommand::Query(query)=>{
+                w.put_u64(query.available_balance);
+                w.put_u64(query.reserved_balance);
+                w.put_u64(query.user_id);
+                w.put_u32(query.symbol);
+                w.put_u32(query.reserved_shares_qty);
+                w.put_u32(query.available_shares_qty);
+                w.put_u8(query.query_type);
+            }
+            InboundCommand::Admin(request)=>{
+                let command = &request.command;
+                w.put_u64(command.request_id);
+                w.put_u64(command.operator_id);
+                w.put_u64(command.user_id);
+                w.put_u64(command.available);
+                w.put_u64(command.reserved);
+                w.put_u32(command.symbol);
+                w.put_u8(command.command_type);
+                w.put_u8(command.log_level);
+                w.put_u8(command.asset);
+                w.put_u8(command.limit);
+                w.put_u8(request.authorized as u8);
+            }
+        }
+    }
+
+    fn decode_payload(kind : u8 , r : &mut ByteReader)->Option<Self>{
+        let command = match kind {
+            KIND_NEW_ORDER =>{
+                let user_id = r.get_u64()?;
+                let order_id = r.get_u64()?;
+                let side = match r.get_u8()? {
+                    0 => Side::Bid,
+                    1 => Side::Ask,
+                    _ => return None
+                };
+                let order_type = r.get_u8()?;
+                let shares_qty = r.get_u32()?;
+                let price = r.get_u64()?;
+                let timestamp = r.get_u64()?;
+                let symbol = r.get_u32()?;
+                InboundCommand::NewOrder(Order::new(user_id, order_id, side, order_type, shares_qty, price, timestamp, symbol))
+            }
+            KIND_CANCEL_ORDER =>{
+                InboundCommand::CancelOrder(OrderToBeCanceled {
+                    order_id: r.get_u64()?,
+                    user_id: r.get_u64()?,
+                    symbol: r.get_u32()?
+                })
+            }
+            KIND_QUERY =>{
+                InboundCommand::Query(Query {
+                    available_balance: r.get_u64()?,
+                    reserved_balance: r.get_u64()?,
+                    user_id: r.get_u64()?,
+                    symbol: r.get_u32()?,
+                    reserved_shares_qty: r.get_u32()?,
+                    available_shares_qty: r.get_u32()?,
+                    query_type: r.get_u8()?
+                })
+            }
+            KIND_ADMIN =>{
+                let command = AdminCommand {
+                    request_id: r.get_u64()?,
+                    operator_id: r.get_u64()?,
+                    user_id: r.get_u64()?,
+                    available: r.get_u64()?,
+                    reserved: r.get_u64()?,
+                    symbol: r.get_u32()?,
+                    command_type: r.get_u8()?,
+                    log_level: r.get_u8()?,
+                    asset: r.get_u8()?,
+                    limit: r.get_u8()?
+                };
+                let authorized = match r.get_u8()? {
+                    0 => false,
+                    1 => true,
+                    _ => return None
+                };
+                InboundCommand::Admin(AdminRequest { command , authorized })
+            }
+            _ => return None
+        };
+        // trailing bytes mean the writer and reader disagree on the layout
+        if r.remaining() != 0 {
+            return None;
+        }
+        Some(command)
+    }
+}
+
+#[derive(Debug , Clone , Copy)]
+pub struct JournalRecord{
+    pub sequence : u64 ,
+    pub timestamp : u64 ,   // nanos since epoch when the core accepted the command
+    pub command : InboundCommand,
+}
+
+impl JournalRecord{
+    pub fn new(sequence : u64 , command : InboundCommand)->Self{
+        Self::at(sequence, now_nanos(), command)
+    }
+
+    pub fn at(sequence : u64 , timestamp : u64 , command : InboundCommand)->Self{
+        Self { sequence , timestamp , command }
+    }
+
+    /// appends the full frame (header , payload , crc) to `buf`
+    pub fn encode_into(&self , buf : &mut Vec<u8>){
+        let start = buf.len();
+        {
+            let mut w = ByteWriter::new(buf);
+            w.put_u32(RECORD_MAGIC);
+            w.put_u8(self.command.kind());
+            w.put_u8(0);
+            w.put_u8(0);
+            w.put_u8(0);
+            w.put_u32(0); // payload len , patched below
+            w.put_u64(self.sequence);
+            w.put_u64(self.timestamp);
+            self.command.encode_payload(&mut w);
+        }
+        let payload_len = (buf.len() - start - HEADER_SIZE) as u32;
+        buf[start + 8..start + 12].copy_from_slice(&payload_len.to_le_bytes());
+        let crc = crc32fast::hash(&buf[start + 4..]);
+        buf.extend_from_slice(&crc.to_le_bytes());
+    }
+}
+
+pub fn now_nanos()->u64{
+    SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .unwrap()
+    .as_nanos() as u64
+}
+
+pub struct JournalReader<R : Read>{
+    src : R ,
+    offset : u64 ,
+    last_sequence : Option<u64>,
+    frame : Vec<u8>,
+}
+
+impl JournalReader<BufReader<File>>{
+    pub fn open<P : AsRef<Path>>(path : P)->Result<Self , JournalError>{
+        let file = File::open(path)?;
+        Ok(Self::new(BufReader::new(file)))
+    }
+}
+
+impl<R : Read> JournalReader<R>{
+    pub fn new(src : R)->Self{
+        Self { src , offset : 0 , last_sequence : None , frame : Vec::with_capacity(HEADER_SIZE + MAX_PAYLOAD_SIZE + CRC_SIZE) }
+    }
+
+    /// byte offset just past the last record that was read successfully
+    pub fn offset(&self)->u64{
+        self.offset
+    }
+
+    pub fn last_sequence(&self)->Option<u64>{
+        self.last_sequence
+    }
+
+    // like read_exact but tells us how much we got before eof instead of failing
+    fn fill(&mut self , from : usize , to : usize)->Result<usize , JournalError>{
+        let mut filled = from;
+        while filled < to {
+            match self.src.read(&mut self.frame[filled..to]) {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(JournalError::Io(e)),
+            }
+        }
+        Ok(filled - from)
+    }
+
+    /// Ok(None) on a clean end of journal
+    pub fn next_record(&mut self)->Result<Option<JournalRecord> , JournalError>{
+        let offset = self.offset;
+        self.frame.resize(HEADER_SIZE, 0);
+        let got = self.fill(0, HEADER_SIZE)?;
+        if got == 0 {
+            return Ok(None);
+        }
+        if got < HEADER_SIZE {
+            return Err(JournalError::TornTail { offset });
+        }
+
+        let (magic , kind , payload_len , sequence , timestamp) = {
+            let mut r = ByteReader::new(&self.frame);
+            let magic = r.get_u32().unwrap();
+            let kind = r.get_u8().unwrap();
+            r.get_u8();
+            r.get_u8();
+            r.get_u8();
+            let payload_len = r.get_u32().unwrap() as usize;
+            (magic , kind , payload_len , r.get_u64().unwrap() , r.get_u64().unwrap())
+        };
+        if magic != RECORD_MAGIC {
+            return Err(JournalError::BadMagic { offset , got : magic });
+        }
+        if payload_len > MAX_PAYLOAD_SIZE {
+            return Err(JournalError::Malformed { offset , kind });
+        }
+
+        let frame_len = HEADER_SIZE + payload_len + CRC_SIZE;
+        self.frame.resize(frame_len, 0);
+        if self.fill(HEADER_SIZE, frame_len)? < payload_len + CRC_SIZE {
+            return Err(JournalError::TornTail { offset });
+        }
+
+        let crc_start = HEADER_SIZE + payload_len;
+        let stored_crc = u32::from_le_bytes(self.frame[crc_start..].try_into().unwrap());
+        if crc32fast::hash(&self.frame[4..crc_start]) != stored_crc {
+            return Err(JournalError::ChecksumMismatch { offset });
+        }
+
+        let command = InboundCommand::decode_payload(kind, &mut ByteReader::new(&self.frame[HEADER_SIZE..crc_start]))
+        .ok_or(JournalError::Malformed { offset , kind })?;
+
+        if let Some(last) = self.last_sequence && sequence != last + 1 {
+            return Err(JournalError::SequenceGap { expected : last + 1 , got : sequence });
+        }
+        self.last_sequence = Some(sequence);
+        self.offset += frame_len as u64;
+
+        Ok(Some(JournalRecord { sequence , timestamp , command }))
+    }
+}
+
+pub struct JournalWriter{
+    file : BufWriter<File>,
+    path : PathBuf,
+    last_sequence : u64,
+    frame : Vec<u8>,
+    dirty : bool,
+}
+
+impl JournalWriter{
+    /// opens (or creates) the journal for appending
+    /// a half written record left by a crash is cut off , any other damage is an error
+    pub fn open<P : AsRef<Path>>(path : P)->Result<Self , JournalError>{
+        let path = path.as_ref().to_path_buf();
+        if let Some(parent) = path.parent() && !parent.as_os_str().is_empty() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let mut last_sequence = 0;
+        let mut valid_len = 0;
+        if path.exists() {
+            let mut reader = JournalReader::open(&path)?;
+            loop {
+                match reader.next_record() {
+                    Ok(Some(record)) => last_sequence = record.sequence,
+                    Ok(None) => break,
+                    Err(JournalError::TornTail { offset }) => {
+                        eprintln!("[Journal] dropping torn record at offset {}", offset);
+                        break;
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+            valid_len = reader.offset();
+        }
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)?;
+        if file.metadata()?.len() != valid_len {
+            file.set_len(valid_len)?;
+        }
+
+        Ok(Self {
+            file : BufWriter::with_capacity(1 << 20, file),
+            path ,
+            last_sequence ,
+            frame : Vec::with_capacity(HEADER_SIZE + MAX_PAYLOAD_SIZE + CRC_SIZE),
+            dirty : false
+        })
+    }
+
+    pub fn path(&self)->&Path{
+        &self.path
+    }
+
+    /// sequence of the last record in the journal , 0 when empty
+    pub fn last_sequence(&self)->u64{
+        self.last_sequence
+    }
+
+    #[cfg_attr(feature = "hotpath", hotpath::measure)]
+    pub fn append(&mut self , record : &JournalRecord)->Result<() , JournalError>{
+        if self.last_sequence != 0 && record.sequence != self.last_sequence + 1 {
+            return Err(JournalError::SequenceGap { expected : self.last_sequence + 1 , got : record.sequence });
+        }
+        self.frame.clear();
+        record.encode_into(&mut self.frame);
+        self.file.write_all(&self.frame)?;
+        self.last_sequence = record.sequence;
+        self.dirty = true;
+        Ok(())
+    }
+
+    /// hands buffered records to the os , they survive a process crash after this
+    pub fn flush(&mut self)->Result<() , JournalError>{
+        if self.dirty {
+            self.file.flush()?;
+            self.dirty = false;
+        }
+        Ok(())
+    }
+
+    /// flush and fdatasync , they survive a power loss after this
+    pub fn sync(&mut self)->Result<() , JournalError>{
+        self.flush()?;
+        self.file.get_ref().sync_data()?;
+        Ok(())
+    }
+}
+
+impl Drop for JournalWriter{
+    fn drop(&mut self){
+        if let Err(e) = self.sync() {
+            eprintln!("[Journal] failed to sync {:?} on close: {}", self.path, e);
+        }
+    }
+}
@@ -0,0 +1,5 @@
+pub mod checkpoint;
+pub mod codec;
+pub mod command_journal;
+pub mod replay;
+pub mod tests;
@@ -0,0 +1,193 @@
+// rebuilds engine books and balances by feeding the journal through TradingCore::apply_record
+// no shared memory and no redis , every outbound queue of the core ends in a sink we drain after each command
+use bounded_spsc_queue::Consumer;
+use std::path::Path;
+use crate::backpressure::policy::BackpressureConfig;
+use crate::config::settings::EngineConfig;
+use crate::journal::checkpoint::{list_checkpoints, restore_checkpoint, CheckpointError};
+use crate::journal::command_journal::{JournalError, JournalReader};
+use crate::logger::types::{BaseLogs, OrderBookSnapShot};
+use crate::orderbook::types::Event;
+use crate::shm::admin_response_queue::AdminResponse;
+use crate::shm::balance_response_queue::BalanceResponse;
+use crate::shm::event_queue::OrderEvents;
+use crate::shm::holdings_response_queue::HoldingResponse;
+use crate::shm::market_maker_feed::MarketMakerFeed;
+use crate::trading_core::my_trading_core::TradingCore;
+
+// big enough that a single command never fills a queue before we get to drain it
+const SINK_CAPACITY: usize = 1 << 16;
+
+pub struct ReplaySink {
+    pub order_events_from_bm: Consumer<OrderEvents>,
+    pub order_events_from_engine: Consumer<OrderEvents>,
+    pub events_to_publisher: Consumer<Event>,
+    pub balance_updates: Consumer<BalanceResponse>,
+    pub holding_updates: Consumer<HoldingResponse>,
+    pub logs: Consumer<BaseLogs>,
+    pub snapshots: Consumer<OrderBookSnapShot>,
+    pub market_maker_feed: Consumer<MarketMakerFeed>,
+    pub admin_responses: Consumer<AdminResponse>,
+}
+
+impl ReplaySink {
+    pub fn drain(&mut self) {
+        while self.order_events_from_bm.try_pop().is_some() {}
+        while self.order_events_from_engine.try_pop().is_some() {}
+        while self.events_to_publisher.try_pop().is_some() {}
+        while self.balance_updates.try_pop().is_some() {}
+        while self.holding_updates.try_pop().is_some() {}
+        while self.logs.try_pop().is_some() {}
+        while self.snapshots.try_pop().is_some() {}
+        while self.market_maker_feed.try_pop().is_some() {}
+        while self.admin_responses.try_pop().is_some() {}
+    }
+}
+
+// a trading core whose outputs go nowhere , starts from the bootstrap state of the default config
+pub fn detached_core() -> (TradingCore, ReplaySink) {
+    detached_core_with(&EngineConfig::default())
+}
+
+// the same with the balances and intervals of `config` , replays must use the config the journal was written under
+pub fn detached_core_with(config: &EngineConfig) -> (TradingCore, ReplaySink) {
+    let (order_event_producer_bm, order_events_from_bm) = bounded_spsc_queue::make::<OrderEvents>(SINK_CAPACITY);
+    let (event_producer_engine, events_to_publisher) = bounded_spsc_queue::make::<Event>(SINK_CAPACITY);
+    let (order_event_producer_engine, order_events_from_engine) = bounded_spsc_queue::make::<OrderEvents>(SINK_CAPACITY);
+    let (balance_event_producer_bm, balance_updates) = bounded_spsc_queue::make::<BalanceResponse>(SINK_CAPACITY);
+    let (holding_event_producer_bm, holding_updates) = bounded_spsc_queue::make::<HoldingResponse>(SINK_CAPACITY);
+    let (log_producer_core, logs) = bounded_spsc_queue::make::<BaseLogs>(SINK_CAPACITY);
+    let (snapshot_sender, snapshots) = bounded_spsc_queue::make::<OrderBookSnapShot>(SINK_CAPACITY);
+    let (mm_feed_sender, market_maker_feed) = bounded_spsc_queue::make::<MarketMakerFeed>(SINK_CAPACITY);
+    let (admin_response_sender, admin_responses) = bounded_spsc_queue::make::<AdminResponse>(SINK_CAPACITY);
+
+    let mut core = TradingCore::new(
+        order_event_producer_bm,
+        event_producer_engine,
+        order_event_producer_engine,
+        balance_event_producer_bm,
+        holding_event_producer_bm,
+        log_producer_core,
+        snapshot_sender,
+        mm_feed_sender,
+        admin_response_sender,
+        // the sink drains after every record , the default policies never get to act
+        &BackpressureConfig::default(),
+        config,
+    );
+    core.bootstrap_state();
+    // a throughput run journals orders of users it never opened through the admin commands
+    #[cfg(any(test, feature = "throughput-users"))]
+    core.add_throughput_users();
+
+    (core, ReplaySink {
+        order_events_from_bm,
+        order_events_from_engine,
+        events_to_publisher,
+        balance_updates,
+        holding_updates,
+        logs,
+        snapshots,
+        market_maker_feed,
+        admin_responses,
+    })
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ReplayStats {
+    pub records_applied: u64,
+    pub first_sequence: u64,
+    pub last_sequence: u64,
+    // last intact record in the journal , applied or not
+    pub journal_end_sequence: u64,
+}
+
+/// applies every record with after_sequence < sequence <= until_sequence (if given)
+/// a torn record at the end of the journal is where a crash cut us off , replay stops there
+pub fn replay_journal<P: AsRef<Path>>(
+    core: &mut TradingCore,
+    sink: &mut ReplaySink,
+    path: P,
+    after_sequence: u64,
+    until_sequence: Option<u64>,
+) -> Result<ReplayStats, JournalError> {
+    let mut stats = ReplayStats::default();
+    let mut reader = JournalReader::open(path)?;
+    loop {
+        let record = match reader.next_record() {
+            Ok(Some(record)) => record,
+            Ok(None) => break,
+            Err(JournalError::TornTail { offset }) => {
+                eprintln!("[Replay] stopping at torn record , offset {}", offset);
+                break;
+            }
+            Err(e) => return Err(e),
+        };
+        if record.sequence <= after_sequence {
+            continue;
+        }
+        if until_sequence.is_some_and(|until| record.sequence > until) {
+            break;
+        }
+        core.apply_record(&record);
+        sink.drain();
+
+        if stats.records_applied == 0 {
+            stats.first_sequence = record.sequence;
+        }
+        stats.last_sequence = record.sequence;
+        stats.records_applied += 1;
+    }
+    stats.journal_end_sequence = reader.last_sequence().unwrap_or(0);
+    Ok(stats)
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RecoveryStats {
+    pub checkpoint_sequence: Option<u64>,
+    pub replay: ReplayStats,
+}
+
+/// rebuilds the state of `core` without emitting anything downstream
+/// starts from the newest checkpoint in `checkpoint_dir` that loads cleanly (or the bootstrap state of `config`) and replays the journal after it
+pub fn recover<P: AsRef<Path>>(core: &mut TradingCore, config: &EngineConfig, journal_path: P, checkpoint_dir: Option<&Path>) -> Result<RecoveryStats, CheckpointError> {
+    let (mut shadow, mut sink) = detached_core_with(config);
+    let mut stats = RecoveryStats::default();
+
+    if let Some(dir) = checkpoint_dir {
+        for path in list_checkpoints(dir)? {
+            match restore_checkpoint(&mut shadow, &path) {
+                Ok(sequence) => {
+                    stats.checkpoint_sequence = Some(sequence);
+                    break;
+                }
+                // an older checkpoint plus a longer replay gets us to the same state
+                Err(e @ (CheckpointError::Corrupt(_) | CheckpointError::VersionMismatch { .. })) => {
+                    eprintln!("[Recovery] skipping checkpoint: {}", e);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        if stats.checkpoint_sequence.is_none() {
+            // a half restored shadow is not the bootstrap state any more
+            (shadow, sink) = detached_core_with(config);
+        }
+    }
+
+    if journal_path.as_ref().exists() {
+        let after_sequence = stats.checkpoint_sequence.unwrap_or(0);
+        stats.replay = replay_journal(&mut shadow, &mut sink, journal_path, after_sequence, None)?;
+        if stats.replay.journal_end_sequence != 0 && stats.replay.journal_end_sequence < after_sequence {
+            return Err(CheckpointError::JournalBehindCheckpoint { journal: stats.replay.journal_end_sequence, checkpoint: after_sequence });
+        }
+    }
+    // a ledger that does not add up is reported , the journal is still the truth and trading goes on
+    for mismatch in shadow.balance_manager.reconcile_reservations() {
+        eprintln!("[Recovery] reservation mismatch: {}", mismatch);
+    }
+    for unbalanced in shadow.balance_manager.check_conservation() {
+        eprintln!("[Recovery] {}", unbalanced);
+    }
+    core.adopt_state(shadow);
+    Ok(stats)
+}
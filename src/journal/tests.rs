@@ -0,0 +1,306 @@
+#[cfg(test)]
+mod tests {
+    use std::fs::OpenOptions;
+    use std::path::PathBuf;
+    use crate::config::settings::EngineConfig;
+    use crate::engine::my_engine::Engine;
+    use crate::journal::checkpoint::{list_checkpoints, restore_checkpoint};
+    use crate::journal::command_journal::{InboundCommand, JournalError, JournalReader, JournalRecord, JournalWriter};
+    use crate::journal::replay::{detached_core, recover, replay_journal};
+    use crate::orderbook::order::{Order, OrderToBeCanceled, Side};
+    use crate::shm::query_queue::Query;
+    use crate::admin::plane::AdminRequest;
+    use crate::shm::admin_command_queue::{AdminCommand, ADMIN_ADD_BOOK};
+    use crate::trading_core::my_trading_core::TradingCore;
+
+    fn journal_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("journal_test_{}_{}.journal", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    fn checkpoint_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("checkpoint_test_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn query(query_type: u8, user_id: u64, symbol: u32) -> Query {
+        Query {
+            available_balance: 0,
+            reserved_balance: 0,
+            user_id,
+            symbol,
+            reserved_shares_qty: 0,
+            available_shares_qty: 0,
+            query_type,
+        }
+    }
+
+    fn limit(user_id: u64, order_id: u64, side: Side, qty: u32, price: u64) -> InboundCommand {
+        InboundCommand::NewOrder(Order::new(user_id, order_id, side, 1, qty, price, order_id, 0))
+    }
+
+    fn sample_commands() -> Vec<InboundCommand> {
+        vec![
+            InboundCommand::Admin(AdminRequest { command: AdminCommand { symbol: 0, command_type: ADMIN_ADD_BOOK, ..AdminCommand::default() }, authorized: true }),
+            limit(20, 1, Side::Ask, 50, 10),
+            limit(20, 2, Side::Ask, 30, 11),
+            limit(20, 3, Side::Ask, 20, 12),
+            limit(10, 4, Side::Bid, 60, 11),
+            limit(10, 5, Side::Bid, 40, 9),
+            InboundCommand::CancelOrder(OrderToBeCanceled { order_id: 3, user_id: 20, symbol: 0 }),
+            InboundCommand::NewOrder(Order::new(10, 6, Side::Bid, 0, 10, 0, 6, 0)),
+            limit(10, 7, Side::Bid, 15, 8),
+            InboundCommand::Query(query(6, 10, 0)),
+        ]
+    }
+
+    // non empty levels in price order with their queue of (order_id , qty)
+    // cancel can leave an empty level behind , a checkpoint only carries orders so those are not compared
+    fn book_state(core: &TradingCore) -> Vec<(u64, Vec<(u64, u32)>)> {
+        let book = core.engine.get_book(0).unwrap();
+        let mut out = Vec::new();
+        for level in book.bidside.levels.values().chain(book.askside.levels.values()) {
+            let mut queue = Vec::new();
+            let mut cursor = level.head;
+            while let Some(index) = cursor {
+                let order = book.manager.get(index).unwrap();
+                queue.push((order.order_id, order.shares_qty));
+                cursor = order.next;
+            }
+            if !queue.is_empty() {
+                out.push((level.price, queue));
+            }
+        }
+        out
+    }
+
+    fn balance_state(core: &TradingCore, user_index: usize) -> (u64, u64, u32, u32) {
+        let balance = core.balance_manager.state.balances[user_index];
+        let holdings = &core.balance_manager.state.holdings[user_index];
+        (balance.available_balance, balance.reserved_balance, holdings.available(0), holdings.reserved(0))
+    }
+
+    #[test]
+    fn test_record_round_trip() {
+        let path = journal_path("round_trip");
+        let commands = sample_commands();
+        {
+            let mut writer = JournalWriter::open(&path).unwrap();
+            for (i, command) in commands.iter().enumerate() {
+                writer.append(&JournalRecord::new(i as u64 + 1, *command)).unwrap();
+            }
+        }
+
+        let mut reader = JournalReader::open(&path).unwrap();
+        let mut count = 0;
+        while let Some(record) = reader.next_record().unwrap() {
+            count += 1;
+            assert_eq!(record.sequence, count);
+            match (record.command, commands[count as usize - 1]) {
+                (InboundCommand::NewOrder(a), InboundCommand::NewOrder(b)) => {
+                    assert_eq!((a.order_id, a.user_id, a.side, a.order_type, a.shares_qty, a.price, a.symbol),
+                        (b.order_id, b.user_id, b.side, b.order_type, b.shares_qty, b.price, b.symbol));
+                }
+                (InboundCommand::CancelOrder(a), InboundCommand::CancelOrder(b)) => {
+                    assert_eq!((a.order_id, a.user_id, a.symbol), (b.order_id, b.user_id, b.symbol));
+                }
+                (InboundCommand::Query(a), InboundCommand::Query(b)) => {
+                    assert_eq!((a.query_type, a.user_id, a.symbol), (b.query_type, b.user_id, b.symbol));
+                }
+                (InboundCommand::Admin(a), InboundCommand::Admin(b)) => {
+                    assert_eq!((a.command.command_type, a.command.symbol, a.command.asset, a.command.limit, a.authorized), (b.command.command_type, b.command.symbol, b.command.asset, b.command.limit, b.authorized));
+                }
+                _ => panic!("command kind changed on the way through the journal"),
+            }
+        }
+        assert_eq!(count, commands.len() as u64);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_torn_tail_is_truncated_on_open() {
+        let path = journal_path("torn_tail");
+        {
+            let mut writer = JournalWriter::open(&path).unwrap();
+            for (i, command) in sample_commands().iter().enumerate() {
+                writer.append(&JournalRecord::new(i as u64 + 1, *command)).unwrap();
+            }
+        }
+        let full_len = std::fs::metadata(&path).unwrap().len();
+        OpenOptions::new().write(true).open(&path).unwrap().set_len(full_len - 5).unwrap();
+
+        let mut reader = JournalReader::open(&path).unwrap();
+        let mut last = 0;
+        let torn = loop {
+            match reader.next_record() {
+                Ok(Some(record)) => last = record.sequence,
+                Ok(None) => panic!("expected a torn tail"),
+                Err(e) => break e,
+            }
+        };
+        assert!(matches!(torn, JournalError::TornTail { .. }));
+        assert_eq!(last, sample_commands().len() as u64 - 1);
+
+        let writer = JournalWriter::open(&path).unwrap();
+        assert_eq!(writer.last_sequence(), last);
+        drop(writer);
+        assert!(std::fs::metadata(&path).unwrap().len() < full_len);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_corrupted_record_is_rejected() {
+        let path = journal_path("corrupt");
+        {
+            let mut writer = JournalWriter::open(&path).unwrap();
+            for (i, command) in sample_commands().iter().enumerate() {
+                writer.append(&JournalRecord::new(i as u64 + 1, *command)).unwrap();
+            }
+        }
+        let mut bytes = std::fs::read(&path).unwrap();
+        // inside the payload of the first record
+        bytes[30] ^= 0xFF;
+        std::fs::write(&path, &bytes).unwrap();
+
+        let mut reader = JournalReader::open(&path).unwrap();
+        assert!(matches!(reader.next_record(), Err(JournalError::ChecksumMismatch { offset: 0 })));
+        assert!(JournalWriter::open(&path).is_err());
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_replay_rebuilds_identical_state() {
+        let path = journal_path("replay");
+        let (mut live, mut live_sink) = detached_core();
+        live.journal = Some(JournalWriter::open(&path).unwrap());
+        for command in sample_commands() {
+            live.submit(command);
+            live_sink.drain();
+        }
+        live.journal = None;
+
+        let (mut replayed, mut sink) = detached_core();
+        let stats = replay_journal(&mut replayed, &mut sink, &path, 0, None).unwrap();
+
+        assert_eq!(stats.records_applied, sample_commands().len() as u64);
+        assert_eq!(replayed.sequence, live.sequence);
+        assert_eq!(book_state(&replayed), book_state(&live));
+        assert_eq!(replayed.engine.get_book(0).unwrap().last_trade_price, live.engine.get_book(0).unwrap().last_trade_price);
+        for user_index in 0..3 {
+            assert_eq!(balance_state(&replayed, user_index), balance_state(&live, user_index));
+        }
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_replay_stops_at_requested_sequence() {
+        let path = journal_path("replay_until");
+        let (mut live, mut live_sink) = detached_core();
+        live.journal = Some(JournalWriter::open(&path).unwrap());
+        for command in sample_commands().into_iter().take(4) {
+            live.submit(command);
+            live_sink.drain();
+        }
+        let partial = book_state(&live);
+        for command in sample_commands().into_iter().skip(4) {
+            live.submit(command);
+            live_sink.drain();
+        }
+        live.journal = None;
+
+        let (mut replayed, mut sink) = detached_core();
+        let stats = replay_journal(&mut replayed, &mut sink, &path, 0, Some(4)).unwrap();
+        assert_eq!(stats.last_sequence, 4);
+        assert_eq!(book_state(&replayed), partial);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_checkpoint_round_trip() {
+        let dir = checkpoint_dir("round_trip");
+        let (mut live, mut live_sink) = detached_core();
+        live.checkpoint_dir = Some(dir.clone());
+        for command in sample_commands() {
+            live.submit(command);
+            live_sink.drain();
+        }
+        let path = live.checkpoint().unwrap().unwrap();
+
+        let (mut restored, _sink) = detached_core();
+        assert_eq!(restore_checkpoint(&mut restored, &path).unwrap(), live.sequence);
+        assert_eq!(restored.sequence, live.sequence);
+        assert_eq!(restored.engine.book_count, live.engine.book_count);
+        assert_eq!(book_state(&restored), book_state(&live));
+        for user_index in 0..3 {
+            assert_eq!(balance_state(&restored, user_index), balance_state(&live, user_index));
+        }
+        assert_eq!(restored.balance_manager.state.next_free_slot, live.balance_manager.state.next_free_slot);
+        assert_eq!(restored.balance_manager.state.user_id_to_index.len(), live.balance_manager.state.user_id_to_index.len());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_recover_from_checkpoint_and_journal_tail() {
+        let path = journal_path("checkpoint_tail");
+        let dir = checkpoint_dir("tail");
+        let (mut live, mut live_sink) = detached_core();
+        live.journal = Some(JournalWriter::open(&path).unwrap());
+        live.checkpoint_dir = Some(dir.clone());
+        for command in sample_commands().into_iter().take(4) {
+            live.submit(command);
+            live_sink.drain();
+        }
+        live.checkpoint().unwrap();
+        for command in sample_commands().into_iter().skip(4) {
+            live.submit(command);
+            live_sink.drain();
+        }
+        live.journal = None;
+
+        let (mut recovered, _sink) = detached_core();
+        let stats = recover(&mut recovered, &EngineConfig::default(), &path, Some(&dir)).unwrap();
+        assert_eq!(stats.checkpoint_sequence, Some(4));
+        assert_eq!(stats.replay.records_applied, sample_commands().len() as u64 - 4);
+        assert_eq!(recovered.sequence, live.sequence);
+        assert_eq!(book_state(&recovered), book_state(&live));
+        for user_index in 0..3 {
+            assert_eq!(balance_state(&recovered, user_index), balance_state(&live, user_index));
+        }
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_corrupt_checkpoint_falls_back_to_older_one() {
+        let path = journal_path("checkpoint_fallback");
+        let dir = checkpoint_dir("fallback");
+        let (mut live, mut live_sink) = detached_core();
+        live.journal = Some(JournalWriter::open(&path).unwrap());
+        live.checkpoint_dir = Some(dir.clone());
+        for (i, command) in sample_commands().into_iter().enumerate() {
+            live.submit(command);
+            live_sink.drain();
+            if i == 2 || i == 6 {
+                live.checkpoint().unwrap();
+            }
+        }
+        live.journal = None;
+
+        let newest = list_checkpoints(&dir).unwrap().remove(0);
+        let mut bytes = std::fs::read(&newest).unwrap();
+        bytes[20] ^= 0xFF;
+        std::fs::write(&newest, &bytes).unwrap();
+
+        let (mut recovered, _sink) = detached_core();
+        let stats = recover(&mut recovered, &EngineConfig::default(), &path, Some(&dir)).unwrap();
+        assert_eq!(stats.checkpoint_sequence, Some(3));
+        assert_eq!(book_state(&recovered), book_state(&live));
+        for user_index in 0..3 {
+            assert_eq!(balance_state(&recovered, user_index), balance_state(&live, user_index));
+        }
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -5,4 +5,16 @@ pub mod shm ;
 pub mod balance_manager;
 pub mod singlepsinglecq;
 pub mod pubsub;
-pub mod logger;
\ No newline at end of file
+pub mod logger;
+pub mod journal;
+pub mod trading_core;
+pub mod replication;
+pub mod digest;
+pub mod shutdown;
+pub mod backpressure;
+pub mod admin;
+pub mod config;
+pub mod metrics;
+pub mod watchdog;
+pub mod supervisor;
+pub mod sharding;
@@ -1,25 +1,37 @@
 use bounded_spsc_queue::Consumer;
-use crate::{logger::types::{BalanceLogWrapper, BaseLogs, HoldingLogWrapper, OrderBookSnapShot, OrderLogWrapper, TradeLogs}, shm::{balance_log_queue::BalanceLogQueue, holdings_log_queue::{self, HoldingLogQueue}, order_log_queue::OrderLogQueue, snapshot_queue::{self, OrderBookSnapShotQueue}, trade_log_queue::TradeLogQueue}};
+use crate::{logger::types::{BalanceLogWrapper, BaseLogs, HoldingLogWrapper, OrderBookSnapShot, OrderLogWrapper, PositionReport, TradeLogs}, shm::{balance_log_queue::BalanceLogQueue, holdings_log_queue::{self, HoldingLogQueue}, order_log_queue::OrderLogQueue, position_queue::PositionQueue, snapshot_queue::{self, OrderBookSnapShotQueue}, trade_log_queue::TradeLogQueue}};
+use crate::shutdown::shutdown_signal::ShutdownHandle;
+use crate::backpressure::policy::{channels, BackpressureConfig};
+use crate::config::settings::QueuePaths;
+use crate::backpressure::sender::PolicySender;
+use crate::sharding::fan_in::FanIn;
+use crate::watchdog::heartbeat::Heartbeat;
+use crate::supervisor::thread_supervisor::{RestartError, Worker};
 use std::time::{SystemTime, UNIX_EPOCH};
 pub struct LogReciever{
-    pub order_log_shm_queue : OrderLogQueue,
-    pub balance_log_shm_queue : BalanceLogQueue ,
-    pub holding_log_shm_queue : HoldingLogQueue ,
-    pub trade_log_queue       : TradeLogQueue,
-    pub snap_shot_queue       : OrderBookSnapShotQueue,
+    pub order_log_shm_queue : PolicySender<OrderLogWrapper , OrderLogQueue>,
+    pub balance_log_shm_queue : PolicySender<BalanceLogWrapper , BalanceLogQueue> ,
+    pub holding_log_shm_queue : PolicySender<HoldingLogWrapper , HoldingLogQueue> ,
+    pub trade_log_queue       : PolicySender<TradeLogs , TradeLogQueue>,
+    pub snap_shot_queue       : PolicySender<OrderBookSnapShot , OrderBookSnapShotQueue>,
+    pub position_queue        : PolicySender<PositionReport , PositionQueue>,
     pub logs_recv_from_core : Consumer<BaseLogs> , 
     pub logs_recv_from_publisher : Consumer<TradeLogs>,
-    pub snapshot_recv : Consumer<OrderBookSnapShot>
-
+    // one input per engine , a single one outside sharded mode
+    pub snapshot_recv : FanIn<OrderBookSnapShot>,
+    pub heartbeat : Option<Heartbeat>,
+    // reopened after a panic
+    pub queues : QueuePaths,
 }
 
 impl LogReciever{
-    pub fn new(logs_recv_from_core : Consumer<BaseLogs> , logs_recv_from_publisher : Consumer<TradeLogs> , snapshot_recv : Consumer<OrderBookSnapShot>)->Self{
-        let order_log_shm_queue = OrderLogQueue::open("/tmp/OrderLogs");
-        let balance_log_shm_queue = BalanceLogQueue::open("/tmp/BalanceLogs");
-        let holdings_log_queue = HoldingLogQueue::open("/tmp/HoldingLogs");
-        let trade_log_queue = TradeLogQueue::open("/tmp/TradeLogs");
-        let snapshot_queue = OrderBookSnapShotQueue::open("/tmp/SnapShot");
+    pub fn new(logs_recv_from_core : Consumer<BaseLogs> , logs_recv_from_publisher : Consumer<TradeLogs> , snapshot_recv : impl Into<FanIn<OrderBookSnapShot>> , backpressure : &BackpressureConfig , queues : &QueuePaths)->Self{
+        let order_log_shm_queue = OrderLogQueue::open(&queues.order_logs);
+        let balance_log_shm_queue = BalanceLogQueue::open(&queues.balance_logs);
+        let holdings_log_queue = HoldingLogQueue::open(&queues.holding_logs);
+        let trade_log_queue = TradeLogQueue::open(&queues.trade_logs);
+        let snapshot_queue = OrderBookSnapShotQueue::open(&queues.snapshot);
+        let position_queue = PositionQueue::open(&queues.positions);
         if order_log_shm_queue.is_err(){
             eprintln!("failed to open the order log queue");
         }
@@ -35,26 +47,42 @@ impl LogReciever{
         if snapshot_queue.is_err(){
             eprintln!("failed to open the trade log queue");
         }
+        if position_queue.is_err(){
+            eprintln!("failed to open the position queue");
+        }
 
         Self{
-            order_log_shm_queue :   order_log_shm_queue.unwrap(),
-            balance_log_shm_queue : balance_log_shm_queue.unwrap(),
-            holding_log_shm_queue : holdings_log_queue.unwrap(),
+            order_log_shm_queue :   PolicySender::from_config(order_log_shm_queue.unwrap(), channels::LOGGER_ORDER_LOGS, backpressure),
+            balance_log_shm_queue : PolicySender::from_config(balance_log_shm_queue.unwrap(), channels::LOGGER_BALANCE_LOGS, backpressure),
+            holding_log_shm_queue : PolicySender::from_config(holdings_log_queue.unwrap(), channels::LOGGER_HOLDING_LOGS, backpressure),
             logs_recv_from_core  ,
-            trade_log_queue : trade_log_queue.unwrap() , 
+            trade_log_queue : PolicySender::from_config(trade_log_queue.unwrap(), channels::LOGGER_TRADE_LOGS, backpressure) , 
             logs_recv_from_publisher ,
-            snapshot_recv , 
-            snap_shot_queue : snapshot_queue.unwrap()
+            snapshot_recv : snapshot_recv.into() , 
+            snap_shot_queue : PolicySender::from_config(snapshot_queue.unwrap(), channels::LOGGER_SNAPSHOTS, backpressure),
+            position_queue : PolicySender::from_config(position_queue.unwrap(), channels::LOGGER_POSITIONS, backpressure),
+            heartbeat : None,
+            queues : queues.clone(),
         }
     }
 
-    pub fn run(&mut self){
+    // runs until shutdown and every log already produced upstream is in shared memory
+    pub fn run(&mut self , shutdown : &ShutdownHandle){
         loop {
+            if let Some(heartbeat) = self.heartbeat.as_mut() {
+                heartbeat.beat_with_depths(|| [
+                    self.logs_recv_from_core.size() as u64,
+                    self.logs_recv_from_publisher.size() as u64,
+                    self.snapshot_recv.size() as u64,
+                ]);
+            }
+            let mut did_work = false;
             if let Some(log) = self.logs_recv_from_core.try_pop(){
+                did_work = true;
                 // we get the deltas , we need to wrap them
                 match log{
                     BaseLogs::BalanceDelta(balance_delta)=>{
-                        let _ = self.balance_log_shm_queue.enqueue(BalanceLogWrapper{
+                        let _ = self.balance_log_shm_queue.send(BalanceLogWrapper{
                             balance_delta : balance_delta ,
                             timestamp : SystemTime::now()
                             .duration_since(UNIX_EPOCH)
@@ -64,7 +92,7 @@ impl LogReciever{
                         });
                     }
                     BaseLogs::HoldingDelta(holdings_delta)=>{
-                        let _ = self.holding_log_shm_queue.enqueue(HoldingLogWrapper{
+                        let _ = self.holding_log_shm_queue.send(HoldingLogWrapper{
                             holding_delta : holdings_delta ,
                             timestamp : SystemTime::now()
                             .duration_since(UNIX_EPOCH)
@@ -73,8 +101,15 @@ impl LogReciever{
                             severity : 0 
                         });
                     }
+                    BaseLogs::Position(mut report)=>{
+                        report.timestamp = SystemTime::now()
+                            .duration_since(UNIX_EPOCH)
+                            .unwrap()
+                            .as_nanos() as i64;
+                        let _ = self.position_queue.send(report);
+                    }
                     BaseLogs::OrderDelta(order_delta)=>{
-                        let _ = self.order_log_shm_queue.enqueue(OrderLogWrapper{
+                        let _ = self.order_log_shm_queue.send(OrderLogWrapper{
                             order_delta : order_delta ,
                             timestamp : SystemTime::now()
                             .duration_since(UNIX_EPOCH)
@@ -87,13 +122,48 @@ impl LogReciever{
             }
 
             if let Some(trade_log) = self.logs_recv_from_publisher.try_pop(){
-                let _ = self.trade_log_queue.enqueue(trade_log);
+                let _ = self.trade_log_queue.send(trade_log);
+                did_work = true;
             }
 
             if let Some(orderbook_snapshot) = self.snapshot_recv.try_pop(){
                 println!("recieved snapshot , enqueing");
-                let _ = self.snap_shot_queue.enqueue(orderbook_snapshot);
+                let _ = self.snap_shot_queue.send(orderbook_snapshot);
+                did_work = true;
+            }
+
+            if !did_work
+            && shutdown.upstream_finished()
+            && self.logs_recv_from_core.size() == 0
+            && self.logs_recv_from_publisher.size() == 0
+            && self.snapshot_recv.size() == 0 {
+                break;
             }
         }
+        self.heartbeat = None;
+        shutdown.finish();
     }
-}
\ No newline at end of file
+}
+
+// the shm log queues are mapped again , parked logs and channel stats carry over
+impl Worker for LogReciever{
+    fn run(&mut self , shutdown : &ShutdownHandle){
+        LogReciever::run(self, shutdown);
+    }
+    fn restart(&mut self)->Result<() , RestartError>{
+        // every queue module has a QueueError of its own
+        fn reopen<E : std::fmt::Debug>(what : &'static str)->impl FnOnce(E)->RestartError{
+            move |e| RestartError::reopen("logger", what, e)
+        }
+        self.order_log_shm_queue.replace_inner(OrderLogQueue::open(&self.queues.order_logs).map_err(reopen("order_logs"))?);
+        self.balance_log_shm_queue.replace_inner(BalanceLogQueue::open(&self.queues.balance_logs).map_err(reopen("balance_logs"))?);
+        self.holding_log_shm_queue.replace_inner(HoldingLogQueue::open(&self.queues.holding_logs).map_err(reopen("holding_logs"))?);
+        self.trade_log_queue.replace_inner(TradeLogQueue::open(&self.queues.trade_logs).map_err(reopen("trade_logs"))?);
+        self.snap_shot_queue.replace_inner(OrderBookSnapShotQueue::open(&self.queues.snapshot).map_err(reopen("snapshot"))?);
+        self.position_queue.replace_inner(PositionQueue::open(&self.queues.positions).map_err(reopen("positions"))?);
+        Ok(())
+    }
+    fn heartbeat(&self)->Option<&Heartbeat>{
+        self.heartbeat.as_ref()
+    }
+}
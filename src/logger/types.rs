@@ -47,6 +47,20 @@ pub struct OrderBookSnapShot{
 
 
 
+// why a BalanceDelta or HoldingDelta was logged
+pub const DELTA_REASON_LOCK : u8 = 0;
+pub const DELTA_REASON_FILL : u8 = 1;
+// reserved funds given back : price improvement , the rest of a finished order , a cancel
+pub const DELTA_REASON_RELEASE : u8 = 2;
+// funds movements , the delta's order_id carries the movement's idempotency key
+pub const DELTA_REASON_DEPOSIT : u8 = 3;
+pub const DELTA_REASON_WITHDRAWAL : u8 = 4;
+pub const DELTA_REASON_TRANSFER : u8 = 5;
+// an operator overwrite of a balance or holding , posted against the system account
+pub const DELTA_REASON_ADJUSTMENT : u8 = 6;
+// a trading fee or maker rebate , the delta's order_id is the order that paid or earned it
+pub const DELTA_REASON_FEE : u8 = 7;
+
 #[derive( Debug, Clone, Copy)]
 pub struct BalanceDelta{
     pub event_id: u64,
@@ -54,8 +68,8 @@ pub struct BalanceDelta{
     pub delta_available: i64,
     pub delta_reserved: i64,
     pub order_id: u64,     // the taker order id which caused the balance updations 
-    pub reason: u8,      // reso for the balance update , either balances locked = 0 , or funds updated =1
-   
+    pub reason: u8,      // reso for the balance update , balances locked = 0 , funds updated = 1 , reserve released = 2 , deposit 3 , withdrawal 4 , transfer 5
+    pub asset: u32,      // 0 the cash balance , otherwise the wallet asset that moved
 }
 
 #[derive(Debug ,Copy, Clone)]
@@ -87,9 +101,29 @@ pub struct OrderDelta{
 pub enum BaseLogs{
     BalanceDelta(BalanceDelta) ,
     HoldingDelta(HoldingDelta) ,
-    OrderDelta(OrderDelta)
+    OrderDelta(OrderDelta) ,
+    Position(PositionReport)
+}
+
+// one user's position in one symbol , answered to a position query and sent for every position with the snapshots
+#[repr(C)]
+#[derive(Debug , Clone , Copy , Default , PartialEq , Eq)]
+pub struct PositionReport{
+    pub timestamp              : i64 ,  // stamped by the logger
+    pub event_id               : u64 ,
+    pub user_id                : u64 ,
+    pub qty                    : i64 ,  // short below zero
+    pub avg_entry_price        : u64 ,
+    pub mark_price             : u64 ,  // 0 before the symbol has a mark or a trade
+    pub realized_pnl           : i64 ,
+    pub unrealized_pnl         : i64 ,
+    pub symbol                 : u32 ,
+    pub kind                   : u32 ,  // POSITION_REPORT_* below
 }
 
+pub const POSITION_REPORT_QUERY : u32 = 0;
+pub const POSITION_REPORT_SNAPSHOT : u32 = 1;
+
 
 #[repr(C)]
 #[derive(Debug , Clone , Copy)]
@@ -100,6 +134,9 @@ pub struct TradeLogs{
     pub price           : u64 ,
     pub symbol          : u32 ,
     pub quantity        : u32 ,
+    // negative for a rebate
+    pub buyer_fee       : i64 ,
+    pub seller_fee      : i64 ,
     pub is_buyer_maker  : bool,
 }
 
@@ -11,6 +11,7 @@ use rust_orderbook_2::pubsub::pubsub_manager::RedisPubSubManager;
 use rust_orderbook_2::shm::fill_queue_mm::MarketMakerFill;
 use rust_orderbook_2::shm::holdings_response_queue::{HoldingResQueue, HoldingResponse};
 use rust_orderbook_2::shm::market_maker_feed::MarketMakerFeed;
+use rust_orderbook_2::shm::admin_response_queue::AdminResponse;
 use rust_orderbook_2::shm::queue::IncomingOrderQueue;
 use rust_orderbook_2::shm::cancel_orders_queue::CancelOrderQueue;
 use rust_orderbook_2::shm::event_queue::{OrderEventQueue, OrderEvents};
@@ -18,48 +19,72 @@ use rust_orderbook_2::shm::query_queue::QueryQueue;
 use rust_orderbook_2::shm::balance_response_queue::{BalanceResQueue, BalanceResponse};
 use rust_orderbook_2::shm::reader::ShmReader;
 use rust_orderbook_2::shm::writer::ShmWriter;
+use rust_orderbook_2::shutdown::shutdown_signal::{Shutdown, Stage};
+use rust_orderbook_2::backpressure::policy::BackpressureConfig;
+use rust_orderbook_2::config::settings::EngineConfig;
 use bounded_spsc_queue;
 
 #[hotpath::main]
 fn main(){
     // initilaise all queues once , mmap with the virtual adddress space of this process 
     // threads can indivisually open the queues (SPSC)
-    let _ = IncomingOrderQueue::create("/tmp/IncomingOrders").expect("failed to create queue");
-    let _ = CancelOrderQueue::create("/tmp/CancelOrders").expect("failed to create queue");
-    let _ = OrderEventQueue::create("/tmp/OrderEvents").expect("failed to create queue");
-    let _ = QueryQueue::create("/tmp/Queries").expect("failed to create queue");
-    let _ = HoldingResQueue::create("/tmp/HoldingsResponse").expect("failed to create queue");
-    let _ = BalanceResQueue::create("/tmp/BalanceResponse").expect("failed to open queue");
+    let config = EngineConfig::load().unwrap_or_else(|e| panic!("[Main] {}", e));
+    let queues = &config.queues;
+    let capacity = config.capacities.channel;
+    let _ = IncomingOrderQueue::create(&queues.incoming_orders).expect("failed to create queue");
+    let _ = CancelOrderQueue::create(&queues.cancel_orders).expect("failed to create queue");
+    let _ = OrderEventQueue::create(&queues.order_events).expect("failed to create queue");
+    let _ = QueryQueue::create(&queues.queries).expect("failed to create queue");
+    let _ = HoldingResQueue::create(&queues.holdings_response).expect("failed to create queue");
+    let _ = BalanceResQueue::create(&queues.balance_response).expect("failed to open queue");
 
     
-    let (fill_producer_engine , fill_consumer_bm ) = bounded_spsc_queue::make::<Fills>(32768);
-    let (event_producer_engine , event_consumer_publisher) = bounded_spsc_queue::make::<Event>(32768);
-    let (order_producer_bm , order_consumer_engine) = bounded_spsc_queue::make::<Order>(32768);
-    let (order_producer_shm_reader , order_consumer_bm) = bounded_spsc_queue::make::<Order>(32768);
-    let (order_event_producer_bm , order_event_consumer_writter_from_bm) = bounded_spsc_queue::make::<OrderEvents>(32768);
-    let (order_event_producer_publisher , order_event_consumer_writter_from_publisher) = bounded_spsc_queue::make::<OrderEvents>(32768);
-    let (order_event_producer_engine , order_event_consumer_writter_from_engine) = bounded_spsc_queue::make::<OrderEvents>(32768);
-    let (balance_event_producer_bm , balance_event_consumer_writter) = bounded_spsc_queue::make::<BalanceResponse>(32768);
-    let (holding_event_producer_bm , holding_event_consumer_writter) = bounded_spsc_queue::make::<HoldingResponse>(32768);
-    let (trade_log_producer_publisher , _)= bounded_spsc_queue::make::<TradeLogs>(32768);
-    let (mm_fill_sender , mm_fill_reciever)= bounded_spsc_queue::make::<MarketMakerFill>(32768);
-    let (_ , mm_feed_receiver) = bounded_spsc_queue::make::<MarketMakerFeed>(32768);
-
-
+    let (fill_producer_engine , fill_consumer_bm ) = bounded_spsc_queue::make::<Fills>(capacity);
+    let (event_producer_engine , event_consumer_publisher) = bounded_spsc_queue::make::<Event>(capacity);
+    let (order_producer_bm , order_consumer_engine) = bounded_spsc_queue::make::<Order>(capacity);
+    let (order_producer_shm_reader , order_consumer_bm) = bounded_spsc_queue::make::<Order>(capacity);
+    let (order_event_producer_bm , order_event_consumer_writter_from_bm) = bounded_spsc_queue::make::<OrderEvents>(capacity);
+    let (order_event_producer_publisher , order_event_consumer_writter_from_publisher) = bounded_spsc_queue::make::<OrderEvents>(capacity);
+    let (order_event_producer_engine , order_event_consumer_writter_from_engine) = bounded_spsc_queue::make::<OrderEvents>(capacity);
+    let (balance_event_producer_bm , balance_event_consumer_writter) = bounded_spsc_queue::make::<BalanceResponse>(capacity);
+    let (holding_event_producer_bm , holding_event_consumer_writter) = bounded_spsc_queue::make::<HoldingResponse>(capacity);
+    let (trade_log_producer_publisher , _)= bounded_spsc_queue::make::<TradeLogs>(capacity);
+    let (mm_fill_sender , mm_fill_reciever)= bounded_spsc_queue::make::<MarketMakerFill>(capacity);
+    let (_ , mm_feed_receiver) = bounded_spsc_queue::make::<MarketMakerFeed>(capacity);
+    let (_ , admin_response_receiver) = bounded_spsc_queue::make::<AdminResponse>(capacity);
+
+
+    let shutdown = Shutdown::new();
+    shutdown.register_signals().expect("failed to register shutdown signals");
+    let reader_shutdown = shutdown.handle(Stage::Reader, &[]);
+    let balance_manager_shutdown = shutdown.handle(Stage::BalanceManager, &[Stage::Reader, Stage::Engine]);
+    let publisher_shutdown = shutdown.handle(Stage::Publisher, &[Stage::Engine]);
+    let writter_shutdown = shutdown.handle(Stage::Writer, &[Stage::BalanceManager, Stage::Publisher, Stage::Engine]);
+    // the engine threads below are commented out , nothing will ever come from that stage
+    shutdown.finish(Stage::Engine);
+
+    let backpressure = BackpressureConfig::from_env().unwrap_or_else(|e| panic!("[Main] bad BACKPRESSURE setting: {}", e));
+    let publisher_backpressure = backpressure.clone();
+    let writter_backpressure = backpressure;
+
+    let reader_core = config.cores.trading_core;
+    let reader_queue = queues.incoming_orders.clone();
     let shm_reader_handle = std::thread::spawn(move || {
-        core_affinity::set_for_current(core_affinity::CoreId { id: 2 });
+        core_affinity::set_for_current(core_affinity::CoreId { id: reader_core });
 
         let mut my_shm_reader = ShmReader::new(      
-            order_producer_shm_reader
+            order_producer_shm_reader ,
+            &reader_queue
         ).unwrap();
 
-        my_shm_reader.run_reader();
+        my_shm_reader.run_reader(&reader_shutdown);
     });
 
     // BALANCE MANAGER HANDLE 
 
+    let balance_manager_config = config.clone();
     let balance_manager_handle = std::thread::spawn(move || {
-        core_affinity::set_for_current(core_affinity::CoreId { id: 6 });
+        core_affinity::set_for_current(core_affinity::CoreId { id: balance_manager_config.cores.balance_manager });
 
         let mut my_balance_manager = MyBalanceManager2::new(
             fill_consumer_bm , 
@@ -67,11 +92,13 @@ fn main(){
             order_producer_bm,
             order_event_producer_bm,
             balance_event_producer_bm,
-            holding_event_producer_bm
+            holding_event_producer_bm,
+            &balance_manager_config.queues,
+            balance_manager_config.balances
         );
 
         my_balance_manager.add_throughput_test_users();
-        my_balance_manager.run_balance_manager();
+        my_balance_manager.run_balance_manager(&balance_manager_shutdown);
     });
 
     // ENGINE HANDLES 
@@ -96,27 +123,30 @@ fn main(){
 
 
 
-    let pubsub_connection = RedisPubSubManager::new("redis://localhost:6379");
+    let pubsub_connection = RedisPubSubManager::new(&config.redis.url);
     if pubsub_connection.is_err(){
         panic!("pubsub error , not initialising publisher");
     }
     //PUBLISHER REQUIRES AN EVENT RECV ONLY 
+    let publisher_core = config.cores.publisher;
     let publisher_handle = std::thread::spawn(move || {
-        core_affinity::set_for_current(core_affinity::CoreId { id: 5 });
+        core_affinity::set_for_current(core_affinity::CoreId { id: publisher_core });
 
         let mut my_publisher = EventPublisher::new(
             
             pubsub_connection.unwrap() , 
             
             event_consumer_publisher,
-            order_event_producer_publisher , trade_log_producer_publisher,mm_fill_sender
+            order_event_producer_publisher , trade_log_producer_publisher,mm_fill_sender ,
+            &publisher_backpressure
         );
 
-        my_publisher.start_publisher();
+        my_publisher.start_publisher(&publisher_shutdown);
     });
     // SHM WRITTER TO WRITE TO QUEUES 
+    let writter_config = config.clone();
     let writter_handle = std::thread::spawn(move|| {
-        core_affinity::set_for_current(core_affinity::CoreId { id: 7 });
+        core_affinity::set_for_current(core_affinity::CoreId { id: writter_config.cores.writer });
         let  shm_writter = ShmWriter::new(
             order_event_consumer_writter_from_bm,
             order_event_consumer_writter_from_publisher,
@@ -124,10 +154,13 @@ fn main(){
             balance_event_consumer_writter,
             holding_event_consumer_writter,
             mm_fill_reciever,
-            mm_feed_receiver
+            mm_feed_receiver,
+            admin_response_receiver,
+            &writter_backpressure,
+            &writter_config.queues
         );
         if shm_writter.is_some(){
-            shm_writter.unwrap().start_shm_writter();
+            shm_writter.unwrap().start_shm_writter(&writter_shutdown);
         }
         else{
             eprintln!("error initialising shm writter")
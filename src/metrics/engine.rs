@@ -0,0 +1,169 @@
+// what the trading core (or the risk thread when sharded) and the engines report
+// counters are bumped inline , the per symbol book gauges are refreshed on the metrics interval
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use rustc_hash::FxHashMap;
+use crate::balance_manager::rate_limits::RateAction;
+use crate::engine::my_engine::STEngine;
+use crate::metrics::registry::{registry, Counter, Gauge, Histogram};
+use crate::shm::event_queue::*;
+
+// commands taken off the shm queues per loop iteration
+pub const BATCH_SIZE_BOUNDS : [u64 ; 8] = [0, 1, 8, 32, 128, 256, 512, 1000];
+// the reject reasons are numbered from 1 up to the last one
+const REJECT_REASONS : usize = REJECT_REASON_AMEND_UNSUPPORTED as usize + 1;
+
+pub fn reject_reason_label(reason : u32)->&'static str{
+    match reason {
+        REJECT_REASON_FUNDS => "funds",
+        REJECT_REASON_HALTED => "halted",
+        REJECT_REASON_UNLISTED => "unlisted",
+        REJECT_REASON_ORDER_QTY => "order_qty",
+        REJECT_REASON_ORDER_NOTIONAL => "order_notional",
+        REJECT_REASON_OPEN_ORDERS => "open_orders",
+        REJECT_REASON_POSITION => "position",
+        REJECT_REASON_DAILY_NOTIONAL => "daily_notional",
+        REJECT_REASON_PRICE_DEVIATION => "price_deviation",
+        REJECT_REASON_THROTTLED => "throttled",
+        REJECT_REASON_USER_FROZEN => "user_frozen",
+        REJECT_REASON_TRADING_STOPPED => "trading_stopped",
+        REJECT_REASON_NOT_OWNER => "not_owner",
+        REJECT_REASON_UNKNOWN_ORDER => "unknown_order",
+        REJECT_REASON_AMEND_UNSUPPORTED => "amend_unsupported",
+        _ => "other",
+    }
+}
+
+pub struct CoreMetrics{
+    pub orders_in : Arc<Counter>,
+    pub cancels_in : Arc<Counter>,
+    pub fills : Arc<Counter>,
+    // by reason , 0 collects the unknown ones
+    rejects : [Arc<Counter> ; REJECT_REASONS],
+    // commands refused by a rate limit , by the bucket they found empty
+    throttled : [Arc<Counter> ; RateAction::ALL.len()],
+    pub batch_size : Arc<Histogram>,
+    // times an asset was found not summing to zero over its accounts
+    pub conservation_failures : Arc<Counter>,
+}
+
+impl Default for CoreMetrics{
+    fn default()->Self{
+        let registry = registry();
+        let reject = |reason : u32| registry.counter("engine_rejects_total", "orders rejected before reaching a book , by reason", &[("reason" , reject_reason_label(reason))]);
+        Self {
+            orders_in : registry.counter("engine_orders_in_total", "new orders taken in by the engine", &[]),
+            cancels_in : registry.counter("engine_cancels_in_total", "cancel requests taken in by the engine", &[]),
+            fills : registry.counter("engine_fills_total", "fills produced by matching", &[]),
+            rejects : std::array::from_fn(|reason| reject(reason as u32)),
+            throttled : RateAction::ALL.map(|action| registry.counter("engine_throttled_total", "commands refused by a user's rate limit , by action", &[("action" , action.label())])),
+            batch_size : registry.histogram("engine_batch_size", "commands taken per loop iteration", &[], &BATCH_SIZE_BOUNDS),
+            conservation_failures : registry.counter("engine_conservation_failures_total", "assets whose accounts did not sum to zero when checked", &[]),
+        }
+    }
+}
+
+impl CoreMetrics{
+    #[inline(always)]
+    pub fn reject(&self , reason : u32){
+        let index = match reason as usize {
+            index @ 1..REJECT_REASONS => index,
+            _ => 0,
+        };
+        self.rejects[index].inc();
+    }
+
+    #[inline(always)]
+    pub fn throttle(&self , action : RateAction){
+        self.throttled[action.as_u8() as usize].inc();
+    }
+}
+
+struct BookGauges{
+    orders : Arc<Gauge>,
+    bid_levels : Arc<Gauge>,
+    ask_levels : Arc<Gauge>,
+}
+
+// resting orders and price levels per symbol for the books of one engine
+// a symbol that is no longer listed loses its series on the next refresh
+pub struct BookMetrics{
+    books : FxHashMap<u32 , BookGauges>,
+    pub interval : Duration,
+    last_refresh : Instant,
+}
+
+impl BookMetrics{
+    pub fn new(interval : Duration)->Self{
+        Self { books : FxHashMap::default() , interval , last_refresh : Instant::now() }
+    }
+
+    pub fn due(&self)->bool{
+        self.last_refresh.elapsed() >= self.interval
+    }
+
+    pub fn refresh(&mut self , engine : &STEngine){
+        let registry = registry();
+        let mut listed = Vec::with_capacity(self.books.len());
+        for book in engine.books_by_symbol() {
+            listed.push(book.symbol);
+            let gauges = self.books.entry(book.symbol).or_insert_with(|| {
+                let symbol = book.symbol.to_string();
+                BookGauges {
+                    orders : registry.gauge("engine_book_orders", "resting orders in the book", &[("symbol" , &symbol)]),
+                    bid_levels : registry.gauge("engine_book_levels", "price levels in the book", &[("symbol" , &symbol) , ("side" , "bid")]),
+                    ask_levels : registry.gauge("engine_book_levels", "price levels in the book", &[("symbol" , &symbol) , ("side" , "ask")]),
+                }
+            });
+            gauges.orders.set(book.manager.id_to_index.len() as i64);
+            gauges.bid_levels.set(book.bidside.levels.len() as i64);
+            gauges.ask_levels.set(book.askside.levels.len() as i64);
+        }
+        self.books.retain(|symbol , _| {
+            if listed.contains(symbol) {
+                return true;
+            }
+            let symbol = symbol.to_string();
+            registry.remove("engine_book_orders", &[("symbol" , &symbol)]);
+            registry.remove("engine_book_levels", &[("symbol" , &symbol) , ("side" , "bid")]);
+            registry.remove("engine_book_levels", &[("symbol" , &symbol) , ("side" , "ask")]);
+            false
+        });
+        self.last_refresh = Instant::now();
+    }
+}
+
+// depth of the spsc queues between the risk thread and the shards , sampled by the risk thread
+pub struct ShardQueueMetrics{
+    commands : Vec<Arc<Gauge>>,
+    reports : Vec<Arc<Gauge>>,
+    interval : Duration,
+    last_sample : Instant,
+}
+
+impl ShardQueueMetrics{
+    pub fn new(shards : usize , interval : Duration)->Self{
+        let registry = registry();
+        let gauge = |shard : usize , queue : &str| registry.gauge("engine_shard_queue_depth", "entries waiting between the risk thread and an engine shard", &[("shard" , &shard.to_string()) , ("queue" , queue)]);
+        Self {
+            commands : (0..shards).map(|shard| gauge(shard, "commands")).collect(),
+            reports : (0..shards).map(|shard| gauge(shard, "reports")).collect(),
+            interval,
+            last_sample : Instant::now(),
+        }
+    }
+
+    pub fn due(&self)->bool{
+        self.last_sample.elapsed() >= self.interval
+    }
+
+    pub fn record(&mut self , commands : impl Iterator<Item = usize> , reports : impl Iterator<Item = usize>){
+        for (gauge , depth) in self.commands.iter().zip(commands) {
+            gauge.set(depth as i64);
+        }
+        for (gauge , depth) in self.reports.iter().zip(reports) {
+            gauge.set(depth as i64);
+        }
+        self.last_sample = Instant::now();
+    }
+}
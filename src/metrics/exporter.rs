@@ -0,0 +1,214 @@
+// serves GET /metrics on a localhost port in the prometheus text format
+// a scrape renders the registry , the backpressure stats of every in process channel and the depth of every shm queue
+// one blocking thread , scrapes are rare and small , nothing here touches the hot path
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::thread::JoinHandle;
+use std::time::Duration;
+use crate::backpressure::sender::all_channel_stats;
+use crate::config::settings::{MetricsSettings, QueuePaths};
+use crate::metrics::latency::last_window;
+use crate::metrics::registry::{registry, write_header, write_sample, MetricKind};
+use crate::shm::admin_command_queue::AdminCommandQueue;
+use crate::shm::admin_response_queue::AdminResponseQueue;
+use crate::shm::balance_log_queue::BalanceLogQueue;
+use crate::shm::balance_response_queue::BalanceResQueue;
+use crate::shm::cancel_orders_queue::CancelOrderQueue;
+use crate::shm::event_queue::OrderEventQueue;
+use crate::shm::fill_queue_mm::MarketMakerFillQueue;
+use crate::shm::holdings_log_queue::HoldingLogQueue;
+use crate::shm::holdings_response_queue::HoldingResQueue;
+use crate::shm::market_maker_feed::MarketMakerFeedQueue;
+use crate::shm::order_log_queue::OrderLogQueue;
+use crate::shm::position_queue::PositionQueue;
+use crate::shm::query_queue::QueryQueue;
+use crate::shm::queue::IncomingOrderQueue;
+use crate::shm::snapshot_queue::OrderBookSnapShotQueue;
+use crate::shm::trade_log_queue::TradeLogQueue;
+
+const CONTENT_TYPE : &str = "text/plain; version=0.0.4; charset=utf-8";
+const READ_TIMEOUT : Duration = Duration::from_secs(2);
+
+// read only view of one shm queue , every queue file has the same header layout behind its own type
+pub trait QueueProbe : Send{
+    fn depth(&self)->u64;
+    fn capacity(&self)->u64;
+}
+
+macro_rules! impl_queue_probe {
+    ($($queue:ty),* $(,)?) => {
+        $(
+            impl QueueProbe for $queue{
+                fn depth(&self)->u64{
+                    <$queue>::depth(self)
+                }
+                fn capacity(&self)->u64{
+                    <$queue>::capacity(self)
+                }
+            }
+        )*
+    };
+}
+
+impl_queue_probe!(
+    IncomingOrderQueue, CancelOrderQueue, OrderEventQueue, QueryQueue, HoldingResQueue, BalanceResQueue,
+    OrderLogQueue, BalanceLogQueue, HoldingLogQueue, TradeLogQueue, OrderBookSnapShotQueue,
+    MarketMakerFillQueue, MarketMakerFeedQueue, AdminCommandQueue, AdminResponseQueue, PositionQueue,
+);
+
+fn open_probe(name : &str , path : &str)->Option<Box<dyn QueueProbe>>{
+    fn boxed<Q : QueueProbe + 'static , E>(queue : Result<Q , E>)->Option<Box<dyn QueueProbe>>{
+        queue.ok().map(|queue| Box::new(queue) as Box<dyn QueueProbe>)
+    }
+    match name {
+        "incoming_orders" => boxed(IncomingOrderQueue::open(path)),
+        "cancel_orders" => boxed(CancelOrderQueue::open(path)),
+        "order_events" => boxed(OrderEventQueue::open(path)),
+        "queries" => boxed(QueryQueue::open(path)),
+        "holdings_response" => boxed(HoldingResQueue::open(path)),
+        "balance_response" => boxed(BalanceResQueue::open(path)),
+        "order_logs" => boxed(OrderLogQueue::open(path)),
+        "balance_logs" => boxed(BalanceLogQueue::open(path)),
+        "holding_logs" => boxed(HoldingLogQueue::open(path)),
+        "trade_logs" => boxed(TradeLogQueue::open(path)),
+        "snapshot" => boxed(OrderBookSnapShotQueue::open(path)),
+        "market_maker_fills" => boxed(MarketMakerFillQueue::open(path)),
+        "market_maker_feed" => boxed(MarketMakerFeedQueue::open(path)),
+        "admin_commands" | "standby_admin_commands" => boxed(AdminCommandQueue::open(path)),
+        "admin_responses" | "standby_admin_responses" => boxed(AdminResponseQueue::open(path)),
+        "positions" => boxed(PositionQueue::open(path)),
+        _ => None,
+    }
+}
+
+// name , path and the probe once the queue could be opened
+type ProbeSlot = (&'static str , String , Option<Box<dyn QueueProbe>>);
+
+// the shm queues of a deployment , a queue that does not exist yet is retried on the next scrape
+pub struct ShmProbes{
+    queues : Vec<ProbeSlot>,
+}
+
+impl ShmProbes{
+    pub fn new(paths : &QueuePaths)->Self{
+        Self { queues : paths.all().iter().map(|(name , path)| (*name , path.to_string() , None)).collect() }
+    }
+
+    pub fn render(&mut self , out : &mut String){
+        for (name , path , probe) in self.queues.iter_mut() {
+            if probe.is_none() {
+                *probe = open_probe(name, path);
+            }
+        }
+        let open = || self.queues.iter().filter_map(|(name , _ , probe)| probe.as_ref().map(|probe| (*name , probe)));
+        write_header(out, "engine_shm_queue_depth", "entries waiting in the shared memory queue", MetricKind::Gauge);
+        for (name , probe) in open() {
+            write_sample(out, "engine_shm_queue_depth", &[("queue" , name.to_string())], None, probe.depth());
+        }
+        write_header(out, "engine_shm_queue_capacity", "slots in the shared memory queue", MetricKind::Gauge);
+        for (name , probe) in open() {
+            write_sample(out, "engine_shm_queue_capacity", &[("queue" , name.to_string())], None, probe.capacity());
+        }
+    }
+}
+
+// one series per channel name , shards each own a channel of the same name and are summed
+pub fn render_channels(out : &mut String){
+    let mut channels : Vec<(&'static str , [u64 ; 4])> = Vec::new();
+    for stats in all_channel_stats() {
+        let values = [stats.sent(), stats.dropped(), stats.full(), stats.depth()];
+        match channels.iter_mut().find(|(name , _)| *name == stats.name) {
+            Some((_ , totals)) => totals.iter_mut().zip(values).for_each(|(total , value)| *total += value),
+            None => channels.push((stats.name , values)),
+        }
+    }
+    let families = [
+        ("engine_channel_sent_total" , "messages handed to the in process channel" , MetricKind::Counter),
+        ("engine_channel_dropped_total" , "messages the channel's backpressure policy dropped" , MetricKind::Counter),
+        ("engine_channel_full_total" , "sends that found the channel full" , MetricKind::Counter),
+        ("engine_channel_depth" , "channel depth as last sampled by the producer" , MetricKind::Gauge),
+    ];
+    for (index , (name , help , kind)) in families.iter().enumerate() {
+        write_header(out, name, help, *kind);
+        for (channel , values) in channels.iter() {
+            write_sample(out, name, &[("channel" , channel.to_string())], None, values[index]);
+        }
+    }
+}
+
+// the last closed latency window , nothing until the dumper has rolled one
+pub fn render_latency(out : &mut String){
+    let Some(report) = last_window() else {
+        return;
+    };
+    write_header(out, "engine_stage_latency_ns", "per stage latency over the last closed window", MetricKind::Summary);
+    for (stage , summary) in report.stages.iter() {
+        let labels = [("stage" , stage.as_str().to_string())];
+        for (quantile , value) in [("0.5" , summary.p50), ("0.99" , summary.p99), ("0.999" , summary.p999)] {
+            write_sample(out, "engine_stage_latency_ns", &labels, Some(("quantile" , quantile)), value);
+        }
+        write_sample(out, "engine_stage_latency_ns_sum", &labels, None, (summary.mean * summary.count as f64) as u64);
+        write_sample(out, "engine_stage_latency_ns_count", &labels, None, summary.count);
+    }
+    write_header(out, "engine_stage_latency_max_ns", "slowest record per stage in the last closed window", MetricKind::Gauge);
+    for (stage , summary) in report.stages.iter() {
+        write_sample(out, "engine_stage_latency_max_ns", &[("stage" , stage.as_str().to_string())], None, summary.max);
+    }
+    write_header(out, "engine_stage_latency_window_seconds", "length of the last closed latency window", MetricKind::Gauge);
+    write_sample(out, "engine_stage_latency_window_seconds", &[], None, report.window.as_secs_f64());
+}
+
+/// the whole scrape body
+pub fn render_all(probes : &mut ShmProbes)->String{
+    let mut out = String::with_capacity(16 * 1024);
+    registry().render(&mut out);
+    render_channels(&mut out);
+    render_latency(&mut out);
+    probes.render(&mut out);
+    out
+}
+
+/// binds the listen address and serves scrapes on a thread of its own , none when disabled
+pub fn spawn_exporter(settings : &MetricsSettings , queues : &QueuePaths)->std::io::Result<Option<(SocketAddr , JoinHandle<()>)>>{
+    if !settings.enabled {
+        return Ok(None);
+    }
+    let listener = TcpListener::bind(&settings.listen)?;
+    let addr = listener.local_addr()?;
+    let mut probes = ShmProbes::new(queues);
+    let handle = std::thread::Builder::new().name("metrics".into()).spawn(move || {
+        eprintln!("[Metrics] serving http://{}/metrics", addr);
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    if let Err(e) = serve(stream, &mut probes) {
+                        eprintln!("[Metrics] scrape failed: {}", e);
+                    }
+                }
+                Err(e) => eprintln!("[Metrics] accept failed: {}", e),
+            }
+        }
+    })?;
+    Ok(Some((addr , handle)))
+}
+
+fn serve(mut stream : TcpStream , probes : &mut ShmProbes)->std::io::Result<()>{
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // the headers are read and ignored , the client may not send the body until they are
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+    let mut parts = request_line.split_whitespace();
+    let (status , content_type , body) = match (parts.next(), parts.next()) {
+        (Some("GET") , Some("/metrics")) => ("200 OK" , CONTENT_TYPE , render_all(probes)),
+        (Some("GET") , Some(_)) => ("404 Not Found" , "text/plain" , "not found , try /metrics\n".to_string()),
+        _ => ("405 Method Not Allowed" , "text/plain" , "only GET is served\n".to_string()),
+    };
+    write!(stream, "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", status, content_type, body.len())?;
+    stream.write_all(body.as_bytes())?;
+    stream.flush()
+}
@@ -0,0 +1,221 @@
+// production latency per pipeline stage in nanoseconds , HDR histograms (3 significant digits , 1ns to 60s)
+// every thread records into histograms of its own without locking and merges them into the shared window
+// every FLUSH_EVERY records or FLUSH_INTERVAL , whichever comes first
+// the shared window is rolled (summarised and reset) by the dumper thread , a scrape shows the last rolled window
+use std::sync::{Mutex, OnceLock};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use hdrhistogram::Histogram;
+
+const LOWEST_NS : u64 = 1;
+const HIGHEST_NS : u64 = 60_000_000_000;
+const SIGNIFICANT_DIGITS : u8 = 3;
+pub const FLUSH_EVERY : u64 = 1024;
+pub const FLUSH_INTERVAL : Duration = Duration::from_secs(1);
+
+#[derive(Debug , Clone , Copy , PartialEq , Eq)]
+pub enum Stage{
+    // ShmOrder.timestamp (set by the producer) to the engine taking the order off the shm queue
+    QueueWait,
+    // reserving funds or holdings for a new order
+    BalanceLock,
+    // the engine matching (or resting) one order
+    Match,
+    // the publisher turning one engine event into its pubsub messages
+    Publish,
+    // the writer putting one event into a shm queue
+    ShmWrite,
+}
+
+impl Stage{
+    pub const ALL : [Stage ; 5] = [Stage::QueueWait, Stage::BalanceLock, Stage::Match, Stage::Publish, Stage::ShmWrite];
+
+    pub fn as_str(self)->&'static str{
+        match self {
+            Self::QueueWait => "queue_wait",
+            Self::BalanceLock => "balance_lock",
+            Self::Match => "match",
+            Self::Publish => "publish",
+            Self::ShmWrite => "shm_write",
+        }
+    }
+}
+
+/// wall clock in nanoseconds since the epoch , the clock the producer stamps orders with
+#[inline(always)]
+pub fn unix_nanos()->u64{
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_nanos() as u64)
+}
+
+fn new_histogram()->Histogram<u64>{
+    Histogram::new_with_bounds(LOWEST_NS, HIGHEST_NS, SIGNIFICANT_DIGITS).expect("latency histogram bounds are valid")
+}
+
+#[derive(Debug , Clone , Copy , Default , PartialEq)]
+pub struct StageSummary{
+    pub count : u64,
+    pub p50 : u64,
+    pub p99 : u64,
+    pub p999 : u64,
+    pub max : u64,
+    pub mean : f64,
+}
+
+impl StageSummary{
+    fn of(histogram : &Histogram<u64>)->Self{
+        if histogram.is_empty() {
+            return Self::default();
+        }
+        Self {
+            count : histogram.len(),
+            p50 : histogram.value_at_quantile(0.5),
+            p99 : histogram.value_at_quantile(0.99),
+            p999 : histogram.value_at_quantile(0.999),
+            max : histogram.max(),
+            mean : histogram.mean(),
+        }
+    }
+}
+
+#[derive(Debug , Clone , PartialEq)]
+pub struct LatencyReport{
+    // how long the window was open
+    pub window : Duration,
+    pub stages : Vec<(Stage , StageSummary)>,
+}
+
+impl LatencyReport{
+    pub fn stage(&self , stage : Stage)->StageSummary{
+        self.stages.iter().find(|(other , _)| *other == stage).map(|(_ , summary)| *summary).unwrap_or_default()
+    }
+}
+
+struct Windows{
+    current : Vec<Histogram<u64>>,
+    started : Instant,
+    last : Option<LatencyReport>,
+}
+
+fn windows()->&'static Mutex<Windows>{
+    static WINDOWS : OnceLock<Mutex<Windows>> = OnceLock::new();
+    WINDOWS.get_or_init(|| Mutex::new(Windows {
+        current : Stage::ALL.iter().map(|_| new_histogram()).collect(),
+        started : Instant::now(),
+        last : None,
+    }))
+}
+
+fn report(windows : &Windows)->LatencyReport{
+    LatencyReport {
+        window : windows.started.elapsed(),
+        stages : Stage::ALL.iter().map(|stage| (*stage , StageSummary::of(&windows.current[*stage as usize]))).collect(),
+    }
+}
+
+/// summary of the open window , nothing is reset
+pub fn current_window()->LatencyReport{
+    report(&windows().lock().unwrap())
+}
+
+/// closes the open window , its summary becomes the last window and a new one starts empty
+pub fn roll_window()->LatencyReport{
+    let mut windows = windows().lock().unwrap();
+    let closed = report(&windows);
+    for histogram in windows.current.iter_mut() {
+        histogram.reset();
+    }
+    windows.started = Instant::now();
+    windows.last = Some(closed.clone());
+    closed
+}
+
+/// the window closed by the last roll , none before the first one
+pub fn last_window()->Option<LatencyReport>{
+    windows().lock().unwrap().last.clone()
+}
+
+// one per thread , only the stages the thread records get a histogram
+pub struct LatencyRecorder{
+    histograms : Vec<Option<Histogram<u64>>>,
+    pending : u64,
+    last_flush : Instant,
+}
+
+impl Default for LatencyRecorder{
+    fn default()->Self{
+        Self { histograms : Stage::ALL.iter().map(|_| None).collect() , pending : 0 , last_flush : Instant::now() }
+    }
+}
+
+impl LatencyRecorder{
+    #[inline(always)]
+    pub fn record(&mut self , stage : Stage , nanos : u64){
+        self.histograms[stage as usize].get_or_insert_with(new_histogram).saturating_record(nanos.max(LOWEST_NS));
+        self.pending += 1;
+        if self.pending >= FLUSH_EVERY {
+            self.flush();
+        }
+    }
+
+    #[inline(always)]
+    pub fn record_since(&mut self , stage : Stage , start : Instant){
+        self.record(stage, start.elapsed().as_nanos() as u64);
+    }
+
+    /// time from a producer's ShmOrder.timestamp to `now` , stamps from the future or unset ones are skipped
+    #[inline(always)]
+    pub fn record_queue_wait(&mut self , stamped_at : u64 , now : u64){
+        if stamped_at != 0 && stamped_at <= now {
+            self.record(Stage::QueueWait, now - stamped_at);
+        }
+    }
+
+    /// for quiet periods , records that never reach FLUSH_EVERY still show up within FLUSH_INTERVAL
+    pub fn flush_if_due(&mut self){
+        if self.pending != 0 && self.last_flush.elapsed() >= FLUSH_INTERVAL {
+            self.flush();
+        }
+    }
+
+    pub fn flush(&mut self){
+        self.last_flush = Instant::now();
+        if self.pending == 0 {
+            return;
+        }
+        self.pending = 0;
+        let mut windows = windows().lock().unwrap();
+        for (stage , histogram) in self.histograms.iter_mut().enumerate() {
+            if let Some(histogram) = histogram && !histogram.is_empty() {
+                // same bounds on both sides , adding cannot fail
+                let _ = windows.current[stage].add(&*histogram);
+                histogram.reset();
+            }
+        }
+    }
+}
+
+impl Drop for LatencyRecorder{
+    fn drop(&mut self){
+        self.flush();
+    }
+}
+
+pub fn log_report(report : &LatencyReport){
+    for (stage , summary) in report.stages.iter().filter(|(_ , summary)| summary.count != 0) {
+        eprintln!(
+            "[Latency] {} over {:?}: count {} p50 {}ns p99 {}ns p99.9 {}ns max {}ns",
+            stage.as_str(), report.window, summary.count, summary.p50, summary.p99, summary.p999, summary.max
+        );
+    }
+}
+
+/// rolls the window every `window` , logging each closed one when `log` is set
+pub fn spawn_latency_dumper(window : Duration , log : bool)->std::io::Result<JoinHandle<()>>{
+    std::thread::Builder::new().name("latency".into()).spawn(move || loop {
+        std::thread::sleep(window);
+        let report = roll_window();
+        if log {
+            log_report(&report);
+        }
+    })
+}
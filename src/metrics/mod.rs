@@ -0,0 +1,5 @@
+pub mod registry;
+pub mod engine;
+pub mod exporter;
+pub mod latency;
+pub mod tests;
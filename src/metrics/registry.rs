@@ -0,0 +1,247 @@
+// process wide metrics , a handle is a few atomics the hot path bumps without locking
+// the registry lock is only taken to create or remove a handle and to render a scrape
+use std::fmt::Write;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+
+#[derive(Debug , Default)]
+pub struct Counter{
+    value : AtomicU64,
+}
+
+impl Counter{
+    #[inline(always)]
+    pub fn inc(&self){
+        self.value.fetch_add(1, Ordering::Relaxed);
+    }
+    #[inline(always)]
+    pub fn add(&self , n : u64){
+        self.value.fetch_add(n, Ordering::Relaxed);
+    }
+    pub fn get(&self)->u64{
+        self.value.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Debug , Default)]
+pub struct Gauge{
+    value : AtomicI64,
+}
+
+impl Gauge{
+    #[inline(always)]
+    pub fn set(&self , value : i64){
+        self.value.store(value, Ordering::Relaxed);
+    }
+    #[inline(always)]
+    pub fn add(&self , delta : i64){
+        self.value.fetch_add(delta, Ordering::Relaxed);
+    }
+    pub fn get(&self)->i64{
+        self.value.load(Ordering::Relaxed)
+    }
+}
+
+// fixed upper bounds , observations above the last one land in +Inf
+#[derive(Debug)]
+pub struct Histogram{
+    bounds : Vec<u64>,
+    // one per bound plus +Inf , not cumulative , rendering sums them up
+    buckets : Vec<AtomicU64>,
+    sum : AtomicU64,
+    count : AtomicU64,
+}
+
+impl Histogram{
+    fn new(bounds : &[u64])->Self{
+        let mut bounds = bounds.to_vec();
+        bounds.sort_unstable();
+        bounds.dedup();
+        let buckets = (0..=bounds.len()).map(|_| AtomicU64::new(0)).collect();
+        Self { bounds , buckets , sum : AtomicU64::new(0) , count : AtomicU64::new(0) }
+    }
+    #[inline(always)]
+    pub fn observe(&self , value : u64){
+        let bucket = self.bounds.partition_point(|bound| *bound < value);
+        self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.sum.fetch_add(value, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+    }
+    pub fn count(&self)->u64{
+        self.count.load(Ordering::Relaxed)
+    }
+    pub fn sum(&self)->u64{
+        self.sum.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Debug , Clone , Copy , PartialEq , Eq)]
+pub enum MetricKind{
+    Counter,
+    Gauge,
+    Histogram,
+    Summary,
+}
+
+impl MetricKind{
+    fn as_str(self)->&'static str{
+        match self {
+            Self::Counter => "counter",
+            Self::Gauge => "gauge",
+            Self::Histogram => "histogram",
+            Self::Summary => "summary",
+        }
+    }
+}
+
+#[derive(Debug , Clone)]
+enum Metric{
+    Counter(Arc<Counter>),
+    Gauge(Arc<Gauge>),
+    Histogram(Arc<Histogram>),
+}
+
+type Labels = Vec<(&'static str , String)>;
+
+#[derive(Debug)]
+struct Family{
+    name : &'static str,
+    help : &'static str,
+    kind : MetricKind,
+    series : Vec<(Labels , Metric)>,
+}
+
+#[derive(Debug , Default)]
+pub struct Registry{
+    families : Mutex<Vec<Family>>,
+}
+
+/// the registry every part of the process reports into
+pub fn registry()->&'static Registry{
+    static REGISTRY : OnceLock<Registry> = OnceLock::new();
+    REGISTRY.get_or_init(Registry::default)
+}
+
+impl Registry{
+    /// the counter for `name` with `labels` , created on first use , later calls get the same one
+    pub fn counter(&self , name : &'static str , help : &'static str , labels : &[(&'static str , &str)])->Arc<Counter>{
+        match self.get_or_insert(name, help, MetricKind::Counter, labels, || Metric::Counter(Arc::default())) {
+            Metric::Counter(counter) => counter,
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn gauge(&self , name : &'static str , help : &'static str , labels : &[(&'static str , &str)])->Arc<Gauge>{
+        match self.get_or_insert(name, help, MetricKind::Gauge, labels, || Metric::Gauge(Arc::default())) {
+            Metric::Gauge(gauge) => gauge,
+            _ => unreachable!(),
+        }
+    }
+
+    /// the bounds only count when the histogram is created
+    pub fn histogram(&self , name : &'static str , help : &'static str , labels : &[(&'static str , &str)] , bounds : &[u64])->Arc<Histogram>{
+        match self.get_or_insert(name, help, MetricKind::Histogram, labels, || Metric::Histogram(Arc::new(Histogram::new(bounds)))) {
+            Metric::Histogram(histogram) => histogram,
+            _ => unreachable!(),
+        }
+    }
+
+    /// drops one series , for things that went away (a delisted symbol)
+    pub fn remove(&self , name : &str , labels : &[(&'static str , &str)]){
+        let mut families = self.families.lock().unwrap();
+        if let Some(family) = families.iter_mut().find(|family| family.name == name) {
+            family.series.retain(|(series_labels , _)| !same_labels(series_labels, labels));
+        }
+    }
+
+    fn get_or_insert(&self , name : &'static str , help : &'static str , kind : MetricKind , labels : &[(&'static str , &str)] , make : impl FnOnce()->Metric)->Metric{
+        let mut families = self.families.lock().unwrap();
+        let index = match families.iter().position(|family| family.name == name) {
+            Some(index) => index,
+            None => {
+                families.push(Family { name , help , kind , series : Vec::new() });
+                families.len() - 1
+            }
+        };
+        let family = &mut families[index];
+        assert_eq!(family.kind, kind, "metric {} registered as two kinds", name);
+        if let Some((_ , metric)) = family.series.iter().find(|(series_labels , _)| same_labels(series_labels, labels)) {
+            return metric.clone();
+        }
+        let metric = make();
+        family.series.push((labels.iter().map(|(key , value)| (*key , value.to_string())).collect(), metric.clone()));
+        metric
+    }
+
+    /// every series in the prometheus text format
+    pub fn render(&self , out : &mut String){
+        let families = self.families.lock().unwrap();
+        for family in families.iter() {
+            if family.series.is_empty() {
+                continue;
+            }
+            write_header(out, family.name, family.help, family.kind);
+            for (labels , metric) in family.series.iter() {
+                match metric {
+                    Metric::Counter(counter) => write_sample(out, family.name, labels, None, counter.get()),
+                    Metric::Gauge(gauge) => write_sample(out, family.name, labels, None, gauge.get()),
+                    Metric::Histogram(histogram) => write_histogram(out, family.name, labels, histogram),
+                }
+            }
+        }
+    }
+}
+
+fn same_labels(series : &[(&'static str , String)] , labels : &[(&'static str , &str)])->bool{
+    series.len() == labels.len() && series.iter().zip(labels).all(|((key , value) , (other_key , other_value))| key == other_key && value == other_value)
+}
+
+pub fn write_header(out : &mut String , name : &str , help : &str , kind : MetricKind){
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind.as_str());
+}
+
+/// one sample line , `extra` is a label appended after `labels` (le for histogram buckets)
+pub fn write_sample(out : &mut String , name : &str , labels : &[(&'static str , String)] , extra : Option<(&str , &str)> , value : impl std::fmt::Display){
+    out.push_str(name);
+    if !labels.is_empty() || extra.is_some() {
+        out.push('{');
+        let mut first = true;
+        for (key , label_value) in labels.iter().map(|(key , value)| (*key , value.as_str())).chain(extra) {
+            if !first {
+                out.push(',');
+            }
+            first = false;
+            let _ = write!(out, "{}=\"", key);
+            escape_label(out, label_value);
+            out.push('"');
+        }
+        out.push('}');
+    }
+    let _ = writeln!(out, " {}", value);
+}
+
+fn write_histogram(out : &mut String , name : &str , labels : &[(&'static str , String)] , histogram : &Histogram){
+    let bucket_name = format!("{}_bucket", name);
+    let mut cumulative = 0u64;
+    for (bound , bucket) in histogram.bounds.iter().zip(histogram.buckets.iter()) {
+        cumulative += bucket.load(Ordering::Relaxed);
+        write_sample(out, &bucket_name, labels, Some(("le" , &bound.to_string())), cumulative);
+    }
+    cumulative += histogram.buckets[histogram.bounds.len()].load(Ordering::Relaxed);
+    write_sample(out, &bucket_name, labels, Some(("le" , "+Inf")), cumulative);
+    write_sample(out, &format!("{}_sum", name), labels, None, histogram.sum());
+    // the +Inf bucket and the count have to agree , counts taken separately could race an observation
+    write_sample(out, &format!("{}_count", name), labels, None, cumulative);
+}
+
+fn escape_label(out : &mut String , value : &str){
+    for c in value.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '"' => out.push_str("\\\""),
+            '\n' => out.push_str("\\n"),
+            c => out.push(c),
+        }
+    }
+}
@@ -0,0 +1,187 @@
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    use crate::config::settings::{EngineConfig, MetricsSettings};
+    use crate::engine::my_engine::Engine;
+    use crate::journal::command_journal::InboundCommand;
+    use crate::journal::replay::detached_core;
+    use crate::metrics::engine::BookMetrics;
+    use crate::metrics::exporter::{render_all, render_latency, spawn_exporter, ShmProbes};
+    use crate::metrics::latency::{current_window, last_window, roll_window, LatencyRecorder, Stage};
+    use crate::metrics::registry::{registry, Registry};
+    use crate::orderbook::order::{Order, OrderToBeCanceled, Side};
+    use crate::shm::event_queue::{OrderEventQueue, OrderEvents};
+
+    // the engine counters are process wide and other tests bump them too , only deltas are checked
+    fn scrape_value(body: &str, series: &str) -> u64 {
+        body.lines()
+            .find_map(|line| line.strip_prefix(series).and_then(|rest| rest.strip_prefix(' ')))
+            .map(|value| value.parse().unwrap())
+            .unwrap_or(0)
+    }
+
+    fn get(addr: std::net::SocketAddr, path: &str) -> String {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn test_registry_renders_the_text_format() {
+        let registry = Registry::default();
+        registry.counter("test_requests_total", "requests", &[("path", "/a\"b")]).add(3);
+        registry.gauge("test_depth", "depth", &[]).set(-2);
+        let histogram = registry.histogram("test_latency", "latency", &[], &[10, 1, 5]);
+        for value in [0, 3, 7, 50] {
+            histogram.observe(value);
+        }
+        // same name and labels is the same handle
+        registry.counter("test_requests_total", "requests", &[("path", "/a\"b")]).inc();
+
+        let mut out = String::new();
+        registry.render(&mut out);
+        let expected = "\
+# HELP test_requests_total requests
+# TYPE test_requests_total counter
+test_requests_total{path=\"/a\\\"b\"} 4
+# HELP test_depth depth
+# TYPE test_depth gauge
+test_depth -2
+# HELP test_latency latency
+# TYPE test_latency histogram
+test_latency_bucket{le=\"1\"} 1
+test_latency_bucket{le=\"5\"} 2
+test_latency_bucket{le=\"10\"} 3
+test_latency_bucket{le=\"+Inf\"} 4
+test_latency_sum 60
+test_latency_count 4
+";
+        assert_eq!(out, expected);
+
+        registry.remove("test_depth", &[]);
+        let mut out = String::new();
+        registry.render(&mut out);
+        assert!(!out.contains("test_depth"));
+    }
+
+    #[test]
+    fn test_core_counts_orders_fills_and_rejects() {
+        let (mut core, _sink) = detached_core();
+        core.engine.add_book(77);
+        let mut before = String::new();
+        registry().render(&mut before);
+
+        core.submit(InboundCommand::NewOrder(Order::new(20, 1, Side::Ask, 1, 2, 9, 1, 77)));
+        core.submit(InboundCommand::NewOrder(Order::new(10, 2, Side::Bid, 1, 2, 9, 2, 77)));
+        // a user with no account has nothing to reserve
+        core.submit(InboundCommand::NewOrder(Order::new(4343, 3, Side::Bid, 1, 1, 9, 3, 77)));
+        core.submit(InboundCommand::CancelOrder(OrderToBeCanceled { order_id: 9, user_id: 10, symbol: 77 }));
+
+        let mut after = String::new();
+        registry().render(&mut after);
+        let delta = |series: &str| scrape_value(&after, series) - scrape_value(&before, series);
+        assert!(delta("engine_orders_in_total") >= 3);
+        assert!(delta("engine_fills_total") >= 1);
+        assert!(delta("engine_cancels_in_total") >= 1);
+        assert!(delta("engine_rejects_total{reason=\"funds\"}") >= 1);
+    }
+
+    #[test]
+    fn test_book_gauges_follow_listing() {
+        let (mut core, _sink) = detached_core();
+        core.engine.add_book(4101);
+        for (order_id, price) in [(1, 10), (2, 10), (3, 11)] {
+            let _ = core.engine.process_order(Order::new(10, order_id, Side::Bid, 1, 1, price, order_id, 4101), |_| {});
+        }
+        let _ = core.engine.process_order(Order::new(11, 4, Side::Ask, 1, 1, 20, 4, 4101), |_| {});
+        let mut books = BookMetrics::new(std::time::Duration::ZERO);
+        books.refresh(&core.engine);
+
+        let mut out = String::new();
+        registry().render(&mut out);
+        assert_eq!(scrape_value(&out, "engine_book_orders{symbol=\"4101\"}"), 4);
+        assert_eq!(scrape_value(&out, "engine_book_levels{symbol=\"4101\",side=\"bid\"}"), 2);
+        assert_eq!(scrape_value(&out, "engine_book_levels{symbol=\"4101\",side=\"ask\"}"), 1);
+
+        core.engine.take_book(4101).unwrap();
+        books.refresh(&core.engine);
+        let mut out = String::new();
+        registry().render(&mut out);
+        assert!(!out.contains("symbol=\"4101\""));
+    }
+
+    #[test]
+    fn test_endpoint_serves_channels_and_shm_depths() {
+        let mut config = EngineConfig::default();
+        let path = std::env::temp_dir().join(format!("metrics_test_order_events_{}", std::process::id()));
+        config.queues.order_events = path.to_string_lossy().into_owned();
+        config.queues.admin_responses = format!("{}_missing", config.queues.order_events);
+        let mut queue = OrderEventQueue::create(&path).unwrap();
+        for order_id in 0..3 {
+            queue.enqueue(OrderEvents { user_id: 1, order_id, symbol: 1, event_kind: 0, filled_qty: 0, remaining_qty: 1, original_qty: 1, error_code: 0, fee: 0 }).unwrap();
+        }
+
+        let settings = MetricsSettings { listen: "127.0.0.1:0".into(), ..MetricsSettings::default() };
+        let (addr, _handle) = spawn_exporter(&settings, &config.queues).unwrap().unwrap();
+        let response = get(addr, "/metrics");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("Content-Type: text/plain; version=0.0.4"));
+        let body = response.split("\r\n\r\n").nth(1).unwrap();
+        assert_eq!(scrape_value(body, "engine_shm_queue_depth{queue=\"order_events\"}"), 3);
+        assert!(body.contains("engine_shm_queue_capacity{queue=\"order_events\"}"));
+        assert!(body.contains("# TYPE engine_channel_dropped_total counter"));
+        // queues that were never created are left out until they show up
+        assert!(!body.contains("queue=\"admin_responses\""));
+
+        assert!(get(addr, "/").starts_with("HTTP/1.1 404"));
+        let disabled = MetricsSettings { enabled: false, ..settings };
+        assert!(spawn_exporter(&disabled, &config.queues).unwrap().is_none());
+
+        // renders without a server too
+        let mut probes = ShmProbes::new(&config.queues);
+        assert!(render_all(&mut probes).contains("engine_shm_queue_depth{queue=\"order_events\"} 3"));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    // the only test that rolls the shared window , publish is a stage no other test records
+    #[test]
+    fn test_latency_windows_summarise_and_reset() {
+        roll_window();
+        let mut recorder = LatencyRecorder::default();
+        for micros in 1..=1000u64 {
+            recorder.record(Stage::Publish, micros * 1000);
+        }
+        // stamps that are unset or ahead of the clock say nothing about the queue
+        recorder.record_queue_wait(0, 10);
+        recorder.record_queue_wait(20, 10);
+        recorder.flush();
+
+        let open = current_window().stage(Stage::Publish);
+        assert_eq!(open.count, 1000);
+        let close = |value: u64, expected: u64| value.abs_diff(expected) <= expected / 500;
+        assert!(close(open.p50, 500_000), "p50 {}", open.p50);
+        assert!(close(open.p99, 990_000), "p99 {}", open.p99);
+        assert!(close(open.p999, 999_000), "p99.9 {}", open.p999);
+        assert!(close(open.max, 1_000_000), "max {}", open.max);
+
+        let closed = roll_window();
+        assert_eq!(closed.stage(Stage::Publish), open);
+        assert_eq!(current_window().stage(Stage::Publish).count, 0);
+        assert_eq!(last_window().unwrap().stage(Stage::Publish), open);
+        let mut out = String::new();
+        render_latency(&mut out);
+        assert!(out.contains("# TYPE engine_stage_latency_ns summary"));
+        assert!(out.contains(&format!("engine_stage_latency_ns{{stage=\"publish\",quantile=\"0.99\"}} {}", open.p99)));
+        assert!(out.contains("engine_stage_latency_ns_count{stage=\"publish\"} 1000"));
+
+        // a few records wait for the flush interval , dropping the recorder hands them over
+        recorder.record(Stage::Publish, 5);
+        recorder.flush_if_due();
+        assert_eq!(current_window().stage(Stage::Publish).count, 0);
+        drop(recorder);
+        assert_eq!(current_window().stage(Stage::Publish).count, 1);
+    }
+}
@@ -6,6 +6,10 @@ pub  enum Side{
     Bid ,
     Ask 
 }
+// an order of this type replaces the user's resting limit order with the same order_id at its qty and price
+// the resting order is canceled and the replacement queues behind the orders already at its price
+pub const ORDER_TYPE_AMEND : u8 = 2;
+
 #[derive(Debug , Copy , Clone)]
 pub struct Order{
    // pub order_type : Type,
@@ -56,7 +60,7 @@ pub struct ShmOrder{
     // Then u8s (1-byte aligned)
     pub symbol: u32,
     pub side: u8,   // 0=buy, 1=sell
-    pub order_type : u8,   // 0 -> market order  , 1 -> limit order 
+    pub order_type : u8,   // 0 -> market order  , 1 -> limit order , 2 -> amend 
     pub status: u8, // 0=pending, 1=filled, 2=rejected
 }
 
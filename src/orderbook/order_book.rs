@@ -3,6 +3,7 @@ use crate::orderbook::order::{ Order, Side };
 use crate::orderbook::order_manager::OrderManager;
 use crate::orderbook::types::{Fill , Fills , MatchResult  , OrderBookError};
 use crate::shm::market_maker_feed::MarketMakerFeed;
+use crate::digest::state_hasher::StateHasher;
 
 #[derive(Debug)]
 pub struct OrderBook{
@@ -32,6 +33,47 @@ impl OrderBook{
         }
     }
 
+    // digest of what the book holds , levels in price order and each level's queue in time priority
+    // slot indexes in the order manager and empty levels left behind by cancels are not part of it
+    pub fn state_hash(&self)->u64{
+        let mut hasher = StateHasher::new(self.symbol as u64);
+        hasher.write_u64(self.last_trade_price);
+        for (tag , side) in [(0u64 , &self.bidside) , (1u64 , &self.askside)] {
+            hasher.write_u64(tag);
+            for level in side.levels.values() {
+                if level.head.is_none() {
+                    continue;
+                }
+                hasher.write_u64(level.price);
+                let mut cursor = level.head;
+                while let Some(index) = cursor {
+                    let order = self.manager.get(index).unwrap();
+                    hasher.write_u64(order.order_id);
+                    hasher.write_u64(order.user_id);
+                    hasher.write_u32(order.shares_qty);
+                    cursor = order.next;
+                }
+            }
+        }
+        hasher.finish()
+    }
+
+    // (order_id , user_id) of every resting order , bids then asks , each level in time priority
+    pub fn resting_orders(&self)->Vec<(u64 , u64)>{
+        let mut out = Vec::with_capacity(self.manager.id_to_index.len());
+        for side in [&self.bidside , &self.askside] {
+            for level in side.levels.values() {
+                let mut cursor = level.head;
+                while let Some(index) = cursor {
+                    let order = self.manager.get(index).unwrap();
+                    out.push((order.order_id , order.user_id));
+                    cursor = order.next;
+                }
+            }
+        }
+        out
+    }
+
     pub fn match_market_order<F>(&mut self , order:&mut Order , mut feedCallBack : F  )->Result<MatchResult , OrderBookError> where F : FnMut(MarketMakerFeed){
         let orignal_shares_qty = order.shares_qty;
         // wejust need to fill the shares 
@@ -49,6 +91,10 @@ impl OrderBook{
                 Some(price) => price,
                 None => break 
             };
+            // a market bid only has its price times its qty locked , it never pays above that price
+            if order.side == Side::Bid && best_price > order.price {
+                break;
+            }
             // now we start fillig orders at this level 
             let empty = {
                 let level = opposite_side.levels.get_mut(&best_price).unwrap();
@@ -72,7 +118,9 @@ impl OrderBook{
                             maker_user_id : user_id , 
                             taker_user_id : order.user_id , 
                             symbol : self.symbol,
-                            taker_side : order.side
+                            taker_side : order.side ,
+                            taker_fee : 0 ,
+                            maker_fee : 0
                         });
                         // was alr popped from the book , need to bre removed from the manager also 
                          self.manager.remove_order(order_id);
@@ -91,7 +139,9 @@ impl OrderBook{
                             maker_user_id : user_id , 
                             taker_user_id : order.user_id , 
                             symbol : self.symbol,
-                            taker_side : order.side
+                            taker_side : order.side ,
+                            taker_fee : 0 ,
+                            maker_fee : 0
                         });
                         order.shares_qty = 0 ; 
                         // take a mutable refrence and update the shares and then insert 
@@ -188,7 +238,9 @@ impl OrderBook{
                             maker_user_id : user_id , 
                             taker_user_id : order.user_id , 
                             symbol : self.symbol,
-                            taker_side : order.side
+                            taker_side : order.side ,
+                            taker_fee : 0 ,
+                            maker_fee : 0
                         });
                         self.manager.remove_order(order_id);
                     }
@@ -206,7 +258,9 @@ impl OrderBook{
                             maker_user_id : user_id , 
                             taker_user_id : order.user_id , 
                             symbol : self.symbol,
-                            taker_side : order.side
+                            taker_side : order.side ,
+                            taker_fee : 0 ,
+                            maker_fee : 0
                         });
                         order.shares_qty = 0 ; 
                         // take a mutable refrence and update the shares and then insert 
@@ -317,7 +371,9 @@ impl OrderBook{
                             maker_user_id : user_id , 
                             taker_user_id : order.user_id , 
                             symbol : self.symbol,
-                            taker_side : order.side
+                            taker_side : order.side ,
+                            taker_fee : 0 ,
+                            maker_fee : 0
                         });
                         self.manager.remove_order(order_id);
                     }
@@ -335,7 +391,9 @@ impl OrderBook{
                             maker_user_id : user_id , 
                             taker_user_id : order.user_id , 
                             symbol : self.symbol,
-                            taker_side : order.side
+                            taker_side : order.side ,
+                            taker_fee : 0 ,
+                            maker_fee : 0
                         });
                         order.shares_qty = 0 ; 
                         // take a mutable refrence and update the shares and then insert 
@@ -494,10 +552,11 @@ impl OrderBook{
             match side{
                 Side::Ask => {
                     self.askside.delete_order(price, &mut self.manager, order_id);
-
+                    self.askside.remove_level_if_empty(price);
                 },
                 Side::Bid => {
                     self.bidside.delete_order(price, &mut self.manager, order_id);
+                    self.bidside.remove_level_if_empty(price);
                 }
             }
        }
@@ -519,8 +578,9 @@ impl OrderBook{
                 last_traded_price: self.last_trade_price, 
                 best_bid, 
                 best_ask, 
-                best_bid_qty: self.bidside.levels[&best_bid].total_vol, 
-                best_ask_qty: self.askside.levels[&best_ask].total_vol,
+                // either side can be empty after the cancel , its best price is 0 and has no level
+                best_bid_qty: self.bidside.levels.get(&best_bid).map_or(0, |level| level.total_vol), 
+                best_ask_qty: self.askside.levels.get(&best_ask).map_or(0, |level| level.total_vol),
                 symbol : self.symbol
             }
         );
@@ -75,6 +75,20 @@ mod tests {
         assert_eq!(book.askside.levels.len(), 1); // 105 level removed
     }
 
+    #[test]
+    fn test_market_bid_stops_at_its_price() {
+        let mut book = OrderBook::new(1);
+        book.insert_order(new_order(30 , 31, Side::Ask, 10, 105, 31, 1));
+        book.insert_order(new_order(30 , 32, Side::Ask, 10, 107, 32, 1));
+
+        let mut market_bid = new_order(40 , 33, Side::Bid, 30, 106, 33, 1);
+        let result = book.match_market_order(&mut market_bid , |_|{}).unwrap();
+        assert_eq!(result.fills.fills.len(), 1);
+        assert_eq!(result.fills.fills[0].price, 105);
+        assert_eq!(result.remaining_qty, 20);
+        assert_eq!(book.askside.levels.get(&107).unwrap().get_total_volume(), 10);
+    }
+
     #[test]
     fn test_resting_orders_and_cancellation() { 
         let mut book = OrderBook::new(1);
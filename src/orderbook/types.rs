@@ -4,6 +4,9 @@ pub type OrderId = u64;
 use thiserror::Error;
 use serde::{Serialize, Deserialize };
 use smallvec::SmallVec;
+use crate::balance_manager::rate_limits::Throttled;
+use crate::balance_manager::risk_limits::RiskError;
+use crate::shm::event_queue::{REJECT_REASON_FUNDS, REJECT_REASON_NOT_OWNER, REJECT_REASON_THROTTLED};
 
 
 
@@ -20,7 +23,10 @@ pub struct Fill{
     pub taker_side : Side ,
     pub maker_user_id : u64 ,
     pub taker_user_id : u64 , 
-    pub symbol : u32
+    pub symbol : u32 ,
+    // what each side paid , a negative fee is a rebate , stamped by the balance manager when it settles the fill
+    pub taker_fee : i64 ,
+    pub maker_fee : i64
 }
 
 impl Fill{
@@ -33,7 +39,9 @@ impl Fill{
              maker_user_id , 
              taker_user_id , 
              symbol ,
-             taker_side
+             taker_side ,
+             taker_fee : 0 ,
+             maker_fee : 0
         }
     }
 
@@ -90,7 +98,7 @@ pub enum PubLishError{
 pub enum PublishSuccess{
     
 }
-#[derive(Debug , Clone, Copy )]
+#[derive(Debug , Clone, Copy , PartialEq , Eq)]
 pub enum BalanceManagerError{
     InsufficientFunds ,
     BalanceLockingFailed , 
@@ -100,7 +108,29 @@ pub enum BalanceManagerError{
     BalanceUpdateErrorAfterCancel,
     CouldntAddUser,
     UserAlreadyExists,
-    MaxUsersReached
+    MaxUsersReached,
+    // no open reservation for the order , it was never locked or is already settled
+    ReservationNotFound,
+    // the postings would not balance or would overflow an account
+    PostingRejected,
+    // a pre-trade limit refused the order before anything was reserved
+    RiskLimit(RiskError),
+    // the user's rate limit bucket for the command was empty
+    Throttled(Throttled),
+    // a cancel named an order of another user
+    NotOrderOwner,
+}
+
+impl BalanceManagerError{
+    /// the error_code of the rejected event for an order the lock step refused
+    pub fn reject_reason(self)->u32{
+        match self {
+            Self::RiskLimit(e) => e.reject_reason(),
+            Self::Throttled(_) => REJECT_REASON_THROTTLED,
+            Self::NotOrderOwner => REJECT_REASON_NOT_OWNER,
+            _ => REJECT_REASON_FUNDS,
+        }
+    }
 }
 
 pub struct BalanceInfo{
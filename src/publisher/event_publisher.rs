@@ -1,37 +1,54 @@
-use bounded_spsc_queue::{Consumer, Producer};
-use std::time::{SystemTime, UNIX_EPOCH};
+use bounded_spsc_queue::Producer;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use crate::shutdown::shutdown_signal::ShutdownHandle;
+use crate::backpressure::policy::{channels, BackpressureConfig};
+use crate::backpressure::sender::{escalate, PolicyProducer, PolicySender};
+use crate::metrics::latency::{LatencyRecorder, Stage};
+use crate::watchdog::heartbeat::Heartbeat;
+use crate::supervisor::thread_supervisor::{RestartError, Worker};
+use crate::sharding::fan_in::FanIn;
 use crate::{logger::types::TradeLogs, orderbook::{order::Side, types::{DepthData, Event, TickerData, TradeData}}, pubsub::pubsub_manager::RedisPubSubManager, shm::{event_queue::OrderEvents, fill_queue_mm::MarketMakerFill}};
 
 pub struct EventPublisher { 
     pub mypubsub : RedisPubSubManager ,
-    pub event_queue_from_engine_try : Consumer<Event>,
-    pub event_queue_sender_to_writter_try : Producer<OrderEvents>,
-    pub trade_log_sender_to_logger : Producer<TradeLogs>,
-    pub mm_fill_sender : Producer<MarketMakerFill> ,
+    // one input per engine , a single one outside sharded mode
+    pub event_queue_from_engine_try : FanIn<Event>,
+    pub event_queue_sender_to_writter_try : PolicyProducer<OrderEvents>,
+    pub trade_log_sender_to_logger : PolicyProducer<TradeLogs>,
+    pub mm_fill_sender : PolicyProducer<MarketMakerFill> ,
+    pub latency : LatencyRecorder,
+    pub heartbeat : Option<Heartbeat>,
 }
 impl EventPublisher {
     pub fn new( 
         mypubsub : RedisPubSubManager  ,
-        event_queue_from_engine_try : Consumer<Event>,
+        event_queue_from_engine_try : impl Into<FanIn<Event>>,
         event_queue_sender_to_writter_try : Producer<OrderEvents> ,
         trade_log_sender_to_logger : Producer<TradeLogs>,
         mm_fill_sender : Producer<MarketMakerFill> ,
+        backpressure : &BackpressureConfig ,
     ) -> Self {
         Self {  
             mypubsub , 
-            event_queue_from_engine_try , 
-            event_queue_sender_to_writter_try , 
-            trade_log_sender_to_logger , 
-            mm_fill_sender
+            event_queue_from_engine_try : event_queue_from_engine_try.into() , 
+            event_queue_sender_to_writter_try : PolicySender::from_config(event_queue_sender_to_writter_try, channels::PUBLISHER_ORDER_EVENTS, backpressure) , 
+            trade_log_sender_to_logger : PolicySender::from_config(trade_log_sender_to_logger, channels::PUBLISHER_TRADE_LOGS, backpressure) , 
+            mm_fill_sender : PolicySender::from_config(mm_fill_sender, channels::PUBLISHER_MM_FILLS, backpressure),
+            latency : LatencyRecorder::default(),
+            heartbeat : None,
         }
     }
-    pub fn start_publisher(&mut self) {
+    // runs until shutdown and everything the engine sent before it is published
+    pub fn start_publisher(&mut self , shutdown : &ShutdownHandle) {
         println!("[PUBLISHER] Started (crossbeam batched mode) on core 5");
         loop {
-            
+            if let Some(heartbeat) = self.heartbeat.as_mut() {
+                heartbeat.beat_with_depths(|| self.event_queue_from_engine_try.input_sizes().map(|size| size as u64));
+            }
             match self.event_queue_from_engine_try.try_pop(){
 
                 Some(rec_event) => {
+                    let publish_started = Instant::now();
                     //println!("event recived byy publisher");
                     //println!("sending ticker depth and trade messages for the FE ");
                     {
@@ -65,6 +82,8 @@ impl EventPublisher {
                             }
                         
                     }
+                    // what the incoming order paid over all its fills , goes out on its execution report
+                    let taker_fee : i64 = rec_event.market_update.match_result.fills.fills.iter().map(|fill| fill.taker_fee).sum();
                     {
 
                         for fill in rec_event.market_update.match_result.fills.fills{
@@ -75,7 +94,7 @@ impl EventPublisher {
                                     Side::Ask=>{
                                         // taker was seeling so the market maker was buying 
                                         // the fill is of a buy order , side 0 
-                                        self.mm_fill_sender.push(MarketMakerFill { 
+                                        escalate(self.mm_fill_sender.send(MarketMakerFill { 
                                             order_id_mm_order : fill.maker_order_id,
                                             timestamp : SystemTime::now()
                                             .duration_since(UNIX_EPOCH)
@@ -85,11 +104,11 @@ impl EventPublisher {
                                             fill_quantity: fill.quantity , 
                                             symbol : fill.symbol , 
                                             side_of_mm_order : 0
-                                        });
+                                        }));
                                     }
                                     Side::Bid=>{
                                         // incoming order was buy order so mm was selling 
-                                        self.mm_fill_sender.push(MarketMakerFill { 
+                                        escalate(self.mm_fill_sender.send(MarketMakerFill { 
                                             order_id_mm_order : fill.maker_order_id,
                                             timestamp : SystemTime::now()
                                             .duration_since(UNIX_EPOCH)
@@ -99,7 +118,7 @@ impl EventPublisher {
                                             fill_quantity: fill.quantity , 
                                             symbol : fill.symbol , 
                                             side_of_mm_order : 1
-                                        });
+                                        }));
                                     }
                                 }
                                 
@@ -109,7 +128,7 @@ impl EventPublisher {
                                 // this was the incoming order 
                                 match fill.taker_side{
                                     Side::Ask=>{
-                                        self.mm_fill_sender.push(MarketMakerFill { 
+                                        escalate(self.mm_fill_sender.send(MarketMakerFill { 
                                             order_id_mm_order : fill.taker_order_id,
                                             timestamp : SystemTime::now()
                                             .duration_since(UNIX_EPOCH)
@@ -119,10 +138,10 @@ impl EventPublisher {
                                             fill_quantity: fill.quantity , 
                                             symbol : fill.symbol , 
                                             side_of_mm_order : 1
-                                        });
+                                        }));
                                     }
                                     Side::Bid=>{
-                                        self.mm_fill_sender.push(MarketMakerFill { 
+                                        escalate(self.mm_fill_sender.send(MarketMakerFill { 
                                             order_id_mm_order : fill.taker_order_id,
                                             timestamp : SystemTime::now()
                                             .duration_since(UNIX_EPOCH)
@@ -132,7 +151,7 @@ impl EventPublisher {
                                             fill_quantity: fill.quantity , 
                                             symbol : fill.symbol , 
                                             side_of_mm_order : 0
-                                        });
+                                        }));
                                     }
                                 }
                             }
@@ -163,6 +182,14 @@ impl EventPublisher {
                                 price : fill.price ,
                                 quantity : fill.quantity,
                                 symbol : fill.symbol ,
+                                buyer_fee : match fill.taker_side {
+                                    Side::Bid => fill.taker_fee,
+                                    Side::Ask => fill.maker_fee
+                                } ,
+                                seller_fee : match fill.taker_side {
+                                    Side::Ask => fill.taker_fee,
+                                    Side::Bid => fill.maker_fee
+                                } ,
                                 is_buyer_maker : match fill.taker_side {
                                     Side::Ask => true,
                                     Side::Bid => false
@@ -173,7 +200,7 @@ impl EventPublisher {
                                 .as_nanos() as i64 , 
                             };
 
-                            let _ = self.trade_log_sender_to_logger.try_push(trade_log);
+                            escalate(self.trade_log_sender_to_logger.send(trade_log));
                         }
                     }
                     // we need to send trade messages for all fills 
@@ -186,7 +213,7 @@ impl EventPublisher {
                    // println!("orignal quantiyi {:?}" , orignal_qty);
                    // println!("remaining quantiyi {:?}" , remaining_qty);
                     if remaining_qty == 0  {
-                        let _ = self.event_queue_sender_to_writter_try.push(OrderEvents {
+                        escalate(self.event_queue_sender_to_writter_try.send(OrderEvents {
                              user_id: rec_event.market_update.match_result.user_id, 
                              order_id: rec_event.market_update.match_result.order_id, 
                              symbol: rec_event.market_update.symbol, 
@@ -194,11 +221,12 @@ impl EventPublisher {
                              filled_qty: orignal_qty, 
                              remaining_qty, 
                              original_qty: orignal_qty,  
-                             error_code: 0
-                             });
+                             error_code: 0,
+                             fee: taker_fee
+                             }));
                     }
                     else if remaining_qty == orignal_qty {
-                        let _ = self.event_queue_sender_to_writter_try.push(OrderEvents {
+                        escalate(self.event_queue_sender_to_writter_try.send(OrderEvents {
                             user_id: rec_event.market_update.match_result.user_id, 
                             order_id: rec_event.market_update.match_result.order_id, 
                             symbol: rec_event.market_update.symbol, 
@@ -206,11 +234,12 @@ impl EventPublisher {
                             filled_qty: 0, 
                             remaining_qty, 
                             original_qty: orignal_qty,  
-                            error_code: 0
-                            });
+                            error_code: 0,
+                            fee: 0
+                            }));
                     }
                     else if orignal_qty - remaining_qty > 0 {
-                        let _ = self.event_queue_sender_to_writter_try.push(OrderEvents {
+                        escalate(self.event_queue_sender_to_writter_try.send(OrderEvents {
                             user_id: rec_event.market_update.match_result.user_id, 
                             order_id: rec_event.market_update.match_result.order_id, 
                             symbol: rec_event.market_update.symbol, 
@@ -218,21 +247,45 @@ impl EventPublisher {
                             filled_qty: orignal_qty - remaining_qty, 
                             remaining_qty, 
                             original_qty: orignal_qty,  
-                            error_code: 0
-                            });
+                            error_code: 0,
+                            fee: taker_fee
+                            }));
                     }
+                    self.latency.record_since(Stage::Publish, publish_started);
                 }
                 
                 None =>{
+                    self.latency.flush_if_due();
+                    if shutdown.upstream_finished() && self.event_queue_from_engine_try.size() == 0 {
+                        break;
+                    }
                     std::hint::spin_loop();
                 }
 
             }
 
         }
+        self.latency.flush();
+        // anything a drop oldest channel parked goes out before the writer and logger are let go
+        self.event_queue_sender_to_writter_try.flush_blocking();
+        self.trade_log_sender_to_logger.flush_blocking();
+        self.mm_fill_sender.flush_blocking();
+        self.heartbeat = None;
+        shutdown.finish();
     }
 
     
 }
 
-
+// stateless apart from the redis connection , which is the usual suspect when it panics
+impl Worker for EventPublisher{
+    fn run(&mut self , shutdown : &ShutdownHandle){
+        self.start_publisher(shutdown);
+    }
+    fn restart(&mut self)->Result<() , RestartError>{
+        self.mypubsub.reconnect().map_err(|e| RestartError::reopen("publisher", "the redis connection", e))
+    }
+    fn heartbeat(&self)->Option<&Heartbeat>{
+        self.heartbeat.as_ref()
+    }
+}
@@ -1,6 +1,7 @@
 use redis::{Client, Connection, Commands, RedisError};
 
 pub struct RedisPubSubManager {
+    url: String,
     connection: Connection,
 }
 
@@ -12,7 +13,13 @@ impl RedisPubSubManager {
         
         let connection = client.get_connection()?;
         
-        Ok(Self { connection })
+        Ok(Self { url: redis_url.to_string(), connection })
+    }
+
+    // a fresh connection to the same server , the old one is dropped once the new one is up
+    pub fn reconnect(&mut self) -> Result<(), RedisError> {
+        self.connection = Client::open(self.url.as_str())?.get_connection()?;
+        Ok(())
     }
     
     pub fn publish(&mut self, stream: &str, message: Vec<u8>) -> Result<usize, RedisError> {
@@ -0,0 +1,4 @@
+pub mod primary;
+pub mod protocol;
+pub mod standby;
+pub mod tests;
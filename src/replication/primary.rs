@@ -0,0 +1,339 @@
+// primary side of replication , split between the trading core thread and a replication thread of its own
+// the core stages every accepted record and hands the batch over once it is in the journal , it never waits on a standby
+// the replication thread accepts standbys , catches them up from the journal and writes to their sockets
+// so a standby is never ahead of the primary's own journal
+use std::io::Write;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use crossbeam::channel::{Receiver, RecvTimeoutError, Sender, TrySendError};
+use crate::journal::command_journal::{JournalError, JournalReader, JournalRecord};
+use crate::replication::protocol::{encode_digest, encode_record, read_handshake, MessageDecoder, ReplicationError, ReplicationMessage};
+
+// how often the replication thread looks for new standbys , and how often the core sends a state digest
+pub const POLL_INTERVAL : Duration = Duration::from_millis(200);
+pub const DIGEST_INTERVAL : Duration = Duration::from_secs(1);
+// catch up writes block the replication thread , a standby that cant take a chunk within this is dropped
+const WRITE_TIMEOUT : Duration = Duration::from_millis(100);
+const HANDSHAKE_TIMEOUT : Duration = Duration::from_millis(500);
+// batches the replication thread has not taken yet , the core drops a batch rather than wait
+// standbys then get the records of a dropped batch from the journal ahead of the next one
+const BATCHES_IN_FLIGHT : usize = 1024;
+// bytes a standby has not taken off its socket yet , past this it is dropped , it reconnects and catches up from the journal
+const MAX_PENDING : usize = 1 << 24;
+// how long the replication thread waits for a batch before it writes to its standbys again
+const IDLE_WAIT : Duration = Duration::from_millis(10);
+const CATCH_UP_CHUNK : usize = 1 << 16;
+
+// the records after sequence `from` up to `through` , and the digest at `through` when one was due
+struct Batch{
+    from : u64,
+    through : u64,
+    bytes : Vec<u8>,
+}
+
+// what the core and the replication thread both look at
+#[derive(Default)]
+struct Shared{
+    standbys : AtomicUsize,
+    // the last sequence in the journal , a standby that connects is caught up to it from the file
+    journalled : AtomicU64,
+    stop : AtomicBool,
+}
+
+pub struct ReplicationServer{
+    socket_path : PathBuf,
+    batches : Sender<Batch>,
+    // buffers of batches already written , filled again instead of allocating
+    recycled : Receiver<Vec<u8>>,
+    outgoing : Vec<u8>,
+    staged : u64,
+    // standbys were connected when the batch started , a batch is staged whole or not at all
+    streaming : bool,
+    overflowing : bool,
+    last_digest : Instant,
+    shared : Arc<Shared>,
+    thread : Option<JoinHandle<()>>,
+}
+
+impl ReplicationServer{
+    /// listens on `socket_path` , standbys that connect are caught up from the journal at `journal_path`
+    /// `journalled` is the last sequence already in that journal
+    pub fn bind<P : AsRef<Path> , J : AsRef<Path>>(socket_path : P , journal_path : J , journalled : u64)->Result<Self , ReplicationError>{
+        let socket_path = socket_path.as_ref().to_path_buf();
+        // a socket file left by a dead primary would make bind fail
+        let _ = std::fs::remove_file(&socket_path);
+        let listener = UnixListener::bind(&socket_path)?;
+        listener.set_nonblocking(true)?;
+        let shared = Arc::new(Shared { journalled : AtomicU64::new(journalled) , ..Shared::default() });
+        let (batches , batch_receiver) = crossbeam::channel::bounded(BATCHES_IN_FLIGHT);
+        let (recycler , recycled) = crossbeam::channel::bounded(BATCHES_IN_FLIGHT);
+        let replicator = Replicator {
+            listener,
+            journal_path : journal_path.as_ref().to_path_buf(),
+            batches : batch_receiver,
+            recycler,
+            shared : shared.clone(),
+            standbys : Vec::new(),
+            last_poll : None,
+        };
+        let thread = std::thread::Builder::new().name("replication".into()).spawn(move || replicator.run())?;
+        Ok(Self {
+            socket_path,
+            batches,
+            recycled,
+            outgoing : Vec::with_capacity(1 << 16),
+            staged : 0,
+            streaming : false,
+            overflowing : false,
+            last_digest : Instant::now(),
+            shared,
+            thread : Some(thread),
+        })
+    }
+
+    pub fn standby_count(&self)->usize{
+        self.shared.standbys.load(Ordering::Acquire)
+    }
+
+    #[inline(always)]
+    pub fn stage(&mut self , record : &JournalRecord){
+        if self.streaming {
+            encode_record(record, &mut self.outgoing);
+            self.staged += 1;
+        }
+    }
+
+    pub fn digest_due(&self)->bool{
+        self.streaming && self.last_digest.elapsed() >= DIGEST_INTERVAL
+    }
+
+    /// queues the digest of the state after applying `sequence` , goes out with the next publish
+    pub fn stage_digest(&mut self , sequence : u64 , digest : u64){
+        encode_digest(sequence, digest, &mut self.outgoing);
+        self.last_digest = Instant::now();
+    }
+
+    /// hands everything staged up to `sequence` to the replication thread , call only after the journal is flushed
+    pub fn publish(&mut self , sequence : u64){
+        self.shared.journalled.store(sequence, Ordering::Release);
+        if !self.outgoing.is_empty() {
+            let bytes = std::mem::replace(&mut self.outgoing, self.recycled.try_recv().unwrap_or_default());
+            match self.batches.try_send(Batch { from : sequence - self.staged , through : sequence , bytes }) {
+                Ok(()) => self.overflowing = false,
+                Err(TrySendError::Full(batch) | TrySendError::Disconnected(batch)) => {
+                    if !self.overflowing {
+                        eprintln!("[Replication] dropping batches from sequence {} , standbys will catch up from the journal", batch.from + 1);
+                    }
+                    self.overflowing = true;
+                    self.outgoing = batch.bytes;
+                    self.outgoing.clear();
+                }
+            }
+        }
+        self.staged = 0;
+        self.streaming = self.standby_count() > 0;
+    }
+}
+
+impl Drop for ReplicationServer{
+    fn drop(&mut self){
+        self.shared.stop.store(true, Ordering::Release);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        let _ = std::fs::remove_file(&self.socket_path);
+    }
+}
+
+struct Follower{
+    stream : UnixStream,
+    // the last sequence written to `pending` or the socket
+    sent_through : u64,
+    pending : Vec<u8>,
+}
+
+impl Follower{
+    // writes what the socket takes without waiting , false once the standby has to go
+    fn flush(&mut self)->bool{
+        let mut written = 0;
+        while written < self.pending.len() {
+            match self.stream.write(&self.pending[written..]) {
+                Ok(0) => return false,
+                Ok(n) => written += n,
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(e) => {
+                    eprintln!("[Replication] dropping standby: {}", e);
+                    return false;
+                }
+            }
+        }
+        self.pending.drain(..written);
+        if self.pending.len() > MAX_PENDING {
+            eprintln!("[Replication] dropping standby at sequence {} , {} bytes behind", self.sent_through, self.pending.len());
+            return false;
+        }
+        true
+    }
+}
+
+// the replication thread
+struct Replicator{
+    listener : UnixListener,
+    journal_path : PathBuf,
+    batches : Receiver<Batch>,
+    recycler : Sender<Vec<u8>>,
+    shared : Arc<Shared>,
+    standbys : Vec<Follower>,
+    last_poll : Option<Instant>,
+}
+
+impl Replicator{
+    fn run(mut self){
+        while !self.shared.stop.load(Ordering::Acquire) {
+            if self.last_poll.is_none_or(|polled| polled.elapsed() >= POLL_INTERVAL) {
+                self.poll_standbys();
+            }
+            match self.batches.recv_timeout(IDLE_WAIT) {
+                Ok(batch) => {
+                    self.forward(batch);
+                    while let Ok(batch) = self.batches.try_recv() {
+                        self.forward(batch);
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+            self.standbys.retain_mut(Follower::flush);
+            self.shared.standbys.store(self.standbys.len(), Ordering::Release);
+        }
+    }
+
+    // accepts waiting standbys and catches each up to what is in the journal now
+    fn poll_standbys(&mut self){
+        self.last_poll = Some(Instant::now());
+        loop {
+            match self.listener.accept() {
+                Ok((stream , _)) => match self.connect(stream) {
+                    Ok(follower) => {
+                        eprintln!("[Replication] standby caught up to sequence {}", follower.sent_through);
+                        self.standbys.push(follower);
+                    }
+                    Err(e) => eprintln!("[Replication] rejected standby: {}", e),
+                },
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    eprintln!("[Replication] accept failed: {}", e);
+                    break;
+                }
+            }
+        }
+        self.shared.standbys.store(self.standbys.len(), Ordering::Release);
+    }
+
+    fn connect(&self , mut stream : UnixStream)->Result<Follower , ReplicationError>{
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+        stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+        let standby_sequence = read_handshake(&mut stream)?;
+        let journalled = self.shared.journalled.load(Ordering::Acquire);
+        if standby_sequence > journalled {
+            return Err(ReplicationError::StandbyAhead { standby : standby_sequence , primary : journalled });
+        }
+        let mut follower = Follower { stream , sent_through : standby_sequence , pending : Vec::new() };
+        self.catch_up(&mut follower, journalled)?;
+        Ok(follower)
+    }
+
+    // every standby gets the records of `batch` it does not have yet , from the journal when batches were dropped before it
+    fn forward(&mut self , batch : Batch){
+        let mut standbys = std::mem::take(&mut self.standbys);
+        standbys.retain_mut(|follower| {
+            if batch.through <= follower.sent_through {
+                return true;
+            }
+            let sent = if batch.from > follower.sent_through {
+                self.catch_up(follower, batch.from).map(|()| follower.pending.extend_from_slice(&batch.bytes))
+            } else if batch.from == follower.sent_through {
+                follower.pending.extend_from_slice(&batch.bytes);
+                Ok(())
+            } else {
+                // it was caught up from the journal past the start of this batch
+                Self::append_after(follower, &batch.bytes)
+            };
+            match sent {
+                Ok(()) => {
+                    follower.sent_through = batch.through;
+                    true
+                }
+                Err(e) => {
+                    eprintln!("[Replication] dropping standby at sequence {}: {}", follower.sent_through, e);
+                    false
+                }
+            }
+        });
+        self.standbys = standbys;
+        let mut bytes = batch.bytes;
+        bytes.clear();
+        let _ = self.recycler.try_send(bytes);
+    }
+
+    fn append_after(follower : &mut Follower , bytes : &[u8])->Result<() , ReplicationError>{
+        let mut decoder = MessageDecoder::new();
+        decoder.extend(bytes);
+        while let Some(message) = decoder.next_message()? {
+            match message {
+                ReplicationMessage::Record(record) if record.sequence > follower.sent_through => encode_record(&record, &mut follower.pending),
+                ReplicationMessage::Digest { sequence , digest } if sequence > follower.sent_through => encode_digest(sequence, digest, &mut follower.pending),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    // writes the journal after the follower's last sequence up to `through` , blocking on the socket
+    fn catch_up(&self , follower : &mut Follower , through : u64)->Result<() , ReplicationError>{
+        if follower.sent_through >= through {
+            return Ok(());
+        }
+        follower.stream.set_nonblocking(false)?;
+        follower.stream.write_all(&follower.pending)?;
+        follower.pending.clear();
+
+        let after = follower.sent_through;
+        let mut reader = JournalReader::open(&self.journal_path)?;
+        let mut buf = Vec::with_capacity(CATCH_UP_CHUNK);
+        let mut expected = after + 1;
+        while expected <= through {
+            let record = match reader.next_record() {
+                Ok(Some(record)) => record,
+                Ok(None) | Err(JournalError::TornTail { .. }) => break,
+                Err(e) => return Err(e.into()),
+            };
+            if record.sequence <= after {
+                continue;
+            }
+            // the journal starts after a checkpoint , the standby needs a copy of that checkpoint first
+            if record.sequence != expected {
+                return Err(ReplicationError::CannotCatchUp { standby : after , journal_start : record.sequence });
+            }
+            expected += 1;
+            encode_record(&record, &mut buf);
+            if buf.len() >= CATCH_UP_CHUNK {
+                follower.stream.write_all(&buf)?;
+                buf.clear();
+            }
+        }
+        if expected != through + 1 {
+            return Err(ReplicationError::CannotCatchUp { standby : after , journal_start : expected });
+        }
+        follower.stream.write_all(&buf)?;
+        follower.stream.set_nonblocking(true)?;
+        follower.sent_through = through;
+        Ok(())
+    }
+}
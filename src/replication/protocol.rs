@@ -0,0 +1,141 @@
+// wire format between the primary trading core and a standby , over a local unix socket
+// standby -> primary , once after connecting : magic u32 | last applied sequence u64
+// primary -> standby , a stream of messages : tag u8 | body_len u32 | body
+//   TAG_RECORD body is a journal frame exactly as it sits in the journal file (own magic and crc)
+//   TAG_DIGEST body is sequence u64 | state digest u64 , sent right after the record with that sequence
+use std::io::{Read, Write};
+use thiserror::Error;
+use crate::journal::codec::{ByteReader, ByteWriter};
+use crate::journal::command_journal::{JournalError, JournalReader, JournalRecord};
+
+pub const HANDSHAKE_MAGIC : u32 = 0x5350_4C52; // "RLPS"
+pub const HANDSHAKE_SIZE : usize = 12;
+pub const TAG_RECORD : u8 = 1;
+pub const TAG_DIGEST : u8 = 2;
+const MESSAGE_HEADER_SIZE : usize = 5;
+// a journal frame is at most a few hundred bytes , anything bigger is a broken stream
+const MAX_BODY_SIZE : usize = 4096;
+
+#[derive(Debug , Error)]
+pub enum ReplicationError{
+    #[error("replication io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("bad record in the replication stream: {0}")]
+    Journal(#[from] JournalError),
+    #[error("bad handshake from standby")]
+    BadHandshake,
+    #[error("unknown message tag {tag} (body of {len} bytes)")]
+    UnknownMessage { tag : u8 , len : usize },
+    #[error("out of order record , expected sequence {expected} got {got}")]
+    OutOfOrder { expected : u64 , got : u64 },
+    #[error("standby is at sequence {standby} but the primary is only at {primary}")]
+    StandbyAhead { standby : u64 , primary : u64 },
+    #[error("primary journal cannot catch up a standby at sequence {standby} , journal resumes at {journal_start}")]
+    CannotCatchUp { standby : u64 , journal_start : u64 },
+    #[error("primary closed the replication stream")]
+    Disconnected,
+}
+
+#[derive(Debug , Clone , Copy)]
+pub enum ReplicationMessage{
+    Record(JournalRecord),
+    Digest { sequence : u64 , digest : u64 },
+}
+
+pub fn write_handshake<W : Write>(stream : &mut W , last_sequence : u64)->Result<() , ReplicationError>{
+    let mut buf = Vec::with_capacity(HANDSHAKE_SIZE);
+    let mut w = ByteWriter::new(&mut buf);
+    w.put_u32(HANDSHAKE_MAGIC);
+    w.put_u64(last_sequence);
+    stream.write_all(&buf)?;
+    Ok(())
+}
+
+pub fn read_handshake<R : Read>(stream : &mut R)->Result<u64 , ReplicationError>{
+    let mut buf = [0u8 ; HANDSHAKE_SIZE];
+    stream.read_exact(&mut buf)?;
+    let mut r = ByteReader::new(&buf);
+    if r.get_u32() != Some(HANDSHAKE_MAGIC) {
+        return Err(ReplicationError::BadHandshake);
+    }
+    r.get_u64().ok_or(ReplicationError::BadHandshake)
+}
+
+fn encode_message(tag : u8 , buf : &mut Vec<u8> , body : impl FnOnce(&mut Vec<u8>)){
+    let start = buf.len();
+    buf.push(tag);
+    buf.extend_from_slice(&[0u8 ; 4]);
+    body(buf);
+    let body_len = (buf.len() - start - MESSAGE_HEADER_SIZE) as u32;
+    buf[start + 1..start + MESSAGE_HEADER_SIZE].copy_from_slice(&body_len.to_le_bytes());
+}
+
+pub fn encode_record(record : &JournalRecord , buf : &mut Vec<u8>){
+    encode_message(TAG_RECORD, buf, |body| record.encode_into(body));
+}
+
+pub fn encode_digest(sequence : u64 , digest : u64 , buf : &mut Vec<u8>){
+    encode_message(TAG_DIGEST, buf, |body| {
+        let mut w = ByteWriter::new(body);
+        w.put_u64(sequence);
+        w.put_u64(digest);
+    });
+}
+
+// collects bytes as they come off the socket and hands out whole messages
+// reads on the standby side time out so we can check for promotion , a message may arrive in pieces
+#[derive(Default)]
+pub struct MessageDecoder{
+    buf : Vec<u8>,
+    start : usize,
+}
+
+impl MessageDecoder{
+    pub fn new()->Self{
+        Self::default()
+    }
+
+    pub fn extend(&mut self , bytes : &[u8]){
+        // compact once everything before start has been consumed
+        if self.start > 0 && self.start == self.buf.len() {
+            self.buf.clear();
+            self.start = 0;
+        }
+        self.buf.extend_from_slice(bytes);
+    }
+
+    pub fn next_message(&mut self)->Result<Option<ReplicationMessage> , ReplicationError>{
+        let pending = &self.buf[self.start..];
+        if pending.len() < MESSAGE_HEADER_SIZE {
+            return Ok(None);
+        }
+        let tag = pending[0];
+        let body_len = u32::from_le_bytes(pending[1..MESSAGE_HEADER_SIZE].try_into().unwrap()) as usize;
+        if body_len > MAX_BODY_SIZE {
+            return Err(ReplicationError::UnknownMessage { tag , len : body_len });
+        }
+        if pending.len() < MESSAGE_HEADER_SIZE + body_len {
+            return Ok(None);
+        }
+        let body = &pending[MESSAGE_HEADER_SIZE..MESSAGE_HEADER_SIZE + body_len];
+        let message = match tag {
+            TAG_RECORD => {
+                let mut reader = JournalReader::new(body);
+                match reader.next_record()? {
+                    Some(record) => ReplicationMessage::Record(record),
+                    None => return Err(ReplicationError::UnknownMessage { tag , len : body_len }),
+                }
+            }
+            TAG_DIGEST => {
+                let mut r = ByteReader::new(body);
+                match (r.get_u64() , r.get_u64()) {
+                    (Some(sequence) , Some(digest)) => ReplicationMessage::Digest { sequence , digest },
+                    _ => return Err(ReplicationError::UnknownMessage { tag , len : body_len }),
+                }
+            }
+            _ => return Err(ReplicationError::UnknownMessage { tag , len : body_len }),
+        };
+        self.start += MESSAGE_HEADER_SIZE + body_len;
+        Ok(Some(message))
+    }
+}
@@ -0,0 +1,191 @@
+// standby side of replication
+// follows the primary's command stream into its own core , journals every record locally and checks the primary's digests
+// it never opens the primary's shared memory queues , on promotion the caller takes the core and starts it as the primary
+// promotion is ADMIN_PROMOTE on the standby's own admin queues , authorized like any admin command , or the caller's flag
+use std::io::Read;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use crate::admin::commands::{admin_response, AdminAction, AdminError, AdminReply};
+use crate::admin::plane::AdminPlane;
+use crate::journal::replay::ReplaySink;
+use crate::replication::protocol::{write_handshake, MessageDecoder, ReplicationError, ReplicationMessage};
+use crate::shm::admin_command_queue::AdminCommandQueue;
+use crate::shm::admin_response_queue::AdminResponseQueue;
+use crate::trading_core::my_trading_core::TradingCore;
+
+// bounds how long a promotion request waits for a blocked read
+const READ_TIMEOUT : Duration = Duration::from_millis(100);
+const RECONNECT_DELAY : Duration = Duration::from_millis(500);
+
+#[derive(Debug , Default , Clone , Copy)]
+pub struct StandbyStats{
+    pub records_applied : u64,
+    pub digests_matched : u64,
+    pub digests_mismatched : u64,
+    pub reconnects : u64,
+}
+
+// the standby's own admin queues , the primary's stay with the primary until promotion
+pub struct StandbyAdmin{
+    pub plane : AdminPlane,
+    pub commands : AdminCommandQueue,
+    pub responses : AdminResponseQueue,
+}
+
+pub struct Standby{
+    core : TradingCore,
+    sink : ReplaySink,
+    socket_path : PathBuf,
+    promote : Arc<AtomicBool>,
+    admin : Option<StandbyAdmin>,
+    pub stats : StandbyStats,
+}
+
+impl Standby{
+    /// `core` should already hold the standby's recovered state with its own journal attached
+    pub fn new<P : AsRef<Path>>(core : TradingCore , sink : ReplaySink , socket_path : P , promote : Arc<AtomicBool>)->Self{
+        Self {
+            core,
+            sink,
+            socket_path : socket_path.as_ref().to_path_buf(),
+            promote,
+            admin : None,
+            stats : StandbyStats::default(),
+        }
+    }
+
+    /// takes ADMIN_PROMOTE from `admin` besides the promote flag
+    pub fn with_admin(mut self , admin : StandbyAdmin)->Self{
+        self.admin = Some(admin);
+        self
+    }
+
+    fn promoted(&mut self)->bool{
+        self.poll_admin();
+        self.promote.load(Ordering::Acquire)
+    }
+
+    // answers every waiting admin command , an authorized ADMIN_PROMOTE raises the promote flag
+    // the standby's state only moves with the primary's , so everything else is refused
+    fn poll_admin(&mut self){
+        let Some(admin) = self.admin.as_mut() else {
+            return;
+        };
+        loop {
+            let command = match admin.commands.dequeue() {
+                Ok(Some(command)) => command,
+                Ok(None) => break,
+                Err(e) => {
+                    eprintln!("[Standby] admin command queue failed: {:?}", e);
+                    break;
+                }
+            };
+            let request = admin.plane.authorize(command);
+            let result = if request.authorized {
+                AdminAction::decode(&command).and_then(|action| match action {
+                    AdminAction::Promote => Ok(AdminReply::Done),
+                    _ => Err(AdminError::Standby(command.command_type)),
+                })
+            } else {
+                Err(AdminError::Unauthorized(command.operator_id))
+            };
+            match &result {
+                Ok(_) => {
+                    eprintln!("[Standby] promotion requested by operator {} at sequence {}", command.operator_id, self.core.sequence);
+                    self.promote.store(true, Ordering::Release);
+                }
+                Err(e) => eprintln!("[Standby] admin request {} (type {}) by operator {} refused: {}", command.request_id, command.command_type, command.operator_id, e),
+            }
+            if let Err(e) = admin.responses.enqueue(admin_response(&command, self.core.sequence, &result)) {
+                eprintln!("[Standby] admin response {} dropped: {:?}", command.request_id, e);
+            }
+        }
+    }
+
+    /// follows the primary until promoted , by ADMIN_PROMOTE or the promote flag , then hands back the core
+    /// losing the primary does not promote on its own , that call is left to the operator
+    pub fn run_until_promoted(mut self)->(TradingCore , StandbyStats){
+        eprintln!("[Standby] following {:?} from sequence {}", self.socket_path, self.core.sequence);
+        while !self.promoted() {
+            match UnixStream::connect(&self.socket_path) {
+                Ok(stream) => match self.follow(stream) {
+                    Ok(()) => {}
+                    Err(e) => {
+                        eprintln!("[Standby] lost the primary at sequence {}: {}", self.core.sequence, e);
+                        self.stats.reconnects += 1;
+                    }
+                },
+                Err(_) => std::thread::sleep(RECONNECT_DELAY),
+            }
+        }
+        if let Some(journal) = self.core.journal.as_mut() && let Err(e) = journal.sync() {
+            panic!("[Standby] journal sync failed on promotion: {}", e);
+        }
+        eprintln!("[Standby] promoted at sequence {} , {:?}", self.core.sequence, self.stats);
+        (self.core , self.stats)
+    }
+
+    // returns Ok only when promoted
+    fn follow(&mut self , mut stream : UnixStream)->Result<() , ReplicationError>{
+        stream.set_read_timeout(Some(READ_TIMEOUT))?;
+        write_handshake(&mut stream, self.core.sequence)?;
+
+        let mut decoder = MessageDecoder::new();
+        let mut chunk = vec![0u8 ; 1 << 16];
+        let mut last_checkpoint = Instant::now();
+        while !self.promoted() {
+            match stream.read(&mut chunk) {
+                Ok(0) => return Err(ReplicationError::Disconnected),
+                Ok(n) => decoder.extend(&chunk[..n]),
+                Err(e) if matches!(e.kind() , std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut) => continue,
+                Err(e) => return Err(e.into()),
+            }
+            while let Some(message) = decoder.next_message()? {
+                self.handle(message)?;
+            }
+            if let Some(journal) = self.core.journal.as_mut() {
+                journal.flush()?;
+            }
+            if last_checkpoint.elapsed() >= self.core.checkpoint_interval {
+                if let Err(e) = self.core.checkpoint() {
+                    eprintln!("[Standby] checkpoint at sequence {} failed: {}", self.core.sequence, e);
+                }
+                last_checkpoint = Instant::now();
+            }
+        }
+        Ok(())
+    }
+
+    fn handle(&mut self , message : ReplicationMessage)->Result<() , ReplicationError>{
+        match message {
+            ReplicationMessage::Record(record) => {
+                if record.sequence != self.core.sequence + 1 {
+                    return Err(ReplicationError::OutOfOrder { expected : self.core.sequence + 1 , got : record.sequence });
+                }
+                if let Some(journal) = self.core.journal.as_mut() {
+                    journal.append(&record)?;
+                }
+                self.core.apply_record(&record);
+                self.sink.drain();
+                self.stats.records_applied += 1;
+            }
+            ReplicationMessage::Digest { sequence , digest } => {
+                // digests follow the record they were taken at , so we are at the same sequence here
+                if sequence != self.core.sequence {
+                    return Err(ReplicationError::OutOfOrder { expected : self.core.sequence , got : sequence });
+                }
+                let ours = self.core.state_digest();
+                if ours == digest {
+                    self.stats.digests_matched += 1;
+                } else {
+                    self.stats.digests_mismatched += 1;
+                    eprintln!("[Standby] STATE DIVERGED at sequence {} , primary {:016x} standby {:016x}", sequence, digest, ours);
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,235 @@
+#[cfg(test)]
+mod tests {
+    use std::os::unix::net::UnixStream;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::Duration;
+    use crate::journal::command_journal::{InboundCommand, JournalRecord, JournalWriter};
+    use crate::journal::replay::detached_core;
+    use crate::orderbook::order::{Order, OrderToBeCanceled, Side};
+    use crate::replication::primary::{ReplicationServer, DIGEST_INTERVAL, POLL_INTERVAL};
+    use crate::replication::protocol::{encode_digest, encode_record, write_handshake, MessageDecoder, ReplicationMessage};
+    use crate::replication::standby::{Standby, StandbyAdmin};
+    use crate::admin::plane::AdminPlane;
+    use crate::shm::admin_command_queue::{AdminCommand, AdminCommandQueue, ADMIN_HALT_SYMBOL, ADMIN_PROMOTE};
+    use crate::shm::admin_response_queue::{AdminResponse, AdminResponseQueue, ADMIN_STATUS_OK, ADMIN_STATUS_UNAUTHORIZED, ADMIN_STATUS_WRONG_ROLE};
+
+    fn temp_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("replication_test_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    fn limit(user_id: u64, order_id: u64, side: Side, qty: u32, price: u64) -> InboundCommand {
+        InboundCommand::NewOrder(Order::new(user_id, order_id, side, 1, qty, price, order_id, 0))
+    }
+
+    fn commands() -> Vec<InboundCommand> {
+        vec![
+            limit(20, 1, Side::Ask, 50, 10),
+            limit(20, 2, Side::Ask, 30, 11),
+            limit(10, 3, Side::Bid, 60, 11),
+            limit(10, 4, Side::Bid, 40, 9),
+            InboundCommand::CancelOrder(OrderToBeCanceled { order_id: 4, user_id: 10, symbol: 0 }),
+            limit(20, 5, Side::Ask, 10, 12),
+            limit(10, 6, Side::Bid, 25, 12),
+        ]
+    }
+
+    #[test]
+    fn test_decoder_handles_split_messages() {
+        let mut bytes = Vec::new();
+        encode_record(&JournalRecord::new(1, limit(10, 1, Side::Bid, 5, 100)), &mut bytes);
+        encode_digest(1, 0xABCD, &mut bytes);
+
+        let mut decoder = MessageDecoder::new();
+        let mut decoded = Vec::new();
+        // one byte at a time , the worst a socket read can do to us
+        for byte in bytes.iter() {
+            decoder.extend(std::slice::from_ref(byte));
+            while let Some(message) = decoder.next_message().unwrap() {
+                decoded.push(message);
+            }
+        }
+        assert_eq!(decoded.len(), 2);
+        assert!(matches!(decoded[0], ReplicationMessage::Record(record) if record.sequence == 1));
+        assert!(matches!(decoded[1], ReplicationMessage::Digest { sequence: 1, digest: 0xABCD }));
+    }
+
+    #[test]
+    fn test_standby_catches_up_follows_and_promotes() {
+        let socket_path = temp_path("socket");
+        let primary_journal = temp_path("primary.journal");
+        let standby_journal = temp_path("standby.journal");
+
+        let (mut primary, mut primary_sink) = detached_core();
+        primary.journal = Some(JournalWriter::open(&primary_journal).unwrap());
+        primary.replication = Some(ReplicationServer::bind(&socket_path, &primary_journal, primary.sequence).unwrap());
+        let commands = commands();
+        // these reach the standby through the journal catch up
+        for command in commands.iter().take(3) {
+            primary.submit(*command);
+            primary_sink.drain();
+        }
+
+        let promote = Arc::new(AtomicBool::new(false));
+        let standby_handle = {
+            let promote = promote.clone();
+            let socket_path = socket_path.clone();
+            let standby_journal = standby_journal.clone();
+            std::thread::spawn(move || {
+                let (mut core, sink) = detached_core();
+                core.journal = Some(JournalWriter::open(&standby_journal).unwrap());
+                Standby::new(core, sink, &socket_path, promote).run_until_promoted()
+            })
+        };
+
+        std::thread::sleep(POLL_INTERVAL + Duration::from_millis(100));
+        primary.submit(commands[3]);
+        primary_sink.drain();
+        assert_eq!(primary.replication.as_ref().unwrap().standby_count(), 1);
+
+        // these are streamed live , the last one also carries a digest
+        std::thread::sleep(DIGEST_INTERVAL);
+        for command in commands.iter().skip(4) {
+            primary.submit(*command);
+            primary_sink.drain();
+        }
+
+        std::thread::sleep(Duration::from_millis(300));
+        promote.store(true, Ordering::Release);
+        let (standby, stats) = standby_handle.join().unwrap();
+
+        assert_eq!(standby.sequence, primary.sequence);
+        assert_eq!(standby.state_digest(), primary.state_digest());
+        assert_eq!(stats.records_applied, commands.len() as u64);
+        assert!(stats.digests_matched >= 1);
+        assert_eq!(stats.digests_mismatched, 0);
+        // the promoted standby has the whole history in its own journal
+        assert_eq!(standby.journal.as_ref().unwrap().last_sequence(), primary.sequence);
+
+        drop(standby);
+        drop(primary);
+        let _ = std::fs::remove_file(&primary_journal);
+        let _ = std::fs::remove_file(&standby_journal);
+    }
+
+    #[test]
+    fn test_standby_promotes_on_an_authorized_admin_command() {
+        const OPERATOR: u64 = 7;
+        let socket_path = temp_path("promote_socket");
+        let primary_journal = temp_path("promote_primary.journal");
+        let standby_journal = temp_path("promote_standby.journal");
+        let command_path = temp_path("promote_admin_commands");
+        let response_path = temp_path("promote_admin_responses");
+
+        let (mut primary, mut primary_sink) = detached_core();
+        primary.journal = Some(JournalWriter::open(&primary_journal).unwrap());
+        primary.replication = Some(ReplicationServer::bind(&socket_path, &primary_journal, primary.sequence).unwrap());
+        let mut tool_commands = AdminCommandQueue::create(&command_path).unwrap();
+        let mut tool_responses = AdminResponseQueue::create(&response_path).unwrap();
+        let mut send = |request_id: u64, operator_id: u64, command_type: u8| -> AdminResponse {
+            tool_commands.enqueue(AdminCommand { request_id, operator_id, command_type, ..AdminCommand::default() }).unwrap();
+            let started = std::time::Instant::now();
+            loop {
+                if let Some(response) = tool_responses.dequeue().unwrap() {
+                    return response;
+                }
+                assert!(started.elapsed() < Duration::from_secs(5), "no response to admin request {}", request_id);
+                std::thread::sleep(Duration::from_millis(10));
+            }
+        };
+
+        // the test never raises the promote flag itself , only the admin command does
+        let promote = Arc::new(AtomicBool::new(false));
+        let standby_handle = {
+            let promote = promote.clone();
+            let (socket_path, standby_journal) = (socket_path.clone(), standby_journal.clone());
+            let (command_path, response_path) = (command_path.clone(), response_path.clone());
+            std::thread::spawn(move || {
+                let (mut core, sink) = detached_core();
+                core.journal = Some(JournalWriter::open(&standby_journal).unwrap());
+                let admin = StandbyAdmin {
+                    plane: AdminPlane::new([OPERATOR]),
+                    commands: AdminCommandQueue::open(&command_path).unwrap(),
+                    responses: AdminResponseQueue::open(&response_path).unwrap(),
+                };
+                Standby::new(core, sink, &socket_path, promote).with_admin(admin).run_until_promoted()
+            })
+        };
+        std::thread::sleep(POLL_INTERVAL + Duration::from_millis(100));
+        for command in commands() {
+            primary.submit(command);
+            primary_sink.drain();
+        }
+        std::thread::sleep(Duration::from_millis(300));
+
+        // an unknown operator cannot promote , and a standby takes nothing but promotion
+        assert_eq!(send(1, OPERATOR + 1, ADMIN_PROMOTE).status, ADMIN_STATUS_UNAUTHORIZED);
+        assert_eq!(send(2, OPERATOR, ADMIN_HALT_SYMBOL).status, ADMIN_STATUS_WRONG_ROLE);
+        assert!(!standby_handle.is_finished());
+
+        // the primary goes away , the standby waiting to reconnect is promoted by the operator
+        let (sequence, digest) = (primary.sequence, primary.state_digest());
+        drop(primary);
+        std::thread::sleep(Duration::from_millis(200));
+        let response = send(3, OPERATOR, ADMIN_PROMOTE);
+        assert_eq!((response.request_id, response.status, response.sequence), (3, ADMIN_STATUS_OK, sequence));
+        let (standby, stats) = standby_handle.join().unwrap();
+        assert_eq!(standby.sequence, sequence);
+        assert_eq!(standby.state_digest(), digest);
+        assert_eq!(stats.records_applied, commands().len() as u64);
+
+        drop(standby);
+        for path in [primary_journal, standby_journal, command_path, response_path] {
+            let _ = std::fs::remove_file(&path);
+        }
+    }
+
+    #[test]
+    fn test_a_standby_that_stops_reading_never_holds_up_the_primary() {
+        let socket_path = temp_path("stalled_socket");
+        let primary_journal = temp_path("stalled_primary.journal");
+        let standby_journal = temp_path("stalled_standby.journal");
+
+        let (mut primary, mut primary_sink) = detached_core();
+        primary.journal = Some(JournalWriter::open(&primary_journal).unwrap());
+        primary.replication = Some(ReplicationServer::bind(&socket_path, &primary_journal, primary.sequence).unwrap());
+        // handshakes and never reads , its socket buffer fills up long before the primary is done
+        let mut stalled = UnixStream::connect(&socket_path).unwrap();
+        write_handshake(&mut stalled, 0).unwrap();
+
+        let promote = Arc::new(AtomicBool::new(false));
+        let standby_handle = {
+            let promote = promote.clone();
+            let (socket_path, standby_journal) = (socket_path.clone(), standby_journal.clone());
+            std::thread::spawn(move || {
+                let (mut core, sink) = detached_core();
+                core.journal = Some(JournalWriter::open(&standby_journal).unwrap());
+                Standby::new(core, sink, &socket_path, promote).run_until_promoted()
+            })
+        };
+        std::thread::sleep(POLL_INTERVAL + Duration::from_millis(100));
+        assert_eq!(primary.replication.as_ref().unwrap().standby_count(), 2);
+
+        const ORDERS: u64 = 3000;
+        for order_id in 1..=ORDERS {
+            let (user_id, side, price) = if order_id % 2 == 0 { (10, Side::Bid, 9) } else { (20, Side::Ask, 11) };
+            primary.submit(limit(user_id, order_id, side, 1, price));
+            primary_sink.drain();
+        }
+        std::thread::sleep(Duration::from_millis(500));
+        promote.store(true, Ordering::Release);
+        let (standby, stats) = standby_handle.join().unwrap();
+        assert_eq!(standby.sequence, ORDERS);
+        assert_eq!(standby.state_digest(), primary.state_digest());
+        assert_eq!(stats.records_applied, ORDERS);
+
+        drop(stalled);
+        drop(standby);
+        drop(primary);
+        let _ = std::fs::remove_file(&primary_journal);
+        let _ = std::fs::remove_file(&standby_journal);
+    }
+}
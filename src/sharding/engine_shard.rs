@@ -0,0 +1,208 @@
+// one engine thread owning a subset of the books
+// it never touches balances , fills and released reservations go back to the risk thread as reports
+// commands arrive in the order the risk thread routed them , so every symbol sees its commands in arrival order
+use bounded_spsc_queue::{Consumer, Producer};
+use std::time::{Duration, Instant};
+use crate::backpressure::policy::{channels, BackpressureConfig};
+use crate::config::settings::EngineConfig;
+use crate::backpressure::sender::{escalate, PolicyProducer, PolicySender};
+use crate::engine::my_engine::{Engine, STEngine};
+use crate::logger::types::OrderBookSnapShot;
+use crate::metrics::engine::BookMetrics;
+use crate::metrics::latency::{LatencyRecorder, Stage};
+use crate::watchdog::heartbeat::Heartbeat;
+use crate::orderbook::order::{Order, OrderToBeCanceled};
+use crate::orderbook::types::Event;
+use crate::sharding::messages::{ShardCommand, ShardReport};
+use crate::shm::event_queue::{OrderEvents, CANCEL_REASON_DELISTED, REJECT_REASON_NOT_OWNER};
+use crate::shm::market_maker_feed::MarketMakerFeed;
+use crate::trading_core::my_trading_core::{cancel_rejected, next_event_id};
+
+pub struct EngineShard{
+    // engine_id is the shard id
+    pub engine : STEngine,
+    commands : Consumer<ShardCommand>,
+    // back to the risk thread , blocking , a lost report would leave funds reserved forever
+    reports : Producer<ShardReport>,
+    market_maker_feed_sender : PolicyProducer<MarketMakerFeed>,
+    snapshot_sender_to_logger : PolicyProducer<OrderBookSnapShot>,
+    last_snap_shot : Instant,
+    snapshot_interval : Duration,
+    book_metrics : BookMetrics,
+    latency : LatencyRecorder,
+    pub heartbeat : Option<Heartbeat>,
+}
+
+impl EngineShard{
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        shard_id : usize,
+        commands : Consumer<ShardCommand>,
+        reports : Producer<ShardReport>,
+        event_sender_to_publisher : Producer<Event>,
+        order_event_sender_to_writter : Producer<OrderEvents>,
+        market_maker_feed_sender : Producer<MarketMakerFeed>,
+        snapshot_sender_to_logger : Producer<OrderBookSnapShot>,
+        backpressure : &BackpressureConfig,
+        config : &EngineConfig,
+    )->Self{
+        Self {
+            engine : STEngine::new(
+                shard_id,
+                PolicySender::from_config(event_sender_to_publisher, channels::ENGINE_EVENTS, backpressure),
+                PolicySender::from_config(order_event_sender_to_writter, channels::ENGINE_ORDER_EVENTS, backpressure),
+                config.engine.depth,
+            ),
+            commands,
+            reports,
+            market_maker_feed_sender : PolicySender::from_config(market_maker_feed_sender, channels::ENGINE_MM_FEED, backpressure),
+            snapshot_sender_to_logger : PolicySender::from_config(snapshot_sender_to_logger, channels::ENGINE_SNAPSHOTS, backpressure),
+            last_snap_shot : Instant::now(),
+            snapshot_interval : config.engine.snapshot_interval(),
+            book_metrics : BookMetrics::new(config.metrics.interval()),
+            latency : LatencyRecorder::default(),
+            heartbeat : None,
+        }
+    }
+
+    pub fn shard_id(&self)->usize{
+        self.engine.engine_id
+    }
+
+    // runs until the risk thread sends Stop
+    pub fn run(&mut self){
+        eprintln!("[Engine Shard {}] started", self.shard_id());
+        loop {
+            if let Some(heartbeat) = self.heartbeat.as_mut() {
+                heartbeat.beat_with_depths(|| [self.commands.size() as u64]);
+            }
+            match self.poll() {
+                Some(true) => {}
+                Some(false) => break,
+                None => {
+                    self.market_maker_feed_sender.flush();
+                    std::hint::spin_loop();
+                }
+            }
+            if self.last_snap_shot.elapsed() >= self.snapshot_interval {
+                self.snapshot_all_books();
+            }
+            if self.book_metrics.due() {
+                self.book_metrics.refresh(&self.engine);
+            }
+            self.latency.flush_if_due();
+        }
+        self.latency.flush();
+        self.heartbeat = None;
+        self.market_maker_feed_sender.flush_blocking();
+        self.snapshot_sender_to_logger.flush_blocking();
+        self.engine.sending_event_to_publisher_try.flush_blocking();
+        self.engine.sending_order_events_to_writter_try.flush_blocking();
+        self.reports.push(ShardReport::Stopped { shard : self.shard_id() });
+        eprintln!("[Engine Shard {}] stopped with {} books", self.shard_id(), self.engine.get_book_count());
+    }
+
+    /// applies the next queued command if there is one , some(false) once told to stop
+    pub fn poll(&mut self)->Option<bool>{
+        let command = self.commands.try_pop()?;
+        Some(self.handle(command))
+    }
+
+    /// applies one command , false once told to stop
+    pub fn handle(&mut self , command : ShardCommand)->bool{
+        match command {
+            ShardCommand::NewOrder(order) => self.process_order(order),
+            ShardCommand::Cancel(order_to_be_canceled , reason) => {
+                self.cancel_resting_order(order_to_be_canceled, reason);
+            }
+            ShardCommand::AddBook(symbol) => self.engine.add_book(symbol),
+            ShardCommand::DelistBook(symbol) => self.delist_book(symbol),
+            ShardCommand::Snapshot => self.snapshot_all_books(),
+            ShardCommand::Stop => return false,
+        }
+        true
+    }
+
+    fn snapshot_all_books(&mut self){
+        self.engine.snapshot_for_all_book(|snapshot| {
+            escalate(self.snapshot_sender_to_logger.send(snapshot));
+        }, next_event_id);
+        self.last_snap_shot = Instant::now();
+    }
+
+    fn process_order(&mut self , order : Order){
+        let match_started = Instant::now();
+        let (match_result , market_update) = self.engine.process_order(order, |feed| {
+            escalate(self.market_maker_feed_sender.send(feed));
+        });
+        self.latency.record_since(Stage::Match, match_started);
+        match match_result {
+            Some(match_result) => {
+                self.reports.push(ShardReport::Matched { order , fills : match_result.fills });
+            }
+            None => {
+                // never matched , the reservation made for it has to come back
+                eprintln!("[Engine Shard {}] failed to process order {}", self.shard_id(), order.order_id);
+                self.reports.push(ShardReport::Released {
+                    order : OrderToBeCanceled { order_id : order.order_id , user_id : order.user_id , symbol : order.symbol },
+                    qty : order.shares_qty,
+                });
+            }
+        }
+        if let Some(market_update) = market_update {
+            escalate(self.engine.sending_event_to_publisher_try.send(Event::new(market_update)));
+        }
+    }
+
+    // same steps as the single threaded core's cancel , except the release happens on the risk thread
+    fn cancel_resting_order(&mut self , order_to_be_canceled : OrderToBeCanceled , reason : u32)->bool{
+        let Some(order_book) = self.engine.get_book_mut(order_to_be_canceled.symbol) else {
+            eprintln!("[Engine Shard {}] cancel on unlisted symbol {}", self.engine.engine_id, order_to_be_canceled.symbol);
+            return false;
+        };
+        let Some(&order_index) = order_book.manager.id_to_index.get(&order_to_be_canceled.order_id) else {
+            eprintln!("[Engine Shard {}] cancel for unknown order {}", self.engine.engine_id, order_to_be_canceled.order_id);
+            return false;
+        };
+        let order_detials = *order_book.manager.get(order_index).unwrap();
+        // only the owner cancels its own order , delisting and freezing name the owner themselves
+        if order_detials.user_id != order_to_be_canceled.user_id {
+            escalate(self.engine.sending_order_events_to_writter_try.send(cancel_rejected(order_to_be_canceled, REJECT_REASON_NOT_OWNER)));
+            return false;
+        }
+        order_book.cancel_order(order_to_be_canceled.order_id, |feed| {
+            escalate(self.market_maker_feed_sender.send(feed));
+        });
+        self.reports.push(ShardReport::Released {
+            order : order_to_be_canceled,
+            qty : order_detials.shares_qty,
+        });
+        escalate(self.engine.sending_order_events_to_writter_try.send(OrderEvents {
+            user_id: order_to_be_canceled.user_id,
+            order_id: order_to_be_canceled.order_id,
+            symbol: order_to_be_canceled.symbol,
+            event_kind: 4, // cancel order
+            filled_qty: 0,
+            remaining_qty: 0,
+            original_qty: 0,
+            error_code: reason,
+            fee: 0
+        }));
+        true
+    }
+
+    fn delist_book(&mut self , symbol : u32){
+        let Some(book) = self.engine.get_book(symbol) else {
+            eprintln!("[Engine Shard {}] delist of unlisted symbol {}", self.shard_id(), symbol);
+            return;
+        };
+        // a resting order is canceled for its owner , whoever asked for the delisting
+        for (order_id , user_id) in book.resting_orders() {
+            self.cancel_resting_order(OrderToBeCanceled { order_id , user_id , symbol }, CANCEL_REASON_DELISTED);
+        }
+        let book = self.engine.get_book(symbol).unwrap();
+        escalate(self.snapshot_sender_to_logger.send(STEngine::book_snapshot(book, self.engine.snapshot_depth, next_event_id())));
+        let _ = self.engine.take_book(symbol);
+        eprintln!("[Engine Shard {}] delisted {}", self.shard_id(), symbol);
+    }
+}
@@ -0,0 +1,51 @@
+// several spsc queues read as one , each shard keeps its own queue to the stages downstream
+// round robin across the inputs , a single input keeps its fifo order so per symbol ordering holds
+use bounded_spsc_queue::Consumer;
+
+pub struct FanIn<T>{
+    inputs : Vec<Consumer<T>>,
+    next : usize,
+}
+
+impl<T> FanIn<T>{
+    pub fn new(inputs : Vec<Consumer<T>>)->Self{
+        Self { inputs , next : 0 }
+    }
+
+    pub fn add_input(&mut self , input : Consumer<T>){
+        self.inputs.push(input);
+    }
+
+    pub fn input_count(&self)->usize{
+        self.inputs.len()
+    }
+
+    #[inline]
+    pub fn try_pop(&mut self)->Option<T>{
+        let count = self.inputs.len();
+        for _ in 0..count {
+            let index = self.next;
+            self.next = if index + 1 == count { 0 } else { index + 1 };
+            if let Some(item) = self.inputs[index].try_pop() {
+                return Some(item);
+            }
+        }
+        None
+    }
+
+    // items waiting in each input , in the order the inputs were added
+    pub fn input_sizes(&self)->impl Iterator<Item = usize> + '_{
+        self.inputs.iter().map(|input| input.size())
+    }
+
+    // items waiting across every input
+    pub fn size(&self)->usize{
+        self.inputs.iter().map(|input| input.size()).sum()
+    }
+}
+
+impl<T> From<Consumer<T>> for FanIn<T>{
+    fn from(input : Consumer<T>)->Self{
+        Self::new(vec![input])
+    }
+}
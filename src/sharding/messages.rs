@@ -0,0 +1,30 @@
+// what goes between the risk thread and the engine shards
+// the risk thread reserves funds before it routes an order , the shard reports back whatever has to be settled or released
+use crate::orderbook::order::{Order, OrderToBeCanceled};
+use crate::orderbook::types::Fills;
+
+#[derive(Debug , Clone , Copy)]
+pub enum ShardCommand{
+    // funds are already reserved for it
+    NewOrder(Order),
+    // the reason goes on the canceled event
+    Cancel(OrderToBeCanceled , u32),
+    AddBook(u32),
+    DelistBook(u32),
+    // top of book snapshots of every book to the logger now
+    Snapshot,
+    // nothing follows , the shard answers with ShardReport::Stopped and exits
+    Stop,
+}
+
+// the ring slots are sized for Matched anyway , boxing the fills would only add an allocation per match
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug)]
+pub enum ShardReport{
+    // the order went through matching , settle the fills
+    Matched { order : Order , fills : Fills },
+    // an order left the book (or never made it on) , give back what is still reserved for it
+    // qty is what the book had left of it , the reservation ledger decides what is released
+    Released { order : OrderToBeCanceled , qty : u32 },
+    Stopped { shard : usize },
+}
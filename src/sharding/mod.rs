@@ -0,0 +1,8 @@
+// the sharded trading core , not a production target : nothing here is journalled or checkpointed
+// so a crash loses all state , sharded_consumer only starts with engine.allow_unjournaled_shards set
+pub mod engine_shard;
+pub mod fan_in;
+pub mod messages;
+pub mod risk;
+pub mod router;
+pub mod tests;
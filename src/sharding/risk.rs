@@ -0,0 +1,496 @@
+// the risk thread , sole owner of the balances when the books are sharded
+// reservation protocol : funds are locked here before an order is routed , the owning shard reports
+// fills (settled here) and anything that left the book without trading (released here)
+// a shard never reads a balance , so balances stay consistent however many shards there are
+use bounded_spsc_queue::{Consumer, Producer};
+use std::time::Instant;
+use crate::admin::commands::{admin_response, AdminAction, AdminError, AdminReply, EngineStats};
+use crate::admin::log_level::set_log_level;
+use crate::admin::plane::{AdminPlane, AdminRequest};
+use crate::backpressure::policy::{channels, BackpressureConfig};
+use crate::config::settings::EngineConfig;
+use crate::metrics::engine::{CoreMetrics, ShardQueueMetrics};
+use crate::metrics::latency::{unix_nanos, LatencyRecorder, Stage};
+use crate::watchdog::heartbeat::Heartbeat;
+use crate::backpressure::sender::{escalate, PolicyProducer, PolicySender};
+use crate::balance_manager::funds_ledger::FundsOutcome;
+use crate::balance_manager::my_balance_manager2::STbalanceManager;
+use crate::balance_manager::rate_limits::RateAction;
+use crate::journal::command_journal::InboundCommand;
+use crate::logger::types::{BaseLogs, OrderDelta, POSITION_REPORT_QUERY, POSITION_REPORT_SNAPSHOT};
+use crate::orderbook::order::{Order, Side, ORDER_TYPE_AMEND};
+use crate::sharding::fan_in::FanIn;
+use crate::sharding::messages::{ShardCommand, ShardReport};
+use crate::engine::symbol_registry::SymbolError;
+use crate::sharding::router::ShardRouter;
+use crate::shm::admin_response_queue::AdminResponse;
+use crate::shm::balance_response_queue::BalanceResponse;
+use crate::shm::event_queue::{OrderEvents, CANCEL_REASON_FROZEN, CANCEL_REASON_USER, REJECT_REASON_AMEND_UNSUPPORTED, REJECT_REASON_HALTED, REJECT_REASON_TRADING_STOPPED, REJECT_REASON_UNLISTED, REJECT_REASON_USER_FROZEN};
+use crate::shm::holdings_response_queue::HoldingResponse;
+use crate::shm::query_queue::Query;
+use crate::shutdown::shutdown_signal::ShutdownHandle;
+use crate::trading_core::my_trading_core::{cancel_rejected, next_event_id, CoreInbound};
+
+const ORDER_BATCH: usize = 1000;
+
+pub struct RiskManager{
+    pub balance_manager : STbalanceManager,
+    pub router : ShardRouter,
+    // index is the shard id , per symbol order is the order of the pushes , see route
+    shard_commands : Vec<Producer<ShardCommand>>,
+    shard_reports : FanIn<ShardReport>,
+    log_sender_to_logger : PolicyProducer<BaseLogs>,
+    // halted symbols are rejected here , before anything is reserved
+    pub admin : AdminPlane,
+    // the venue wide kill switch , the shards never see a new order while it is set
+    pub trading_stopped : bool,
+    admin_response_sender : PolicyProducer<AdminResponse>,
+    // sequence numbers for admin responses , sharded mode has no journal to take them from
+    admin_sequence : u64,
+    stopped_shards : usize,
+    stopping : bool,
+    processed_count : u64,
+    // wall time of the batch being handled , the rate limit buckets refill by it
+    pub clock : u64,
+    pub metrics : CoreMetrics,
+    queue_metrics : ShardQueueMetrics,
+    latency : LatencyRecorder,
+    pub heartbeat : Option<Heartbeat>,
+}
+
+impl RiskManager{
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        event_sender_to_writter : Producer<OrderEvents>,
+        balance_event_producer : Producer<BalanceResponse>,
+        holding_event_producer : Producer<HoldingResponse>,
+        log_sender_to_logger : Producer<BaseLogs>,
+        shard_commands : Vec<Producer<ShardCommand>>,
+        shard_reports : Vec<Consumer<ShardReport>>,
+        admin_response_sender : Producer<AdminResponse>,
+        backpressure : &BackpressureConfig,
+        config : &EngineConfig,
+    )->Self{
+        assert_eq!(shard_commands.len(), shard_reports.len(), "every shard needs a command and a report queue");
+        let shards = shard_commands.len();
+        Self {
+            balance_manager : STbalanceManager::new(event_sender_to_writter, balance_event_producer, holding_event_producer, config),
+            router : ShardRouter::new(shards),
+            shard_commands,
+            shard_reports : FanIn::new(shard_reports),
+            log_sender_to_logger : PolicySender::from_config(log_sender_to_logger, channels::CORE_LOGS, backpressure),
+            admin : AdminPlane::default(),
+            trading_stopped : false,
+            admin_response_sender : PolicySender::from_config(admin_response_sender, channels::CORE_ADMIN_RESPONSES, backpressure),
+            admin_sequence : 0,
+            stopped_shards : 0,
+            stopping : false,
+            processed_count : 0,
+            clock : 0,
+            metrics : CoreMetrics::default(),
+            queue_metrics : ShardQueueMetrics::new(shards, config.metrics.interval()),
+            latency : LatencyRecorder::default(),
+            heartbeat : None,
+        }
+    }
+
+    // same starting users as the single threaded core
+    pub fn bootstrap_state(&mut self){
+        let _ = self.balance_manager.add_market_maker(|log| escalate(self.log_sender_to_logger.send(log)), next_event_id);
+    }
+
+    #[cfg(any(test , feature = "throughput-users"))]
+    pub fn add_throughput_users(&mut self){
+        if let Err(e) = self.balance_manager.add_throughput_test_users(|log| escalate(self.log_sender_to_logger.send(log)), next_event_id) {
+            eprintln!("[Risk] throughput users not opened: {:?}", e);
+        }
+    }
+
+    pub fn processed_count(&self)->u64{
+        self.processed_count
+    }
+
+    // true once every shard has reported Stopped , nothing more will come back from them
+    pub fn all_shards_stopped(&self)->bool{
+        self.stopped_shards == self.shard_commands.len()
+    }
+
+    pub fn run(&mut self , inbound : &mut CoreInbound , shutdown : &ShutdownHandle){
+        eprintln!("[Risk] Starting with {} engine shards", self.shard_commands.len());
+        let mut pending = Vec::with_capacity(ORDER_BATCH + 2);
+        loop {
+            if let Some(heartbeat) = self.heartbeat.as_mut() {
+                heartbeat.beat_with_depths(|| [
+                    inbound.shm_reader.depth(),
+                    inbound.cancel_order_queue.depth(),
+                    inbound.query_queue.depth(),
+                    inbound.admin_queue.depth(),
+                    self.shard_reports.size() as u64,
+                ]);
+            }
+            // reports first , a fill settled now frees funds for the orders read below
+            self.drain_reports();
+            if self.stopping {
+                if self.all_shards_stopped() {
+                    break;
+                }
+                std::hint::spin_loop();
+                continue;
+            }
+            if shutdown.stop_requested() {
+                self.stop_shards();
+                continue;
+            }
+            for _ in 0..ORDER_BATCH {
+                if let Some(order) = inbound.shm_reader.receive_order() {
+                    pending.push(InboundCommand::NewOrder(order));
+                } else {
+                    break;
+                }
+            }
+            let dequeued_at = unix_nanos();
+            for command in pending.iter() {
+                if let InboundCommand::NewOrder(order) = command {
+                    self.latency.record_queue_wait(order.timestamp, dequeued_at);
+                }
+            }
+            if let Ok(Some(order_to_be_canceled)) = inbound.cancel_order_queue.dequeue() {
+                pending.push(InboundCommand::CancelOrder(order_to_be_canceled));
+            }
+            if let Ok(Some(query)) = inbound.query_queue.dequeue() {
+                pending.push(InboundCommand::Query(query));
+            }
+            if let Ok(Some(command)) = inbound.admin_queue.dequeue() {
+                pending.push(InboundCommand::Admin(self.admin.authorize(command)));
+            }
+            self.metrics.batch_size.observe(pending.len() as u64);
+            self.clock = dequeued_at;
+            if self.balance_manager.roll_day(self.clock) {
+                eprintln!("[Risk] trading day {} starts", self.balance_manager.state.trading_day);
+            }
+            for command in pending.drain(..) {
+                self.handle_command(command);
+            }
+            self.latency.flush_if_due();
+            if self.queue_metrics.due() {
+                self.queue_metrics.record(self.shard_commands.iter().map(|commands| commands.size()), self.shard_reports.input_sizes());
+            }
+        }
+        eprintln!("[Risk] stopping after {} orders", self.processed_count);
+        self.latency.flush();
+        self.heartbeat = None;
+        self.log_sender_to_logger.flush_blocking();
+        self.admin_response_sender.flush_blocking();
+        shutdown.finish();
+    }
+
+    /// tells every shard to stop , the shards finish what is queued ahead of it first
+    pub fn stop_shards(&mut self){
+        if self.stopping {
+            return;
+        }
+        self.stopping = true;
+        for shard in 0..self.shard_commands.len() {
+            self.route(shard, ShardCommand::Stop);
+        }
+    }
+
+    pub fn handle_command(&mut self , command : InboundCommand){
+        // the shards are told to stop , nothing can be routed after that
+        if self.stopping {
+            match command {
+                InboundCommand::NewOrder(order) => self.reject(&order, REJECT_REASON_UNLISTED),
+                _ => eprintln!("[Risk] dropping {:?} received after the shards were stopped", command),
+            }
+            return;
+        }
+        match command {
+            InboundCommand::NewOrder(order) => self.process_order(order),
+            InboundCommand::CancelOrder(order_to_be_canceled) => {
+                self.metrics.cancels_in.inc();
+                if let Err(e) = self.balance_manager.throttle(order_to_be_canceled.user_id, RateAction::Cancel, self.clock) {
+                    self.metrics.throttle(RateAction::Cancel);
+                    self.balance_manager.events_to_wrriter_try.push(cancel_rejected(order_to_be_canceled, e.reject_reason()));
+                    return;
+                }
+                match self.router.shard_of(order_to_be_canceled.symbol) {
+                    Some(shard) => self.route(shard, ShardCommand::Cancel(order_to_be_canceled, CANCEL_REASON_USER)),
+                    None => eprintln!("[Risk] cancel on unlisted symbol {}", order_to_be_canceled.symbol),
+                }
+            }
+            InboundCommand::Query(query) => self.process_query(query),
+            InboundCommand::Admin(request) => self.process_admin(request),
+        }
+    }
+
+    /// hands `command` to `shard` , settling reports while its queue is full
+    /// a shard blocks on a full report queue , waiting here without draining it would leave both threads stuck
+    fn route(&mut self , shard : usize , command : ShardCommand){
+        let mut pending = command;
+        while let Some(rejected) = self.shard_commands[shard].try_push(pending) {
+            pending = rejected;
+            self.drain_reports();
+            std::hint::spin_loop();
+        }
+    }
+
+    /// settles and releases everything the shards have reported so far
+    pub fn drain_reports(&mut self){
+        while let Some(report) = self.shard_reports.try_pop() {
+            match report {
+                // the shard already published this match , its execution reports carry no fees and the fee deltas are what counts
+                ShardReport::Matched { order , mut fills } => {
+                    self.metrics.fills.add(fills.fills.len() as u64);
+                    self.log_order(&order, 1);
+                    if let Err(e) = self.balance_manager.update_balances_after_trade(
+                        &mut fills,
+                        |log| {
+                            escalate(self.log_sender_to_logger.send(log));
+                        },
+                        next_event_id,
+                    ) {
+                        eprintln!("[Risk] Balance update error: {:?}", e);
+                    }
+                    // a market order never rests , whatever it did not fill has nothing left to pay for
+                    if order.order_type == 0 {
+                        self.balance_manager.release_order(
+                            order.order_id,
+                            |log| {
+                                escalate(self.log_sender_to_logger.send(log));
+                            },
+                            next_event_id,
+                        );
+                    }
+                }
+                ShardReport::Released { order , qty } => {
+                    if let Err(e) = self.balance_manager.update_balance_after_order_cancel(
+                        order,
+                        qty,
+                        |log| {
+                            escalate(self.log_sender_to_logger.send(log));
+                        },
+                        next_event_id,
+                    ) {
+                        // the order is already off its book , nothing to retry
+                        eprintln!("[Risk] release for order {} failed: {:?}", order.order_id, e);
+                    }
+                }
+                ShardReport::Stopped { shard } => {
+                    eprintln!("[Risk] engine shard {} stopped", shard);
+                    self.stopped_shards += 1;
+                }
+            }
+        }
+    }
+
+    fn log_order(&mut self , order : &Order , order_event_type : u8){
+        escalate(self.log_sender_to_logger.send(BaseLogs::OrderDelta(OrderDelta {
+            event_id: next_event_id(),
+            order_id: order.order_id,
+            user_id: order.user_id,
+            price: order.price,
+            symbol: order.symbol,
+            shares_qty: order.shares_qty,
+            side: match order.side {
+                Side::Ask => 1,
+                Side::Bid => 0
+            },
+            order_event_type
+        })));
+    }
+
+    fn reject(&mut self , order : &Order , reason : u32){
+        self.metrics.reject(reason);
+        self.log_order(order, 2);
+        self.balance_manager.events_to_wrriter_try.push(
+            OrderEvents {
+                user_id: order.user_id,
+                order_id: order.order_id,
+                symbol: order.symbol,
+                event_kind: 3,
+                filled_qty: 0,
+                remaining_qty: order.shares_qty,
+                original_qty: order.shares_qty,
+                error_code: reason,
+                fee: 0
+            }
+        );
+    }
+
+    fn process_order(&mut self , order : Order){
+        self.metrics.orders_in.inc();
+        self.log_order(&order, 0);
+        // the kill switches come first , a frozen user or a stopped venue takes no new order at all
+        if self.trading_stopped {
+            self.reject(&order, REJECT_REASON_TRADING_STOPPED);
+            return;
+        }
+        if self.balance_manager.is_frozen(order.user_id) {
+            self.reject(&order, REJECT_REASON_USER_FROZEN);
+            return;
+        }
+        // nothing is reserved for a symbol no shard trades , it would never be released
+        let Some(shard) = self.router.shard_of(order.symbol) else {
+            eprintln!("[Risk] order {} on unlisted symbol {}", order.order_id, order.symbol);
+            self.reject(&order, REJECT_REASON_UNLISTED);
+            return;
+        };
+        if self.admin.is_halted(order.symbol) {
+            self.reject(&order, REJECT_REASON_HALTED);
+            return;
+        }
+        let action = if order.order_type == ORDER_TYPE_AMEND { RateAction::Amend } else { RateAction::NewOrder };
+        if let Err(e) = self.balance_manager.throttle(order.user_id, action, self.clock) {
+            self.metrics.throttle(action);
+            self.reject(&order, e.reject_reason());
+            return;
+        }
+        // the resting order is only released once its shard reports the cancel , the replacement would have to be locked before that
+        if action == RateAction::Amend {
+            self.reject(&order, REJECT_REASON_AMEND_UNSUPPORTED);
+            return;
+        }
+        let lock_started = Instant::now();
+        let locked = self.balance_manager.check_and_lock_funds(order, |log| escalate(self.log_sender_to_logger.send(log)), next_event_id);
+        self.latency.record_since(Stage::BalanceLock, lock_started);
+        if let Err(e) = locked {
+            self.reject(&order, e.reject_reason());
+            return;
+        }
+        self.route(shard, ShardCommand::NewOrder(order));
+        self.processed_count += 1;
+    }
+
+    fn process_query(&mut self , query : Query){
+        match query.query_type {
+            0 | 1 => {
+                // balances are no longer overwritten from outside , they move through deposits , withdrawals and transfers
+                eprintln!("[Risk] query type {} for user {} ignored , funds go through the admin deposit and withdraw commands", query.query_type, query.user_id);
+            }
+            2..=5 => {
+                // the query queue is not authenticated , users , books and shutdown are operator actions on the admin queue
+                eprintln!("[Risk] query type {} ignored , users , books and shutdown go through the admin commands", query.query_type);
+            }
+            6 => {
+                self.balance_manager.report_positions(Some(query.user_id), POSITION_REPORT_QUERY, |log| escalate(self.log_sender_to_logger.send(log)), next_event_id);
+            }
+            _ => {}
+        }
+    }
+
+    fn list_symbol(&mut self , symbol : u32)->Result<usize , SymbolError>{
+        let shard = self.router.assign(symbol)?;
+        eprintln!("[Risk] listing {} on engine shard {}", symbol, shard);
+        self.route(shard, ShardCommand::AddBook(symbol));
+        Ok(shard)
+    }
+
+    // the shard cancels every resting order and reports the releases before the book goes
+    fn delist_symbol(&mut self , symbol : u32)->Result<usize , SymbolError>{
+        let shard = self.router.remove(symbol)?;
+        self.route(shard, ShardCommand::DelistBook(symbol));
+        self.admin.halted.remove(&symbol);
+        Ok(shard)
+    }
+
+    // same commands and responses as the single threaded core , only without a journal behind them
+    fn process_admin(&mut self , request : AdminRequest){
+        self.admin_sequence += 1;
+        let command = request.command;
+        let result = if request.authorized {
+            AdminAction::decode(&command).and_then(|action| self.apply_admin(action))
+        } else {
+            Err(AdminError::Unauthorized(command.operator_id))
+        };
+        match &result {
+            Ok(_) => eprintln!("[Risk] admin request {} (type {}) by operator {} applied", command.request_id, command.command_type, command.operator_id),
+            Err(e) => eprintln!("[Risk] admin request {} (type {}) by operator {} failed: {}", command.request_id, command.command_type, command.operator_id, e),
+        }
+        // admin commands are where funds enter and leave , the books are checked after every one
+        // books that do not add up stop trading until an operator has looked and resumes it
+        let unbalanced = self.balance_manager.check_conservation();
+        for e in unbalanced.iter() {
+            eprintln!("[Risk] after admin request {}: {}", command.request_id, e);
+            self.metrics.conservation_failures.inc();
+        }
+        if !unbalanced.is_empty() && !self.trading_stopped {
+            eprintln!("[Risk] trading stopped after admin request {} , the balances do not add up", command.request_id);
+            self.trading_stopped = true;
+        }
+        escalate(self.admin_response_sender.send(admin_response(&command, self.admin_sequence, &result)));
+    }
+
+    fn apply_admin(&mut self , action : AdminAction)->Result<AdminReply , AdminError>{
+        match action {
+            AdminAction::HaltSymbol(symbol) => {
+                if self.router.shard_of(symbol).is_none() {
+                    return Err(SymbolError::NotListed(symbol).into());
+                }
+                self.admin.halt(symbol)?;
+            }
+            AdminAction::ResumeSymbol(symbol) => self.admin.resume(symbol)?,
+            AdminAction::AddBook(symbol) => {
+                self.list_symbol(symbol)?;
+            }
+            AdminAction::RemoveBook(symbol) => {
+                self.delist_symbol(symbol)?;
+            }
+            AdminAction::AddUser(user_id) => {
+                self.balance_manager.add_user(user_id, |log| escalate(self.log_sender_to_logger.send(log)), next_event_id)?;
+            }
+            AdminAction::Funds { key , request } => {
+                let outcome = self.balance_manager.apply_funds(
+                    key,
+                    request,
+                    |log| {
+                        escalate(self.log_sender_to_logger.send(log));
+                    },
+                    next_event_id,
+                )?;
+                if outcome == FundsOutcome::Duplicate {
+                    eprintln!("[Risk] funds request {} was already applied", key);
+                }
+            }
+            AdminAction::SetLimit { user_id , kind , value } => {
+                self.balance_manager.set_limit(user_id, kind, value)?;
+            }
+            AdminAction::FreezeUser(user_id) => {
+                // the shard reports each release back , the reservation is given back when it does
+                for order_to_be_canceled in self.balance_manager.freeze_user(user_id)? {
+                    if let Some(shard) = self.router.shard_of(order_to_be_canceled.symbol) {
+                        self.route(shard, ShardCommand::Cancel(order_to_be_canceled, CANCEL_REASON_FROZEN));
+                    }
+                }
+            }
+            AdminAction::UnfreezeUser(user_id) => self.balance_manager.unfreeze_user(user_id)?,
+            AdminAction::StopTrading => self.trading_stopped = true,
+            AdminAction::ResumeTrading => self.trading_stopped = false,
+            AdminAction::SetMarkPrice { symbol , price } => {
+                if self.router.shard_of(symbol).is_none() {
+                    return Err(SymbolError::NotListed(symbol).into());
+                }
+                self.balance_manager.set_mark_price(symbol, price);
+            }
+            AdminAction::Snapshot => {
+                for shard in 0..self.shard_commands.len() {
+                    self.route(shard, ShardCommand::Snapshot);
+                }
+                // positions settle here , the shards only have the books
+                self.balance_manager.report_positions(None, POSITION_REPORT_SNAPSHOT, |log| escalate(self.log_sender_to_logger.send(log)), next_event_id);
+            }
+            AdminAction::DumpStats => {
+                return Ok(AdminReply::Stats(EngineStats {
+                    processed_orders : self.processed_count,
+                    book_count : self.router.symbol_count() as u32,
+                    user_count : self.balance_manager.state.total_users,
+                    halted_count : self.admin.halted.len() as u32,
+                }));
+            }
+            AdminAction::SetLogLevel(level) => set_log_level(level),
+            AdminAction::Promote => return Err(AdminError::NotStandby),
+            // whatever was routed before this is still applied by the shards
+            AdminAction::Shutdown => self.stop_shards(),
+        }
+        Ok(AdminReply::Done)
+    }
+}
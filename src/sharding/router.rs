@@ -0,0 +1,66 @@
+// decides which engine shard owns a symbol
+// a symbol lives on exactly one shard for as long as it is listed , so everything for it goes down one fifo queue
+// new listings go to the shard with the fewest symbols , ties to the lowest shard id
+use rustc_hash::FxHashMap;
+use crate::engine::symbol_registry::SymbolError;
+
+#[derive(Debug)]
+pub struct ShardRouter{
+    symbol_to_shard : FxHashMap<u32 , usize>,
+    symbols_per_shard : Vec<usize>,
+}
+
+impl ShardRouter{
+    pub fn new(shard_count : usize)->Self{
+        assert!(shard_count > 0, "need at least one engine shard");
+        Self { symbol_to_shard : FxHashMap::default() , symbols_per_shard : vec![0 ; shard_count] }
+    }
+
+    pub fn shard_count(&self)->usize{
+        self.symbols_per_shard.len()
+    }
+
+    /// picks a shard for a new listing
+    pub fn assign(&mut self , symbol : u32)->Result<usize , SymbolError>{
+        if self.symbol_to_shard.contains_key(&symbol) {
+            return Err(SymbolError::AlreadyListed(symbol));
+        }
+        let shard = (0..self.symbols_per_shard.len()).min_by_key(|shard| self.symbols_per_shard[*shard]).unwrap();
+        self.place(symbol, shard);
+        Ok(shard)
+    }
+
+    /// pins a symbol to a given shard , for listings that should share or avoid a core
+    pub fn assign_to(&mut self , symbol : u32 , shard : usize)->Result<usize , SymbolError>{
+        if self.symbol_to_shard.contains_key(&symbol) {
+            return Err(SymbolError::AlreadyListed(symbol));
+        }
+        assert!(shard < self.symbols_per_shard.len(), "no shard {}", shard);
+        self.place(symbol, shard);
+        Ok(shard)
+    }
+
+    fn place(&mut self , symbol : u32 , shard : usize){
+        self.symbol_to_shard.insert(symbol, shard);
+        self.symbols_per_shard[shard] += 1;
+    }
+
+    pub fn remove(&mut self , symbol : u32)->Result<usize , SymbolError>{
+        let shard = self.symbol_to_shard.remove(&symbol).ok_or(SymbolError::NotListed(symbol))?;
+        self.symbols_per_shard[shard] -= 1;
+        Ok(shard)
+    }
+
+    #[inline(always)]
+    pub fn shard_of(&self , symbol : u32)->Option<usize>{
+        self.symbol_to_shard.get(&symbol).copied()
+    }
+
+    pub fn symbol_count(&self)->usize{
+        self.symbol_to_shard.len()
+    }
+
+    pub fn symbols_on(&self , shard : usize)->usize{
+        self.symbols_per_shard[shard]
+    }
+}
@@ -0,0 +1,276 @@
+#[cfg(test)]
+mod tests {
+    use bounded_spsc_queue::Consumer;
+    use crate::backpressure::policy::BackpressureConfig;
+    use crate::config::settings::EngineConfig;
+    use crate::engine::my_engine::Engine;
+    use crate::engine::symbol_registry::SymbolError;
+    use crate::journal::command_journal::InboundCommand;
+    use crate::logger::types::{BaseLogs, OrderBookSnapShot};
+    use crate::orderbook::order::{Order, OrderToBeCanceled, Side, ORDER_TYPE_AMEND};
+    use crate::orderbook::types::Event;
+    use crate::sharding::engine_shard::EngineShard;
+    use crate::sharding::fan_in::FanIn;
+    use crate::sharding::risk::RiskManager;
+    use crate::sharding::router::ShardRouter;
+    use crate::shm::admin_response_queue::AdminResponse;
+    use crate::shm::balance_response_queue::BalanceResponse;
+    use crate::shm::event_queue::{OrderEvents, CANCEL_REASON_USER, REJECT_REASON_AMEND_UNSUPPORTED, REJECT_REASON_NOT_OWNER, REJECT_REASON_TRADING_STOPPED};
+    use crate::shm::holdings_response_queue::HoldingResponse;
+    use crate::shm::market_maker_feed::MarketMakerFeed;
+    use crate::admin::plane::AdminRequest;
+    use crate::shm::admin_command_queue::{AdminCommand, ADMIN_ADD_BOOK, ADMIN_DUMP_STATS, ADMIN_SHUTDOWN};
+
+    const QUEUE_SIZE: usize = 4096;
+
+    // the risk thread and its shards stepped by hand on the test thread
+    struct ShardedHarness {
+        risk: RiskManager,
+        shards: Vec<EngineShard>,
+        rejects: Consumer<OrderEvents>,
+        cancels: FanIn<OrderEvents>,
+        _balances: Consumer<BalanceResponse>,
+        _holdings: Consumer<HoldingResponse>,
+        _logs: Consumer<BaseLogs>,
+        _admin_responses: Consumer<AdminResponse>,
+        _events: Vec<Consumer<Event>>,
+        _feeds: Vec<Consumer<MarketMakerFeed>>,
+        _snapshots: Vec<Consumer<OrderBookSnapShot>>,
+    }
+
+    impl ShardedHarness {
+        fn new(shard_count: usize) -> Self {
+            Self::with_shard_queues(shard_count, QUEUE_SIZE)
+        }
+
+        // `shard_queue_size` for the command and report queues between the risk thread and each shard
+        fn with_shard_queues(shard_count: usize, shard_queue_size: usize) -> Self {
+            let backpressure = BackpressureConfig::default();
+            let config = EngineConfig::default();
+            let (rejects_tx, rejects) = bounded_spsc_queue::make(QUEUE_SIZE);
+            let (balances_tx, _balances) = bounded_spsc_queue::make(QUEUE_SIZE);
+            let (holdings_tx, _holdings) = bounded_spsc_queue::make(QUEUE_SIZE);
+            let (logs_tx, _logs) = bounded_spsc_queue::make(QUEUE_SIZE);
+            let mut command_senders = Vec::new();
+            let mut report_receivers = Vec::new();
+            let mut shards = Vec::new();
+            let mut cancels = FanIn::new(Vec::new());
+            let (mut _events, mut _feeds, mut _snapshots) = (Vec::new(), Vec::new(), Vec::new());
+            for shard_id in 0..shard_count {
+                let (command_tx, command_rx) = bounded_spsc_queue::make(shard_queue_size);
+                let (report_tx, report_rx) = bounded_spsc_queue::make(shard_queue_size);
+                let (event_tx, event_rx) = bounded_spsc_queue::make(QUEUE_SIZE);
+                let (order_event_tx, order_event_rx) = bounded_spsc_queue::make(QUEUE_SIZE);
+                let (feed_tx, feed_rx) = bounded_spsc_queue::make(QUEUE_SIZE);
+                let (snapshot_tx, snapshot_rx) = bounded_spsc_queue::make(QUEUE_SIZE);
+                shards.push(EngineShard::new(shard_id, command_rx, report_tx, event_tx, order_event_tx, feed_tx, snapshot_tx, &backpressure, &config));
+                command_senders.push(command_tx);
+                report_receivers.push(report_rx);
+                cancels.add_input(order_event_rx);
+                _events.push(event_rx);
+                _feeds.push(feed_rx);
+                _snapshots.push(snapshot_rx);
+            }
+            let (admin_tx, _admin_responses) = bounded_spsc_queue::make(QUEUE_SIZE);
+            let mut risk = RiskManager::new(rejects_tx, balances_tx, holdings_tx, logs_tx, command_senders, report_receivers, admin_tx, &backpressure, &config);
+            risk.bootstrap_state();
+            risk.add_throughput_users();
+            Self { risk, shards, rejects, cancels, _balances, _holdings, _logs, _admin_responses, _events, _feeds, _snapshots }
+        }
+
+        // one command through the risk thread , every shard runs what it was sent and the reports are settled
+        fn apply(&mut self, command: InboundCommand) {
+            self.risk.handle_command(command);
+            for shard in self.shards.iter_mut() {
+                while shard.poll().is_some() {}
+            }
+            self.risk.drain_reports();
+        }
+
+        fn user_index(&self, user_id: u64) -> usize {
+            *self.risk.balance_manager.state.user_id_to_index.get(&user_id).unwrap() as usize
+        }
+    }
+
+    fn admin(command_type: u8, symbol: u32) -> InboundCommand {
+        InboundCommand::Admin(AdminRequest { command: AdminCommand { symbol, command_type, ..AdminCommand::default() }, authorized: true })
+    }
+
+    #[test]
+    fn test_router_spreads_symbols_and_refills_the_emptiest_shard() {
+        let mut router = ShardRouter::new(3);
+        let shards: Vec<usize> = [10, 11, 12, 13].iter().map(|symbol| router.assign(*symbol).unwrap()).collect();
+        assert_eq!(shards, vec![0, 1, 2, 0]);
+        assert_eq!(router.assign(11), Err(SymbolError::AlreadyListed(11)));
+
+        assert_eq!(router.remove(11), Ok(1));
+        assert_eq!(router.shard_of(11), None);
+        // shard 1 is the emptiest now
+        assert_eq!(router.assign(14), Ok(1));
+        assert_eq!(router.assign_to(15, 2), Ok(2));
+        assert_eq!(router.symbols_on(2), 2);
+        assert_eq!(router.remove(99), Err(SymbolError::NotListed(99)));
+    }
+
+    #[test]
+    fn test_fan_in_keeps_each_input_in_order() {
+        let (a_tx, a_rx) = bounded_spsc_queue::make::<u32>(16);
+        let (b_tx, b_rx) = bounded_spsc_queue::make::<u32>(16);
+        for i in 0..3 {
+            a_tx.push(i);
+            b_tx.push(100 + i);
+        }
+        let mut fan_in = FanIn::new(vec![a_rx, b_rx]);
+        assert_eq!(fan_in.size(), 6);
+        let mut popped = Vec::new();
+        while let Some(item) = fan_in.try_pop() {
+            popped.push(item);
+        }
+        assert_eq!(popped, vec![0, 100, 1, 101, 2, 102]);
+    }
+
+    #[test]
+    fn test_trade_on_a_second_shard_settles_on_the_risk_thread() {
+        let mut harness = ShardedHarness::new(2);
+        harness.apply(admin(ADMIN_ADD_BOOK, 7));
+        harness.apply(admin(ADMIN_ADD_BOOK, 8));
+        assert_eq!(harness.risk.router.shard_of(8), Some(1));
+        assert!(harness.shards[1].engine.has_book(8));
+        assert!(!harness.shards[0].engine.has_book(8));
+
+        let buyer = harness.user_index(10);
+        let seller = harness.user_index(20);
+        let state = &harness.risk.balance_manager.state;
+        let buyer_before = state.balances[buyer].available_balance;
+        let seller_shares_before = state.holdings[seller].available(8);
+
+        harness.apply(InboundCommand::NewOrder(Order::new(20, 1, Side::Ask, 1, 30, 10, 1, 8)));
+        harness.apply(InboundCommand::NewOrder(Order::new(10, 2, Side::Bid, 1, 20, 10, 2, 8)));
+
+        let book = harness.shards[1].engine.get_book(8).unwrap();
+        assert_eq!(book.last_trade_price, 10);
+        let state = &harness.risk.balance_manager.state;
+        assert_eq!(state.balances[buyer].available_balance, buyer_before - 20 * 10);
+        assert_eq!(state.balances[buyer].reserved_balance, 0);
+        assert_eq!(state.holdings[buyer].available(8) as u64, state.holdings[buyer].default_available as u64 + 20);
+        assert_eq!(state.holdings[seller].available(8), seller_shares_before - 30);
+        assert_eq!(state.holdings[seller].reserved(8), 10);
+    }
+
+    #[test]
+    fn test_cancel_on_a_shard_releases_the_reservation() {
+        let mut harness = ShardedHarness::new(2);
+        harness.apply(admin(ADMIN_ADD_BOOK, 7));
+        let buyer = harness.user_index(10);
+        let buyer_before = harness.risk.balance_manager.state.balances[buyer].available_balance;
+
+        harness.apply(InboundCommand::NewOrder(Order::new(10, 5, Side::Bid, 1, 4, 9, 1, 7)));
+        assert_eq!(harness.risk.balance_manager.state.balances[buyer].reserved_balance, 36);
+
+        // another user cannot cancel it
+        harness.apply(InboundCommand::CancelOrder(OrderToBeCanceled { order_id: 5, user_id: 20, symbol: 7 }));
+        assert_eq!(harness.risk.balance_manager.state.balances[buyer].reserved_balance, 36);
+        let event = harness.cancels.try_pop().unwrap();
+        assert_eq!((event.order_id, event.user_id, event.event_kind, event.error_code), (5, 20, 3, REJECT_REASON_NOT_OWNER));
+
+        // nor can it be amended here , the order rests as it was
+        harness.apply(InboundCommand::NewOrder(Order::new(10, 5, Side::Bid, ORDER_TYPE_AMEND, 2, 9, 2, 7)));
+        assert_eq!(harness.risk.balance_manager.state.balances[buyer].reserved_balance, 36);
+        let event = harness.rejects.try_pop().unwrap();
+        assert_eq!((event.order_id, event.event_kind, event.error_code), (5, 3, REJECT_REASON_AMEND_UNSUPPORTED));
+
+        harness.apply(InboundCommand::CancelOrder(OrderToBeCanceled { order_id: 5, user_id: 10, symbol: 7 }));
+        let state = &harness.risk.balance_manager.state;
+        assert_eq!(state.balances[buyer].available_balance, buyer_before);
+        assert_eq!(state.balances[buyer].reserved_balance, 0);
+        let event = harness.cancels.try_pop().unwrap();
+        assert_eq!((event.order_id, event.event_kind, event.error_code), (5, 4, CANCEL_REASON_USER));
+    }
+
+    #[test]
+    fn test_orders_on_unlisted_symbols_are_rejected_before_reserving() {
+        let mut harness = ShardedHarness::new(2);
+        let buyer = harness.user_index(10);
+        harness.apply(InboundCommand::NewOrder(Order::new(10, 1, Side::Bid, 1, 4, 9, 1, 77)));
+        assert_eq!(harness.risk.balance_manager.state.balances[buyer].reserved_balance, 0);
+        let event = harness.rejects.try_pop().unwrap();
+        assert_eq!((event.order_id, event.event_kind), (1, 3));
+    }
+
+    #[test]
+    fn test_books_that_do_not_add_up_after_an_admin_command_stop_trading() {
+        let mut harness = ShardedHarness::new(2);
+        harness.apply(admin(ADMIN_ADD_BOOK, 7));
+        let buyer = harness.user_index(10);
+        harness.risk.balance_manager.state.balances[buyer].available_balance += 7;
+        harness.apply(admin(ADMIN_DUMP_STATS, 0));
+        assert!(harness.risk.trading_stopped);
+        harness.apply(InboundCommand::NewOrder(Order::new(10, 1, Side::Bid, 1, 4, 9, 1, 7)));
+        let event = harness.rejects.try_pop().unwrap();
+        assert_eq!((event.order_id, event.error_code), (1, REJECT_REASON_TRADING_STOPPED));
+    }
+
+    #[test]
+    fn test_stopped_shards_finish_the_work_queued_ahead() {
+        let mut harness = ShardedHarness::new(2);
+        harness.apply(admin(ADMIN_ADD_BOOK, 7));
+        let buyer = harness.user_index(10);
+        let shards: Vec<_> = harness.shards.drain(..).map(|mut shard| std::thread::spawn(move || {
+            shard.run();
+            shard
+        })).collect();
+
+        // crosses nothing , its reservation stays while it rests
+        harness.risk.handle_command(InboundCommand::NewOrder(Order::new(10, 1, Side::Bid, 1, 4, 9, 1, 7)));
+        harness.risk.handle_command(admin(ADMIN_SHUTDOWN, 0));
+        while !harness.risk.all_shards_stopped() {
+            harness.risk.drain_reports();
+        }
+        // routed after the stop , rejected without reserving anything
+        harness.risk.handle_command(InboundCommand::NewOrder(Order::new(10, 2, Side::Bid, 1, 4, 9, 2, 7)));
+        assert_eq!(harness.rejects.try_pop().unwrap().order_id, 2);
+        assert_eq!(harness.risk.balance_manager.state.balances[buyer].reserved_balance, 36);
+
+        let shards: Vec<EngineShard> = shards.into_iter().map(|handle| handle.join().unwrap()).collect();
+        assert_eq!(shards[0].engine.get_book(7).unwrap().manager.id_to_index.len(), 1);
+    }
+
+    #[test]
+    fn test_full_command_and_report_queues_do_not_deadlock() {
+        const PAIRS: u64 = 100;
+        let mut harness = ShardedHarness::with_shard_queues(2, 2);
+        harness.apply(admin(ADMIN_ADD_BOOK, 7));
+        harness.apply(admin(ADMIN_ADD_BOOK, 8));
+        let shards: Vec<_> = harness.shards.drain(..).map(|mut shard| std::thread::spawn(move || {
+            shard.run();
+            shard
+        })).collect();
+
+        // every pair trades , so each shard has reports to send back while the risk thread is still routing to it
+        let (done_tx, done_rx) = std::sync::mpsc::channel();
+        let risk = std::thread::spawn(move || {
+            for pair in 0..PAIRS {
+                let symbol = 7 + (pair % 2) as u32;
+                harness.risk.handle_command(InboundCommand::NewOrder(Order::new(20, 2 * pair + 1, Side::Ask, 1, 1, 100, pair, symbol)));
+                harness.risk.handle_command(InboundCommand::NewOrder(Order::new(10, 2 * pair + 2, Side::Bid, 1, 1, 100, pair, symbol)));
+            }
+            harness.risk.handle_command(admin(ADMIN_SHUTDOWN, 0));
+            while !harness.risk.all_shards_stopped() {
+                harness.risk.drain_reports();
+            }
+            let _ = done_tx.send(());
+            harness
+        });
+        done_rx.recv_timeout(std::time::Duration::from_secs(30)).expect("the risk thread and the shards deadlocked");
+        let harness = risk.join().unwrap();
+        for shard in shards {
+            let shard = shard.join().unwrap();
+            assert!(shard.engine.books.iter().flatten().all(|book| book.manager.id_to_index.is_empty()));
+        }
+        let buyer = harness.user_index(10);
+        let state = &harness.risk.balance_manager.state;
+        assert_eq!(state.balances[buyer].reserved_balance, 0);
+        assert_eq!(state.holdings[buyer].available(7) as u64, state.holdings[buyer].default_available as u64 + PAIRS / 2);
+        assert!(harness.risk.balance_manager.check_conservation().is_empty());
+    }
+}
@@ -0,0 +1,360 @@
+use memmap2::MmapMut;
+use std::fs::{self, OpenOptions };
+use std::path::Path;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::os::unix::fs::OpenOptionsExt;
+// QueueHeader with cache-line padding matching Go
+#[repr(C)]
+pub struct QueueHeader {
+    producer_head: AtomicU64, // offset 0
+    _pad1: [u8; 56],          // pad to 64B
+    consumer_tail: AtomicU64, // offset 64
+    _pad2: [u8; 56],          // pad to 128B
+    magic: AtomicU32,         // offset 128
+    capacity: AtomicU32,      // offset 132
+}
+
+// operator commands for the running core , written by the admin tooling
+// every command is journalled with whether its operator was authorized , and answered on the admin response queue
+#[repr(C)]
+#[derive(Debug , Clone, Copy , Default)]
+pub struct AdminCommand{
+    pub request_id : u64 ,  // echoed in the response , the idempotency key of a funds movement
+    pub operator_id : u64 ,
+    pub user_id : u64 ,
+    pub available : u64 ,   // the amount of a funds movement , signed for ADMIN_ADJUST
+    pub reserved : u64 ,    // the receiving user of a transfer
+    pub symbol : u32 ,
+    pub command_type : u8 , // see ADMIN_* below
+    pub log_level : u8 ,    // 0 error , 1 warn , 2 info , 3 debug
+    pub asset : u8 ,        // funds movements only , ASSET_CASH , ASSET_SHARES of `symbol` or ASSET_COIN `symbol`
+    pub limit : u8 ,        // ADMIN_SET_LIMIT only , LIMIT_* below
+}
+
+pub const ADMIN_HALT_SYMBOL : u8 = 0;
+pub const ADMIN_RESUME_SYMBOL : u8 = 1;
+pub const ADMIN_ADD_BOOK : u8 = 2;
+pub const ADMIN_REMOVE_BOOK : u8 = 3;
+// 4 and 5 were the balance and holdings overwrites , retired for ADMIN_ADJUST
+pub const ADMIN_ADD_USER : u8 = 6;
+pub const ADMIN_SNAPSHOT : u8 = 7;
+pub const ADMIN_DUMP_STATS : u8 = 8;
+pub const ADMIN_SET_LOG_LEVEL : u8 = 9;
+// funds movements , the amount goes in `available`
+pub const ADMIN_DEPOSIT : u8 = 10;
+pub const ADMIN_WITHDRAW : u8 = 11;
+pub const ADMIN_TRANSFER : u8 = 12;
+// one pre-trade limit of `user_id` , the value goes in `available` and 0 turns it off
+pub const ADMIN_SET_LIMIT : u8 = 13;
+// kill switch , freezing `user_id` cancels its resting orders , rejects its new orders and refuses its withdrawals
+pub const ADMIN_FREEZE_USER : u8 = 14;
+pub const ADMIN_UNFREEZE_USER : u8 = 15;
+// venue wide , every new order is rejected until trading resumes , cancels still go through
+pub const ADMIN_STOP_TRADING : u8 = 16;
+pub const ADMIN_RESUME_TRADING : u8 = 17;
+// the price open positions in `symbol` are marked at , in `available` , 0 goes back to the last trade
+pub const ADMIN_SET_MARK_PRICE : u8 = 18;
+// an operator correction to `user_id`'s available `asset` , a funds movement like a deposit
+// `available` is read as a signed i64 , below zero takes from the user
+pub const ADMIN_ADJUST : u8 = 19;
+// promotes a standby to primary , sent on the standby's own admin queue , a primary refuses it
+pub const ADMIN_PROMOTE : u8 = 20;
+// stops the core , journalled like any admin command so a replay sees where the run ended
+pub const ADMIN_SHUTDOWN : u8 = 21;
+
+pub const ASSET_CASH : u8 = 0;
+pub const ASSET_SHARES : u8 = 1;
+// a wallet asset , its id goes in `symbol`
+pub const ASSET_COIN : u8 = 2;
+
+pub const LIMIT_MAX_ORDER_QTY : u8 = 0;
+pub const LIMIT_MAX_ORDER_NOTIONAL : u8 = 1;
+pub const LIMIT_MAX_OPEN_ORDERS : u8 = 2;
+pub const LIMIT_MAX_POSITION : u8 = 3;
+pub const LIMIT_MAX_DAILY_NOTIONAL : u8 = 4;
+pub const LIMIT_MAX_PRICE_DEVIATION_BPS : u8 = 5;
+const QUEUE_MAGIC: u32 = 0x41444D43;
+// reduce size 
+const QUEUE_CAPACITY: usize = 65536;
+const ORDER_SIZE: usize = std::mem::size_of::<AdminCommand>();
+const HEADER_SIZE: usize = std::mem::size_of::<QueueHeader>();
+const TOTAL_SIZE: usize = HEADER_SIZE + (QUEUE_CAPACITY * ORDER_SIZE);
+
+// Compile-time layout assertions (fail build if wrong)
+const _: () = assert!(ORDER_SIZE == 48, "AdminCommand must be 48 bytes");
+const _: () = assert!(HEADER_SIZE == 136, "QueueHeader must be 136 bytes");
+const _: () = {
+    // Verify ConsumerTail is at offset 64
+    assert!(
+        std::mem::offset_of!(QueueHeader, consumer_tail) == 64,
+        "ConsumerTail must be at offset 64"
+    );
+};
+
+#[derive(Debug)]
+pub struct AdminCommandQueue {
+    mmap: MmapMut,
+    header_ptr: *mut QueueHeader, // Cached pointer
+    orders_ptr: *mut AdminCommand,       // Cached orders pointer
+}
+
+impl AdminCommandQueue {
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self, QueueError> {
+        let _ = fs::remove_file(&path);
+    
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true) // O_EXCL
+            .mode(0o666)
+            .open(&path)
+            .map_err(|e| QueueError::FileOpen(e.to_string()))?;
+    
+        file.set_len(TOTAL_SIZE as u64)
+            .map_err(|e| QueueError::FileStat(e.to_string()))?;
+    
+        file.sync_all()
+            .map_err(|e| QueueError::FileStat(e.to_string()))?;
+    
+        let mut mmap =
+            unsafe { MmapMut::map_mut(&file) }.map_err(|e| QueueError::Mmap(e.to_string()))?;
+    
+        if let Err(e) = mmap.lock() {
+            eprintln!("Warning: failed to mlock: {}", e);
+        }
+    
+        let header_ptr = mmap.as_mut_ptr() as *mut QueueHeader;
+    
+        unsafe {
+            (*header_ptr)
+                .producer_head
+                .store(0, Ordering::SeqCst);
+            (*header_ptr)
+                .consumer_tail
+                .store(0, Ordering::SeqCst);
+            (*header_ptr)
+                .magic
+                .store(QUEUE_MAGIC, Ordering::SeqCst);
+            (*header_ptr)
+                .capacity
+                .store(QUEUE_CAPACITY as u32, Ordering::SeqCst);
+        }
+    
+        mmap.flush()
+            .map_err(|e| QueueError::Flush(e.to_string()))?;
+    
+        let orders_ptr = unsafe {
+            mmap.as_mut_ptr().add(HEADER_SIZE) as *mut AdminCommand
+        };
+    
+        Ok(AdminCommandQueue {
+            mmap,
+            header_ptr,
+            orders_ptr,
+        })
+    }
+    
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, QueueError> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path)
+            .map_err(|e| QueueError::FileOpen(e.to_string()))?;
+
+        let metadata = file
+            .metadata()
+            .map_err(|e| QueueError::FileStat(e.to_string()))?;
+        if metadata.len() != TOTAL_SIZE as u64 {
+            return Err(QueueError::InvalidSize {
+                got: metadata.len(),
+                expected: TOTAL_SIZE as u64,
+            });
+        }
+
+        let mut mmap =
+            unsafe { MmapMut::map_mut(&file) }.map_err(|e| QueueError::Mmap(e.to_string()))?;
+
+        if let Err(e) = mmap.lock() {
+            eprintln!("Warning: failed to mlock: {}", e);
+        }
+
+        // Cache both pointers
+        let header_ptr = { mmap.as_mut_ptr() as *mut QueueHeader };
+        let orders_ptr = unsafe { mmap.as_mut_ptr().add(HEADER_SIZE) as *mut AdminCommand };
+
+        // Validate
+        let header = unsafe { &*header_ptr };
+        let magic = header.magic.load(Ordering::Relaxed);
+        if magic != QUEUE_MAGIC {
+            return Err(QueueError::InvalidMagic { got: magic });
+        }
+
+        let capacity = header.capacity.load(Ordering::Relaxed);
+        if capacity != QUEUE_CAPACITY as u32 {
+            return Err(QueueError::CapacityMismatch {
+                got: capacity,
+                expected: QUEUE_CAPACITY as u32,
+            });
+        }
+
+        Ok(AdminCommandQueue {
+            mmap,
+            header_ptr,
+            orders_ptr,
+        })
+    }
+
+    /// Get mutable header reference - ZERO COST
+    // the header lives in the shared mapping , not behind &self
+    #[allow(clippy::mut_from_ref)]
+    #[inline(always)]
+    fn header_mut(&self) -> &mut QueueHeader {
+        unsafe { &mut *self.header_ptr }
+    }
+
+    /// Get immutable header reference - ZERO COST
+    #[inline(always)]
+    fn header(&self) -> &QueueHeader {
+        unsafe { &*self.header_ptr }
+    }
+
+    /// Get order at position - ZERO COST pointer arithmetic
+    #[inline(always)]
+    fn get_order(&self, pos: usize) -> AdminCommand {
+        unsafe { *self.orders_ptr.add(pos) }
+    }
+
+    /// Set order at position - ZERO COST pointer arithmetic
+    #[inline(always)]
+    fn set_order(&self, pos: usize, order: AdminCommand) {
+        unsafe {
+            *self.orders_ptr.add(pos) = order;
+        }
+    }
+
+    /// ULTRA-FAST dequeue - all pointers cached, no borrows
+    #[inline]
+    pub fn dequeue(&mut self) -> Result<Option<AdminCommand>, QueueError> {
+        let header = self.header_mut();
+
+        let producer_head = header.producer_head.load(Ordering::Acquire);
+        let consumer_tail = header.consumer_tail.load(Ordering::Relaxed);
+
+        if consumer_tail == producer_head {
+            return Ok(None);
+        }
+
+        let pos = (consumer_tail % QUEUE_CAPACITY as u64) as usize;
+        std::sync::atomic::fence(Ordering::Acquire);
+        let order = self.get_order(pos);
+
+        header
+            .consumer_tail
+            .store(consumer_tail + 1, Ordering::Release);
+
+        Ok(Some(order))
+    }
+
+    pub fn enqueue(&mut self, order: AdminCommand) -> Result<(), QueueError> {
+        let header = self.header_mut();
+
+        let consumer_tail = header.consumer_tail.load(Ordering::Acquire);
+        let producer_head = header.producer_head.load(Ordering::Relaxed);
+
+        let next_head = producer_head + 1;
+
+        if next_head - consumer_tail > QUEUE_CAPACITY as u64 {
+            return Err(QueueError::QueueFull {
+                depth: next_head - consumer_tail,
+            });
+        }
+
+        let pos = (producer_head % QUEUE_CAPACITY as u64) as usize;
+        self.set_order(pos, order);
+
+        header.producer_head.store(next_head, Ordering::Release);
+
+        Ok(())
+    }
+
+    pub fn depth(&self) -> u64 {
+        let header = self.header();
+        let producer_head = header.producer_head.load(Ordering::Relaxed);
+        let consumer_tail = header.consumer_tail.load(Ordering::Relaxed);
+        producer_head.saturating_sub(consumer_tail)
+    }
+
+    pub fn capacity(&self) -> u64 {
+        QUEUE_CAPACITY as u64
+    }
+
+    pub fn flush(&self) -> Result<(), QueueError> {
+        self.mmap
+            .flush()
+            .map_err(|e| QueueError::Flush(e.to_string()))
+    }
+
+    pub fn dequeue_spin(&mut self, max_spins: usize) -> Result<Option<AdminCommand>, QueueError> {
+        for _ in 0..max_spins {
+            match self.dequeue()? {
+                Some(order) => return Ok(Some(order)),
+                None => std::hint::spin_loop(),
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl Drop for AdminCommandQueue {
+    fn drop(&mut self) {
+        // Flush before closing
+        let _ = self.mmap.flush();
+        // Unlock pages (memmap2 handles this automatically)
+        let _ = self.mmap.unlock();
+    }
+}
+
+// Error types
+#[derive(Debug , Clone)]
+pub enum QueueError {
+    FileOpen(String),
+    FileStat(String),
+    InvalidSize { got: u64, expected: u64 },
+    Mmap(String),
+    InvalidMagic { got: u32 },
+    CapacityMismatch { got: u32, expected: u32 },
+    CorruptedOrder,
+    QueueFull { depth: u64 },
+    Flush(String),
+}
+
+impl std::fmt::Display for QueueError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QueueError::FileOpen(e) => write!(f, "Failed to open file: {}", e),
+            QueueError::FileStat(e) => write!(f, "Failed to stat file: {}", e),
+            QueueError::InvalidSize { got, expected } => {
+                write!(f, "Invalid file size: got {}, expected {}", got, expected)
+            }
+            QueueError::Mmap(e) => write!(f, "Failed to mmap: {}", e),
+            QueueError::InvalidMagic { got } => {
+                write!(f, "Invalid queue magic: got 0x{:X}", got)
+            }
+            QueueError::CapacityMismatch { got, expected } => {
+                write!(f, "Capacity mismatch: got {}, expected {}", got, expected)
+            }
+            QueueError::CorruptedOrder => write!(f, "Corrupted order detected"),
+            QueueError::QueueFull { depth } => {
+                write!(f, "Queue full - backpressure at depth {}", depth)
+            }
+            QueueError::Flush(e) => write!(f, "Failed to flush: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for QueueError {}
+
+// Thread-safe: Queue can be sent between threads
+unsafe impl Send for AdminCommandQueue {}
+// Not Sync: only one thread should access at a time (SPSC model)
+
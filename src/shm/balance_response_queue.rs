@@ -14,6 +14,8 @@ pub struct BalanceResponse{
     pub user_id : u64 , 
     pub delta_available_balance : i64 ,
     pub delta_reserved_balance  : i64 ,
+    pub asset : u32 ,            // 0 the cash balance , otherwise the wallet asset
+    pub _pad : u32 ,
 }
 // QueueHeader with cache-line padding matching Go
 #[repr(C)]
@@ -35,7 +37,7 @@ const HEADER_SIZE: usize = std::mem::size_of::<QueueHeader>();
 const TOTAL_SIZE: usize = HEADER_SIZE + (QUEUE_CAPACITY * ORDER_SIZE);
 
 // Compile-time layout assertions (fail build if wrong)
-const _: () = assert!(ORDER_SIZE == 24, "Order must be 24 bytes");
+const _: () = assert!(ORDER_SIZE == 32, "Order must be 32 bytes");
 const _: () = assert!(HEADER_SIZE == 136, "QueueHeader must be 136 bytes");
 const _: () = {
     // Verify ConsumerTail is at offset 64
@@ -22,6 +22,7 @@ pub struct QueueHeader {
 }
 
 #[repr(C)]
+#[derive(Debug , Clone , Copy)]
 pub struct OrderEvents {
     pub user_id: u64,
     pub order_id: u64,
@@ -34,8 +35,40 @@ pub struct OrderEvents {
     pub original_qty: u32,
 
     pub error_code: u32,     // error code for different balance manaer errors , insuff funds , user not found etc 
+
+    pub fee: i64,            // fees the order paid on the fills of this event , negative for a rebate
 }
 
+// error_code on a canceled (4) event , why the order left the book
+pub const CANCEL_REASON_USER : u32 = 0;
+pub const CANCEL_REASON_DELISTED : u32 = 1;
+pub const CANCEL_REASON_FROZEN : u32 = 2;
+// an amend took the order off to place it again at its new qty and price
+pub const CANCEL_REASON_AMENDED : u32 = 3;
+// error_code on a rejected (3) event
+pub const REJECT_REASON_FUNDS : u32 = 1;
+pub const REJECT_REASON_HALTED : u32 = 2;
+pub const REJECT_REASON_UNLISTED : u32 = 3;
+// pre-trade limits , see balance_manager::risk_limits
+pub const REJECT_REASON_ORDER_QTY : u32 = 4;
+pub const REJECT_REASON_ORDER_NOTIONAL : u32 = 5;
+pub const REJECT_REASON_OPEN_ORDERS : u32 = 6;
+pub const REJECT_REASON_POSITION : u32 = 7;
+pub const REJECT_REASON_DAILY_NOTIONAL : u32 = 8;
+pub const REJECT_REASON_PRICE_DEVIATION : u32 = 9;
+// the user's rate limit bucket was empty , see balance_manager::rate_limits
+// also sent for a throttled cancel , the order it named stays on the book
+pub const REJECT_REASON_THROTTLED : u32 = 10;
+// kill switch , the user is frozen or trading is stopped venue wide
+pub const REJECT_REASON_USER_FROZEN : u32 = 11;
+pub const REJECT_REASON_TRADING_STOPPED : u32 = 12;
+// a cancel for another user's order , the order stays on the book
+pub const REJECT_REASON_NOT_OWNER : u32 = 13;
+// an amend named no order resting on the book
+pub const REJECT_REASON_UNKNOWN_ORDER : u32 = 14;
+// the sharded risk thread cannot cancel and place in one step , amends are refused there
+pub const REJECT_REASON_AMEND_UNSUPPORTED : u32 = 15;
+
 
 
 const QUEUE_MAGIC: u32 = 0xEAAAAAAC;
@@ -46,7 +79,7 @@ const HEADER_SIZE: usize = std::mem::size_of::<QueueHeader>();
 const TOTAL_SIZE: usize = HEADER_SIZE + (QUEUE_CAPACITY * ORDER_SIZE);
 
 // Compile-time layout assertions (fail build if wrong)
-const _: () = assert!(ORDER_SIZE == 40, "Order must be 24 bytes");
+const _: () = assert!(ORDER_SIZE == 48, "Order must be 48 bytes");
 const _: () = assert!(HEADER_SIZE == 136, "QueueHeader must be 136 bytes");
 const _: () = {
     // Verify ConsumerTail is at offset 64
@@ -19,6 +19,7 @@ pub struct QueueHeader {
 }
 
 #[repr(C)]
+#[derive(Debug , Clone , Copy)]
 pub struct MarketMakerFeed{
     pub timestamp   : u64 , 
     pub last_traded_price : u64 , 
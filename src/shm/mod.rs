@@ -12,4 +12,8 @@ pub mod holdings_log_queue;
 pub mod trade_log_queue;
 pub mod snapshot_queue;
 pub mod fill_queue_mm;
-pub mod market_maker_feed;
\ No newline at end of file
+pub mod market_maker_feed;
+pub mod admin_command_queue;
+pub mod admin_response_queue;
+pub mod status_page;
+pub mod position_queue;
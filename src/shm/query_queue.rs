@@ -24,7 +24,7 @@ pub struct Query{
     pub symbol : u32 , 
     pub reserved_shares_qty: u32,
     pub available_shares_qty : u32,
-    pub query_type : u8 ,   // 0 -> change available balance , 1 -> change availableholdings , 2 -> add user on login 3-> add orderbok
+    pub query_type : u8 ,   // 6 -> report the user's positions , 0 to 5 are retired and ignored : the balance and holdings overwrites and add user , add book , shutdown and delist , now ADMIN_* commands
 }
 const QUEUE_MAGIC: u32 = 0x51554552;
 // reduce size 
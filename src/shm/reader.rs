@@ -5,6 +5,7 @@ use bounded_spsc_queue::Producer;
 // SHM reader , passed ordrs to the balance manager 
 use crate::{orderbook::order::ShmOrder, shm::queue::IncomingOrderQueue};
 use crate::orderbook::order::Side;
+use crate::shutdown::shutdown_signal::ShutdownHandle;
 use crate::orderbook::order::{Order };
 pub struct ShmReader {
     pub queue: IncomingOrderQueue,  
@@ -13,8 +14,8 @@ pub struct ShmReader {
 }
 impl ShmReader {
     /// Returns None if queue can't be opened
-    pub fn new( shm_bm_order_queue_try : Producer<Order>) -> Option<Self> {
-        match IncomingOrderQueue::open("/tmp/IncomingOrders") {
+    pub fn new( shm_bm_order_queue_try : Producer<Order> , path : &str) -> Option<Self> {
+        match IncomingOrderQueue::open(path) {
             Ok(queue) => Some(Self { queue   , order_batch : Vec::with_capacity(1000) , shm_bm_order_queue_try}),
             Err(e) => {
                 eprintln!("[SHM Reader] Failed to open queue: {:?}", e);
@@ -22,14 +23,15 @@ impl ShmReader {
             }
         }
     }
-    pub fn run_reader(&mut self) {
+    pub fn run_reader(&mut self , shutdown : &ShutdownHandle) {
         eprintln!("[SHM Reader] Starting on Core 2");
         
         self.order_batch.clear();
         let mut count = 0u64;
         let mut last_log = std::time::Instant::now();
         
-        loop {
+        // orders left in the shm queue stay there for the next run
+        while !shutdown.stop_requested() {
             for _ in 0..1000{
                 match self.queue.dequeue(){
                     Ok(Some(shm_order))=>{
@@ -79,6 +81,7 @@ impl ShmReader {
                 last_log = std::time::Instant::now();
             }
         }
+        shutdown.finish();
     }
 }
 
@@ -87,8 +90,11 @@ pub struct StShmReader{
 }
 
 impl StShmReader{
-    pub fn new() -> Option<Self> {
-        match IncomingOrderQueue::open("/tmp/IncomingOrders") {
+    pub fn depth(&self)->u64{
+        self.queue.depth()
+    }
+    pub fn new(path : &str) -> Option<Self> {
+        match IncomingOrderQueue::open(path) {
             Ok(queue) => Some(Self { queue }),
             Err(e) => {
                 eprintln!("[SHM Reader] Failed to open queue: {:?}", e);
@@ -0,0 +1,266 @@
+// shared memory status page , one slot per pipeline thread
+// the thread bumps its heartbeat and reports its input queue depths , the watchdog reads the heartbeats and
+// writes the state and the last progress time , external tools open the page read only and take a snapshot
+// every field is an atomic in the mapping , so the page is shared by all threads of the engine process
+use memmap2::MmapMut;
+use std::fs::{self, OpenOptions};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+use std::sync::atomic::{AtomicU32, AtomicU64, AtomicU8, Ordering};
+use thiserror::Error;
+
+const STATUS_MAGIC : u32 = 0xEAAA5747;
+pub const MAX_THREADS : usize = 32;
+pub const NAME_LEN : usize = 32;
+pub const MAX_INPUTS : usize = 8;
+
+pub const THREAD_STATE_EMPTY : u32 = 0;
+pub const THREAD_STATE_RUNNING : u32 = 1;
+pub const THREAD_STATE_STALLED : u32 = 2;
+pub const THREAD_STATE_PANICKED : u32 = 3;
+pub const THREAD_STATE_STOPPED : u32 = 4;
+
+#[repr(C)]
+pub struct StatusHeader{
+    magic : AtomicU32,          // offset 0
+    max_threads : AtomicU32,    // offset 4
+    slot_count : AtomicU32,     // offset 8 , slots handed out so far
+    _pad0 : u32,
+    created_ns : AtomicU64,     // offset 16
+    watchdog_ns : AtomicU64,    // offset 24 , last watchdog pass , a stale value means nobody is watching
+    _pad1 : [u8; 32],           // pad to 64B
+}
+
+#[repr(C)]
+pub struct ThreadSlot{
+    name : [AtomicU8; NAME_LEN],            // offset 0 , utf8 , zero padded
+    state : AtomicU32,                      // offset 32
+    input_count : AtomicU32,                // offset 36
+    heartbeat : AtomicU64,                  // offset 40 , bumped by the thread every loop iteration
+    last_progress_ns : AtomicU64,           // offset 48 , unix ns of the last heartbeat change the watchdog saw
+    _pad0 : [u8; 8],
+    input_depths : [AtomicU64; MAX_INPUTS], // offset 64 , sampled by the thread , ends at 128B
+}
+
+const HEADER_SIZE : usize = std::mem::size_of::<StatusHeader>();
+const SLOT_SIZE : usize = std::mem::size_of::<ThreadSlot>();
+const TOTAL_SIZE : usize = HEADER_SIZE + SLOT_SIZE * MAX_THREADS;
+
+const _ : () = {
+    assert!(HEADER_SIZE == 64, "StatusHeader must be 64 bytes");
+    assert!(SLOT_SIZE == 128, "ThreadSlot must be 128 bytes");
+    assert!(std::mem::offset_of!(ThreadSlot, heartbeat) == 40, "heartbeat must be at offset 40");
+    assert!(std::mem::offset_of!(ThreadSlot, input_depths) == 64, "input_depths must be at offset 64");
+};
+
+#[derive(Debug , Error)]
+pub enum StatusPageError{
+    #[error("cannot open status page: {0}")]
+    FileOpen(String),
+    #[error("cannot map status page: {0}")]
+    Mmap(String),
+    #[error("status page has size {got}, expected {expected}")]
+    InvalidSize { got : u64 , expected : u64 },
+    #[error("status page has magic 0x{got:X}")]
+    InvalidMagic { got : u32 },
+    #[error("status page has no free slot for {0}")]
+    Full(String),
+}
+
+#[derive(Debug , Clone , Copy , PartialEq , Eq)]
+pub enum ThreadState{
+    Running,
+    Stalled,
+    Panicked,
+    Stopped,
+}
+
+impl ThreadState{
+    pub fn from_u32(state : u32)->Option<Self>{
+        match state {
+            THREAD_STATE_RUNNING => Some(Self::Running),
+            THREAD_STATE_STALLED => Some(Self::Stalled),
+            THREAD_STATE_PANICKED => Some(Self::Panicked),
+            THREAD_STATE_STOPPED => Some(Self::Stopped),
+            _ => None,
+        }
+    }
+    pub fn as_u32(self)->u32{
+        match self {
+            Self::Running => THREAD_STATE_RUNNING,
+            Self::Stalled => THREAD_STATE_STALLED,
+            Self::Panicked => THREAD_STATE_PANICKED,
+            Self::Stopped => THREAD_STATE_STOPPED,
+        }
+    }
+    pub fn as_str(self)->&'static str{
+        match self {
+            Self::Running => "running",
+            Self::Stalled => "stalled",
+            Self::Panicked => "panicked",
+            Self::Stopped => "stopped",
+        }
+    }
+}
+
+// one slot as read at one moment , fields are read one by one and may be a few nanoseconds apart
+#[derive(Debug , Clone , PartialEq , Eq)]
+pub struct ThreadStatus{
+    pub slot : usize,
+    pub name : String,
+    pub state : ThreadState,
+    pub heartbeat : u64,
+    pub last_progress_ns : u64,
+    pub input_depths : Vec<u64>,
+}
+
+pub struct StatusPage{
+    mmap : MmapMut,
+}
+
+// every access goes through the atomics in the mapping
+unsafe impl Send for StatusPage {}
+unsafe impl Sync for StatusPage {}
+
+impl StatusPage{
+    pub fn create<P : AsRef<Path>>(path : P)->Result<Self , StatusPageError>{
+        let _ = fs::remove_file(&path);
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .mode(0o666)
+            .open(&path)
+            .map_err(|e| StatusPageError::FileOpen(e.to_string()))?;
+        file.set_len(TOTAL_SIZE as u64).map_err(|e| StatusPageError::FileOpen(e.to_string()))?;
+        let mmap = unsafe { MmapMut::map_mut(&file) }.map_err(|e| StatusPageError::Mmap(e.to_string()))?;
+        let page = Self { mmap };
+        let header = page.header();
+        header.max_threads.store(MAX_THREADS as u32, Ordering::Relaxed);
+        header.slot_count.store(0, Ordering::Relaxed);
+        header.created_ns.store(crate::metrics::latency::unix_nanos(), Ordering::Relaxed);
+        // magic last , an opener never sees a half initialised page
+        header.magic.store(STATUS_MAGIC, Ordering::Release);
+        Ok(page)
+    }
+
+    pub fn open<P : AsRef<Path>>(path : P)->Result<Self , StatusPageError>{
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path)
+            .map_err(|e| StatusPageError::FileOpen(e.to_string()))?;
+        let len = file.metadata().map_err(|e| StatusPageError::FileOpen(e.to_string()))?.len();
+        if len != TOTAL_SIZE as u64 {
+            return Err(StatusPageError::InvalidSize { got : len , expected : TOTAL_SIZE as u64 });
+        }
+        let mmap = unsafe { MmapMut::map_mut(&file) }.map_err(|e| StatusPageError::Mmap(e.to_string()))?;
+        let page = Self { mmap };
+        let magic = page.header().magic.load(Ordering::Acquire);
+        if magic != STATUS_MAGIC {
+            return Err(StatusPageError::InvalidMagic { got : magic });
+        }
+        Ok(page)
+    }
+
+    fn header(&self)->&StatusHeader{
+        unsafe { &*(self.mmap.as_ptr() as *const StatusHeader) }
+    }
+
+    pub(crate) fn slot(&self , index : usize)->&ThreadSlot{
+        assert!(index < MAX_THREADS, "status slot {} out of range", index);
+        unsafe { &*(self.mmap.as_ptr().add(HEADER_SIZE + index * SLOT_SIZE) as *const ThreadSlot) }
+    }
+
+    pub fn slot_count(&self)->usize{
+        (self.header().slot_count.load(Ordering::Acquire) as usize).min(MAX_THREADS)
+    }
+
+    /// the slot for `name` , a thread restarted under the same name gets its old slot back
+    pub fn register(&self , name : &str)->Result<usize , StatusPageError>{
+        let index = match (0..self.slot_count()).find(|index| self.slot(*index).name() == name) {
+            Some(index) => index,
+            None => {
+                let index = self.header().slot_count.fetch_add(1, Ordering::AcqRel) as usize;
+                if index >= MAX_THREADS {
+                    self.header().slot_count.store(MAX_THREADS as u32, Ordering::Release);
+                    return Err(StatusPageError::Full(name.to_string()));
+                }
+                let slot = self.slot(index);
+                for (byte , value) in slot.name.iter().zip(name.bytes().chain(std::iter::repeat(0))) {
+                    byte.store(value, Ordering::Relaxed);
+                }
+                index
+            }
+        };
+        let slot = self.slot(index);
+        slot.last_progress_ns.store(crate::metrics::latency::unix_nanos(), Ordering::Relaxed);
+        slot.state.store(THREAD_STATE_RUNNING, Ordering::Release);
+        Ok(index)
+    }
+
+    pub fn watchdog_ns(&self)->u64{
+        self.header().watchdog_ns.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn set_watchdog_ns(&self , now_ns : u64){
+        self.header().watchdog_ns.store(now_ns, Ordering::Relaxed);
+    }
+
+    /// every registered thread
+    pub fn snapshot(&self)->Vec<ThreadStatus>{
+        (0..self.slot_count()).filter_map(|index| {
+            let slot = self.slot(index);
+            let state = ThreadState::from_u32(slot.state.load(Ordering::Acquire))?;
+            let inputs = (slot.input_count.load(Ordering::Relaxed) as usize).min(MAX_INPUTS);
+            Some(ThreadStatus {
+                slot : index,
+                name : slot.name(),
+                state,
+                heartbeat : slot.heartbeat(),
+                last_progress_ns : slot.last_progress_ns(),
+                input_depths : slot.input_depths[..inputs].iter().map(|depth| depth.load(Ordering::Relaxed)).collect(),
+            })
+        }).collect()
+    }
+}
+
+impl ThreadSlot{
+    pub fn name(&self)->String{
+        let bytes : Vec<u8> = self.name.iter().map(|byte| byte.load(Ordering::Relaxed)).take_while(|byte| *byte != 0).collect();
+        String::from_utf8_lossy(&bytes).into_owned()
+    }
+    pub fn state(&self)->u32{
+        self.state.load(Ordering::Acquire)
+    }
+    /// moves the slot from one state to another , false when it was no longer in `from`
+    pub fn transition(&self , from : u32 , to : u32)->bool{
+        self.state.compare_exchange(from, to, Ordering::AcqRel, Ordering::Acquire).is_ok()
+    }
+    pub fn set_state(&self , state : u32){
+        self.state.store(state, Ordering::Release);
+    }
+    #[inline(always)]
+    pub fn heartbeat(&self)->u64{
+        self.heartbeat.load(Ordering::Relaxed)
+    }
+    // single writer , the owning thread , a plain store is enough
+    #[inline(always)]
+    pub fn set_heartbeat(&self , beats : u64){
+        self.heartbeat.store(beats, Ordering::Relaxed);
+    }
+    pub fn last_progress_ns(&self)->u64{
+        self.last_progress_ns.load(Ordering::Relaxed)
+    }
+    pub fn set_last_progress_ns(&self , now_ns : u64){
+        self.last_progress_ns.store(now_ns, Ordering::Relaxed);
+    }
+    pub fn set_input_depths(&self , depths : impl IntoIterator<Item = u64>){
+        let mut count = 0;
+        for (slot , depth) in self.input_depths.iter().zip(depths) {
+            slot.store(depth, Ordering::Relaxed);
+            count += 1;
+        }
+        self.input_count.store(count, Ordering::Relaxed);
+    }
+}
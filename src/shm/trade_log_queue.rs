@@ -25,7 +25,7 @@ const HEADER_SIZE: usize = std::mem::size_of::<QueueHeader>();
 const TOTAL_SIZE: usize = HEADER_SIZE + (QUEUE_CAPACITY * LOG_SIZE);
 
 // Compile-time layout assertions (fail build if wrong)
-const _: () = assert!(LOG_SIZE == 48, "Order must be  48 bytes");
+const _: () = assert!(LOG_SIZE == 64, "Order must be  64 bytes");
 const _: () = assert!(HEADER_SIZE == 136, "QueueHeader must be 136 bytes");
 const _: () = {
     // Verify ConsumerTail is at offset 64
@@ -5,41 +5,73 @@ use crate::{shm::{balance_response_queue::BalanceResponse, event_queue::{OrderEv
 // publisher more priority so that we get the order responses for post req 
 // insufficient funds would have low probobaility 
 use crate::shm::holdings_response_queue::HoldingResQueue;
+use crate::shutdown::shutdown_signal::ShutdownHandle;
 use crate::shm::balance_response_queue::BalanceResQueue;
+use crate::shm::admin_response_queue::{AdminResponse, AdminResponseQueue};
+use crate::backpressure::policy::{channels, BackpressureConfig};
+use crate::config::settings::QueuePaths;
+use crate::backpressure::sender::PolicySender;
+use crate::sharding::fan_in::FanIn;
+use crate::metrics::latency::{LatencyRecorder, Stage};
+use crate::watchdog::heartbeat::Heartbeat;
+use crate::supervisor::thread_supervisor::{RestartError, Worker};
+use std::time::Instant;
 
+// one shm write , the time a full queue costs under its policy included
+#[inline(always)]
+fn timed_write<R>(latency : &mut LatencyRecorder , write : impl FnOnce()->R)->R{
+    let started = Instant::now();
+    let result = write();
+    latency.record_since(Stage::ShmWrite, started);
+    result
+}
+
+// a full shm queue means a reader on the other side is behind , the channel policy decides what happens
+// fail fast is only counted here , the writer is the last stage and has nobody to hand the error to
 pub struct ShmWriter{
-    pub order_event_queue       : OrderEventQueue ,
-    pub balance_response_queue  : BalanceResQueue,
-    pub holding_response_queue  : HoldingResQueue,
-    pub market_maker_fill_queue : MarketMakerFillQueue,
-    pub market_maker_feed_queue : MarketMakerFeedQueue,
+    pub order_event_queue       : PolicySender<OrderEvents , OrderEventQueue> ,
+    pub balance_response_queue  : PolicySender<BalanceResponse , BalanceResQueue>,
+    pub holding_response_queue  : PolicySender<HoldingResponse , HoldingResQueue>,
+    pub market_maker_fill_queue : PolicySender<MarketMakerFill , MarketMakerFillQueue>,
+    pub market_maker_feed_queue : PolicySender<MarketMakerFeed , MarketMakerFeedQueue>,
+    // none when the admin response queue could not be opened , responses are then dropped
+    pub admin_response_queue    : Option<PolicySender<AdminResponse , AdminResponseQueue>>,
 
 
     pub rec_from_bm_try : Consumer<OrderEvents>,
     pub rec_from_publisher_try : Consumer<OrderEvents> , 
-    pub rec_from_engine_try : Consumer<OrderEvents>,
+    // one input per engine for these two , a single one outside sharded mode
+    pub rec_from_engine_try : FanIn<OrderEvents>,
     pub rec_balance_update : Consumer<BalanceResponse>,
     pub rec_holdings_updates : Consumer<HoldingResponse>,
     pub mm_fill_recive : Consumer<MarketMakerFill>,
-    pub mm_feed_recive : Consumer<MarketMakerFeed>
+    pub mm_feed_recive : FanIn<MarketMakerFeed>,
+    pub rec_admin_responses : Consumer<AdminResponse>,
+    pub latency : LatencyRecorder,
+    pub heartbeat : Option<Heartbeat>,
+    // reopened after a panic
+    pub queues : QueuePaths,
 }
 
 
 impl ShmWriter{
+    #[allow(clippy::too_many_arguments)]
     pub fn new( rec_from_bm_try : Consumer<OrderEvents>,
         rec_from_publisher_try : Consumer<OrderEvents> , 
-        rec_from_engine_try : Consumer<OrderEvents>,
+        rec_from_engine_try : impl Into<FanIn<OrderEvents>>,
         rec_balance_update : Consumer<BalanceResponse>,
         rec_holdings_updates : Consumer<HoldingResponse>,
         mm_fill_recive : Consumer<MarketMakerFill>,
-        mm_feed_recive : Consumer<MarketMakerFeed>
-        
+        mm_feed_recive : impl Into<FanIn<MarketMakerFeed>>,
+        rec_admin_responses : Consumer<AdminResponse>,
+        backpressure : &BackpressureConfig,
+        queues : &QueuePaths
     )->Option<Self>{
-        let order_event_queue = OrderEventQueue::open("/tmp/OrderEvents");
-        let holding_response_queue = HoldingResQueue::open("/tmp/HoldingsResponse");
-        let balance_response_queue = BalanceResQueue::open("/tmp/BalanceResponse");
-        let market_maker_fill_queue = MarketMakerFillQueue::open("/tmp/MarketMakerFills");
-        let market_maker_feed_queue = MarketMakerFeedQueue::open("/tmp/MarketMakerFeed");
+        let order_event_queue = OrderEventQueue::open(&queues.order_events);
+        let holding_response_queue = HoldingResQueue::open(&queues.holdings_response);
+        let balance_response_queue = BalanceResQueue::open(&queues.balance_response);
+        let market_maker_fill_queue = MarketMakerFillQueue::open(&queues.market_maker_fills);
+        let market_maker_feed_queue = MarketMakerFeedQueue::open(&queues.market_maker_feed);
         if balance_response_queue.is_err(){
             eprintln!("response queue init error in balance manager");
             eprintln!("{:?}" , balance_response_queue)
@@ -51,22 +83,33 @@ impl ShmWriter{
         if market_maker_fill_queue.is_err(){
             eprintln!("market maker queue init error")
         }
+        let admin_response_queue = match AdminResponseQueue::open(&queues.admin_responses) {
+            Ok(queue) => Some(PolicySender::from_config(queue, channels::WRITER_ADMIN_RESPONSES, backpressure)),
+            Err(e) => {
+                eprintln!("admin response queue init error , admin responses will be dropped: {}", e);
+                None
+            }
+        };
         match order_event_queue {
             Ok(queue)=>{
                 Some(Self{
-                    mm_feed_recive,
-                    order_event_queue : queue ,
+                    mm_feed_recive : mm_feed_recive.into(),
+                    order_event_queue : PolicySender::from_config(queue, channels::WRITER_ORDER_EVENTS, backpressure) ,
                     rec_from_bm_try , 
                     rec_from_publisher_try , 
-                    rec_from_engine_try,
-                    holding_response_queue : holding_response_queue.unwrap(),
-                    balance_response_queue : balance_response_queue.unwrap(),
-                    market_maker_fill_queue : market_maker_fill_queue.unwrap(),
-                    market_maker_feed_queue : market_maker_feed_queue.unwrap(),
+                    rec_from_engine_try : rec_from_engine_try.into(),
+                    holding_response_queue : PolicySender::from_config(holding_response_queue.unwrap(), channels::WRITER_HOLDING_RESPONSES, backpressure),
+                    balance_response_queue : PolicySender::from_config(balance_response_queue.unwrap(), channels::WRITER_BALANCE_RESPONSES, backpressure),
+                    market_maker_fill_queue : PolicySender::from_config(market_maker_fill_queue.unwrap(), channels::WRITER_MM_FILLS, backpressure),
+                    market_maker_feed_queue : PolicySender::from_config(market_maker_feed_queue.unwrap(), channels::WRITER_MM_FEED, backpressure),
                     rec_balance_update,
                     rec_holdings_updates,
-                    mm_fill_recive
-                    
+                    mm_fill_recive,
+                    admin_response_queue,
+                    rec_admin_responses,
+                    latency : LatencyRecorder::default(),
+                    heartbeat : None,
+                    queues : queues.clone(),
                 })
             }
             Err(_)=>{
@@ -76,47 +119,129 @@ impl ShmWriter{
         }
     }
 
-    pub fn start_shm_writter(&mut self){
+    fn inputs_empty(&self)->bool{
+        self.rec_from_bm_try.size() == 0
+        && self.rec_from_publisher_try.size() == 0
+        && self.rec_from_engine_try.size() == 0
+        && self.rec_balance_update.size() == 0
+        && self.rec_holdings_updates.size() == 0
+        && self.mm_fill_recive.size() == 0
+        && self.mm_feed_recive.size() == 0
+        && self.rec_admin_responses.size() == 0
+    }
+
+    fn flush_parked(&mut self){
+        self.order_event_queue.flush();
+        self.balance_response_queue.flush();
+        self.holding_response_queue.flush();
+        self.market_maker_fill_queue.flush();
+        self.market_maker_feed_queue.flush();
+        if let Some(queue) = self.admin_response_queue.as_mut() {
+            queue.flush();
+        }
+    }
+
+    // runs until shutdown and every event already produced upstream is in shared memory
+    pub fn start_shm_writter(&mut self , shutdown : &ShutdownHandle){
         loop {
+            if let Some(heartbeat) = self.heartbeat.as_mut() {
+                heartbeat.beat_with_depths(|| [
+                    self.rec_from_bm_try.size() as u64,
+                    self.rec_from_publisher_try.size() as u64,
+                    self.rec_from_engine_try.size() as u64,
+                    self.rec_balance_update.size() as u64,
+                    self.rec_holdings_updates.size() as u64,
+                    self.mm_fill_recive.size() as u64,
+                    self.mm_feed_recive.size() as u64,
+                    self.rec_admin_responses.size() as u64,
+                ]);
+            }
             let mut did_work = false;
             // THE BALANCE AND THE HOLDINGS EVENTS FOR THE UPDATED BALANCE , HOLDINGS , AFTER EACH TRADE 
             if let Some(balance_updates) = self.rec_balance_update.try_pop(){
-                let _ = self.balance_response_queue.enqueue(balance_updates).expect("balance response enqeueue error ");
+                let _ = timed_write(&mut self.latency, || self.balance_response_queue.send(balance_updates));
                 did_work = true;
             }
 
             if let Some(holding_updates) = self.rec_holdings_updates.try_pop(){
-                let _= self.holding_response_queue.enqueue(holding_updates).expect("holding response enqeueue error ");
+                let _ = timed_write(&mut self.latency, || self.holding_response_queue.send(holding_updates));
                 did_work = true;
             }
             // THE ORDER EVENT FOR USER TOO SEE , THE RESULT OF HIS PLACED ORDER 
             if let Some(event) = self.rec_from_publisher_try.try_pop(){
-                let _ = self.order_event_queue.enqueue(event).expect("order event enqeueue error ");
+                let _ = timed_write(&mut self.latency, || self.order_event_queue.send(event));
                 did_work = true;
             }
             if let Some(feed )= self.mm_feed_recive.try_pop(){
-                let _ = self.market_maker_feed_queue.enqueue(feed).expect("mm feed enqeueue error ");
+                let _ = timed_write(&mut self.latency, || self.market_maker_feed_queue.send(feed));
                 did_work = true;
             }
             if let Some(fill) = self.mm_fill_recive.try_pop(){
-                let _ = self.market_maker_fill_queue.enqueue(fill).expect("mm fill enqeueue error ");
+                let _ = timed_write(&mut self.latency, || self.market_maker_fill_queue.send(fill));
+                did_work = true;
+            }
+            if let Some(response) = self.rec_admin_responses.try_pop(){
+                if let Some(queue) = self.admin_response_queue.as_mut() {
+                    let _ = timed_write(&mut self.latency, || queue.send(response));
+                }
                 did_work = true;
             }
             // THE INSUFFICIENT FUND EVENT 
             if let Some(event) = self.rec_from_bm_try.try_pop(){
-                let _ = self.order_event_queue.enqueue(event).expect("order event enqeueue error ");
+                let _ = timed_write(&mut self.latency, || self.order_event_queue.send(event));
                 did_work = true;
             }
             // THE SUCCESSFULL CANCELLATION OF ORDER EVENT 
             if let Some(event) = self.rec_from_engine_try.try_pop(){
-                let _= self.order_event_queue.enqueue(event).expect("order event  enqeueue error ");
+                let _ = timed_write(&mut self.latency, || self.order_event_queue.send(event));
                 did_work = true ;
             }
             
             if !did_work{
+                self.latency.flush_if_due();
+                self.flush_parked();
+                if shutdown.upstream_finished() && self.inputs_empty() {
+                    break;
+                }
                 std::hint::spin_loop();
             }
         }
+        self.latency.flush();
+        // the shm readers may already be gone , whatever is still parked is reported and not waited for
+        self.flush_parked();
+        let parked = self.order_event_queue.parked() + self.balance_response_queue.parked() + self.holding_response_queue.parked()
+            + self.market_maker_fill_queue.parked() + self.market_maker_feed_queue.parked()
+            + self.admin_response_queue.as_ref().map_or(0, |queue| queue.parked());
+        if parked != 0 {
+            eprintln!("[Shm Writer] exiting with {} parked messages undelivered", parked);
+        }
+        self.heartbeat = None;
+        shutdown.finish();
     }
 
-}
\ No newline at end of file
+}
+
+// the shm queues are mapped again , parked messages and channel stats carry over
+impl Worker for ShmWriter{
+    fn run(&mut self , shutdown : &ShutdownHandle){
+        self.start_shm_writter(shutdown);
+    }
+    fn restart(&mut self)->Result<() , RestartError>{
+        // every queue module has a QueueError of its own
+        fn reopen<E : std::fmt::Debug>(what : &'static str)->impl FnOnce(E)->RestartError{
+            move |e| RestartError::reopen("writer", what, e)
+        }
+        self.order_event_queue.replace_inner(OrderEventQueue::open(&self.queues.order_events).map_err(reopen("order_events"))?);
+        self.balance_response_queue.replace_inner(BalanceResQueue::open(&self.queues.balance_response).map_err(reopen("balance_response"))?);
+        self.holding_response_queue.replace_inner(HoldingResQueue::open(&self.queues.holdings_response).map_err(reopen("holdings_response"))?);
+        self.market_maker_fill_queue.replace_inner(MarketMakerFillQueue::open(&self.queues.market_maker_fills).map_err(reopen("market_maker_fills"))?);
+        self.market_maker_feed_queue.replace_inner(MarketMakerFeedQueue::open(&self.queues.market_maker_feed).map_err(reopen("market_maker_feed"))?);
+        if let Some(queue) = self.admin_response_queue.as_mut() {
+            queue.replace_inner(AdminResponseQueue::open(&self.queues.admin_responses).map_err(reopen("admin_responses"))?);
+        }
+        Ok(())
+    }
+    fn heartbeat(&self)->Option<&Heartbeat>{
+        self.heartbeat.as_ref()
+    }
+}
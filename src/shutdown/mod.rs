@@ -0,0 +1,2 @@
+pub mod shutdown_signal;
+pub mod tests;
@@ -0,0 +1,96 @@
+// coordinated shutdown for the pipeline threads
+// a request (SIGTERM , SIGINT or an admin query) only tells the sources to stop taking input
+// every other thread keeps draining and exits once all of its upstream stages have finished and its queues are empty
+// so the in flight events in the spsc queues reach shared memory in dependency order before anything exits
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+#[derive(Debug , Clone , Copy , PartialEq , Eq)]
+pub enum Stage{
+    Reader,
+    BalanceManager,
+    Core,
+    Engine,
+    Publisher,
+    Writer,
+    Logger,
+}
+
+const STAGE_COUNT : usize = 7;
+
+pub struct Shutdown{
+    // an Arc of its own so signal_hook can set it straight from the handler
+    requested : Arc<AtomicBool>,
+    finished : [AtomicBool ; STAGE_COUNT],
+}
+
+impl Shutdown{
+    pub fn new()->Arc<Self>{
+        Arc::new(Self {
+            requested : Arc::new(AtomicBool::new(false)),
+            finished : std::array::from_fn(|_| AtomicBool::new(false)),
+        })
+    }
+
+    /// SIGTERM and SIGINT request a shutdown instead of killing the process
+    pub fn register_signals(&self)->std::io::Result<()>{
+        signal_hook::flag::register(signal_hook::consts::SIGTERM, self.requested.clone())?;
+        signal_hook::flag::register(signal_hook::consts::SIGINT, self.requested.clone())?;
+        Ok(())
+    }
+
+    pub fn request(&self){
+        if !self.requested.swap(true, Ordering::AcqRel) {
+            eprintln!("[Shutdown] requested , draining the pipeline");
+        }
+    }
+
+    #[inline(always)]
+    pub fn is_requested(&self)->bool{
+        self.requested.load(Ordering::Relaxed)
+    }
+
+    // everything the stage pushed before this is visible to whoever sees it finished
+    pub fn finish(&self , stage : Stage){
+        self.finished[stage as usize].store(true, Ordering::Release);
+        eprintln!("[Shutdown] {:?} finished", stage);
+    }
+
+    pub fn is_finished(&self , stage : Stage)->bool{
+        self.finished[stage as usize].load(Ordering::Acquire)
+    }
+
+    pub fn handle(self : &Arc<Self> , stage : Stage , upstream : &[Stage])->ShutdownHandle{
+        ShutdownHandle { shutdown : self.clone() , stage , upstream : upstream.to_vec() }
+    }
+}
+
+// what a single thread sees of the shutdown , its own stage and the stages feeding its queues
+#[derive(Clone)]
+pub struct ShutdownHandle{
+    shutdown : Arc<Shutdown>,
+    stage : Stage,
+    upstream : Vec<Stage>,
+}
+
+impl ShutdownHandle{
+    /// for sources , stop taking new input
+    #[inline(always)]
+    pub fn stop_requested(&self)->bool{
+        self.shutdown.is_requested()
+    }
+
+    pub fn request(&self){
+        self.shutdown.request();
+    }
+
+    /// nothing more will arrive on this stage's input queues once this is true
+    /// check it before the final emptiness check of the queues , not after
+    pub fn upstream_finished(&self)->bool{
+        self.upstream.iter().all(|stage| self.shutdown.is_finished(*stage))
+    }
+
+    pub fn finish(&self){
+        self.shutdown.finish(self.stage);
+    }
+}
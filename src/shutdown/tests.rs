@@ -0,0 +1,83 @@
+#[cfg(test)]
+mod tests {
+    use crate::journal::command_journal::InboundCommand;
+    use crate::journal::replay::detached_core;
+    use crate::admin::plane::AdminRequest;
+    use crate::shm::admin_command_queue::{AdminCommand, ADMIN_SHUTDOWN};
+    use crate::shm::query_queue::Query;
+    use crate::shutdown::shutdown_signal::{Shutdown, Stage};
+
+    fn shutdown_query() -> Query {
+        Query {
+            available_balance: 0,
+            reserved_balance: 0,
+            user_id: 0,
+            symbol: 0,
+            reserved_shares_qty: 0,
+            available_shares_qty: 0,
+            query_type: 4,
+        }
+    }
+
+    fn shutdown_admin(authorized: bool) -> InboundCommand {
+        InboundCommand::Admin(AdminRequest { command: AdminCommand { command_type: ADMIN_SHUTDOWN, ..AdminCommand::default() }, authorized })
+    }
+
+    #[test]
+    fn test_consumer_drains_everything_before_exiting() {
+        let shutdown = Shutdown::new();
+        let producer_handle = shutdown.handle(Stage::Core, &[]);
+        let consumer_handle = shutdown.handle(Stage::Publisher, &[Stage::Core]);
+        let (producer, consumer) = bounded_spsc_queue::make::<u64>(1024);
+
+        let consumer_thread = std::thread::spawn(move || {
+            let mut received = 0u64;
+            loop {
+                match consumer.try_pop() {
+                    Some(_) => received += 1,
+                    None => {
+                        if consumer_handle.upstream_finished() && consumer.size() == 0 {
+                            break;
+                        }
+                        std::hint::spin_loop();
+                    }
+                }
+            }
+            consumer_handle.finish();
+            received
+        });
+
+        shutdown.request();
+        assert!(producer_handle.stop_requested());
+        // the producer keeps flushing what it already had after the request
+        for i in 0..100_000u64 {
+            producer.push(i);
+        }
+        assert!(!shutdown.is_finished(Stage::Publisher));
+        producer_handle.finish();
+
+        assert_eq!(consumer_thread.join().unwrap(), 100_000);
+        assert!(shutdown.is_finished(Stage::Publisher));
+    }
+
+    #[test]
+    fn test_only_an_authorized_admin_shutdown_requests_shutdown() {
+        let shutdown = Shutdown::new();
+        let (mut core, mut sink) = detached_core();
+        core.shutdown = Some(shutdown.handle(Stage::Core, &[]));
+        // the query queue is not authenticated , the retired shutdown query does nothing
+        core.submit(InboundCommand::Query(shutdown_query()));
+        core.submit(shutdown_admin(false));
+        sink.drain();
+        assert!(!shutdown.is_requested());
+        core.submit(shutdown_admin(true));
+        sink.drain();
+        assert!(shutdown.is_requested());
+
+        // a replayed shutdown has nothing to stop
+        let (mut replayed, mut sink) = detached_core();
+        replayed.submit(shutdown_admin(true));
+        sink.drain();
+        assert!(replayed.shutdown.is_none());
+    }
+}
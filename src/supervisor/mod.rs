@@ -0,0 +1,2 @@
+pub mod thread_supervisor;
+pub mod tests;
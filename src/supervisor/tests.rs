@@ -0,0 +1,155 @@
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::time::Duration;
+    use bounded_spsc_queue::{Consumer, Producer};
+    use crate::shm::status_page::{StatusPage, ThreadState};
+    use crate::shutdown::shutdown_signal::{Shutdown, ShutdownHandle, Stage};
+    use crate::supervisor::thread_supervisor::{RestartError, RestartPolicy, Supervisor, SupervisorEvent, Worker};
+    use crate::watchdog::heartbeat::Heartbeat;
+
+    const POLICY: RestartPolicy = RestartPolicy { max_restarts: 2, window: Duration::from_secs(60), backoff: Duration::ZERO };
+
+    // takes numbers off its queue and panics on every poisoned one it sees
+    struct Collector {
+        input: Consumer<u32>,
+        output: Producer<u32>,
+        poison: u32,
+        restarts: Arc<AtomicU32>,
+        fail_restarts: u32,
+        heartbeat: Option<Heartbeat>,
+    }
+
+    impl Worker for Collector {
+        fn run(&mut self, shutdown: &ShutdownHandle) {
+            loop {
+                match self.input.try_pop() {
+                    Some(value) if value == self.poison => panic!("poisoned by {}", value),
+                    Some(value) => self.output.push(value),
+                    None if shutdown.upstream_finished() && self.input.size() == 0 => break,
+                    None => std::thread::yield_now(),
+                }
+            }
+            shutdown.finish();
+        }
+        fn restart(&mut self) -> Result<(), RestartError> {
+            self.restarts.fetch_add(1, Ordering::Relaxed);
+            if self.fail_restarts != 0 {
+                self.fail_restarts -= 1;
+                return Err(RestartError::reopen("collector", "its queue", "still gone"));
+            }
+            Ok(())
+        }
+        fn heartbeat(&self) -> Option<&Heartbeat> {
+            self.heartbeat.as_ref()
+        }
+    }
+
+    fn collector(input: Consumer<u32>, output: Producer<u32>, restarts: &Arc<AtomicU32>, fail_restarts: u32) -> Collector {
+        Collector { input, output, poison: 13, restarts: restarts.clone(), fail_restarts, heartbeat: None }
+    }
+
+    #[test]
+    fn test_worker_is_restarted_with_its_queues() {
+        let path = std::env::temp_dir().join(format!("supervisor_test_restart_{}", std::process::id()));
+        let page = Arc::new(StatusPage::create(&path).unwrap());
+        let shutdown = Shutdown::new();
+        let (input, worker_input) = bounded_spsc_queue::make::<u32>(64);
+        let (worker_output, output) = bounded_spsc_queue::make::<u32>(64);
+        let restarts = Arc::new(AtomicU32::new(0));
+        let mut supervisor = Supervisor::new(shutdown.clone(), POLICY);
+        let worker_restarts = restarts.clone();
+        let worker_page = page.clone();
+        supervisor.spawn_worker("collector", shutdown.handle(Stage::Publisher, &[Stage::Core]), move || {
+            let mut worker = collector(worker_input, worker_output, &worker_restarts, 0);
+            worker.heartbeat = Some(Heartbeat::register(&worker_page, "collector").unwrap());
+            Some(worker)
+        }).unwrap();
+
+        for value in [1, 2, 13, 3, 4] {
+            input.push(value);
+        }
+        shutdown.finish(Stage::Core);
+        let report = supervisor.run();
+
+        // only the message in hand when it panicked is lost , the queue was kept
+        let received: Vec<u32> = std::iter::from_fn(|| output.try_pop()).collect();
+        assert_eq!(received, vec![1, 2, 3, 4]);
+        assert_eq!(restarts.load(Ordering::Relaxed), 1);
+        assert!(!report.escalated);
+        assert!(!shutdown.is_requested());
+        assert!(matches!(&report.events[0], SupervisorEvent::Restarted { name: "collector", restarts: 1, reason } if reason == "poisoned by 13"));
+        assert_eq!(report.events[1], SupervisorEvent::Exited { name: "collector" });
+        // marked running again by the restart , so a clean exit leaves it stopped rather than panicked
+        assert_eq!(page.snapshot()[0].state, ThreadState::Stopped);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_worker_out_of_restarts_shuts_everything_down() {
+        let shutdown = Shutdown::new();
+        let (input, worker_input) = bounded_spsc_queue::make::<u32>(64);
+        let (worker_output, _output) = bounded_spsc_queue::make::<u32>(64);
+        let restarts = Arc::new(AtomicU32::new(0));
+        let mut supervisor = Supervisor::new(shutdown.clone(), POLICY);
+        let worker_restarts = restarts.clone();
+        // the first reopen fails and counts against the budget like a panic
+        supervisor.spawn_worker("collector", shutdown.handle(Stage::Publisher, &[Stage::Core]), move || {
+            Some(collector(worker_input, worker_output, &worker_restarts, 1))
+        }).unwrap();
+        for _ in 0..3 {
+            input.push(13);
+        }
+        let report = supervisor.run();
+
+        assert!(report.escalated);
+        assert!(shutdown.is_requested());
+        // downstream of the dead worker is let go
+        assert!(shutdown.is_finished(Stage::Publisher));
+        assert_eq!(restarts.load(Ordering::Relaxed), 2);
+        assert!(matches!(&report.events[..], [
+            SupervisorEvent::Restarted { restarts: 2, reason: restart_reason, .. },
+            SupervisorEvent::GaveUp { reason: give_up_reason, .. },
+        ] if restart_reason.contains("still gone") && give_up_reason == "poisoned by 13"));
+    }
+
+    #[test]
+    fn test_worker_that_cannot_be_set_up_is_given_up() {
+        let shutdown = Shutdown::new();
+        let mut supervisor = Supervisor::new(shutdown.clone(), POLICY);
+        supervisor.spawn_worker::<Collector>("writer", shutdown.handle(Stage::Writer, &[Stage::Core]), || None).unwrap();
+        let report = supervisor.run();
+        assert!(report.escalated);
+        assert_eq!(report.events, vec![SupervisorEvent::GaveUp { name: "writer", reason: "setup failed".into() }]);
+    }
+
+    #[test]
+    fn test_critical_death_escalates_and_downstream_drains() {
+        let shutdown = Shutdown::new();
+        let (input, worker_input) = bounded_spsc_queue::make::<u32>(64);
+        let (worker_output, output) = bounded_spsc_queue::make::<u32>(64);
+        let restarts = Arc::new(AtomicU32::new(0));
+        let mut supervisor = Supervisor::new(shutdown.clone(), POLICY);
+        supervisor.spawn_critical("trading_core", shutdown.handle(Stage::Core, &[]), move || {
+            for value in [5, 6] {
+                input.push(value);
+            }
+            panic!("book corrupted");
+        }).unwrap();
+        let worker_restarts = restarts.clone();
+        supervisor.spawn_worker("collector", shutdown.handle(Stage::Publisher, &[Stage::Core]), move || {
+            Some(collector(worker_input, worker_output, &worker_restarts, 0))
+        }).unwrap();
+        let report = supervisor.run();
+
+        assert!(report.escalated);
+        assert!(shutdown.is_requested());
+        assert!(report.events.contains(&SupervisorEvent::Died { name: "trading_core", reason: "book corrupted".into() }));
+        assert!(report.events.contains(&SupervisorEvent::Exited { name: "collector" }));
+        // what the core produced before dying still got through
+        let received: Vec<u32> = std::iter::from_fn(|| output.try_pop()).collect();
+        assert_eq!(received, vec![5, 6]);
+        assert_eq!(restarts.load(Ordering::Relaxed), 0);
+    }
+}
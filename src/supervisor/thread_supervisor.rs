@@ -0,0 +1,226 @@
+// owns the pipeline threads of a consumer binary and decides what a panic costs
+// a stateless worker (publisher , writer , logger) is restarted in place : the struct outlives the unwind with its spsc
+// endpoints , only its handles to the outside (shm queues , redis) are reopened , the message it held when it panicked is lost
+// a critical thread (the trading core) that dies , or a worker out of restarts , escalates to a controlled full shutdown :
+// its stage is marked finished on its behalf so everything downstream drains what was already produced and exits
+use std::any::Any;
+use std::collections::VecDeque;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::Arc;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use crate::config::settings::SupervisorSettings;
+use crate::shm::status_page::ThreadState;
+use crate::shutdown::shutdown_signal::{Shutdown, ShutdownHandle};
+use crate::watchdog::heartbeat::Heartbeat;
+
+#[derive(Debug , Error)]
+pub enum RestartError{
+    #[error("{worker} could not reopen {what}: {reason}")]
+    Reopen { worker : &'static str , what : &'static str , reason : String },
+}
+
+impl RestartError{
+    pub fn reopen(worker : &'static str , what : &'static str , error : impl std::fmt::Debug)->Self{
+        Self::Reopen { worker , what , reason : format!("{:?}", error) }
+    }
+}
+
+// a thread that can be restarted in place after a panic
+pub trait Worker : Send{
+    /// runs until shutdown , finishing the stage on the way out
+    fn run(&mut self , shutdown : &ShutdownHandle);
+    /// reopens everything the worker holds besides its spsc endpoints
+    fn restart(&mut self)->Result<() , RestartError>;
+    fn heartbeat(&self)->Option<&Heartbeat>;
+}
+
+#[derive(Debug , Clone , PartialEq , Eq)]
+pub enum SupervisorEvent{
+    // finished its stage and returned
+    Exited { name : &'static str },
+    Restarted { name : &'static str , restarts : u32 , reason : String },
+    // a worker out of restarts or one that could not even be set up
+    GaveUp { name : &'static str , reason : String },
+    // a critical thread , never restarted
+    Died { name : &'static str , reason : String },
+}
+
+#[derive(Debug , Clone , Default , PartialEq , Eq)]
+pub struct SupervisorReport{
+    pub events : Vec<SupervisorEvent>,
+    // true once a death or a give up shut the engine down
+    pub escalated : bool,
+}
+
+#[derive(Debug , Clone , Copy , PartialEq , Eq)]
+pub struct RestartPolicy{
+    pub max_restarts : u32,
+    pub window : Duration,
+    pub backoff : Duration,
+}
+
+impl From<&SupervisorSettings> for RestartPolicy{
+    fn from(settings : &SupervisorSettings)->Self{
+        Self { max_restarts : settings.max_restarts , window : settings.restart_window() , backoff : settings.backoff() }
+    }
+}
+
+// the restarts of one worker within the sliding window
+struct RestartBudget{
+    policy : RestartPolicy,
+    recent : VecDeque<Instant>,
+    total : u32,
+}
+
+impl RestartBudget{
+    fn new(policy : RestartPolicy)->Self{
+        Self { policy , recent : VecDeque::new() , total : 0 }
+    }
+
+    fn allow(&mut self , now : Instant)->bool{
+        while self.recent.front().is_some_and(|at| now.duration_since(*at) >= self.policy.window) {
+            self.recent.pop_front();
+        }
+        if self.recent.len() >= self.policy.max_restarts as usize {
+            return false;
+        }
+        self.recent.push_back(now);
+        self.total += 1;
+        true
+    }
+}
+
+pub fn panic_message(payload : &(dyn Any + Send))->String{
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "non string panic payload".to_string()
+    }
+}
+
+pub struct Supervisor{
+    shutdown : Arc<Shutdown>,
+    policy : RestartPolicy,
+    sender : Sender<SupervisorEvent>,
+    events : Receiver<SupervisorEvent>,
+    threads : Vec<JoinHandle<()>>,
+}
+
+impl Supervisor{
+    pub fn new(shutdown : Arc<Shutdown> , policy : RestartPolicy)->Self{
+        let (sender , events) = channel();
+        Self { shutdown , policy , sender , events , threads : Vec::new() }
+    }
+
+    /// a thread whose death takes the engine down , `body` is expected to finish its stage when it returns
+    pub fn spawn_critical(&mut self , name : &'static str , stage : ShutdownHandle , body : impl FnOnce() + Send + 'static)->std::io::Result<()>{
+        let sender = self.sender.clone();
+        let handle = std::thread::Builder::new().name(name.into()).spawn(move || {
+            let event = match catch_unwind(AssertUnwindSafe(body)) {
+                Ok(()) => SupervisorEvent::Exited { name },
+                Err(payload) => {
+                    stage.finish();
+                    SupervisorEvent::Died { name , reason : panic_message(&*payload) }
+                }
+            };
+            let _ = sender.send(event);
+        })?;
+        self.threads.push(handle);
+        Ok(())
+    }
+
+    /// a worker built by `setup` on its own thread (so core pinning applies to it) and restarted in place when it panics
+    pub fn spawn_worker<W : Worker + 'static>(
+        &mut self ,
+        name : &'static str ,
+        stage : ShutdownHandle ,
+        setup : impl FnOnce()->Option<W> + Send + 'static
+    )->std::io::Result<()>{
+        let sender = self.sender.clone();
+        let policy = self.policy;
+        let handle = std::thread::Builder::new().name(name.into()).spawn(move || {
+            let event = match catch_unwind(AssertUnwindSafe(setup)) {
+                Ok(Some(worker)) => supervise(name, worker, &stage, policy, &sender),
+                Ok(None) => SupervisorEvent::GaveUp { name , reason : "setup failed".into() },
+                Err(payload) => SupervisorEvent::GaveUp { name , reason : panic_message(&*payload) },
+            };
+            if matches!(event , SupervisorEvent::GaveUp { .. }) {
+                stage.finish();
+            }
+            let _ = sender.send(event);
+        })?;
+        self.threads.push(handle);
+        Ok(())
+    }
+
+    /// blocks until every thread has exited , a death or a give up requests the shutdown for everyone else
+    pub fn run(self)->SupervisorReport{
+        let Self { shutdown , sender , events , threads , .. } = self;
+        drop(sender);
+        let mut report = SupervisorReport::default();
+        let mut running = threads.len();
+        while running != 0 {
+            let Ok(event) = events.recv() else {
+                break;
+            };
+            log_event(&event);
+            match &event {
+                SupervisorEvent::Exited { .. } => running -= 1,
+                SupervisorEvent::Restarted { .. } => {}
+                SupervisorEvent::GaveUp { .. } | SupervisorEvent::Died { .. } => {
+                    running -= 1;
+                    report.escalated = true;
+                    shutdown.request();
+                }
+            }
+            report.events.push(event);
+        }
+        for thread in threads {
+            let _ = thread.join();
+        }
+        report
+    }
+}
+
+// the restart loop of one worker , returns once it exits cleanly or is given up on
+fn supervise<W : Worker>(name : &'static str , mut worker : W , stage : &ShutdownHandle , policy : RestartPolicy , sender : &Sender<SupervisorEvent>)->SupervisorEvent{
+    let mut budget = RestartBudget::new(policy);
+    loop {
+        let mut reason = match catch_unwind(AssertUnwindSafe(|| worker.run(stage))) {
+            Ok(()) => return SupervisorEvent::Exited { name },
+            Err(payload) => panic_message(&*payload),
+        };
+        if let Some(heartbeat) = worker.heartbeat() {
+            heartbeat.set_state(ThreadState::Panicked);
+        }
+        // a failed reopen counts against the budget like the panic did
+        loop {
+            if !budget.allow(Instant::now()) {
+                return SupervisorEvent::GaveUp { name , reason };
+            }
+            std::thread::sleep(policy.backoff);
+            match worker.restart() {
+                Ok(()) => break,
+                Err(e) => reason = e.to_string(),
+            }
+        }
+        if let Some(heartbeat) = worker.heartbeat() {
+            heartbeat.set_state(ThreadState::Running);
+        }
+        let _ = sender.send(SupervisorEvent::Restarted { name , restarts : budget.total , reason });
+    }
+}
+
+pub fn log_event(event : &SupervisorEvent){
+    match event {
+        SupervisorEvent::Exited { name } => eprintln!("[Supervisor] {} exited", name),
+        SupervisorEvent::Restarted { name , restarts , reason } => eprintln!("[Supervisor] {} panicked ({}) , restarted ({} so far)", name, reason, restarts),
+        SupervisorEvent::GaveUp { name , reason } => eprintln!("[Supervisor] giving up on {} ({}) , shutting the engine down", name, reason),
+        SupervisorEvent::Died { name , reason } => eprintln!("[Supervisor] {} died ({}) , shutting the engine down", name, reason),
+    }
+}
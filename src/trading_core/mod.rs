@@ -0,0 +1 @@
+pub mod my_trading_core;
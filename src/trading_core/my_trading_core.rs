@@ -0,0 +1,852 @@
+// single threaded trading core , owns the balance manager and the engine
+// every inbound command (new order , cancel , query) gets a sequence number , is journalled and only then applied
+// apply_command is the one code path used by the live loop and by journal replay
+use bounded_spsc_queue::Producer;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use crate::admin::commands::{admin_response, AdminAction, AdminError, AdminReply, EngineStats};
+use crate::admin::log_level::{log_enabled, set_log_level, LogLevel};
+use crate::admin::plane::{AdminPlane, AdminRequest};
+use crate::balance_manager::funds_ledger::FundsOutcome;
+use crate::balance_manager::my_balance_manager2::STbalanceManager;
+use crate::balance_manager::postings::ConservationError;
+use crate::balance_manager::rate_limits::RateAction;
+use crate::engine::my_engine::{Engine, STEngine};
+use crate::engine::symbol_registry::SymbolError;
+use crate::journal::checkpoint::{write_checkpoint, CheckpointError};
+use crate::journal::command_journal::{InboundCommand, JournalRecord, JournalWriter};
+use crate::digest::digest_log::DigestLog;
+use crate::digest::state_hasher::StateHasher;
+use crate::replication::primary::ReplicationServer;
+use crate::shutdown::shutdown_signal::ShutdownHandle;
+use crate::backpressure::policy::{channels, BackpressureConfig};
+use crate::config::settings::{EngineConfig, QueuePaths};
+use crate::metrics::engine::{BookMetrics, CoreMetrics};
+use crate::metrics::latency::{unix_nanos, LatencyRecorder, Stage};
+use crate::watchdog::heartbeat::Heartbeat;
+use crate::backpressure::sender::{escalate, PolicyProducer, PolicySender};
+use crate::logger::types::{BaseLogs, OrderBookSnapShot, OrderDelta, POSITION_REPORT_QUERY, POSITION_REPORT_SNAPSHOT};
+use crate::orderbook::order::{Order, OrderToBeCanceled, Side, ORDER_TYPE_AMEND};
+use crate::orderbook::order_book::OrderBook;
+use crate::orderbook::types::Event;
+use crate::shm::admin_command_queue::AdminCommandQueue;
+use crate::shm::admin_response_queue::AdminResponse;
+use crate::shm::balance_response_queue::BalanceResponse;
+use crate::shm::cancel_orders_queue::CancelOrderQueue;
+use crate::shm::event_queue::{OrderEvents, CANCEL_REASON_AMENDED, CANCEL_REASON_DELISTED, CANCEL_REASON_FROZEN, CANCEL_REASON_USER, REJECT_REASON_HALTED, REJECT_REASON_NOT_OWNER, REJECT_REASON_TRADING_STOPPED, REJECT_REASON_UNKNOWN_ORDER, REJECT_REASON_USER_FROZEN};
+use crate::shm::holdings_response_queue::HoldingResponse;
+use crate::shm::market_maker_feed::MarketMakerFeed;
+use crate::shm::query_queue::{Query, QueryQueue};
+use crate::shm::reader::StShmReader;
+
+static EVENT_ID: AtomicU64 = AtomicU64::new(1);
+#[inline(always)]
+pub fn next_event_id() -> u64 {
+    EVENT_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+// a cancel the engine refused , the order it named is left where it was
+pub fn cancel_rejected(order_to_be_canceled: OrderToBeCanceled, reason: u32) -> OrderEvents {
+    OrderEvents {
+        user_id: order_to_be_canceled.user_id,
+        order_id: order_to_be_canceled.order_id,
+        symbol: order_to_be_canceled.symbol,
+        event_kind: 3,
+        filled_qty: 0,
+        remaining_qty: 0,
+        original_qty: 0,
+        error_code: reason,
+        fee: 0
+    }
+}
+
+const ORDER_BATCH: usize = 1000;
+
+// the shared memory inputs , only the live process opens these
+pub struct CoreInbound {
+    pub shm_reader: StShmReader,
+    pub cancel_order_queue: CancelOrderQueue,
+    pub query_queue: QueryQueue,
+    pub admin_queue: AdminCommandQueue,
+}
+
+impl CoreInbound {
+    pub fn open(queues: &QueuePaths) -> Option<Self> {
+        let shm_reader = StShmReader::new(&queues.incoming_orders)?;
+        let cancel_order_queue = CancelOrderQueue::open(&queues.cancel_orders);
+        if cancel_order_queue.is_err() {
+            eprintln!("Error initialising the cancel order queue in the trading core");
+            return None;
+        }
+        let query_queue = QueryQueue::open(&queues.queries);
+        if query_queue.is_err() {
+            eprintln!("query queue init error in trading core");
+            eprintln!("{:?}", query_queue);
+            return None;
+        }
+        let admin_queue = match AdminCommandQueue::open(&queues.admin_commands) {
+            Ok(queue) => queue,
+            Err(e) => {
+                eprintln!("admin queue init error in trading core");
+                eprintln!("{:?}", e);
+                return None;
+            }
+        };
+        Some(Self {
+            shm_reader,
+            cancel_order_queue: cancel_order_queue.unwrap(),
+            query_queue: query_queue.unwrap(),
+            admin_queue,
+        })
+    }
+}
+
+pub struct TradingCore {
+    pub balance_manager: STbalanceManager,
+    pub engine: STEngine,
+    pub journal: Option<JournalWriter>,
+    // where periodic checkpoints go , none disables them
+    pub checkpoint_dir: Option<PathBuf>,
+    pub last_checkpoint: Instant,
+    pub checkpoint_interval: Duration,
+    // standbys following this core , none when running alone or as a standby
+    pub replication: Option<ReplicationServer>,
+    // state digests published every n sequence numbers , none disables them
+    pub digest_log: Option<DigestLog>,
+    // none runs until the process is killed , replay cores never shut down
+    pub shutdown: Option<ShutdownHandle>,
+    // sequence of the last command accepted (live) or applied (replay)
+    pub sequence: u64,
+    // journal timestamp of the command being applied , the rate limit buckets refill by it so replay throttles the same
+    pub clock: u64,
+    processed_count: u64,
+    pending: Vec<InboundCommand>,
+    pub log_sender_to_logger: PolicyProducer<BaseLogs>,
+    pub snapshot_sender_to_logger: PolicyProducer<OrderBookSnapShot>,
+    pub last_snap_shot: Instant,
+    pub snapshot_interval: Duration,
+    pub market_maker_feed_sender: PolicyProducer<MarketMakerFeed>,
+    // operators and halted symbols , the halted set is part of the checkpointed state
+    pub admin: AdminPlane,
+    pub admin_response_sender: PolicyProducer<AdminResponse>,
+    pub metrics: CoreMetrics,
+    pub book_metrics: BookMetrics,
+    pub latency: LatencyRecorder,
+    // none when nothing watches this core (replay , tests)
+    pub heartbeat: Option<Heartbeat>,
+}
+
+impl TradingCore {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        event_sender_to_writter: Producer<OrderEvents>,
+        event_sender_to_publisher_by_engine: Producer<Event>,
+        order_event_producer_engine: Producer<OrderEvents>,
+        balance_event_producer_bm: Producer<BalanceResponse>,
+        holding_event_producer_bm: Producer<HoldingResponse>,
+        log_sender_to_logger: Producer<BaseLogs>,
+        snapshot_sender_to_logger: Producer<OrderBookSnapShot>,
+        market_maker_feed_sender: Producer<MarketMakerFeed>,
+        admin_response_sender: Producer<AdminResponse>,
+        backpressure: &BackpressureConfig,
+        config: &EngineConfig,
+    ) -> Self {
+        Self {
+            market_maker_feed_sender: PolicySender::from_config(market_maker_feed_sender, channels::CORE_MM_FEED, backpressure),
+            balance_manager: STbalanceManager::new(event_sender_to_writter, balance_event_producer_bm, holding_event_producer_bm, config),
+            engine: STEngine::new(
+                0,
+                PolicySender::from_config(event_sender_to_publisher_by_engine, channels::ENGINE_EVENTS, backpressure),
+                PolicySender::from_config(order_event_producer_engine, channels::ENGINE_ORDER_EVENTS, backpressure),
+                config.engine.depth,
+            ),
+            journal: None,
+            checkpoint_dir: None,
+            last_checkpoint: Instant::now(),
+            checkpoint_interval: config.engine.checkpoint_interval(),
+            replication: None,
+            digest_log: None,
+            shutdown: None,
+            sequence: 0,
+            processed_count: 0,
+            clock: 0,
+            pending: Vec::with_capacity(ORDER_BATCH + 2),
+            log_sender_to_logger: PolicySender::from_config(log_sender_to_logger, channels::CORE_LOGS, backpressure),
+            snapshot_sender_to_logger: PolicySender::from_config(snapshot_sender_to_logger, channels::CORE_SNAPSHOTS, backpressure),
+            last_snap_shot: Instant::now(),
+            snapshot_interval: config.engine.snapshot_interval(),
+            admin: AdminPlane::default(),
+            admin_response_sender: PolicySender::from_config(admin_response_sender, channels::CORE_ADMIN_RESPONSES, backpressure),
+            metrics: CoreMetrics::default(),
+            book_metrics: BookMetrics::new(config.metrics.interval()),
+            latency: LatencyRecorder::default(),
+            heartbeat: None,
+        }
+    }
+
+    // the state every run starts from before the first journalled command , replay must start from the same place
+    pub fn bootstrap_state(&mut self) {
+        // initiliased the market maker
+        let _ = self.balance_manager.add_market_maker(|log| escalate(self.log_sender_to_logger.send(log)), next_event_id);
+    }
+
+    // the producer's users on top of the bootstrap state , a journal written with them replays only in a build that opens them too
+    #[cfg(any(test, feature = "throughput-users"))]
+    pub fn add_throughput_users(&mut self) {
+        if let Err(e) = self.balance_manager.add_throughput_test_users(|log| escalate(self.log_sender_to_logger.send(log)), next_event_id) {
+            eprintln!("[Trading Core] throughput users not opened: {:?}", e);
+        }
+    }
+
+    // takes over the books , balances and sequence of another core (used after replaying into a detached core)
+    pub fn adopt_state(&mut self, mut other: TradingCore) {
+        std::mem::swap(&mut self.engine.books, &mut other.engine.books);
+        std::mem::swap(&mut self.engine.symbols, &mut other.engine.symbols);
+        std::mem::swap(&mut self.engine.book_count, &mut other.engine.book_count);
+        std::mem::swap(&mut self.engine.trading_stopped, &mut other.engine.trading_stopped);
+        std::mem::swap(&mut self.balance_manager.state, &mut other.balance_manager.state);
+        std::mem::swap(&mut self.admin.halted, &mut other.admin.halted);
+        self.sequence = other.sequence;
+    }
+
+    pub fn processed_count(&self) -> u64 {
+        self.processed_count
+    }
+
+    pub fn run(&mut self, inbound: &mut CoreInbound) {
+        eprintln!("[Trading Core] Starting single-threaded mode at sequence {}", self.sequence);
+
+        loop {
+            if let Some(heartbeat) = self.heartbeat.as_mut() {
+                heartbeat.beat_with_depths(|| [
+                    inbound.shm_reader.depth(),
+                    inbound.cancel_order_queue.depth(),
+                    inbound.query_queue.depth(),
+                    inbound.admin_queue.depth(),
+                ]);
+            }
+            // the batch already taken is fully applied , stop before reading the next one
+            if self.shutdown.as_ref().is_some_and(|shutdown| shutdown.stop_requested()) {
+                break;
+            }
+            let mut pending = std::mem::take(&mut self.pending);
+            pending.clear();
+            for _ in 0..ORDER_BATCH {
+                if let Some(order) = inbound.shm_reader.receive_order() {
+                    pending.push(InboundCommand::NewOrder(order));
+                } else {
+                    break;
+                }
+            }
+            // one clock read for the whole batch , reading it takes a few microseconds at most
+            let dequeued_at = unix_nanos();
+            for command in pending.iter() {
+                if let InboundCommand::NewOrder(order) = command {
+                    self.latency.record_queue_wait(order.timestamp, dequeued_at);
+                }
+            }
+            if let Ok(Some(order_to_be_canceled)) = inbound.cancel_order_queue.dequeue() {
+                pending.push(InboundCommand::CancelOrder(order_to_be_canceled));
+            }
+            if let Ok(Some(query)) = inbound.query_queue.dequeue() {
+                pending.push(InboundCommand::Query(query));
+            }
+            if let Ok(Some(command)) = inbound.admin_queue.dequeue() {
+                pending.push(InboundCommand::Admin(self.admin.authorize(command)));
+            }
+
+            self.metrics.batch_size.observe(pending.len() as u64);
+            // write ahead , the whole batch is in the journal before any of it is applied
+            // stamped with the batch's clock read , replay applies it at the same time
+            for command in pending.iter() {
+                self.accept(*command, dequeued_at);
+            }
+            self.flush_journal();
+            self.clock = dequeued_at;
+            let first_sequence = self.sequence + 1 - pending.len() as u64;
+            for (offset, command) in pending.drain(..).enumerate() {
+                self.apply_command(command);
+                self.after_apply(first_sequence + offset as u64);
+            }
+            self.pending = pending;
+            self.replicate();
+            // drop oldest channels hold back what did not fit , hand it over as soon as there is room
+            self.market_maker_feed_sender.flush();
+
+            if self.book_metrics.due() {
+                self.book_metrics.refresh(&self.engine);
+            }
+            self.latency.flush_if_due();
+
+            if self.last_snap_shot.elapsed() >= self.snapshot_interval {
+                self.engine.snapshot_for_all_book(|snapshot| {
+                    escalate(self.snapshot_sender_to_logger.send(snapshot));
+                }, next_event_id);
+                self.report_all_positions();
+                self.last_snap_shot = Instant::now();
+            }
+
+            // taken between batches so every command up to self.sequence is applied and nothing after it
+            if self.last_checkpoint.elapsed() >= self.checkpoint_interval {
+                match self.checkpoint() {
+                    Ok(None) => {}
+                    Ok(Some(path)) => eprintln!("[Trading Core] checkpoint at sequence {} written to {:?}", self.sequence, path),
+                    // the journal still has everything , a missed checkpoint only makes the next restart slower
+                    Err(e) => eprintln!("[Trading Core] checkpoint at sequence {} failed: {}", self.sequence, e),
+                }
+                self.last_checkpoint = Instant::now();
+            }
+        }
+        self.drain_and_finish();
+    }
+
+    // last steps once input has stopped , everything downstream is still draining
+    // a final top of book snapshot for the logger , a full checkpoint and the journal on disk
+    fn drain_and_finish(&mut self) {
+        eprintln!("[Trading Core] stopping at sequence {} after {} commands", self.sequence, self.processed_count);
+        // blocking sends , the logger and publisher keep running until we are finished
+        self.engine.snapshot_for_all_book(|snapshot| {
+            self.snapshot_sender_to_logger.send_blocking(snapshot);
+        }, next_event_id);
+        self.balance_manager.report_positions(None, POSITION_REPORT_SNAPSHOT, |log| self.log_sender_to_logger.send_blocking(log), next_event_id);
+        self.flush_senders_blocking();
+        self.latency.flush();
+        match self.checkpoint() {
+            Ok(Some(path)) => eprintln!("[Trading Core] final checkpoint written to {:?}", path),
+            Ok(None) => {}
+            Err(e) => eprintln!("[Trading Core] final checkpoint failed: {}", e),
+        }
+        if let Some(journal) = self.journal.as_mut() && let Err(e) = journal.sync() {
+            eprintln!("[Trading Core] final journal sync failed: {}", e);
+        }
+        if let Some(log) = self.digest_log.as_mut() && let Err(e) = log.flush() {
+            eprintln!("[Trading Core] digest log flush failed: {}", e);
+        }
+        // marks the slot stopped
+        self.heartbeat = None;
+        if let Some(shutdown) = self.shutdown.as_ref() {
+            shutdown.finish();
+        }
+    }
+
+    // parked messages on every outgoing channel , delivered before the downstream stages are let go
+    fn flush_senders_blocking(&mut self) {
+        self.log_sender_to_logger.flush_blocking();
+        self.snapshot_sender_to_logger.flush_blocking();
+        self.market_maker_feed_sender.flush_blocking();
+        self.admin_response_sender.flush_blocking();
+        self.engine.sending_event_to_publisher_try.flush_blocking();
+        self.engine.sending_order_events_to_writter_try.flush_blocking();
+    }
+
+    // writes a full state checkpoint at the current sequence into checkpoint_dir , none when checkpoints are disabled
+    // the journal is synced first so it never ends before the newest checkpoint
+    pub fn checkpoint(&mut self) -> Result<Option<PathBuf>, CheckpointError> {
+        let Some(dir) = self.checkpoint_dir.clone() else {
+            return Ok(None);
+        };
+        if let Some(journal) = self.journal.as_mut() {
+            journal.sync()?;
+        }
+        // a restart point has to add up , the journal still has everything to find out what went wrong
+        if let Some(unbalanced) = self.unconserved_asset() {
+            return Err(CheckpointError::NotConserved(unbalanced));
+        }
+        write_checkpoint(self, dir).map(Some)
+    }
+
+    // every asset summed over all its accounts , the first that is not zero is alerted on
+    fn unconserved_asset(&self) -> Option<ConservationError> {
+        let unbalanced = self.balance_manager.check_conservation();
+        for e in unbalanced.iter() {
+            eprintln!("[Trading Core] at sequence {}: {}", self.sequence, e);
+            self.metrics.conservation_failures.inc();
+        }
+        unbalanced.first().copied()
+    }
+
+    // assigns the next sequence number and journals the command
+    fn accept(&mut self, command: InboundCommand, accepted_at: u64) -> JournalRecord {
+        self.sequence += 1;
+        let record = JournalRecord::at(self.sequence, accepted_at, command);
+        if let Some(journal) = self.journal.as_mut() && let Err(e) = journal.append(&record) {
+            // without the journal we cant rebuild this state , stop before applying anything
+            panic!("[Trading Core] journal append failed at sequence {}: {}", record.sequence, e);
+        }
+        if let Some(replication) = self.replication.as_mut() {
+            replication.stage(&record);
+        }
+        record
+    }
+
+    fn flush_journal(&mut self) {
+        if let Some(journal) = self.journal.as_mut() && let Err(e) = journal.flush() {
+            panic!("[Trading Core] journal flush failed: {}", e);
+        }
+    }
+
+    // hands the applied batch (already in the journal) to the replication thread , with a digest when one is due
+    fn replicate(&mut self) {
+        let digest_due = match self.replication.as_ref() {
+            Some(replication) => replication.digest_due(),
+            None => return,
+        };
+        let digest = if digest_due { Some(self.state_digest()) } else { None };
+        let sequence = self.sequence;
+        let replication = self.replication.as_mut().unwrap();
+        if let Some(digest) = digest {
+            replication.stage_digest(sequence, digest);
+        }
+        replication.publish(sequence);
+    }
+
+    // accept , journal and apply a single command outside of the batched run loop
+    pub fn submit(&mut self, command: InboundCommand) {
+        self.clock = self.accept(command, unix_nanos()).timestamp;
+        self.flush_journal();
+        self.apply_command(command);
+        self.after_apply(self.sequence);
+        self.replicate();
+    }
+
+    // digest of the books and balances , equal digests at equal sequences mean equal state
+    // walks every resting order and every mapped user , fine every few thousand commands , not per command
+    pub fn state_digest(&self) -> u64 {
+        let mut hasher = StateHasher::new(0);
+        // symbol order , two cores with the same books may have them in different slots
+        for book in self.engine.books_by_symbol() {
+            hasher.write_u64(book.state_hash());
+        }
+        hasher.write_u64(self.balance_manager.state.state_hash());
+        hasher.finish()
+    }
+
+    // runs after the command with `sequence` is applied , live and in replay alike
+    fn after_apply(&mut self, sequence: u64) {
+        if self.digest_log.as_ref().is_some_and(|log| log.due(sequence)) {
+            let digest = self.state_digest();
+            let log = self.digest_log.as_mut().unwrap();
+            if let Err(e) = log.record(sequence, digest).and_then(|_| log.flush()) {
+                eprintln!("[Trading Core] digest log write failed at sequence {}: {}", sequence, e);
+            }
+        }
+    }
+
+    pub fn apply_record(&mut self, record: &JournalRecord) {
+        self.sequence = record.sequence;
+        self.clock = record.timestamp;
+        self.apply_command(record.command);
+        self.after_apply(record.sequence);
+    }
+
+    pub fn apply_command(&mut self, command: InboundCommand) {
+        // the day comes from the journalled timestamp , a replay rolls over exactly where the live run did
+        if self.balance_manager.roll_day(self.clock) {
+            eprintln!("[Trading Core] trading day {} starts at sequence {}", self.balance_manager.state.trading_day, self.sequence);
+        }
+        match command {
+            InboundCommand::NewOrder(order) => self.process_order(order),
+            InboundCommand::CancelOrder(order_to_be_canceled) => self.process_cancel(order_to_be_canceled),
+            InboundCommand::Query(query) => self.process_query(query),
+            InboundCommand::Admin(request) => self.process_admin(request),
+        }
+    }
+
+    fn process_order(&mut self, order: Order) {
+        self.metrics.orders_in.inc();
+        escalate(self.log_sender_to_logger.send(BaseLogs::OrderDelta(OrderDelta {
+            event_id: next_event_id(),
+            order_id: order.order_id,
+            user_id: order.user_id,
+            price: order.price,
+            symbol: order.symbol,
+            shares_qty: order.shares_qty,
+            side: match order.side {
+                Side::Ask => 1,
+                Side::Bid => 0
+            },
+            order_event_type: 0
+        })));
+
+        // the kill switches come first , a frozen user or a stopped venue takes no new order at all
+        if self.engine.trading_stopped {
+            self.reject_order(order, REJECT_REASON_TRADING_STOPPED);
+            return;
+        }
+        if self.balance_manager.is_frozen(order.user_id) {
+            self.reject_order(order, REJECT_REASON_USER_FROZEN);
+            return;
+        }
+        // halted symbols take cancels only , nothing gets reserved for a new order
+        if self.admin.is_halted(order.symbol) {
+            self.reject_order(order, REJECT_REASON_HALTED);
+            return;
+        }
+        // an amend draws on its own bucket
+        let action = if order.order_type == ORDER_TYPE_AMEND { RateAction::Amend } else { RateAction::NewOrder };
+        if let Err(e) = self.balance_manager.throttle(order.user_id, action, self.clock) {
+            self.metrics.throttle(action);
+            self.reject_order(order, e.reject_reason());
+            return;
+        }
+        let order = if action == RateAction::Amend {
+            match self.cancel_for_amend(order) {
+                Some(replacement) => replacement,
+                None => return,
+            }
+        } else {
+            order
+        };
+
+        let lock_started = Instant::now();
+        let locked = self.balance_manager.check_and_lock_funds(order, |log| escalate(self.log_sender_to_logger.send(log)), next_event_id);
+        self.latency.record_since(Stage::BalanceLock, lock_started);
+        match locked {
+            Ok(()) => {
+                // balances have been locked or holding shave been reserved , the lock entry logged it
+                // Process order in engine
+                let match_started = Instant::now();
+                let engine_res = self.engine.process_order(order, |feed| {
+                    escalate(self.market_maker_feed_sender.send(feed));
+                });
+                self.latency.record_since(Stage::Match, match_started);
+                let mut market_update = engine_res.1;
+                match engine_res.0 {
+                    Some(mut match_result) => {
+                        // log that order has been matched
+                        escalate(self.log_sender_to_logger.send(BaseLogs::OrderDelta(OrderDelta {
+                            event_id: next_event_id(),
+                            order_id: order.order_id,
+                            user_id: order.user_id,
+                            price: order.price,
+                            symbol: order.symbol,
+                            shares_qty: order.shares_qty,
+                            side: match order.side {
+                                Side::Ask => 1,
+                                Side::Bid => 0
+                            },
+                            order_event_type: 1
+                        })));
+                        self.metrics.fills.add(match_result.fills.fills.len() as u64);
+                        // Update balances from fills
+                        if let Err(e) = self.balance_manager.update_balances_after_trade(
+                            &mut match_result.fills,
+                            |log| {
+                                escalate(self.log_sender_to_logger.send(log));
+                            },
+                            next_event_id,
+                        ) {
+                            eprintln!("[Trading Core] Balance update error: {:?}", e);
+                        }
+                        // the execution reports carry the fees the balance manager just stamped on the fills
+                        if let Some(update) = market_update.as_mut() {
+                            update.match_result.fills = match_result.fills;
+                        }
+                        // a market order never rests , whatever it did not fill has nothing left to pay for
+                        if order.order_type == 0 {
+                            self.balance_manager.release_order(
+                                order.order_id,
+                                |log| {
+                                    escalate(self.log_sender_to_logger.send(log));
+                                },
+                                next_event_id,
+                            );
+                        }
+                    }
+                    None => {
+                        eprintln!("[Trading Core] Failed to process order");
+                        // never matched , the reservation made for it has to come back
+                        self.balance_manager.release_order(
+                            order.order_id,
+                            |log| {
+                                escalate(self.log_sender_to_logger.send(log));
+                            },
+                            next_event_id,
+                        );
+                    }
+                }
+
+                if let Some(market_update) = market_update {
+                    escalate(self.engine.sending_event_to_publisher_try.send(Event::new(market_update)));
+                }
+
+                self.processed_count += 1;
+            }
+            Err(e) => {
+                if log_enabled(LogLevel::Debug) {
+                    eprintln!("[Trading Core] order {} refused: {:?}", order.order_id, e);
+                }
+                self.reject_order(order, e.reject_reason());
+            }
+        }
+    }
+
+    // takes the resting order an amend names off the book , its reservation is released like any cancel
+    // the replacement is a limit order on the same side , None when the amend was rejected
+    fn cancel_for_amend(&mut self, amend: Order) -> Option<Order> {
+        let resting = self.engine.get_book(amend.symbol).and_then(|book| {
+            let index = *book.manager.id_to_index.get(&amend.order_id)?;
+            book.manager.get(index).map(|order| (order.user_id, order.side))
+        });
+        let Some((owner, side)) = resting else {
+            self.reject_order(amend, REJECT_REASON_UNKNOWN_ORDER);
+            return None;
+        };
+        if owner != amend.user_id {
+            self.reject_order(amend, REJECT_REASON_NOT_OWNER);
+            return None;
+        }
+        if !self.cancel_resting_order(OrderToBeCanceled { order_id: amend.order_id, user_id: amend.user_id, symbol: amend.symbol }, CANCEL_REASON_AMENDED) {
+            self.reject_order(amend, REJECT_REASON_UNKNOWN_ORDER);
+            return None;
+        }
+        Some(Order { side, order_type: 1, ..amend })
+    }
+
+    fn reject_order(&mut self, order: Order, reason: u32) {
+        self.metrics.reject(reason);
+        // log that order has been rejected
+        escalate(self.log_sender_to_logger.send(BaseLogs::OrderDelta(OrderDelta {
+            event_id: next_event_id(),
+            order_id: order.order_id,
+            user_id: order.user_id,
+            price: order.price,
+            symbol: order.symbol,
+            shares_qty: order.shares_qty,
+            side: match order.side {
+                Side::Ask => 1,
+                Side::Bid => 0
+            },
+            order_event_type: 2,
+        })));
+        // Order rejected (insufficient funds, halted symbol)
+        self.balance_manager.events_to_wrriter_try.push(
+            OrderEvents {
+                user_id: order.user_id,
+                order_id: order.order_id,
+                symbol: order.symbol,
+                event_kind: 3,
+                filled_qty: 0,
+                remaining_qty: order.shares_qty,
+                original_qty: order.shares_qty,
+                error_code: reason,
+                fee: 0
+            }
+        );
+    }
+
+    fn process_cancel(&mut self, order_to_be_canceled: OrderToBeCanceled) {
+        self.metrics.cancels_in.inc();
+        if let Err(e) = self.balance_manager.throttle(order_to_be_canceled.user_id, RateAction::Cancel, self.clock) {
+            self.metrics.throttle(RateAction::Cancel);
+            self.balance_manager.events_to_wrriter_try.push(cancel_rejected(order_to_be_canceled, e.reject_reason()));
+            return;
+        }
+        self.cancel_resting_order(order_to_be_canceled, CANCEL_REASON_USER);
+    }
+
+    // the one cancel path , user cancels and delisting both come through here
+    // releases the reservation , takes the order off the book and tells the writer why it went
+    // false when nothing was canceled
+    fn cancel_resting_order(&mut self, order_to_be_canceled: OrderToBeCanceled, reason: u32) -> bool {
+        if let Some(order_book) = self.engine.get_book_mut(order_to_be_canceled.symbol) {
+            // we canceled the order update the balance , pass the orderEvent to the Writter too
+            // this queue would be only for canceling the limit orderrs
+            // we need to get the detials of this order from the order manager , side , qty nd price
+            // the order may have been filled already , then there is nothing left to cancel
+            let Some(&order_index) = order_book.manager.id_to_index.get(&order_to_be_canceled.order_id) else {
+                if log_enabled(LogLevel::Warn) {
+                    eprintln!("[Trading Core] cancel for unknown order {}", order_to_be_canceled.order_id);
+                }
+                return false;
+            };
+            let order_detials = order_book.manager.get(order_index).unwrap();
+            // only the owner cancels its own order , delisting and freezing name the owner themselves
+            if order_detials.user_id != order_to_be_canceled.user_id {
+                self.balance_manager.events_to_wrriter_try.push(cancel_rejected(order_to_be_canceled, REJECT_REASON_NOT_OWNER));
+                return false;
+            }
+            let released = self.balance_manager.update_balance_after_order_cancel(
+                order_to_be_canceled,
+                order_detials.shares_qty,
+                |log| {
+                    escalate(self.log_sender_to_logger.send(log));
+                },
+                next_event_id,
+            );
+            if released.is_ok() {
+                order_book.cancel_order(order_to_be_canceled.order_id, |feed| {
+                    escalate(self.market_maker_feed_sender.send(feed));
+                });
+                // order can be aprtialyl filled also when cancl order comes
+                // need to chnage the order struct to include '
+                escalate(self.engine.sending_order_events_to_writter_try.send(OrderEvents {
+                    user_id: order_to_be_canceled.user_id,
+                    order_id: order_to_be_canceled.order_id,
+                    symbol: order_to_be_canceled.symbol,
+                    event_kind: 4, // cancel order
+                    filled_qty: 0,
+                    remaining_qty: 0,
+                    original_qty: 0,
+                    error_code: reason,
+                    fee: 0
+                }));
+                return true;
+            }
+            false
+        } else {
+            eprint!("invalid order");
+            false
+        }
+    }
+
+    // delisting , every resting order is canceled through the normal cancel path so its reservation is released
+    // the logger gets the book's final state before the book and its slot go away
+    pub fn delist_book(&mut self, symbol: u32) {
+        let Some(book) = self.engine.get_book(symbol) else {
+            eprintln!("[Trading Core] delist of unlisted symbol {}", symbol);
+            return;
+        };
+        let resting = book.resting_orders();
+        let mut canceled = 0usize;
+        for (order_id, user_id) in resting.iter().copied() {
+            if self.cancel_resting_order(OrderToBeCanceled { order_id, user_id, symbol }, CANCEL_REASON_DELISTED) {
+                canceled += 1;
+            }
+        }
+
+        let book = self.engine.get_book(symbol).unwrap();
+        escalate(self.snapshot_sender_to_logger.send(STEngine::book_snapshot(book, self.engine.snapshot_depth, next_event_id())));
+        let left = book.manager.id_to_index.len();
+        if left != 0 {
+            // only a resting order whose owner is unknown to the balance manager gets here , there is nothing to release
+            eprintln!("[Trading Core] delisting {} drops {} orders that could not be canceled", symbol, left);
+        }
+        let _ = self.engine.take_book(symbol);
+        // a later relisting starts out trading
+        self.admin.halted.remove(&symbol);
+        eprintln!("[Trading Core] delisted {} , canceled {} of {} resting orders", symbol, canceled, resting.len());
+    }
+
+    // every open or booked position , next to the book snapshots
+    fn report_all_positions(&mut self) {
+        self.balance_manager.report_positions(None, POSITION_REPORT_SNAPSHOT, |log| escalate(self.log_sender_to_logger.send(log)), next_event_id);
+    }
+
+    fn process_query(&mut self, query: Query) {
+        match query.query_type {
+            0 | 1 => {
+                // balances are no longer overwritten from outside , they move through deposits , withdrawals and transfers
+                eprintln!("[Trading Core] query type {} for user {} ignored , funds go through the admin deposit and withdraw commands", query.query_type, query.user_id);
+            }
+            2..=5 => {
+                // the query queue is not authenticated , users , books and shutdown are operator actions on the admin queue
+                eprintln!("[Trading Core] query type {} ignored , users , books and shutdown go through the admin commands", query.query_type);
+            }
+            6 => {
+                self.balance_manager.report_positions(Some(query.user_id), POSITION_REPORT_QUERY, |log| escalate(self.log_sender_to_logger.send(log)), next_event_id);
+            }
+            _ => {}
+        }
+    }
+
+    // every admin command is answered , denied and failed ones included , and leaves a line in the process log
+    fn process_admin(&mut self, request: AdminRequest) {
+        let command = request.command;
+        let result = if request.authorized {
+            AdminAction::decode(&command).and_then(|action| self.apply_admin(action))
+        } else {
+            Err(AdminError::Unauthorized(command.operator_id))
+        };
+        match &result {
+            Ok(_) => eprintln!("[Trading Core] admin request {} (type {}) by operator {} applied at sequence {}", command.request_id, command.command_type, command.operator_id, self.sequence),
+            Err(e) => eprintln!("[Trading Core] admin request {} (type {}) by operator {} failed at sequence {}: {}", command.request_id, command.command_type, command.operator_id, self.sequence, e),
+        }
+        // admin commands are where funds enter and leave , the books are checked after every one and at every checkpoint
+        // books that do not add up stop trading until an operator has looked and resumes it
+        if self.unconserved_asset().is_some() && !self.engine.trading_stopped {
+            eprintln!("[Trading Core] trading stopped at sequence {} , the balances do not add up", self.sequence);
+            self.engine.trading_stopped = true;
+        }
+        escalate(self.admin_response_sender.send(admin_response(&command, self.sequence, &result)));
+    }
+
+    fn apply_admin(&mut self, action: AdminAction) -> Result<AdminReply, AdminError> {
+        match action {
+            AdminAction::HaltSymbol(symbol) => {
+                if !self.engine.has_book(symbol) {
+                    return Err(SymbolError::NotListed(symbol).into());
+                }
+                self.admin.halt(symbol)?;
+            }
+            AdminAction::ResumeSymbol(symbol) => self.admin.resume(symbol)?,
+            AdminAction::AddBook(symbol) => {
+                self.engine.insert_book(OrderBook::new(symbol))?;
+            }
+            AdminAction::RemoveBook(symbol) => {
+                if !self.engine.has_book(symbol) {
+                    return Err(SymbolError::NotListed(symbol).into());
+                }
+                self.delist_book(symbol);
+            }
+            AdminAction::AddUser(user_id) => {
+                self.balance_manager.add_user(user_id, |log| escalate(self.log_sender_to_logger.send(log)), next_event_id)?;
+            }
+            AdminAction::Funds { key, request } => {
+                let outcome = self.balance_manager.apply_funds(
+                    key,
+                    request,
+                    |log| {
+                        escalate(self.log_sender_to_logger.send(log));
+                    },
+                    next_event_id,
+                )?;
+                if outcome == FundsOutcome::Duplicate {
+                    eprintln!("[Trading Core] funds request {} was already applied", key);
+                }
+            }
+            AdminAction::SetLimit { user_id, kind, value } => {
+                self.balance_manager.set_limit(user_id, kind, value)?;
+            }
+            AdminAction::FreezeUser(user_id) => {
+                for order_to_be_canceled in self.balance_manager.freeze_user(user_id)? {
+                    self.cancel_resting_order(order_to_be_canceled, CANCEL_REASON_FROZEN);
+                }
+            }
+            AdminAction::UnfreezeUser(user_id) => self.balance_manager.unfreeze_user(user_id)?,
+            AdminAction::StopTrading => self.engine.trading_stopped = true,
+            AdminAction::ResumeTrading => self.engine.trading_stopped = false,
+            AdminAction::SetMarkPrice { symbol, price } => {
+                if !self.engine.has_book(symbol) {
+                    return Err(SymbolError::NotListed(symbol).into());
+                }
+                self.balance_manager.set_mark_price(symbol, price);
+            }
+            AdminAction::Snapshot => {
+                self.engine.snapshot_for_all_book(|snapshot| {
+                    escalate(self.snapshot_sender_to_logger.send(snapshot));
+                }, next_event_id);
+                self.report_all_positions();
+                self.last_snap_shot = Instant::now();
+            }
+            AdminAction::DumpStats => {
+                return Ok(AdminReply::Stats(EngineStats {
+                    processed_orders: self.processed_count,
+                    book_count: self.engine.get_book_count() as u32,
+                    user_count: self.balance_manager.state.total_users,
+                    halted_count: self.admin.halted.len() as u32,
+                }));
+            }
+            AdminAction::SetLogLevel(level) => set_log_level(level),
+            AdminAction::Promote => return Err(AdminError::NotStandby),
+            AdminAction::Shutdown => {
+                // a replayed shutdown has nothing to stop
+                if let Some(shutdown) = self.shutdown.as_ref() {
+                    shutdown.request();
+                }
+            }
+        }
+        Ok(AdminReply::Done)
+    }
+}
@@ -0,0 +1,54 @@
+// the thread side of the status page , owned by the thread it reports for
+// beat() every loop iteration , busy or idle , a thread blocked anywhere stops beating
+// dropped while unwinding marks the slot panicked , dropped normally marks it stopped
+use std::sync::Arc;
+use crate::shm::status_page::{StatusPage, StatusPageError, ThreadState, THREAD_STATE_PANICKED, THREAD_STATE_STOPPED};
+
+// input depths are sampled once every this many beats , a size() per queue is not free
+pub const DEPTH_SAMPLE_EVERY : u64 = 1024;
+
+pub struct Heartbeat{
+    page : Arc<StatusPage>,
+    slot : usize,
+    beats : u64,
+}
+
+impl Heartbeat{
+    pub fn register(page : &Arc<StatusPage> , name : &str)->Result<Self , StatusPageError>{
+        let slot = page.register(name)?;
+        // a restarted thread carries on from the old count , the watchdog only looks for change
+        let beats = page.slot(slot).heartbeat();
+        Ok(Self { page : page.clone() , slot , beats })
+    }
+
+    pub fn slot(&self)->usize{
+        self.slot
+    }
+
+    #[inline(always)]
+    pub fn beat(&mut self){
+        self.beats = self.beats.wrapping_add(1);
+        self.page.slot(self.slot).set_heartbeat(self.beats);
+    }
+
+    /// beat , and every DEPTH_SAMPLE_EVERY beats publish the depths of the thread's inputs
+    #[inline(always)]
+    pub fn beat_with_depths<I : IntoIterator<Item = u64>>(&mut self , depths : impl FnOnce()->I){
+        self.beat();
+        if self.beats.is_multiple_of(DEPTH_SAMPLE_EVERY) {
+            self.page.slot(self.slot).set_input_depths(depths());
+        }
+    }
+
+    /// for a thread that outlives its own panic , the supervisor marks it panicked and running again once restarted
+    pub fn set_state(&self , state : ThreadState){
+        self.page.slot(self.slot).set_state(state.as_u32());
+    }
+}
+
+impl Drop for Heartbeat{
+    fn drop(&mut self){
+        let state = if std::thread::panicking() { THREAD_STATE_PANICKED } else { THREAD_STATE_STOPPED };
+        self.page.slot(self.slot).set_state(state);
+    }
+}
@@ -0,0 +1,3 @@
+pub mod heartbeat;
+pub mod monitor;
+pub mod tests;
@@ -0,0 +1,96 @@
+// watches every slot of the status page from a thread of its own
+// a running thread whose heartbeat has not moved for stall_after is marked stalled , it goes back to
+// running once it beats again , panicked and stopped threads are reported once
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use crate::metrics::latency::unix_nanos;
+use crate::metrics::registry::registry;
+use crate::shm::status_page::{StatusPage, ThreadState, MAX_THREADS, THREAD_STATE_RUNNING, THREAD_STATE_STALLED};
+
+#[derive(Debug , Clone , PartialEq , Eq)]
+pub enum WatchdogEvent{
+    Stalled { name : String , since : Duration },
+    Recovered { name : String },
+    Panicked { name : String },
+    Stopped { name : String },
+}
+
+#[derive(Clone , Copy)]
+struct Seen{
+    heartbeat : u64,
+    changed_at : Instant,
+    state : Option<ThreadState>,
+}
+
+pub struct Watchdog{
+    page : Arc<StatusPage>,
+    stall_after : Duration,
+    seen : Vec<Option<Seen>>,
+}
+
+impl Watchdog{
+    pub fn new(page : Arc<StatusPage> , stall_after : Duration)->Self{
+        Self { page , stall_after , seen : vec![None; MAX_THREADS] }
+    }
+
+    pub fn check(&mut self)->Vec<WatchdogEvent>{
+        self.check_at(Instant::now())
+    }
+
+    /// one pass over every registered thread as of `now`
+    pub fn check_at(&mut self , now : Instant)->Vec<WatchdogEvent>{
+        let now_ns = unix_nanos();
+        let mut events = Vec::new();
+        for index in 0..self.page.slot_count() {
+            let slot = self.page.slot(index);
+            let heartbeat = slot.heartbeat();
+            let seen = self.seen[index].get_or_insert(Seen { heartbeat , changed_at : now , state : None });
+            if heartbeat != seen.heartbeat {
+                seen.heartbeat = heartbeat;
+                seen.changed_at = now;
+                slot.set_last_progress_ns(now_ns);
+                slot.transition(THREAD_STATE_STALLED, THREAD_STATE_RUNNING);
+            } else if now.saturating_duration_since(seen.changed_at) >= self.stall_after {
+                slot.transition(THREAD_STATE_RUNNING, THREAD_STATE_STALLED);
+            }
+            let state = ThreadState::from_u32(slot.state());
+            if state != seen.state {
+                let name = slot.name();
+                match state {
+                    Some(ThreadState::Stalled) => events.push(WatchdogEvent::Stalled { name , since : now.saturating_duration_since(seen.changed_at) }),
+                    // a thread seen for the first time is not a recovery
+                    Some(ThreadState::Running) if seen.state.is_some() => events.push(WatchdogEvent::Recovered { name }),
+                    Some(ThreadState::Panicked) => events.push(WatchdogEvent::Panicked { name }),
+                    Some(ThreadState::Stopped) => events.push(WatchdogEvent::Stopped { name }),
+                    _ => {}
+                }
+                seen.state = state;
+                registry().gauge("engine_thread_state", "pipeline thread state , 1 running 2 stalled 3 panicked 4 stopped", &[("thread" , &slot.name())])
+                    .set(state.map_or(0, |state| state.as_u32()) as i64);
+            }
+        }
+        self.page.set_watchdog_ns(now_ns);
+        events
+    }
+}
+
+pub fn log_event(event : &WatchdogEvent){
+    match event {
+        WatchdogEvent::Stalled { name , since } => eprintln!("[Watchdog] {} stalled , no heartbeat for {:?}", name, since),
+        WatchdogEvent::Recovered { name } => eprintln!("[Watchdog] {} is beating again", name),
+        WatchdogEvent::Panicked { name } => eprintln!("[Watchdog] {} panicked", name),
+        WatchdogEvent::Stopped { name } => eprintln!("[Watchdog] {} stopped", name),
+    }
+}
+
+/// checks the page every `period` for as long as the process runs
+pub fn spawn_watchdog(page : Arc<StatusPage> , period : Duration , stall_after : Duration)->std::io::Result<JoinHandle<()>>{
+    let mut watchdog = Watchdog::new(page, stall_after);
+    std::thread::Builder::new().name("watchdog".into()).spawn(move || loop {
+        for event in watchdog.check() {
+            log_event(&event);
+        }
+        std::thread::sleep(period);
+    })
+}
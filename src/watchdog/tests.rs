@@ -0,0 +1,119 @@
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+    use crate::shm::status_page::{StatusPage, StatusPageError, ThreadState};
+    use crate::watchdog::heartbeat::{Heartbeat, DEPTH_SAMPLE_EVERY};
+    use crate::watchdog::monitor::{Watchdog, WatchdogEvent};
+
+    fn page_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("watchdog_test_{}_{}", name, std::process::id()))
+    }
+
+    fn state_of(page: &StatusPage, name: &str) -> ThreadState {
+        page.snapshot().into_iter().find(|thread| thread.name == name).unwrap().state
+    }
+
+    #[test]
+    fn test_register_and_snapshot() {
+        let path = page_path("register");
+        let page = Arc::new(StatusPage::create(&path).unwrap());
+        let mut core = Heartbeat::register(&page, "trading_core").unwrap();
+        let writer = Heartbeat::register(&page, "writer").unwrap();
+        assert_ne!(core.slot(), writer.slot());
+        for _ in 0..3 {
+            core.beat();
+        }
+
+        // another process maps the same file
+        let reader = StatusPage::open(&path).unwrap();
+        let threads = reader.snapshot();
+        assert_eq!(threads.len(), 2);
+        assert_eq!(threads[0].name, "trading_core");
+        assert_eq!(threads[0].state, ThreadState::Running);
+        assert_eq!(threads[0].heartbeat, 3);
+        assert_eq!(threads[1].heartbeat, 0);
+
+        // a restarted thread gets its slot and its count back
+        drop(core);
+        assert_eq!(state_of(&reader, "trading_core"), ThreadState::Stopped);
+        let mut core = Heartbeat::register(&page, "trading_core").unwrap();
+        core.beat();
+        assert_eq!(page.slot_count(), 2);
+        assert_eq!(reader.snapshot()[0].heartbeat, 4);
+        assert_eq!(state_of(&reader, "trading_core"), ThreadState::Running);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_stall_is_flagged_and_cleared() {
+        let path = page_path("stall");
+        let page = Arc::new(StatusPage::create(&path).unwrap());
+        let mut heartbeat = Heartbeat::register(&page, "publisher").unwrap();
+        let stall_after = Duration::from_millis(500);
+        let mut watchdog = Watchdog::new(page.clone(), stall_after);
+        let start = Instant::now();
+
+        assert!(watchdog.check_at(start).is_empty());
+        assert!(watchdog.check_at(start + stall_after / 2).is_empty());
+        let events = watchdog.check_at(start + stall_after);
+        assert_eq!(events, vec![WatchdogEvent::Stalled { name: "publisher".into(), since: stall_after }]);
+        assert_eq!(state_of(&page, "publisher"), ThreadState::Stalled);
+        // reported once , not on every pass
+        assert!(watchdog.check_at(start + stall_after * 2).is_empty());
+        assert_ne!(page.watchdog_ns(), 0);
+
+        heartbeat.beat();
+        let events = watchdog.check_at(start + stall_after * 3);
+        assert_eq!(events, vec![WatchdogEvent::Recovered { name: "publisher".into() }]);
+        assert_eq!(state_of(&page, "publisher"), ThreadState::Running);
+        assert_ne!(page.snapshot()[0].last_progress_ns, 0);
+
+        drop(heartbeat);
+        let events = watchdog.check_at(start + stall_after * 10);
+        assert_eq!(events, vec![WatchdogEvent::Stopped { name: "publisher".into() }]);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_panicking_thread_is_marked_panicked() {
+        let path = page_path("panic");
+        let page = Arc::new(StatusPage::create(&path).unwrap());
+        let mut watchdog = Watchdog::new(page.clone(), Duration::from_secs(60));
+        let thread_page = page.clone();
+        let handle = std::thread::spawn(move || {
+            let mut heartbeat = Heartbeat::register(&thread_page, "logger").unwrap();
+            heartbeat.beat();
+            panic!("logger lost its queue");
+        });
+        assert!(handle.join().is_err());
+
+        assert_eq!(state_of(&page, "logger"), ThreadState::Panicked);
+        assert_eq!(watchdog.check(), vec![WatchdogEvent::Panicked { name: "logger".into() }]);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_input_depths_are_sampled() {
+        let path = page_path("depths");
+        let page = Arc::new(StatusPage::create(&path).unwrap());
+        let mut heartbeat = Heartbeat::register(&page, "risk").unwrap();
+        for _ in 1..DEPTH_SAMPLE_EVERY {
+            heartbeat.beat_with_depths(|| [7u64, 9]);
+        }
+        assert!(page.snapshot()[0].input_depths.is_empty());
+        heartbeat.beat_with_depths(|| [7u64, 9]);
+        assert_eq!(page.snapshot()[0].input_depths, vec![7, 9]);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_open_rejects_foreign_files() {
+        let path = page_path("foreign");
+        std::fs::write(&path, b"not a status page").unwrap();
+        assert!(matches!(StatusPage::open(&path), Err(StatusPageError::InvalidSize { .. })));
+        let _ = std::fs::remove_file(&path);
+        assert!(matches!(StatusPage::open(&path), Err(StatusPageError::FileOpen(_))));
+    }
+}